@@ -0,0 +1,92 @@
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use qk::engine::Backend;
+use qk::session::Session;
+
+/// Ackermann's function over Church numerals, which the prelude doesn't have
+const ACK: &str = "ack m = m (fn f n => n f (f one)) succ";
+
+/// `(((I I) I) .. I)`, with every application in its own parenthesis
+fn nesting(depth: usize) -> String {
+    format!("{}I{}", "(".repeat(depth), " I)".repeat(depth))
+}
+
+/// `I I .. I`, all in a single application chain
+fn wide(width: usize) -> String {
+    vec!["I"; width].join(" ")
+}
+
+fn terms() -> Vec<(&'static str, String)> {
+    vec![
+        ("church", "mul (add three two) (pow two three)".into()),
+        ("ackermann", "ack two two".into()),
+        ("nesting", nesting(100)),
+        ("wide", wide(200)),
+    ]
+}
+
+fn session() -> Session {
+    let mut s = Session::new();
+    s.set_prelude(true).unwrap();
+    s.eval_str(ACK).unwrap();
+    s
+}
+
+fn pipeline(c: &mut Criterion) {
+    for (name, src) in terms() {
+        let src = src.as_str();
+        let mut group = c.benchmark_group(name);
+        let mut s = session();
+        let tokens = s.lexer(src).unwrap();
+        let ast = s.parse(&tokens, src).unwrap();
+
+        group.bench_function("lexer", |b| b.iter(|| s.lexer(src).unwrap()));
+        group.bench_function("parser", |b| b.iter(|| s.parse(&tokens, src).unwrap()));
+        // each run leaves its terms behind, so every batch gets a fresh session
+        group.bench_function("ir", |b| {
+            b.iter_batched_ref(
+                || (session(), Some(ast.clone())),
+                |(s, ast)| s.ir(ast.take().unwrap(), src).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_function("compiler", |b| {
+            b.iter_batched_ref(
+                || {
+                    let mut s = session();
+                    let ir = s.ir(ast.clone(), src).unwrap().unwrap();
+                    (s, Some(ir))
+                },
+                |(s, ir)| s.compile(ir.take().unwrap(), src).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_function("eval", |b| {
+            b.iter_batched_ref(session, |s| s.eval_str(src).unwrap(), BatchSize::SmallInput)
+        });
+        group.finish();
+    }
+}
+
+/// the same arithmetic, with primitives, on every backend
+fn backends(c: &mut Criterion) {
+    let src = "(fn x y => + (* x x) (* 2 (* y y))) 1234 5678";
+    let mut group = c.benchmark_group("arithmetic");
+    for backend in Backend::ALL {
+        group.bench_function(backend.name(), |b| {
+            b.iter_batched_ref(
+                || {
+                    let mut s = session();
+                    s.irc.primitives = true;
+                    s.backend = *backend;
+                    s
+                },
+                |s| s.eval_str(src).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, pipeline, backends);
+criterion_main!(benches);
@@ -0,0 +1,6 @@
+//! arbitrary sequences of tokens, compiled and run on a budget
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| qk::fuzz::compile(data));
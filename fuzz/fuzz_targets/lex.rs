@@ -0,0 +1,6 @@
+//! arbitrary bytes, lexed with every syntax
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| qk::fuzz::lex(data));
@@ -0,0 +1,6 @@
+//! arbitrary sequences of tokens, parsed
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| qk::fuzz::parse(data));
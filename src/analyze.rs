@@ -0,0 +1,286 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use miette::Diagnostic;
+
+use crate::ast::{Ast, Node};
+use crate::session::{self, Session};
+
+/// the last stage a file went through
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Stage {
+    Lexer,
+    Parser,
+    Ir,
+    Done,
+}
+
+impl Stage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Lexer => "lexer",
+            Self::Parser => "parser",
+            Self::Ir => "ir",
+            Self::Done => "done",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub stage: Stage,
+    /// the diagnostic code of the error that stopped the file
+    pub error: Option<String>,
+    /// size (in AST nodes) of each definition body
+    pub term_sizes: Vec<usize>,
+}
+
+impl FileReport {
+    pub fn average_term_size(&self) -> f64 {
+        average(self.term_sizes.iter().copied(), self.term_sizes.len())
+    }
+}
+
+/// aggregated statistics over a corpus of `.qk` files
+#[derive(Debug, Default, Clone)]
+pub struct Report {
+    pub files: Vec<FileReport>,
+    pub variables: HashMap<String, usize>,
+    pub errors: HashMap<String, usize>,
+}
+
+impl Report {
+    /// lexes, parses and compiles every `.qk` file inside `dir`, recursively
+    pub fn from_dir(dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        let mut paths = Vec::new();
+        collect_files(dir.as_ref(), &mut paths)?;
+        paths.sort();
+        let mut report = Self::default();
+        for path in paths {
+            let src = std::fs::read_to_string(&path)?;
+            report.push(path, &src);
+        }
+        Ok(report)
+    }
+
+    pub fn push(&mut self, path: PathBuf, src: &str) {
+        let mut file = FileReport {
+            path,
+            stage: Stage::Lexer,
+            error: None,
+            term_sizes: Vec::new(),
+        };
+        if let Err(e) = self.run(&mut file, src) {
+            let code = e.code().map_or_else(|| "unknown".into(), |c| c.to_string());
+            *self.errors.entry(code.clone()).or_default() += 1;
+            file.error = Some(code);
+        }
+        self.files.push(file);
+    }
+
+    fn run(&mut self, file: &mut FileReport, src: &str) -> session::Result<()> {
+        let mut s = Session::new();
+        let tokens = s.lexer(src)?;
+        if tokens.is_empty() {
+            file.stage = Stage::Done;
+            return Ok(());
+        }
+        file.stage = Stage::Parser;
+        let ast = s.parse(&tokens, src)?;
+        self.visit(file, &ast, src);
+        file.stage = Stage::Ir;
+        if let Some(expr) = s.ir(ast, src)? {
+            s.compile(expr, src)?;
+        } else {
+            s.irc.scope.check_for_pendings()?;
+        }
+        file.stage = Stage::Done;
+        Ok(())
+    }
+
+    fn visit(&mut self, file: &mut FileReport, n: &Node, src: &str) {
+        match &n.item {
+            Ast::Program(defs) => defs.iter().for_each(|d| self.visit(file, d, src)),
+            Ast::Def { body, .. } => file.term_sizes.push(self.count(body, src)),
+            _ => file.term_sizes.push(self.count(n, src)),
+        }
+    }
+
+    /// counts the nodes of a term, registering its variables
+    fn count(&mut self, n: &Node, src: &str) -> usize {
+        match &n.item {
+            Ast::Var => {
+                *self.variables.entry(n.from_code(src).into()).or_default() += 1;
+                1
+            }
+            Ast::Abs(_, inner) | Ast::Annotated { body: inner, .. } => 1 + self.count(inner, src),
+            Ast::Unpack { names, body } => names.len() + self.count(body, src),
+            Ast::App(l, r)
+            | Ast::Let {
+                value: l, body: r, ..
+            } => 1 + self.count(l, src) + self.count(r, src),
+            Ast::Def { body, .. } => self.count(body, src),
+            Ast::Tuple(items) | Ast::List(items) => {
+                1 + items.iter().map(|i| self.count(i, src)).sum::<usize>()
+            }
+            // an application between each item
+            Ast::Infix { items, .. } => {
+                items.len() - 1 + items.iter().map(|i| self.count(i, src)).sum::<usize>()
+            }
+            Ast::Program(defs) => defs.iter().map(|d| self.count(d, src)).sum(),
+        }
+    }
+
+    /// how many files couldn't be lexed or parsed
+    pub fn parse_error_rate(&self) -> f64 {
+        let failed = self
+            .files
+            .iter()
+            .filter(|f| f.error.is_some() && f.stage <= Stage::Parser)
+            .count();
+        average(std::iter::once(failed), self.files.len())
+    }
+
+    pub fn average_term_size(&self) -> f64 {
+        let sizes = self.files.iter().flat_map(|f| f.term_sizes.iter().copied());
+        let len = self.files.iter().map(|f| f.term_sizes.len()).sum();
+        average(sizes, len)
+    }
+
+    /// the `n` most used variables, from the most to the least used
+    pub fn most_used_variables(&self, n: usize) -> Vec<(&str, usize)> {
+        let mut vars: Vec<_> = self
+            .variables
+            .iter()
+            .map(|(k, v)| (k.as_str(), *v))
+            .collect();
+        vars.sort_by(|(lk, lv), (rk, rv)| rv.cmp(lv).then(lk.cmp(rk)));
+        vars.truncate(n);
+        vars
+    }
+
+    /// one row per file
+    pub fn to_csv(&self) -> String {
+        let mut s = String::from("file,stage,error,terms,average_term_size\n");
+        for f in &self.files {
+            let _ = writeln!(
+                s,
+                "{},{},{},{},{:.2}",
+                csv_field(&f.path.display().to_string()),
+                f.stage.as_str(),
+                csv_field(f.error.as_deref().unwrap_or("")),
+                f.term_sizes.len(),
+                f.average_term_size()
+            );
+        }
+        s
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> String {
+        let summary = Summary {
+            files: self.files.len(),
+            parse_error_rate: self.parse_error_rate(),
+            average_term_size: self.average_term_size(),
+            most_used_variables: self.most_used_variables(10).into_iter().collect(),
+            errors: self.errors.iter().map(|(k, v)| (k.as_str(), *v)).collect(),
+            per_file: self
+                .files
+                .iter()
+                .map(|f| FileSummary {
+                    file: f.path.display().to_string(),
+                    stage: f.stage,
+                    error: f.error.as_deref(),
+                    terms: f.term_sizes.len(),
+                    average_term_size: f.average_term_size(),
+                })
+                .collect(),
+        };
+        serde_json::to_string(&summary).expect("a summary is always serializable")
+    }
+}
+
+/// what `Report::to_json` writes
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct Summary<'a> {
+    files: usize,
+    parse_error_rate: f64,
+    average_term_size: f64,
+    most_used_variables: std::collections::BTreeMap<&'a str, usize>,
+    errors: std::collections::BTreeMap<&'a str, usize>,
+    per_file: Vec<FileSummary<'a>>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize)]
+struct FileSummary<'a> {
+    file: String,
+    stage: Stage,
+    error: Option<&'a str>,
+    terms: usize,
+    average_term_size: f64,
+}
+
+/// every `.qk` file inside `dir`, recursively
+pub(crate) fn collect_files(dir: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, paths)?;
+        } else if path.extension().is_some_and(|e| e == "qk") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn average(values: impl Iterator<Item = usize>, len: usize) -> f64 {
+    if len == 0 {
+        0.0
+    } else {
+        values.sum::<usize>() as f64 / len as f64
+    }
+}
+
+/// `field` as a CSV field, quoted if it has a separator, a quote or a line break in it
+fn csv_field(field: &str) -> Cow<'_, str> {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")).into(),
+        false => field.into(),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::analyze::{Report, Stage};
+
+    #[test]
+    pub fn aggregates_files() {
+        let mut r = Report::default();
+        r.push("ok.qk".into(), "I x = x\nK x y = x\n");
+        r.push("bad.qk".into(), "K x y = (x\n");
+        r.push("undeclared.qk".into(), "K x = y\n");
+
+        assert_eq!(r.files[0].stage, Stage::Done);
+        assert_eq!(r.files[1].stage, Stage::Parser);
+        assert_eq!(r.files[2].stage, Stage::Ir);
+        assert_eq!(r.parse_error_rate(), 1.0 / 3.0);
+        assert_eq!(r.most_used_variables(1), vec![("x", 2)]);
+        assert_eq!(r.errors["parser::unexpected_token"], 1);
+        assert_eq!(r.errors["ir::undeclared_variable"], 1);
+    }
+
+    #[test]
+    pub fn quotes_csv_fields() {
+        let mut r = Report::default();
+        r.push("a,\"b\".qk".into(), "I x = x\n");
+        let row = r.to_csv().lines().nth(1).unwrap().to_string();
+        assert_eq!(row, "\"a,\"\"b\"\".qk\",done,,1,1.00");
+    }
+}
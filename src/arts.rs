@@ -1,18 +1,140 @@
 use crate::ir;
+use crate::printer::{self, Notation, View};
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt::Write;
+use std::ops::Range;
+
+use miette::{Diagnostic, SourceSpan};
+use thiserror::Error;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TermIdx(pub usize);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OuterIdx(pub usize);
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Term {
     Var(OuterIdx),
-    Abs { inner: TermIdx },
+    Abs {
+        inner: TermIdx,
+    },
     App(TermIdx, TermIdx),
+    /// a native value, only compiled with `IrCompiler::primitives` on
+    Const(Const),
+}
+
+/// what the `primitives` mode adds to the λ-calculus: integers and the operations on them, which
+/// the reductors compute instead of β-reducing an encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Const {
+    Int(i64),
+    Prim(Prim),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Prim {
+    Add,
+    Sub,
+    Mul,
+    /// `if0 n a b` is `a` when `n` is 0, and `b` otherwise
+    If0,
+    /// checks that its argument is a function, failing with the `Blame` of this index in
+    /// `CompArtifact::casts` if it isn't. Inserted by the typed mode, and never written
+    Cast(u32),
+}
+
+impl Prim {
+    pub const ALL: [Self; 4] = [Self::Add, Self::Sub, Self::Mul, Self::If0];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::If0 => "if0",
+            Self::Cast(_) => "cast",
+        }
+    }
+
+    /// how many arguments it takes before it's computed
+    pub fn arity(self) -> usize {
+        match self {
+            Self::If0 => 3,
+            Self::Cast(_) => 1,
+            _ => 2,
+        }
+    }
+
+    /// how many of its first arguments it needs as numbers
+    pub fn strict(self) -> usize {
+        match self {
+            Self::If0 => 1,
+            Self::Cast(_) => 0,
+            _ => 2,
+        }
+    }
+}
+
+impl Const {
+    /// the constant `name` stands for, if any
+    pub fn parse(name: &str) -> Option<Self> {
+        if let Some(p) = Prim::ALL.into_iter().find(|p| p.name() == name) {
+            return Some(Self::Prim(p));
+        }
+        let digits = name.strip_prefix('-').unwrap_or(name);
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        name.parse().ok().map(Self::Int)
+    }
+}
+
+impl std::fmt::Display for Const {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Int(n) => write!(f, "{n}"),
+            Self::Prim(p) => f.write_str(p.name()),
+        }
+    }
+}
+
+/// what `CompArtifact::validate` finds wrong with a pool
+#[derive(Error, Diagnostic, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    #[error("term {term} points to term {to}, which isn't before it")]
+    #[diagnostic(
+        code(arts::forward_reference),
+        help("a term can only point to the ones pushed before it, so the pool has no cycles")
+    )]
+    ForwardReference { term: usize, to: usize },
+
+    #[error("{name} is term {term}, but there are only {len} terms")]
+    #[diagnostic(code(arts::unknown_term))]
+    UnknownTerm {
+        name: Box<str>,
+        term: usize,
+        len: usize,
+    },
+
+    #[error("{name} (term {term}) has a variable bound {needs} λ outside of it")]
+    #[diagnostic(
+        code(arts::open_term),
+        help(
+            "the root and the definitions are closed: every variable is bound by a λ inside them"
+        )
+    )]
+    OpenTerm {
+        name: Box<str>,
+        term: usize,
+        needs: usize,
+    },
 }
 
 /// the goat.
@@ -22,11 +144,32 @@ pub enum Term {
 /// regardless of the moment it's executed
 /// `Compiler Artifact` handles the job of ensuring everything
 /// done can be reutilized
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CompArtifact {
     arena: Vec<Term>,
     pub obj_cache: HashMap<ir::Id, TermIdx>,
     pub root: Option<TermIdx>,
+    /// where the applications of the last input compiled were written. Those inside a
+    /// definition point to where it was referred to
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub spans: HashMap<TermIdx, SourceSpan>,
+    /// the thunks the `need` strategy forced, with the last value they were updated with
+    pub forced: HashMap<TermIdx, TermIdx>,
+    /// how `pretty` writes abstractions
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub notation: Notation,
+    /// what each `Prim::Cast` blames when it fails
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub casts: Vec<Blame>,
+}
+
+/// where a cast was inserted: the annotation the function came from, or the term that had to be
+/// one. If it's in a definition, `at` is a span of the definition's source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Blame {
+    pub at: SourceSpan,
+    pub def: Option<ir::Id>,
 }
 
 impl CompArtifact {
@@ -35,18 +178,24 @@ impl CompArtifact {
     }
 
     pub fn arena_to_string(&self) -> String {
+        let all: Vec<_> = (0..self.arena.len()).map(TermIdx).collect();
+        self.terms_to_string(&all)
+    }
+
+    fn terms_to_string(&self, terms: &[TermIdx]) -> String {
         let mut s = String::new();
         s.push_str("[ ");
-        for (i, t) in self.arena.iter().enumerate() {
-            if i > 0 {
+        for (n, &TermIdx(i)) in terms.iter().enumerate() {
+            if n > 0 {
                 s.push_str(", ");
             }
-            let _ = match t {
+            let _ = match &self.arena[i] {
                 Term::Var(OuterIdx(idx)) => write!(s, "[{i}]=ν{idx}"),
                 Term::Abs {
                     inner: TermIdx(idx),
                 } => write!(s, "[{i}]=λ{idx}"),
                 Term::App(TermIdx(l), TermIdx(r)) => write!(s, "[{i}]={l}⋅{r}"),
+                Term::Const(c) => write!(s, "[{i}]={c}"),
             };
         }
         s.push_str(" ]");
@@ -56,14 +205,14 @@ impl CompArtifact {
     pub fn obj_cache_to_string(&self, aliases: &HashMap<ir::Id, Box<str>>) -> String {
         let mut s = String::new();
         let use_alias = !aliases.is_empty();
-        s.push_str("{");
+        s.push('{');
         for (i, (id, term_idx)) in self.obj_cache.iter().enumerate() {
             if i > 0 {
                 s.push_str(", ");
             } else {
                 s.push(' ');
             }
-            if let Some(Some(name)) = use_alias.then(|| aliases.get(&id)) {
+            if let Some(Some(name)) = use_alias.then(|| aliases.get(id)) {
                 s.push_str(name)
             } else {
                 let _ = write!(s, "{}", id.0);
@@ -85,6 +234,80 @@ impl CompArtifact {
             }
         )
     }
+
+    /// like `to_string`, but only with the terms reachable from `root`, and the definitions
+    /// among them
+    pub fn to_string_from(&self, root: TermIdx, aliases: &HashMap<ir::Id, Box<str>>) -> String {
+        let terms = self.reachable(&[root]);
+        let mut defs: Vec<_> = self
+            .obj_cache
+            .iter()
+            .filter(|(_, idx)| terms.binary_search(idx).is_ok())
+            .map(|(id, idx)| match aliases.get(id) {
+                Some(name) => format!("{name} => {}", idx.0),
+                None => format!("{} => {}", id.0, idx.0),
+            })
+            .collect();
+        defs.sort();
+        let defs = match defs.is_empty() {
+            true => "{ }".to_string(),
+            false => format!("{{ {} }}", defs.join(", ")),
+        };
+        format!(
+            "arena: {} | cache: {defs} | root: {}",
+            self.terms_to_string(&terms),
+            root.0
+        )
+    }
+    /// checks the pool is well formed: every term only points to the ones before it, and the
+    /// root and the definitions are closed. Useful after deserializing or building one by hand,
+    /// as the rest of qk takes it for granted
+    pub fn validate(&self) -> Vec<Error> {
+        let mut errors = Vec::new();
+        // how many λ around each term its variables need
+        let mut needs: Vec<usize> = Vec::with_capacity(self.arena.len());
+        for (i, t) in self.arena.iter().enumerate() {
+            let mut child = |c: TermIdx| {
+                if c.0 < i {
+                    needs[c.0]
+                } else {
+                    errors.push(Error::ForwardReference { term: i, to: c.0 });
+                    0
+                }
+            };
+            let n = match t {
+                Term::Var(v) => v.0 + 1,
+                Term::Abs { inner } => child(*inner).saturating_sub(1),
+                Term::App(l, r) => child(*l).max(child(*r)),
+                Term::Const(_) => 0,
+            };
+            needs.push(n);
+        }
+        let mut roots: Vec<(Box<str>, TermIdx)> = self
+            .obj_cache
+            .iter()
+            .map(|(id, idx)| (format!("definition {}", id.0).into(), *idx))
+            .collect();
+        roots.sort_by_key(|(_, idx)| *idx);
+        roots.extend(self.root.map(|r| ("the root".into(), r)));
+        for (name, idx) in roots {
+            match needs.get(idx.0) {
+                None => errors.push(Error::UnknownTerm {
+                    name,
+                    term: idx.0,
+                    len: self.arena.len(),
+                }),
+                Some(0) => (),
+                Some(n) => errors.push(Error::OpenTerm {
+                    name,
+                    term: idx.0,
+                    needs: *n,
+                }),
+            }
+        }
+        errors
+    }
+
     pub fn push(&mut self, t: Term) -> TermIdx {
         let idx = self.arena.len();
         self.arena.push(t);
@@ -95,53 +318,275 @@ impl CompArtifact {
         self.arena[i.0].clone()
     }
 
-    pub fn pretty_print(&self, idx: TermIdx, aliases: &HashMap<ir::Id, Box<str>>) {
-        let mut layers = Vec::new();
-        let mut inverse_cache = self.obj_cache.iter().map(|(ir, ti)| (*ti, *ir)).collect();
-        self.pretty_print_inner(idx, &mut inverse_cache, &mut layers, aliases);
-        println!();
+    /// `idx` without the casts left in it, which are stuck on a variable and say nothing about
+    /// its value. The terms reachable are rebuilt in the order of the arena, so the children are
+    /// rebuilt first
+    pub fn erase_casts(&mut self, idx: TermIdx) -> TermIdx {
+        let mut erased: HashMap<TermIdx, TermIdx> = HashMap::new();
+        for t in self.reachable(&[idx]) {
+            let new = match self.get(t) {
+                Term::App(l, r)
+                    if matches!(self.get(l), Term::Const(Const::Prim(Prim::Cast(_)))) =>
+                {
+                    erased[&r]
+                }
+                Term::App(l, r) if (erased[&l], erased[&r]) != (l, r) => {
+                    self.push(Term::App(erased[&l], erased[&r]))
+                }
+                Term::Abs { inner } if erased[&inner] != inner => self.push(Term::Abs {
+                    inner: erased[&inner],
+                }),
+                _ => t,
+            };
+            erased.insert(t, new);
+        }
+        erased[&idx]
+    }
+
+    /// whether `a` and `b` only differ in the names of their variables. With de Bruijn indexes,
+    /// that's having the same shape
+    pub fn alpha_eq(&self, a: TermIdx, b: TermIdx) -> bool {
+        let mut pending = vec![(a, b)];
+        while let Some((a, b)) = pending.pop() {
+            if a == b {
+                continue;
+            }
+            match (&self.arena[a.0], &self.arena[b.0]) {
+                (Term::Abs { inner: a }, Term::Abs { inner: b }) => pending.push((*a, *b)),
+                (Term::App(al, ar), Term::App(bl, br)) => {
+                    pending.extend([(*al, *bl), (*ar, *br)]);
+                }
+                (Term::Var(a), Term::Var(b)) if a == b => {}
+                (Term::Const(a), Term::Const(b)) if a == b => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// `idx` in the `notation` of the pool, as `λa b.a`, with the terms of definitions called
+    /// after `aliases`
+    pub fn pretty(&self, idx: TermIdx, aliases: &HashMap<ir::Id, Box<str>>) -> String {
+        printer::print(idx, self.notation, self.view(aliases, false))
     }
 
-    fn pretty_print_inner(
+    /// as `pretty`, with the binders named after how deep they are, so a term is printed the same
+    /// wherever in the pool it is
+    pub fn pretty_stable(&self, idx: TermIdx, aliases: &HashMap<ir::Id, Box<str>>) -> String {
+        printer::print(idx, self.notation, self.view(aliases, true))
+    }
+
+    /// as `pretty`, also giving where `target` was printed. `None` if it's hidden by an alias
+    pub fn pretty_marked(
         &self,
         idx: TermIdx,
-        inverse_cache: &HashMap<TermIdx, ir::Id>,
-        abs_layers: &mut Vec<usize>,
+        target: TermIdx,
         aliases: &HashMap<ir::Id, Box<str>>,
-    ) {
-        if let Some(alias) = inverse_cache.get(&idx).and_then(|i| aliases.get(i)) {
-            print!("{alias}");
-            return;
-        }
-        match self.get(idx) {
-            Term::Var(v) => print!(
-                "{}",
-                abs_layers
-                    .len()
-                    .checked_sub(v.0 + 1)
-                    .and_then(|n| abs_layers.get(n))
-                    .map_or_else(|| "?".to_string(), |&v| ir::Scope::id_to_str(&ir::Id(v)))
-            ),
-            Term::App(l, r) => {
-                self.pretty_print_inner(l, inverse_cache, abs_layers, aliases);
-                print!(" ");
-                if let Term::App(..) = self.get(r) {
-                    print!("(");
-                    self.pretty_print_inner(r, inverse_cache, abs_layers, aliases);
-                    print!(")");
-                } else {
-                    self.pretty_print_inner(r, inverse_cache, abs_layers, aliases);
+    ) -> (String, Option<Range<usize>>) {
+        printer::print_marked(idx, self.notation, target, self.view(aliases, false))
+    }
+
+    fn view<'a>(
+        &'a self,
+        aliases: &'a HashMap<ir::Id, Box<str>>,
+        stable: bool,
+    ) -> impl FnMut(TermIdx) -> View<'a, TermIdx> {
+        let inverse_cache = self.inverse_cache();
+        move |idx| {
+            if let Some(alias) = inverse_cache.get(&idx).and_then(|i| aliases.get(i)) {
+                return View::Atom(Cow::Borrowed(alias));
+            }
+            match self.get(idx) {
+                Term::Var(v) => View::Bound(v.0),
+                Term::Const(c) => View::Atom(c.to_string().into()),
+                Term::App(l, r) => View::App(l, r),
+                Term::Abs { inner } if stable => View::Fresh(inner),
+                Term::Abs { inner } => {
+                    View::Abs(ir::Scope::id_to_str(&ir::Id(idx.0)).into(), inner)
                 }
             }
-            Term::Abs { inner } => {
-                abs_layers.push(idx.0);
-                print!(
-                    "λ{}.",
-                    ir::Scope::id_to_str(&ir::Id(*abs_layers.last().unwrap()))
+        }
+    }
+
+    /// the whole arena as a Graphviz graph. Every term is drawn once, so a shared term shows up
+    /// as a node with many parents, and a forced thunk has a dashed edge to its value
+    pub fn to_dot(&self) -> String {
+        let all: Vec<_> = (0..self.arena.len()).map(TermIdx).collect();
+        self.dot(&all, &[], &HashMap::new())
+    }
+
+    /// like `to_dot`, but only with the terms reachable from `roots`, which are highlighted.
+    /// Definitions are named after `aliases`
+    pub fn to_dot_from(&self, roots: &[TermIdx], aliases: &HashMap<ir::Id, Box<str>>) -> String {
+        self.dot(&self.reachable(roots), roots, aliases)
+    }
+
+    /// the terms reachable from `roots`, in the order of the arena
+    pub fn reachable(&self, roots: &[TermIdx]) -> Vec<TermIdx> {
+        let mut seen = vec![false; self.arena.len()];
+        let mut stack = roots.to_vec();
+        while let Some(idx) = stack.pop() {
+            if std::mem::replace(&mut seen[idx.0], true) {
+                continue;
+            }
+            match self.get(idx) {
+                Term::Var(_) | Term::Const(_) => (),
+                Term::Abs { inner } => stack.push(inner),
+                Term::App(l, r) => stack.extend([l, r]),
+            }
+        }
+        (0..self.arena.len())
+            .filter(|i| seen[*i])
+            .map(TermIdx)
+            .collect()
+    }
+
+    fn dot(
+        &self,
+        terms: &[TermIdx],
+        roots: &[TermIdx],
+        aliases: &HashMap<ir::Id, Box<str>>,
+    ) -> String {
+        let inverse_cache = self.inverse_cache();
+        let mut s = String::from("digraph pool {\n");
+        for &idx in terms {
+            let label = match self.get(idx) {
+                Term::Var(v) => format!("ν{}", v.0),
+                Term::Abs { .. } => "λ".into(),
+                Term::App(..) => "@".into(),
+                Term::Const(c) => c.to_string(),
+            };
+            let _ = write!(s, "  t{} [label=\"{label}\"", idx.0);
+            if let Some(alias) = inverse_cache.get(&idx).and_then(|i| aliases.get(i)) {
+                let _ = write!(s, ", xlabel={alias:?}");
+            }
+            if roots.contains(&idx) {
+                s.push_str(", shape=doublecircle");
+            }
+            s.push_str("];\n");
+            let _ = match self.get(idx) {
+                Term::Var(_) | Term::Const(_) => Ok(()),
+                Term::Abs { inner } => writeln!(s, "  t{} -> t{};", idx.0, inner.0),
+                Term::App(l, r) => writeln!(
+                    s,
+                    "  t{i} -> t{} [label=\"f\"];\n  t{i} -> t{} [label=\"x\"];",
+                    l.0,
+                    r.0,
+                    i = idx.0
+                ),
+            };
+        }
+        for &idx in terms {
+            // `terms` is in the order of the arena
+            let drawn = |v: &&TermIdx| terms.binary_search(v).is_ok();
+            if let Some(value) = self.forced.get(&idx).filter(drawn) {
+                let _ = writeln!(
+                    s,
+                    "  t{} -> t{} [style=dashed, label=\"forced\"];",
+                    idx.0, value.0
                 );
-                self.pretty_print_inner(inner, inverse_cache, abs_layers, aliases);
-                abs_layers.pop();
             }
         }
+        s.push_str("}\n");
+        s
+    }
+
+    /// maps back a compiled term to the definition it came from
+    pub fn inverse_cache(&self) -> HashMap<TermIdx, ir::Id> {
+        self.obj_cache.iter().map(|(ir, ti)| (*ti, *ir)).collect()
+    }
+
+    /// the name of the `v` variable, given the `TermIdx` of the abstractions above it
+    pub fn var_name(abs_layers: &[usize], v: OuterIdx) -> String {
+        abs_layers
+            .len()
+            .checked_sub(v.0 + 1)
+            .and_then(|n| abs_layers.get(n))
+            .map_or_else(|| "?".to_string(), |&v| ir::Scope::id_to_str(&ir::Id(v)))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::arts::{CompArtifact, Error, OuterIdx, Term, TermIdx};
+    use crate::session::Session;
+
+    #[test]
+    pub fn validate() {
+        let mut s = Session::new();
+        s.set_prelude(true).unwrap();
+        s.cancel.budget = Some(100);
+        s.eval_str("Omega").unwrap_err();
+        s.eval_str("K I (fn x y => y x)").unwrap();
+        assert_eq!(s.art.validate(), vec![]);
+
+        let mut art = CompArtifact::default();
+        let x = art.push(Term::Var(OuterIdx(0)));
+        let id = art.push(Term::Abs { inner: x });
+        // a placeholder that was never patched
+        art.push(Term::Abs { inner: TermIdx(2) });
+        art.push(Term::App(id, TermIdx(9)));
+        art.root = Some(x);
+        art.obj_cache.insert(crate::ir::Id(0), TermIdx(7));
+        let errors = art.validate();
+        assert_eq!(
+            errors,
+            vec![
+                Error::ForwardReference { term: 2, to: 2 },
+                Error::ForwardReference { term: 3, to: 9 },
+                Error::UnknownTerm {
+                    name: "definition 0".into(),
+                    term: 7,
+                    len: 4
+                },
+                Error::OpenTerm {
+                    name: "the root".into(),
+                    term: 0,
+                    needs: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    pub fn only_what_is_reachable() {
+        let mut s = Session::new();
+        s.eval_str("I x = x\nK x y = x").unwrap();
+        let root = s.compile_expr("K (fn y => y)").unwrap().unwrap();
+        let aliases = s.irc.scope.get_aliases();
+        let shown = s.art.to_string_from(root, &aliases);
+        assert!(shown.contains("cache: { K => "), "{shown}");
+        assert!(!shown.contains("I =>"), "{shown}");
+        assert!(shown.ends_with(&format!("root: {}", root.0)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn serde_round_trip() {
+        use std::collections::HashMap;
+
+        use crate::ast::Node;
+        use crate::session::Output;
+
+        let mut s = Session::new();
+        s.eval_str("K x y = x").unwrap();
+        let out = s.eval_str("K (fn x => x)").unwrap();
+        let Output::Normal(nf) = out.output else {
+            panic!("expected a normal form");
+        };
+
+        let json = serde_json::to_string(&s.art).unwrap();
+        let art: CompArtifact = serde_json::from_str(&json).unwrap();
+        assert_eq!(art.validate(), vec![]);
+        assert_eq!(art.arena(), s.art.arena());
+        assert_eq!(art.obj_cache, s.art.obj_cache);
+        assert_eq!(
+            art.pretty(nf, &HashMap::new()),
+            s.art.pretty(nf, &HashMap::new())
+        );
+
+        let ast = out.ast.unwrap();
+        let json = serde_json::to_string(&ast).unwrap();
+        assert_eq!(serde_json::from_str::<Node>(&json).unwrap(), ast);
     }
 }
@@ -1,57 +1,418 @@
+use std::fmt::Write;
+
 use miette::SourceSpan;
 
-use crate::lexer::Meta;
+use crate::lexer::{Meta, from_code, shift};
 
 pub type Node = Box<Meta<Ast>>;
 
-#[derive(Debug, Clone, PartialEq)]
+/// since it's dropped and cloned through explicit stacks, to handle any nesting depth, it can't be
+/// destructured by value. Use `Meta::take` to move the children out instead
+#[derive(Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Ast {
     Abs(SourceSpan, Node),
+    /// `fn (x, y) => body`, taking a Church pair and binding its sides: `fn p => p (fn x y => body)`.
+    /// `(x, y, z)` takes `(x, (y, z))`
+    Unpack {
+        names: Vec<SourceSpan>,
+        body: Node,
+    },
+    /// `fn (x : ty) => body`, an abstraction with the type its parameter is checked against. `ty`
+    /// is the span of the type, read when the term is typed
+    Annotated {
+        name: SourceSpan,
+        ty: SourceSpan,
+        body: Node,
+    },
     App(Node, Node),
+    #[default]
     Var,
     Def {
         ident: SourceSpan,
         params: Vec<SourceSpan>,
         body: Node,
     },
+    /// `let f = value in body`, or with `letrec`, where `value` may refer to `f`. Parameters are
+    /// already abstractions of `value`
+    Let {
+        rec: bool,
+        ident: SourceSpan,
+        value: Node,
+        body: Node,
+    },
+    /// an application with operators written between its terms, as `a + b c`. `operators` are
+    /// the positions in `items` of the ones not in parenthesis, which may be infix. Which are, and
+    /// how they group, is only known with the fixities declared
+    Infix {
+        items: Vec<Node>,
+        operators: Vec<usize>,
+    },
+    /// `(a, b)`, a Church pair. `(a, b, c)` is `(a, (b, c))`
+    Tuple(Vec<Node>),
+    /// `[a, b, c]`, a Church list: pairs nested to the right, ended by `nil`
+    List(Vec<Node>),
     Program(Vec<Node>),
 }
 
-pub fn display_node(n: &Node) {
-    fn span_str(span: &SourceSpan) -> String {
-        format!("{}..{}", span.offset(), span.offset() + span.len())
+impl Ast {
+    pub(crate) fn children(&self) -> Vec<&Node> {
+        match self {
+            Ast::Var => Vec::new(),
+            Ast::Abs(_, inner)
+            | Ast::Unpack { body: inner, .. }
+            | Ast::Annotated { body: inner, .. }
+            | Ast::Def { body: inner, .. } => vec![inner],
+            Ast::App(l, r)
+            | Ast::Let {
+                value: l, body: r, ..
+            } => vec![l, r],
+            Ast::Tuple(items)
+            | Ast::List(items)
+            | Ast::Infix { items, .. }
+            | Ast::Program(items) => items.iter().collect(),
+        }
+    }
+}
+
+impl Meta<Ast> {
+    /// moves the node out, leaving a variable in its place
+    pub fn take(&mut self) -> Meta<Ast> {
+        Meta {
+            item: std::mem::take(&mut self.item),
+            at: self.at,
+            sugar: self.sugar,
+        }
+    }
+
+    /// moves every span `by` bytes further, as when the source is parsed from an offset
+    pub fn shift(&mut self, by: usize) {
+        let mut stack = vec![self];
+        while let Some(n) = stack.pop() {
+            n.at = shift(n.at, by);
+            match &mut n.item {
+                Ast::Var => (),
+                Ast::Abs(v, inner) => {
+                    *v = shift(*v, by);
+                    stack.push(inner);
+                }
+                Ast::Unpack { names, body } => {
+                    names.iter_mut().for_each(|v| *v = shift(*v, by));
+                    stack.push(body);
+                }
+                Ast::Annotated { name, ty, body } => {
+                    *name = shift(*name, by);
+                    *ty = shift(*ty, by);
+                    stack.push(body);
+                }
+                Ast::App(l, r) => {
+                    stack.push(l);
+                    stack.push(r);
+                }
+                Ast::Let {
+                    ident, value, body, ..
+                } => {
+                    *ident = shift(*ident, by);
+                    stack.push(value);
+                    stack.push(body);
+                }
+                Ast::Def {
+                    ident,
+                    params,
+                    body,
+                } => {
+                    *ident = shift(*ident, by);
+                    params.iter_mut().for_each(|p| *p = shift(*p, by));
+                    stack.push(body);
+                }
+                Ast::Tuple(items)
+                | Ast::List(items)
+                | Ast::Infix { items, .. }
+                | Ast::Program(items) => stack.extend(items.iter_mut().map(|d| &mut **d)),
+            }
+        }
+    }
+}
+
+impl Clone for Ast {
+    fn clone(&self) -> Self {
+        enum Task<'a> {
+            Visit(&'a Ast),
+            /// rebuilds the node out of its last cloned children
+            Build(&'a Ast),
+        }
+
+        let mut tasks = vec![Task::Visit(self)];
+        let mut done: Vec<Ast> = Vec::new();
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Visit(ast) => {
+                    tasks.push(Task::Build(ast));
+                    tasks.extend(
+                        ast.children()
+                            .into_iter()
+                            .rev()
+                            .map(|c| Task::Visit(&c.item)),
+                    );
+                }
+                Task::Build(ast) => {
+                    let children = done.split_off(done.len() - ast.children().len());
+                    let mut children =
+                        ast.children().into_iter().zip(children).map(|(old, item)| {
+                            Box::new(Meta {
+                                item,
+                                at: old.at,
+                                sugar: old.sugar,
+                            })
+                        });
+                    let mut next = || children.next().expect("every child was cloned");
+                    done.push(match ast {
+                        Ast::Var => Ast::Var,
+                        Ast::Abs(v, _) => Ast::Abs(*v, next()),
+                        Ast::Unpack { names, .. } => Ast::Unpack {
+                            names: names.clone(),
+                            body: next(),
+                        },
+                        Ast::Annotated { name, ty, .. } => Ast::Annotated {
+                            name: *name,
+                            ty: *ty,
+                            body: next(),
+                        },
+                        Ast::App(..) => Ast::App(next(), next()),
+                        Ast::Let { rec, ident, .. } => Ast::Let {
+                            rec: *rec,
+                            ident: *ident,
+                            value: next(),
+                            body: next(),
+                        },
+                        Ast::Def { ident, params, .. } => Ast::Def {
+                            ident: *ident,
+                            params: params.clone(),
+                            body: next(),
+                        },
+                        Ast::Tuple(items) => Ast::Tuple(items.iter().map(|_| next()).collect()),
+                        Ast::List(items) => Ast::List(items.iter().map(|_| next()).collect()),
+                        Ast::Infix { items, operators } => Ast::Infix {
+                            items: items.iter().map(|_| next()).collect(),
+                            operators: operators.clone(),
+                        },
+                        Ast::Program(defs) => Ast::Program(defs.iter().map(|_| next()).collect()),
+                    });
+                }
+            }
+        }
+        done.pop().expect("the root was cloned")
+    }
+}
+
+impl Drop for Ast {
+    fn drop(&mut self) {
+        fn take_children(a: &mut Ast, stack: &mut Vec<Ast>) {
+            match a {
+                Ast::Var => (),
+                Ast::Abs(_, inner)
+                | Ast::Unpack { body: inner, .. }
+                | Ast::Annotated { body: inner, .. }
+                | Ast::Def { body: inner, .. } => stack.push(std::mem::take(&mut inner.item)),
+                Ast::App(l, r)
+                | Ast::Let {
+                    value: l, body: r, ..
+                } => {
+                    stack.push(std::mem::take(&mut l.item));
+                    stack.push(std::mem::take(&mut r.item));
+                }
+                Ast::Tuple(items)
+                | Ast::List(items)
+                | Ast::Infix { items, .. }
+                | Ast::Program(items) => {
+                    stack.extend(items.iter_mut().map(|d| std::mem::take(&mut d.item)))
+                }
+            }
+        }
+
+        let mut stack = Vec::new();
+        take_children(self, &mut stack);
+        while let Some(mut a) = stack.pop() {
+            take_children(&mut a, &mut stack);
+        }
+    }
+}
+
+/// how `display_node` draws a tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeStyle {
+    /// box-drawing characters and `λ`, or else only ASCII around the source
+    pub unicode: bool,
+    /// how many levels below the root are shown. Deeper children are folded into a `…`
+    pub max_depth: Option<usize>,
+}
+
+impl Default for TreeStyle {
+    fn default() -> Self {
+        Self {
+            unicode: true,
+            max_depth: None,
+        }
     }
+}
 
-    fn indented(n: &Node, mut depth: usize) {
-        print!("{}", " ".repeat(depth * 2));
-        depth += 1;
-        let span = span_str(&n.at);
-        match &n.item {
-            Ast::Var => println!("ν @ {span}"),
-            Ast::Abs(v, inner) => {
-                println!("λ {} @ {span} ∈", span_str(v));
-                indented(inner, depth);
+/// the tree of `n`, a node per line, with the text of each name in `src` and where it is
+///
+/// ```text
+/// app @ 0..11
+/// ├─ λ x @ 3..4
+/// │  └─ x @ 8..9
+/// └─ y @ 10..11
+/// ```
+pub fn display_node(n: &Node, src: &str, style: TreeStyle) -> String {
+    let (branch, last, bar, lambda, folded) = match style.unicode {
+        true => ("├─ ", "└─ ", "│  ", "λ", "…"),
+        false => ("|- ", "`- ", "|  ", "\\", "..."),
+    };
+    let span = |at: &SourceSpan| format!("{}..{}", at.offset(), at.offset() + at.len());
+    let mut s = String::new();
+    // the node, the start of its line, the start of its children's lines and its depth
+    let mut stack = vec![(n, String::new(), String::new(), 0)];
+    while let Some((n, line, indent, depth)) = stack.pop() {
+        s.push_str(&line);
+        let _ = match &n.item {
+            Ast::Var => write!(s, "{} @ {}", n.from_code(src), span(&n.at)),
+            Ast::Abs(v, _) => write!(s, "{lambda} {} @ {}", from_code(*v, src), span(v)),
+            Ast::Unpack { names, .. } => {
+                let names: Vec<_> = names.iter().map(|v| from_code(*v, src)).collect();
+                write!(s, "{lambda} ({}) @ {}", names.join(", "), span(&n.at))
             }
-            Ast::Program(p) => {
-                println!("exec");
-                p.iter().for_each(|p| indented(p, depth));
+            Ast::Annotated { name, ty, .. } => write!(
+                s,
+                "{lambda} {} : {} @ {}",
+                from_code(*name, src),
+                from_code(*ty, src),
+                span(name)
+            ),
+            Ast::App(..) => write!(s, "app @ {}", span(&n.at)),
+            Ast::Def { ident, params, .. } => {
+                let _ = write!(s, "def {}", from_code(*ident, src));
+                params
+                    .iter()
+                    .for_each(|p| s.extend([" ", from_code(*p, src)]));
+                write!(s, " @ {}", span(ident))
             }
-            Ast::Def {
-                ident,
-                params,
-                body,
-            } => {
-                print!("def @ {} (", span_str(ident));
-                params.iter().for_each(|p| print!(" {}", span_str(p)));
-                println!(" ) =");
-                indented(body, depth);
+            Ast::Let { rec, ident, .. } => {
+                let kw = if *rec { "letrec" } else { "let" };
+                write!(s, "{kw} {} @ {}", from_code(*ident, src), span(ident))
             }
-            Ast::App(l, r) => {
-                println!("app @ {span}");
-                indented(l, depth);
-                indented(r, depth);
+            Ast::Tuple(_) => write!(s, "tuple @ {}", span(&n.at)),
+            Ast::List(_) => write!(s, "list @ {}", span(&n.at)),
+            Ast::Infix { .. } => write!(s, "infix @ {}", span(&n.at)),
+            Ast::Program(_) => write!(s, "program @ {}", span(&n.at)),
+        };
+        let children = n.item.children();
+        if style.max_depth.is_some_and(|max| depth >= max) && !children.is_empty() {
+            s.push(' ');
+            s.push_str(folded);
+            s.push('\n');
+            continue;
+        }
+        s.push('\n');
+        for (i, child) in children.iter().enumerate().rev() {
+            let (line, next) = match i + 1 == children.len() {
+                true => (format!("{indent}{last}"), format!("{indent}   ")),
+                false => (format!("{indent}{branch}"), format!("{indent}{bar}")),
+            };
+            stack.push((child, line, next, depth + 1));
+        }
+    }
+    s
+}
+
+/// the syntax tree as a Graphviz graph
+pub fn to_dot(n: &Node, src: &str) -> String {
+    fn node(s: &mut String, n: &Node, src: &str, next: &mut usize) -> usize {
+        let id = *next;
+        *next += 1;
+        let label = match &n.item {
+            Ast::Var => n.from_code(src).to_string(),
+            Ast::Abs(v, _) => format!("λ{}", from_code(*v, src)),
+            Ast::Unpack { names, .. } => {
+                let names: Vec<_> = names.iter().map(|v| from_code(*v, src)).collect();
+                format!("λ({})", names.join(", "))
+            }
+            Ast::Annotated { name, ty, .. } => {
+                format!("λ{} : {}", from_code(*name, src), from_code(*ty, src))
             }
+            Ast::App(..) => "@".into(),
+            Ast::Def { ident, params, .. } => {
+                let mut label = from_code(*ident, src).to_string();
+                params.iter().for_each(|p| {
+                    label.push(' ');
+                    label.push_str(from_code(*p, src));
+                });
+                label.push_str(" =");
+                label
+            }
+            Ast::Let { rec, ident, .. } => {
+                let kw = if *rec { "letrec" } else { "let" };
+                format!("{kw} {} =", from_code(*ident, src))
+            }
+            Ast::Tuple(..) => "(,)".into(),
+            Ast::List(..) => "[]".into(),
+            Ast::Infix { .. } => "infix".into(),
+            Ast::Program(..) => "program".into(),
+        };
+        let _ = writeln!(s, "  n{id} [label={label:?}];");
+        let children: Vec<&Node> = match &n.item {
+            Ast::Var => Vec::new(),
+            Ast::Abs(_, inner)
+            | Ast::Unpack { body: inner, .. }
+            | Ast::Annotated { body: inner, .. } => vec![inner],
+            Ast::App(l, r)
+            | Ast::Let {
+                value: l, body: r, ..
+            } => vec![l, r],
+            Ast::Def { body, .. } => vec![body],
+            Ast::Tuple(items)
+            | Ast::List(items)
+            | Ast::Infix { items, .. }
+            | Ast::Program(items) => items.iter().collect(),
+        };
+        for c in children {
+            let c = node(s, c, src, next);
+            let _ = writeln!(s, "  n{id} -> n{c};");
         }
+        id
+    }
+
+    let mut s = String::from("digraph ast {\n");
+    node(&mut s, n, src, &mut 0);
+    s.push_str("}\n");
+    s
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::ast::{TreeStyle, display_node};
+    use crate::padam::grammar;
+
+    #[test]
+    pub fn displays_with_the_source() {
+        let src = "(fn x => x) y";
+        let ast = grammar::tests::parse(grammar::EXPR, src).unwrap();
+        let tree = display_node(&ast, src, TreeStyle::default());
+        assert_eq!(
+            tree,
+            "app @ 0..13\n├─ λ x @ 4..5\n│  └─ x @ 9..10\n└─ y @ 12..13\n"
+        );
+
+        let ascii = TreeStyle {
+            unicode: false,
+            max_depth: Some(1),
+        };
+        let tree = display_node(&ast, src, ascii);
+        assert_eq!(tree, "app @ 0..13\n|- \\ x @ 4..5 ...\n`- y @ 12..13\n");
+
+        let src = "K x y = x";
+        let ast = grammar::tests::parse(grammar::PROGRAM, src).unwrap();
+        let tree = display_node(&ast, src, TreeStyle::default());
+        assert!(tree.contains("def K x y @ 0..1\n"), "{tree}");
     }
-    indented(n, 0)
 }
@@ -0,0 +1,457 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::arts::{CompArtifact, Const, Term, TermIdx};
+use crate::ir;
+
+pub type Aliases = HashMap<ir::Id, Box<str>>;
+
+/// an output format for compiled terms
+pub trait Renderer: std::fmt::Debug {
+    /// the name used by `:export`
+    fn name(&self) -> &'static str;
+
+    /// renders the term at `root`. Formats meant for humans may use `aliases` to print a
+    /// definition name instead of its whole body
+    fn render(&self, art: &CompArtifact, root: TermIdx, aliases: &Aliases) -> String;
+
+    /// renders every step of a reduction, from the first term to the normal form. One term per
+    /// line, unless the format knows better
+    fn render_steps(&self, art: &CompArtifact, steps: &[TermIdx], aliases: &Aliases) -> String {
+        let steps: Vec<_> = steps
+            .iter()
+            .map(|s| self.render(art, *s, aliases))
+            .collect();
+        steps.join("\n")
+    }
+}
+
+/// every output format qk knows by default
+pub fn defaults() -> Vec<Box<dyn Renderer>> {
+    vec![
+        Box::new(Text),
+        Box::new(SExpr),
+        Box::new(Json),
+        Box::new(DeBruijn),
+        Box::new(Html),
+        Box::new(Blc),
+        Box::new(Dot),
+        Box::new(Latex),
+    ]
+}
+
+/// the same notation used by the REPL
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Text;
+
+impl Renderer for Text {
+    fn name(&self) -> &'static str {
+        "text"
+    }
+
+    fn render(&self, art: &CompArtifact, root: TermIdx, aliases: &Aliases) -> String {
+        art.pretty(root, aliases)
+    }
+}
+
+/// `(lambda (a) (a a))`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SExpr;
+
+impl SExpr {
+    fn write(s: &mut String, art: &CompArtifact, idx: TermIdx, layers: &mut Vec<usize>) {
+        match art.get(idx) {
+            Term::Var(v) => s.push_str(&CompArtifact::var_name(layers, v)),
+            Term::Const(c) => {
+                let _ = write!(s, "{c}");
+            }
+            Term::App(l, r) => {
+                s.push('(');
+                Self::write(s, art, l, layers);
+                s.push(' ');
+                Self::write(s, art, r, layers);
+                s.push(')');
+            }
+            Term::Abs { inner } => {
+                layers.push(idx.0);
+                let _ = write!(s, "(lambda ({}) ", ir::Scope::id_to_str(&ir::Id(idx.0)));
+                Self::write(s, art, inner, layers);
+                s.push(')');
+                layers.pop();
+            }
+        }
+    }
+}
+
+impl Renderer for SExpr {
+    fn name(&self) -> &'static str {
+        "sexpr"
+    }
+
+    fn render(&self, art: &CompArtifact, root: TermIdx, _aliases: &Aliases) -> String {
+        let mut s = String::new();
+        Self::write(&mut s, art, root, &mut Vec::new());
+        s
+    }
+}
+
+/// the de Bruijn tree: `{"abs":{"var":0}}`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Json;
+
+impl Json {
+    fn write(s: &mut String, art: &CompArtifact, idx: TermIdx) {
+        match art.get(idx) {
+            Term::Var(v) => {
+                let _ = write!(s, "{{\"var\":{}}}", v.0);
+            }
+            Term::Const(Const::Int(n)) => {
+                let _ = write!(s, "{{\"int\":{n}}}");
+            }
+            Term::Const(Const::Prim(p)) => {
+                let _ = write!(s, "{{\"prim\":{:?}}}", p.name());
+            }
+            Term::App(l, r) => {
+                s.push_str("{\"app\":[");
+                Self::write(s, art, l);
+                s.push(',');
+                Self::write(s, art, r);
+                s.push_str("]}");
+            }
+            Term::Abs { inner } => {
+                s.push_str("{\"abs\":");
+                Self::write(s, art, inner);
+                s.push('}');
+            }
+        }
+    }
+}
+
+impl Renderer for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn render(&self, art: &CompArtifact, root: TermIdx, _aliases: &Aliases) -> String {
+        let mut s = String::new();
+        Self::write(&mut s, art, root);
+        s
+    }
+}
+
+/// nameless, with each variable as the number of abstractions between it and its binder:
+/// `λ λ 1 (1 0)`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DeBruijn;
+
+impl DeBruijn {
+    fn write(s: &mut String, art: &CompArtifact, idx: TermIdx) {
+        match art.get(idx) {
+            Term::Var(v) => {
+                let _ = write!(s, "{}", v.0);
+            }
+            Term::Const(c) => {
+                let _ = write!(s, "{c}");
+            }
+            Term::App(l, r) => {
+                let paren = matches!(art.get(l), Term::Abs { .. });
+                Self::write_paren(s, art, l, paren);
+                s.push(' ');
+                let paren = !matches!(art.get(r), Term::Var(..) | Term::Const(..));
+                Self::write_paren(s, art, r, paren);
+            }
+            Term::Abs { inner } => {
+                s.push_str("λ ");
+                Self::write(s, art, inner);
+            }
+        }
+    }
+
+    fn write_paren(s: &mut String, art: &CompArtifact, idx: TermIdx, paren: bool) {
+        if paren {
+            s.push('(');
+        }
+        Self::write(s, art, idx);
+        if paren {
+            s.push(')');
+        }
+    }
+}
+
+impl Renderer for DeBruijn {
+    fn name(&self) -> &'static str {
+        "debruijn"
+    }
+
+    fn render(&self, art: &CompArtifact, root: TermIdx, _aliases: &Aliases) -> String {
+        let mut s = String::new();
+        Self::write(&mut s, art, root);
+        s
+    }
+}
+
+/// nested `<span>`s, with a class for each kind of term, so it can be styled
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Html;
+
+impl Html {
+    fn write(
+        s: &mut String,
+        art: &CompArtifact,
+        idx: TermIdx,
+        inverse_cache: &HashMap<TermIdx, ir::Id>,
+        layers: &mut Vec<usize>,
+        aliases: &Aliases,
+    ) {
+        if let Some(alias) = inverse_cache.get(&idx).and_then(|i| aliases.get(i)) {
+            let _ = write!(s, "<span class=\"qk-def\">{}</span>", escape_html(alias));
+            return;
+        }
+        match art.get(idx) {
+            Term::Var(v) => {
+                let _ = write!(s, "<var>{}</var>", CompArtifact::var_name(layers, v));
+            }
+            Term::Const(c) => {
+                let _ = write!(
+                    s,
+                    "<span class=\"qk-const\">{}</span>",
+                    escape_html(&c.to_string())
+                );
+            }
+            Term::App(l, r) => {
+                s.push_str("<span class=\"qk-app\">");
+                Self::write(s, art, l, inverse_cache, layers, aliases);
+                s.push(' ');
+                let paren = matches!(art.get(r), Term::App(..));
+                if paren {
+                    s.push('(');
+                }
+                Self::write(s, art, r, inverse_cache, layers, aliases);
+                if paren {
+                    s.push(')');
+                }
+                s.push_str("</span>");
+            }
+            Term::Abs { inner } => {
+                layers.push(idx.0);
+                let _ = write!(
+                    s,
+                    "<span class=\"qk-abs\">λ<var>{}</var>.",
+                    ir::Scope::id_to_str(&ir::Id(idx.0))
+                );
+                Self::write(s, art, inner, inverse_cache, layers, aliases);
+                s.push_str("</span>");
+                layers.pop();
+            }
+        }
+    }
+}
+
+impl Renderer for Html {
+    fn name(&self) -> &'static str {
+        "html"
+    }
+
+    fn render(&self, art: &CompArtifact, root: TermIdx, aliases: &Aliases) -> String {
+        let mut s = String::from("<code class=\"qk-term\">");
+        let inverse_cache = art.inverse_cache();
+        Self::write(&mut s, art, root, &inverse_cache, &mut Vec::new(), aliases);
+        s.push_str("</code>");
+        s
+    }
+}
+
+pub fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// binary lambda calculus: `00` for λ, `01` for application and `1ⁿ⁺¹0` for the variable `n`.
+/// Constants are written between brackets, as in `[+]`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Blc;
+
+impl Blc {
+    fn write(s: &mut String, art: &CompArtifact, idx: TermIdx) {
+        match art.get(idx) {
+            Term::Var(v) => {
+                (0..=v.0).for_each(|_| s.push('1'));
+                s.push('0');
+            }
+            // there are no bits for them, so they're kept as they're written
+            Term::Const(c) => {
+                let _ = write!(s, "[{c}]");
+            }
+            Term::App(l, r) => {
+                s.push_str("01");
+                Self::write(s, art, l);
+                Self::write(s, art, r);
+            }
+            Term::Abs { inner } => {
+                s.push_str("00");
+                Self::write(s, art, inner);
+            }
+        }
+    }
+}
+
+impl Renderer for Blc {
+    fn name(&self) -> &'static str {
+        "blc"
+    }
+
+    fn render(&self, art: &CompArtifact, root: TermIdx, _aliases: &Aliases) -> String {
+        let mut s = String::new();
+        Self::write(&mut s, art, root);
+        s
+    }
+}
+
+/// Graphviz, showing which terms are shared. See `CompArtifact::to_dot`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Dot;
+
+impl Renderer for Dot {
+    fn name(&self) -> &'static str {
+        "dot"
+    }
+
+    fn render(&self, art: &CompArtifact, root: TermIdx, aliases: &Aliases) -> String {
+        art.to_dot_from(&[root], aliases)
+    }
+}
+
+/// `\lambda x.\, x\ y`, ready to be pasted inside a math environment
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Latex;
+
+/// where a term is, to know if it needs parenthesis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pos {
+    Top,
+    AppLeft,
+    AppRight,
+}
+
+impl Latex {
+    fn write(
+        s: &mut String,
+        art: &CompArtifact,
+        idx: TermIdx,
+        pos: Pos,
+        inverse_cache: &HashMap<TermIdx, ir::Id>,
+        layers: &mut Vec<usize>,
+        aliases: &Aliases,
+    ) {
+        if let Some(alias) = inverse_cache.get(&idx).and_then(|i| aliases.get(i)) {
+            let _ = write!(s, "\\mathsf{{{}}}", escape_latex(alias));
+            return;
+        }
+        match art.get(idx) {
+            Term::Var(v) => s.push_str(&escape_latex(&CompArtifact::var_name(layers, v))),
+            Term::Const(c) => {
+                let _ = write!(s, "\\mathtt{{{c}}}");
+            }
+            Term::App(l, r) => {
+                let paren = pos == Pos::AppRight;
+                if paren {
+                    s.push('(');
+                }
+                Self::write(s, art, l, Pos::AppLeft, inverse_cache, layers, aliases);
+                s.push_str("\\ ");
+                Self::write(s, art, r, Pos::AppRight, inverse_cache, layers, aliases);
+                if paren {
+                    s.push(')');
+                }
+            }
+            Term::Abs { inner } => {
+                let paren = pos != Pos::Top;
+                if paren {
+                    s.push('(');
+                }
+                layers.push(idx.0);
+                let name = ir::Scope::id_to_str(&ir::Id(idx.0));
+                let _ = write!(s, "\\lambda {}.\\, ", escape_latex(&name));
+                Self::write(s, art, inner, Pos::Top, inverse_cache, layers, aliases);
+                layers.pop();
+                if paren {
+                    s.push(')');
+                }
+            }
+        }
+    }
+}
+
+impl Renderer for Latex {
+    fn name(&self) -> &'static str {
+        "latex"
+    }
+
+    fn render(&self, art: &CompArtifact, root: TermIdx, aliases: &Aliases) -> String {
+        let mut s = String::new();
+        let inverse_cache = art.inverse_cache();
+        Self::write(
+            &mut s,
+            art,
+            root,
+            Pos::Top,
+            &inverse_cache,
+            &mut Vec::new(),
+            aliases,
+        );
+        s
+    }
+
+    /// an aligned derivation, one β step per line
+    fn render_steps(&self, art: &CompArtifact, steps: &[TermIdx], aliases: &Aliases) -> String {
+        let mut s = String::from("\\begin{align*}\n");
+        for (i, step) in steps.iter().enumerate() {
+            let rel = if i == 0 { "" } else { "\\to_\\beta" };
+            let end = if i + 1 < steps.len() { " \\\\" } else { "" };
+            let _ = writeln!(s, "  {rel} &\\ {}{end}", self.render(art, *step, aliases));
+        }
+        s.push_str("\\end{align*}");
+        s
+    }
+}
+
+pub fn escape_latex(s: &str) -> String {
+    s.replace('_', "\\_")
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::backend::Aliases;
+    use crate::session::{Output, Session};
+
+    fn export(format: &str, expr: &str) -> String {
+        let mut s = Session::new();
+        s.eval_str("K x y = x").unwrap();
+        let Output::Normal(nf) = s.eval_str(expr).unwrap().output else {
+            panic!("expected a normal form");
+        };
+        s.renderer(format)
+            .unwrap()
+            .render(&s.art, nf, &Aliases::new())
+    }
+
+    #[test]
+    pub fn formats() {
+        assert_eq!(export("blc", "K"), "0000110");
+        assert_eq!(export("json", "K"), r#"{"abs":{"abs":{"var":1}}}"#);
+        assert_eq!(export("blc", "fn f x => f (f x)"), "0000011100111010");
+        assert_eq!(export("debruijn", "fn f x => f (f x)"), "λ λ 1 (1 0)");
+        let sexpr = export("sexpr", "fn f => f f");
+        let name = &sexpr["(lambda (".len()..sexpr.find(')').unwrap()];
+        assert_eq!(sexpr, format!("(lambda ({name}) ({name} {name}))"));
+        let dot = export("dot", "K");
+        assert!(dot.starts_with("digraph pool {\n") && dot.ends_with("}\n"));
+        assert_eq!(dot.matches("->").count(), 2);
+        assert_eq!(
+            export("latex", "fn f x => f (f x)").replace(char::is_alphabetic, "v"),
+            r"\vvvvvv v.\, \vvvvvv v.\, v\ (v\ v)"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt::Write;
+
+use crate::arts::{OuterIdx, Term, TermIdx};
+use crate::cancel::{self, CancelToken};
+use crate::cpu::{Cpu, Head, Op, Reductor};
+use crate::ir;
+
+/// the head reductions a term may take before it's taken as having no head normal form
+pub const HEAD_FUEL: usize = 1_000;
+
+/// a finite approximation of a Böhm tree: the head normal forms of a term and of its arguments,
+/// level by level
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bohm {
+    /// no head normal form was found within the fuel
+    Bottom,
+    /// deeper than the approximation goes
+    Cut,
+    /// `λx.. y M..`. `head` counts the binders of every node above it too
+    Node {
+        binders: usize,
+        head: OuterIdx,
+        args: Vec<Bohm>,
+    },
+}
+
+impl Bohm {
+    /// approximates the tree of `root` up to `depth` levels. Each head can take `fuel` head
+    /// reductions, and `token` is checked between them
+    pub fn approximate(
+        cpu: &mut Cpu,
+        root: TermIdx,
+        depth: usize,
+        fuel: usize,
+        token: &CancelToken,
+    ) -> cancel::Result<Self> {
+        if depth == 0 {
+            return Ok(Self::Cut);
+        }
+        let mut idx = root;
+        let mut spent = 0;
+        while let Op::Reduced(i) = Head::step(cpu, idx) {
+            token.check()?;
+            spent += 1;
+            if spent > fuel {
+                return Ok(Self::Bottom);
+            }
+            idx = i;
+        }
+        let mut binders = 0;
+        while let Term::Abs { inner } = cpu.art.get(idx) {
+            binders += 1;
+            idx = inner;
+        }
+        let mut args = Vec::new();
+        while let Term::App(l, r) = cpu.art.get(idx) {
+            args.push(r);
+            idx = l;
+        }
+        let Term::Var(head) = cpu.art.get(idx) else {
+            unreachable!("a head normal form has a variable at its head")
+        };
+        let args = args
+            .into_iter()
+            .rev()
+            .map(|a| Self::approximate(cpu, a, depth - 1, fuel, token))
+            .collect::<cancel::Result<_>>()?;
+        Ok(Self::Node {
+            binders,
+            head,
+            args,
+        })
+    }
+
+    /// a node per line, its arguments below it
+    pub fn to_tree(&self) -> String {
+        let mut s = String::new();
+        self.write(&mut s, "", &mut Vec::new());
+        s
+    }
+
+    fn write(&self, s: &mut String, indent: &str, names: &mut Vec<String>) {
+        let (binders, head, args) = match self {
+            Self::Bottom => return s.push_str("⊥\n"),
+            Self::Cut => return s.push_str("…\n"),
+            Self::Node {
+                binders,
+                head,
+                args,
+            } => (*binders, head, args),
+        };
+        let above = names.len();
+        names.extend((above..above + binders).map(|i| ir::Scope::id_to_str(&ir::Id(i))));
+        if binders > 0 {
+            let _ = write!(s, "λ{}. ", names[above..].join(" "));
+        }
+        let head = names
+            .len()
+            .checked_sub(head.0 + 1)
+            .map_or("?", |i| names[i].as_str());
+        let _ = writeln!(s, "{head}");
+        for (i, arg) in args.iter().enumerate() {
+            let (branch, next) = match i + 1 == args.len() {
+                true => ("└─ ", "   "),
+                false => ("├─ ", "│  "),
+            };
+            let _ = write!(s, "{indent}{branch}");
+            arg.write(s, &format!("{indent}{next}"), names);
+        }
+        names.truncate(above);
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::bohm::Bohm;
+    use crate::session::Session;
+
+    #[test]
+    pub fn approximations() {
+        let mut s = Session::new();
+        s.eval_str("Omega = (fn x => x x) (fn x => x x)").unwrap();
+        assert_eq!(s.bohm("Omega", 3).unwrap(), Some(Bohm::Bottom));
+
+        let t = s.bohm("fn f => f Omega (fn x y => y)", 3).unwrap().unwrap();
+        assert_eq!(t.to_tree(), "λa. a\n├─ ⊥\n└─ λb c. c\n");
+
+        // Y has no normal form, but its tree is `λf. f (f (f …))`
+        let y = "fn f => (fn x => f (x x)) (fn x => f (x x))";
+        let t = s.bohm(y, 3).unwrap().unwrap();
+        assert_eq!(t.to_tree(), "λa. a\n└─ a\n   └─ a\n      └─ …\n");
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::arts::{CompArtifact, OuterIdx, Term, TermIdx};
+
+#[derive(Error, Diagnostic, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    #[error("the variable {index} isn't bound, with only {binders} λ around it")]
+    #[diagnostic(
+        code(builder::unbound_index),
+        help("indices count the λ from the innermost one, starting at 0")
+    )]
+    UnboundIndex { index: usize, binders: usize },
+
+    #[error("no λ around binds {0:?}")]
+    #[diagnostic(code(builder::unbound_name))]
+    UnboundName(Box<str>),
+
+    #[error("there's no term {0} in the pool")]
+    #[diagnostic(code(builder::unknown_term))]
+    UnknownTerm(usize),
+
+    #[error("term {term} needs {needs} λ around it, but there are only {binders}")]
+    #[diagnostic(
+        code(builder::escaping_term),
+        help("a term built inside an `abs` can't be used outside of it")
+    )]
+    Escaping {
+        term: usize,
+        needs: usize,
+        binders: usize,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// builds terms right into a pool, without any source code. Every variable is checked to be
+/// bound by a λ around it, or to be one of the `free` ones
+///
+/// `λx.x x` is `b.abs(|b| { let x = b.var(0)?; b.app(x, x) })`, or `term!(fn x => x x)`
+#[derive(Debug)]
+pub struct PoolBuilder<'a> {
+    pub art: &'a mut CompArtifact,
+    /// the λ around what's being built
+    binders: usize,
+    /// the variables that may be free in the result, as if bound outside of it
+    free: usize,
+    /// the name of each λ around, if it has one
+    names: Vec<Option<Box<str>>>,
+    /// how many λ each term needs around it, so its variables are bound
+    needs: HashMap<TermIdx, usize>,
+}
+
+impl<'a> PoolBuilder<'a> {
+    pub fn new(art: &'a mut CompArtifact) -> Self {
+        Self {
+            art,
+            binders: 0,
+            free: 0,
+            names: Vec::new(),
+            needs: HashMap::new(),
+        }
+    }
+
+    /// allows `n` free variables, with the indices right after the bound ones
+    pub fn with_free(mut self, n: usize) -> Self {
+        self.free = n;
+        self
+    }
+
+    /// the variable bound by the `i`th λ around it, from the innermost
+    pub fn var(&mut self, i: usize) -> Result<TermIdx> {
+        if i >= self.binders + self.free {
+            return Err(Error::UnboundIndex {
+                index: i,
+                binders: self.binders,
+            });
+        }
+        let idx = self.art.push(Term::Var(OuterIdx(i)));
+        self.needs.insert(idx, i + 1);
+        Ok(idx)
+    }
+
+    /// the variable bound by the innermost λ called `name`
+    pub fn named(&mut self, name: &str) -> Result<TermIdx> {
+        let i = self
+            .names
+            .iter()
+            .rev()
+            .position(|n| n.as_deref() == Some(name));
+        self.var(i.ok_or_else(|| Error::UnboundName(name.into()))?)
+    }
+
+    /// a λ whose body is built by `body`, with one more binder around it
+    pub fn abs(&mut self, body: impl FnOnce(&mut Self) -> Result<TermIdx>) -> Result<TermIdx> {
+        self.bind(None, body)
+    }
+
+    /// like `abs`, with `named` finding its variable
+    pub fn lam(
+        &mut self,
+        name: &str,
+        body: impl FnOnce(&mut Self) -> Result<TermIdx>,
+    ) -> Result<TermIdx> {
+        self.bind(Some(name.into()), body)
+    }
+
+    fn bind(
+        &mut self,
+        name: Option<Box<str>>,
+        body: impl FnOnce(&mut Self) -> Result<TermIdx>,
+    ) -> Result<TermIdx> {
+        self.binders += 1;
+        self.names.push(name);
+        let inner = body(self).and_then(|inner| self.check(inner).map(|_| inner));
+        self.names.pop();
+        self.binders -= 1;
+        let inner = inner?;
+        let needs = self.needs[&inner].saturating_sub(1);
+        let idx = self.art.push(Term::Abs { inner });
+        self.needs.insert(idx, needs);
+        Ok(idx)
+    }
+
+    /// `n` λ around what `body` builds
+    pub fn abs_n(
+        &mut self,
+        n: usize,
+        body: impl FnOnce(&mut Self) -> Result<TermIdx>,
+    ) -> Result<TermIdx> {
+        match n {
+            0 => body(self),
+            n => self.abs(|b| b.abs_n(n - 1, body)),
+        }
+    }
+
+    pub fn app(&mut self, l: TermIdx, r: TermIdx) -> Result<TermIdx> {
+        let needs = self.check(l)?.max(self.check(r)?);
+        let idx = self.art.push(Term::App(l, r));
+        self.needs.insert(idx, needs);
+        Ok(idx)
+    }
+
+    /// `f` applied to every argument, from the left
+    pub fn apps(&mut self, f: TermIdx, args: &[TermIdx]) -> Result<TermIdx> {
+        args.iter().try_fold(f, |f, a| self.app(f, *a))
+    }
+
+    /// makes `root` the root of the pool, so a `Cpu` can reduce it
+    pub fn finish(self, root: TermIdx) -> Result<TermIdx> {
+        self.check(root)?;
+        self.art.root = Some(root);
+        Ok(root)
+    }
+
+    /// how many λ `idx` needs, if it's fine where it's being used
+    fn check(&self, idx: TermIdx) -> Result<usize> {
+        let needs = self.needs_of(idx)?;
+        if needs > self.binders + self.free {
+            return Err(Error::Escaping {
+                term: idx.0,
+                needs,
+                binders: self.binders,
+            });
+        }
+        Ok(needs)
+    }
+
+    /// what a term needs, measuring the ones that were already in the pool
+    fn needs_of(&self, idx: TermIdx) -> Result<usize> {
+        if let Some(needs) = self.needs.get(&idx) {
+            return Ok(*needs);
+        }
+        if idx.0 >= self.art.arena().len() {
+            return Err(Error::UnknownTerm(idx.0));
+        }
+        // (term, λ above it inside the term being measured)
+        let mut stack = vec![(idx, 0)];
+        let mut needs = 0;
+        while let Some((t, depth)) = stack.pop() {
+            match self.art.get(t) {
+                Term::Var(OuterIdx(i)) => needs = needs.max((i + 1).saturating_sub(depth)),
+                Term::Const(_) => (),
+                Term::Abs { inner } => stack.push((inner, depth + 1)),
+                Term::App(l, r) => stack.extend([(l, depth), (r, depth)]),
+            }
+        }
+        Ok(needs)
+    }
+}
+
+/// builds a term in the `qk` syntax, without parsing it: `term!(fn x => x (fn y => y x))` is a
+/// `Result<CompArtifact>` with it as the root. `term!(in b; ...)` builds it with the `b`
+/// builder instead, into a `Result<TermIdx>`
+#[macro_export]
+macro_rules! term {
+    (in $b:ident; $($t:tt)+) => {
+        $crate::__term!(@app $b; (); $($t)+)
+    };
+    ($($t:tt)+) => {{
+        let mut art = $crate::arts::CompArtifact::default();
+        let mut b = $crate::builder::PoolBuilder::new(&mut art);
+        $crate::term!(in b; $($t)+)
+            .and_then(|t| b.finish(t))
+            .map(|_| art)
+    }};
+}
+
+/// a tt muncher for `term!`, applying what it finds to the `$acc` built so far
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __term {
+    (@app $b:ident; ($acc:expr); ) => {
+        $acc
+    };
+    (@app $b:ident; ($($acc:expr)?); fn $($x:ident)+ => $($body:tt)+) => {
+        $crate::__term!(@apply $b; ($($acc)?); $crate::__term!(@lam $b; $($x)+; $($body)+))
+    };
+    (@app $b:ident; ($($acc:expr)?); ($($inner:tt)+) $($rest:tt)*) => {
+        $crate::__term!(@app $b;
+            ($crate::__term!(@apply $b; ($($acc)?); $crate::__term!(@app $b; (); $($inner)+)));
+            $($rest)*)
+    };
+    (@app $b:ident; ($($acc:expr)?); $x:ident $($rest:tt)*) => {
+        $crate::__term!(@app $b;
+            ($crate::__term!(@apply $b; ($($acc)?); $b.named(stringify!($x))));
+            $($rest)*)
+    };
+    (@apply $b:ident; (); $r:expr) => {
+        $r
+    };
+    (@apply $b:ident; ($l:expr); $r:expr) => {
+        match $l {
+            Ok(l) => match $r {
+                Ok(r) => $b.app(l, r),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    };
+    (@lam $b:ident; $x:ident; $($body:tt)+) => {
+        $b.lam(stringify!($x), |$b| $crate::__term!(@app $b; (); $($body)+))
+    };
+    (@lam $b:ident; $x:ident $($xs:ident)+; $($body:tt)+) => {
+        $b.lam(stringify!($x), |$b| $crate::__term!(@lam $b; $($xs)+; $($body)+))
+    };
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::arts::{CompArtifact, OuterIdx, Term, TermIdx};
+    use crate::builder::{Error, PoolBuilder};
+    use crate::cpu::{Cpu, Normal, Reductor};
+    use crate::session::Session;
+
+    #[test]
+    pub fn builds_and_checks() {
+        let mut art = CompArtifact::default();
+        let mut b = PoolBuilder::new(&mut art);
+        // S K K ~> λx.x
+        let k = b.abs_n(2, |b| b.var(1)).unwrap();
+        let s = b
+            .abs_n(3, |b| {
+                let (x, y, z) = (b.var(2)?, b.var(1)?, b.var(0)?);
+                let xz = b.app(x, z)?;
+                let yz = b.app(y, z)?;
+                b.app(xz, yz)
+            })
+            .unwrap();
+        let skk = b.apps(s, &[k, k]).unwrap();
+        let root = b.finish(skk).unwrap();
+        let mut cpu = Cpu::new(art);
+        let nf = Normal::reduce(&mut cpu, root);
+        assert_eq!(
+            cpu.art.pretty(nf, &Default::default()).matches('λ').count(),
+            1
+        );
+
+        let mut art = CompArtifact::default();
+        let mut b = PoolBuilder::new(&mut art);
+        assert_eq!(
+            b.abs(|b| b.var(1)),
+            Err(Error::UnboundIndex {
+                index: 1,
+                binders: 1
+            })
+        );
+        let mut inner = None;
+        b.abs(|b| {
+            inner = Some(b.var(0)?);
+            Ok(inner.unwrap())
+        })
+        .unwrap();
+        assert!(matches!(
+            b.app(inner.unwrap(), inner.unwrap()),
+            Err(Error::Escaping { .. })
+        ));
+        assert_eq!(
+            b.app(TermIdx(100), TermIdx(100)),
+            Err(Error::UnknownTerm(100))
+        );
+        let mut b = PoolBuilder::new(&mut art).with_free(1);
+        assert!(b.var(0).is_ok());
+    }
+
+    fn same(a: &CompArtifact, x: TermIdx, b: &CompArtifact, y: TermIdx) -> bool {
+        match (a.get(x), b.get(y)) {
+            (Term::Var(i), Term::Var(j)) => i == j,
+            (Term::Abs { inner: x }, Term::Abs { inner: y }) => same(a, x, b, y),
+            (Term::App(xl, xr), Term::App(yl, yr)) => same(a, xl, b, yl) && same(a, xr, b, yr),
+            _ => false,
+        }
+    }
+
+    #[test]
+    pub fn term_macro() {
+        let mut s = Session::new();
+        let src = "fn x => x (fn y z => y (x z)) x";
+        let parsed = s.compile_expr(src).unwrap().unwrap();
+        let art = term!(fn x => x (fn y z => y (x z)) x).unwrap();
+        let built = art.root.unwrap();
+        assert!(same(&art, built, &s.art, parsed));
+
+        let mut art = CompArtifact::default();
+        let mut b = PoolBuilder::new(&mut art);
+        let k = term!(in b; fn x y => x).unwrap();
+        let i = term!(in b; fn x => x).unwrap();
+        assert!(b.apps(k, &[i, i]).is_ok());
+        assert_eq!(term!(in b; fn x => y), Err(Error::UnboundName("y".into())));
+        // unnamed binders still count for the indices
+        let k2 = b.lam("x", |b| b.abs(|b| b.named("x"))).unwrap();
+        let body = |t| match art.get(t) {
+            Term::Abs { inner } => inner,
+            t => panic!("{t:?} isn't an abstraction"),
+        };
+        assert_eq!(art.get(body(body(k2))), Term::Var(OuterIdx(1)));
+    }
+}
@@ -0,0 +1,184 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use std::time::{Duration, Instant};
+
+use miette::{Diagnostic, SourceSpan};
+use thiserror::Error;
+
+#[derive(Error, Diagnostic, Debug, Clone, PartialEq, Eq)]
+pub enum Cancelled {
+    #[error("interrupted")]
+    #[diagnostic(code(cancel::interrupted))]
+    Interrupted,
+
+    #[error("took longer than {0:?}")]
+    #[diagnostic(
+        code(cancel::timeout),
+        help("maybe it has no normal form? The limit can be changed with `set timeout`")
+    )]
+    Timeout(Duration),
+
+    #[error("took more than {0} steps")]
+    #[diagnostic(
+        code(cancel::budget),
+        help("maybe it has no normal form? The limit can be changed with `set budget`")
+    )]
+    Budget(usize),
+
+    #[error("the term grew to {size} nodes, over the limit of {max}")]
+    #[diagnostic(
+        code(cancel::term_size),
+        help(
+            "some terms keep growing instead of reaching a normal form. The limit can be changed with `set max-term-size`"
+        )
+    )]
+    TermSize {
+        size: usize,
+        max: usize,
+        #[label("the last redex reduced that was written here")]
+        at: Option<SourceSpan>,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Cancelled>;
+
+/// asks a running stage to stop, be it from another thread (as a Ctrl-C handler), a timeout or a
+/// limit of steps. Stages check it cooperatively, between steps. Clones share the same flag, but
+/// count their own steps
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    flag: Arc<AtomicBool>,
+    pub timeout: Option<Duration>,
+    pub budget: Option<usize>,
+    /// how many nodes a term may have while it's reduced
+    pub max_size: Option<usize>,
+    deadline: Option<Instant>,
+    steps: usize,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_budget(mut self, steps: usize) -> Self {
+        self.budget = Some(steps);
+        self
+    }
+
+    pub fn with_max_size(mut self, nodes: usize) -> Self {
+        self.max_size = Some(nodes);
+        self
+    }
+
+    /// a copy for a new run: its deadline starts counting now and no step is spent yet
+    pub fn start(&self) -> Self {
+        Self {
+            flag: self.flag.clone(),
+            timeout: self.timeout,
+            budget: self.budget,
+            max_size: self.max_size,
+            deadline: self.timeout.map(|t| Instant::now() + t),
+            steps: 0,
+        }
+    }
+
+    /// a token sharing the same flag, without any limit
+    pub fn unlimited(&self) -> Self {
+        Self {
+            flag: self.flag.clone(),
+            ..Self::default()
+        }
+    }
+
+    /// stops every run sharing this flag, on their next check
+    pub fn cancel(&self) {
+        self.flag.store(true, Ordering::Relaxed);
+    }
+
+    /// lowers the flag, so it can be used again
+    pub fn reset(&self) {
+        self.flag.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.flag.load(Ordering::Relaxed)
+    }
+
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Cancelled::Interrupted);
+        }
+        match (self.deadline, self.timeout) {
+            (Some(deadline), Some(timeout)) if Instant::now() >= deadline => {
+                Err(Cancelled::Timeout(timeout))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// spends a step of the budget, then checks
+    pub fn tick(&mut self) -> Result<()> {
+        self.steps += 1;
+        match self.budget {
+            Some(budget) if self.steps > budget => Err(Cancelled::Budget(budget)),
+            _ => self.check(),
+        }
+    }
+
+    /// fails if a term of `size` nodes is over `max_size`. `at` is where the last redex reduced
+    /// was written, if it's known
+    pub fn check_size(&self, size: usize, at: Option<SourceSpan>) -> Result<()> {
+        match self.max_size {
+            Some(max) if size > max => Err(Cancelled::TermSize { size, max, at }),
+            _ => Ok(()),
+        }
+    }
+
+    /// how many steps were spent since `start`
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::time::Duration;
+
+    use crate::cancel::{CancelToken, Cancelled};
+
+    #[test]
+    pub fn limits() {
+        let token = CancelToken::new().with_budget(2);
+        let mut run = token.start();
+        assert_eq!(run.tick(), Ok(()));
+        assert_eq!(run.tick(), Ok(()));
+        assert_eq!(run.tick(), Err(Cancelled::Budget(2)));
+        assert_eq!(token.start().tick(), Ok(()));
+
+        token.cancel();
+        assert_eq!(run.check(), Err(Cancelled::Interrupted));
+        token.reset();
+        assert_eq!(run.check(), Ok(()));
+
+        let run = CancelToken::new().with_timeout(Duration::ZERO).start();
+        assert_eq!(run.check(), Err(Cancelled::Timeout(Duration::ZERO)));
+
+        let run = CancelToken::new().with_max_size(10).start();
+        assert_eq!(run.check_size(10, None), Ok(()));
+        let at = Some((2, 3).into());
+        let too_big = Cancelled::TermSize {
+            size: 11,
+            max: 10,
+            at,
+        };
+        assert_eq!(run.check_size(11, at), Err(too_big));
+    }
+}
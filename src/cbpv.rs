@@ -0,0 +1,623 @@
+// Comp =
+//      "λ" <Ident> "." Comp
+//      "let" <Ident> "=" Value "in" Comp
+//      App ("to" <Ident> "." Comp)?
+// App =
+//      Head Value*
+// Head =
+//      "return" Value
+//      "force" Value
+//      "(" Comp ")"
+// Value =
+//      <Ident>
+//      "thunk" Head
+
+use std::fmt;
+use std::rc::Rc;
+
+use miette::{Diagnostic, SourceSpan};
+use thiserror::Error;
+
+use crate::ast::{Ast, Node};
+use crate::cancel::{CancelToken, Cancelled};
+use crate::lexer::from_code;
+use crate::padam::{
+    Token,
+    lexer::{
+        ASSIGN_TY, CLOSE_PAREN_TY, FN_IMPL_TY, FN_KW_TY, IDENT_TY, IN_KW_TY, LET_KW_TY, Lexer,
+        OPEN_PAREN_TY, Tokenizer, comment, ident, literal, single_char,
+    },
+    parser,
+};
+
+pub const RETURN_KW_TY: &str = "ReturnKw";
+pub const FORCE_KW_TY: &str = "ForceKw";
+pub const THUNK_KW_TY: &str = "ThunkKw";
+pub const TO_KW_TY: &str = "ToKw";
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Error, Diagnostic, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Lexer(#[from] crate::lexer::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Parser(#[from] parser::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Cancelled(#[from] Cancelled),
+
+    #[error("unknown variable `{name}`")]
+    #[diagnostic(code(cbpv::unbound), help("a computation only sees its own binders"))]
+    Unbound {
+        name: Box<str>,
+        #[label("not bound here")]
+        at: SourceSpan,
+    },
+
+    #[error("{what} has no translation to call-by-push-value")]
+    #[diagnostic(
+        code(cbpv::unsupported),
+        help("only variables, abstractions, applications and `let` are translated")
+    )]
+    Unsupported {
+        what: &'static str,
+        #[label("this one")]
+        at: SourceSpan,
+    },
+
+    #[error("the computation got stuck at `{at}`")]
+    #[diagnostic(
+        code(cbpv::stuck),
+        help("only an abstraction pops an argument, and only a `return` passes a value to `to`")
+    )]
+    Stuck { at: Box<Comp> },
+}
+
+/// what a variable stands for: it's never run by itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Var(Box<str>),
+    /// a suspended computation, run by `force`
+    Thunk(Box<Comp>),
+}
+
+/// what runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Comp {
+    /// `return v`, ending in a value
+    Return(Value),
+    /// `m to x. n`, running `m` and then `n` with `x` as what it returned
+    To(Box<Comp>, Box<str>, Box<Comp>),
+    Force(Value),
+    /// `λx. m`, popping a value off the stack
+    Lam(Box<str>, Box<Comp>),
+    /// `m v`, pushing `v` before running `m`
+    App(Box<Comp>, Value),
+    Let(Box<str>, Value, Box<Comp>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Var(v) => f.write_str(v),
+            Self::Thunk(m) => write!(f, "thunk ({m})"),
+        }
+    }
+}
+
+/// abstractions, `let` and `to` stretch as far right as they can
+impl fmt::Display for Comp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let head = |m: &Comp, f: &mut fmt::Formatter<'_>| match m {
+            Self::Lam(..) | Self::Let(..) | Self::To(..) => write!(f, "({m})"),
+            _ => write!(f, "{m}"),
+        };
+        match self {
+            Self::Return(v) => write!(f, "return {v}"),
+            Self::Force(v) => write!(f, "force {v}"),
+            Self::To(m, x, n) => {
+                head(m, f)?;
+                write!(f, " to {x}. {n}")
+            }
+            Self::Lam(x, m) => write!(f, "λ{x}. {m}"),
+            Self::App(m, v) => {
+                head(m, f)?;
+                write!(f, " {v}")
+            }
+            Self::Let(x, v, m) => write!(f, "let {x} = {v} in {m}"),
+        }
+    }
+}
+
+pub fn lexer() -> Lexer {
+    let tokenizers = [
+        Tokenizer::new(FN_KW_TY, single_char('λ')),
+        Tokenizer::new(FN_KW_TY, single_char('\\')),
+        Tokenizer::new(RETURN_KW_TY, literal("return")),
+        Tokenizer::new(FORCE_KW_TY, literal("force")),
+        Tokenizer::new(THUNK_KW_TY, literal("thunk")),
+        Tokenizer::new(TO_KW_TY, literal("to")),
+        Tokenizer::new(LET_KW_TY, literal("let")),
+        Tokenizer::new(IN_KW_TY, literal("in")),
+        Tokenizer::new(FN_IMPL_TY, single_char('.')),
+        Tokenizer::new(ASSIGN_TY, single_char('=')),
+        Tokenizer::new(OPEN_PAREN_TY, single_char('(')),
+        Tokenizer::new(CLOSE_PAREN_TY, single_char(')')),
+        Tokenizer::ignore(single_char(' ')),
+        Tokenizer::ignore(single_char('\t')),
+        Tokenizer::ignore(single_char('\n')),
+        Tokenizer::new(IDENT_TY, ident()),
+        Tokenizer::ignore(comment()),
+    ];
+    Lexer::new(tokenizers.into_iter())
+}
+
+/// parses a closed computation
+pub fn parse(src: &str) -> Result<Comp> {
+    let lexer = lexer();
+    let tokens = lexer.lex(src)?;
+    let mut p = Parser {
+        lexer: &lexer,
+        src,
+        tokens: &tokens,
+        scope: Vec::new(),
+    };
+    let m = p.comp()?;
+    match p.tokens.first() {
+        Some(tk) => Err(parser::Error::UnexpectedToken { at: tk.at }.into()),
+        None => Ok(m),
+    }
+}
+
+struct Parser<'a> {
+    lexer: &'a Lexer,
+    src: &'a str,
+    tokens: &'a [Token],
+    /// the variables bound around the current token
+    scope: Vec<&'a str>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.first().map(|t| self.lexer.get_type(t.item))
+    }
+
+    fn next(&mut self) -> parser::Result<Token> {
+        let (tk, rest) = self
+            .tokens
+            .split_first()
+            .ok_or(parser::Error::UnexpectedEof)?;
+        self.tokens = rest;
+        Ok(tk.clone())
+    }
+
+    fn expect(&mut self, ty: &str) -> parser::Result<Token> {
+        let tk = self.next()?;
+        match self.lexer.get_type(tk.item) == ty {
+            true => Ok(tk),
+            false => Err(parser::Error::UnexpectedToken { at: tk.at }),
+        }
+    }
+
+    fn name(&mut self) -> parser::Result<&'a str> {
+        let at = self.expect(IDENT_TY)?.at;
+        Ok(from_code(at, self.src))
+    }
+
+    /// `body` with `x` in scope
+    fn bind<T>(&mut self, x: &'a str, body: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.scope.push(x);
+        let r = body(self);
+        self.scope.pop();
+        r
+    }
+
+    fn comp(&mut self) -> Result<Comp> {
+        match self.peek() {
+            Some(FN_KW_TY) => {
+                self.next()?;
+                let x = self.name()?;
+                self.expect(FN_IMPL_TY)?;
+                let m = self.bind(x, Self::comp)?;
+                Ok(Comp::Lam(x.into(), m.into()))
+            }
+            Some(LET_KW_TY) => {
+                self.next()?;
+                let x = self.name()?;
+                self.expect(ASSIGN_TY)?;
+                let v = self.value()?;
+                self.expect(IN_KW_TY)?;
+                let m = self.bind(x, Self::comp)?;
+                Ok(Comp::Let(x.into(), v, m.into()))
+            }
+            _ => {
+                let mut m = self.head()?;
+                while matches!(self.peek(), Some(IDENT_TY | THUNK_KW_TY)) {
+                    m = Comp::App(m.into(), self.value()?);
+                }
+                if self.peek() != Some(TO_KW_TY) {
+                    return Ok(m);
+                }
+                self.next()?;
+                let x = self.name()?;
+                self.expect(FN_IMPL_TY)?;
+                let n = self.bind(x, Self::comp)?;
+                Ok(Comp::To(m.into(), x.into(), n.into()))
+            }
+        }
+    }
+
+    fn head(&mut self) -> Result<Comp> {
+        let tk = self.next()?;
+        match self.lexer.get_type(tk.item) {
+            RETURN_KW_TY => Ok(Comp::Return(self.value()?)),
+            FORCE_KW_TY => Ok(Comp::Force(self.value()?)),
+            OPEN_PAREN_TY => {
+                let m = self.comp()?;
+                self.expect(CLOSE_PAREN_TY)?;
+                Ok(m)
+            }
+            _ => Err(parser::Error::UnexpectedToken { at: tk.at }.into()),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        let tk = self.next()?;
+        match self.lexer.get_type(tk.item) {
+            IDENT_TY => {
+                let name = tk.from_code(self.src);
+                if !self.scope.contains(&name) {
+                    return Err(Error::Unbound {
+                        name: name.into(),
+                        at: tk.at,
+                    });
+                }
+                Ok(Value::Var(name.into()))
+            }
+            THUNK_KW_TY => Ok(Value::Thunk(self.head()?.into())),
+            _ => Err(parser::Error::UnexpectedToken { at: tk.at }.into()),
+        }
+    }
+}
+
+/// how the λ-calculus is embedded into call-by-push-value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    /// arguments are computed before the call, and variables hold what they returned
+    ByValue,
+    /// arguments are passed as thunks, and variables are forced each time they're used
+    ByName,
+}
+
+impl Order {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::ByValue => "cbv",
+            Self::ByName => "cbn",
+        }
+    }
+}
+
+impl TryFrom<&str> for Order {
+    type Error = ();
+
+    fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
+        [Self::ByValue, Self::ByName]
+            .into_iter()
+            .find(|o| o.name() == value)
+            .ok_or(())
+    }
+}
+
+/// translates the closed term `node` with `order`
+///
+/// ```text
+///              by value                             by name
+/// x            return x                             force x
+/// λx. m        return thunk (λx. [m])               λx. [m]
+/// m n          [m] to f. [n] to v. force f v        [m] thunk ([n])
+/// let x = v in m   [v] to x. [m]                    let x = thunk ([v]) in [m]
+/// ```
+pub fn translate(node: &Node, src: &str, order: Order) -> Result<Comp> {
+    Translator {
+        src,
+        order,
+        scope: Vec::new(),
+        fresh: 0,
+    }
+    .comp(node)
+}
+
+struct Translator<'a> {
+    src: &'a str,
+    order: Order,
+    scope: Vec<&'a str>,
+    fresh: usize,
+}
+
+impl<'a> Translator<'a> {
+    /// a name no source can bind, as it has a subscript
+    fn fresh(&mut self, prefix: char) -> Box<str> {
+        const SUBSCRIPTS: [char; 10] = ['₀', '₁', '₂', '₃', '₄', '₅', '₆', '₇', '₈', '₉'];
+        let digits = self.fresh.to_string();
+        self.fresh += 1;
+        let digits = digits.bytes().map(|d| SUBSCRIPTS[(d - b'0') as usize]);
+        std::iter::once(prefix).chain(digits).collect()
+    }
+
+    fn bind(&mut self, x: &'a str, node: &'a Node) -> Result<Comp> {
+        self.scope.push(x);
+        let m = self.comp(node);
+        self.scope.pop();
+        m
+    }
+
+    fn comp(&mut self, node: &'a Node) -> Result<Comp> {
+        let (src, order) = (self.src, self.order);
+        Ok(match (&node.item, order) {
+            (Ast::Var, _) => {
+                let name = node.from_code(src);
+                if !self.scope.contains(&name) {
+                    return Err(Error::Unbound {
+                        name: name.into(),
+                        at: node.at,
+                    });
+                }
+                let x = Value::Var(name.into());
+                match order {
+                    Order::ByValue => Comp::Return(x),
+                    Order::ByName => Comp::Force(x),
+                }
+            }
+            (Ast::Abs(x, m), _) => {
+                let x = from_code(*x, src);
+                let lam = Comp::Lam(x.into(), self.bind(x, m)?.into());
+                match order {
+                    Order::ByValue => Comp::Return(Value::Thunk(lam.into())),
+                    Order::ByName => lam,
+                }
+            }
+            (Ast::App(m, n), Order::ByValue) => {
+                let (f, v) = (self.fresh('f'), self.fresh('v'));
+                let call = Comp::App(
+                    Comp::Force(Value::Var(f.clone())).into(),
+                    Value::Var(v.clone()),
+                );
+                let n = Comp::To(self.comp(n)?.into(), v, call.into());
+                Comp::To(self.comp(m)?.into(), f, n.into())
+            }
+            (Ast::App(m, n), Order::ByName) => {
+                let n = Value::Thunk(self.comp(n)?.into());
+                Comp::App(self.comp(m)?.into(), n)
+            }
+            (
+                Ast::Let {
+                    rec: false,
+                    ident,
+                    value,
+                    body,
+                },
+                _,
+            ) => {
+                let x = from_code(*ident, src);
+                let v = self.comp(value)?;
+                let m = self.bind(x, body)?;
+                match order {
+                    Order::ByValue => Comp::To(v.into(), x.into(), m.into()),
+                    Order::ByName => Comp::Let(x.into(), Value::Thunk(v.into()), m.into()),
+                }
+            }
+            (other, _) => {
+                let what = match other {
+                    Ast::Let { .. } => "`letrec`",
+                    Ast::Unpack { .. } => "a pattern",
+                    Ast::Annotated { .. } => "an annotation",
+                    Ast::Tuple(..) => "a tuple",
+                    Ast::List(..) => "a list",
+                    Ast::Infix { .. } => "an operator",
+                    _ => "a program",
+                };
+                return Err(Error::Unsupported { what, at: node.at });
+            }
+        })
+    }
+}
+
+/// the values in scope, the innermost first
+#[derive(Clone, Default)]
+struct Env<'c>(Option<Rc<(&'c str, Closure<'c>, Env<'c>)>>);
+
+/// a thunk, along with the values it was made with
+#[derive(Clone)]
+struct Closure<'c>(&'c Comp, Env<'c>);
+
+impl<'c> Env<'c> {
+    fn push(&self, x: &'c str, v: Closure<'c>) -> Self {
+        Self(Some(Rc::new((x, v, self.clone()))))
+    }
+
+    fn get(&self, x: &str) -> Option<&Closure<'c>> {
+        let mut env = self;
+        while let Some(node) = &env.0 {
+            if node.0 == x {
+                return Some(&node.1);
+            }
+            env = &node.2;
+        }
+        None
+    }
+
+    /// what `v` stands for. Every variable is bound, as it was checked when parsed
+    fn eval(&self, v: &'c Value) -> Closure<'c> {
+        match v {
+            Value::Var(x) => self.get(x).expect("the computation is closed").clone(),
+            Value::Thunk(m) => Closure(m, self.clone()),
+        }
+    }
+}
+
+enum Frame<'c> {
+    /// a value pushed for an abstraction
+    Arg(Closure<'c>),
+    /// what runs after a `return`, with what it returned as `x`
+    To(&'c str, &'c Comp, Env<'c>),
+}
+
+/// how a computation ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    /// the terminal computation, a `return` or an abstraction with nothing to pop, with the
+    /// values it was given written in
+    pub result: Comp,
+    /// every transition of the machine
+    pub steps: usize,
+    /// the abstractions that popped a value
+    pub beta: usize,
+}
+
+/// runs `m` on a stack machine, each transition spending a step of `token`
+pub fn run(m: &Comp, token: &mut CancelToken) -> Result<Run> {
+    let (mut m, mut env) = (m, Env::default());
+    let mut stack: Vec<Frame> = Vec::new();
+    let (mut steps, mut beta) = (0, 0);
+    loop {
+        let stuck = |m: &Comp| Error::Stuck {
+            at: m.clone().into(),
+        };
+        (m, env) = match (m, stack.pop()) {
+            (Comp::Return(v), Some(Frame::To(x, n, outer))) => (n, outer.push(x, env.eval(v))),
+            (Comp::Lam(x, body), Some(Frame::Arg(v))) => {
+                beta += 1;
+                (&**body, env.push(x, v))
+            }
+            (Comp::Return(_) | Comp::Lam(..), None) => {
+                let result = quote(m, &env, &mut Vec::new());
+                return Ok(Run {
+                    result,
+                    steps,
+                    beta,
+                });
+            }
+            (Comp::Return(_) | Comp::Lam(..), Some(_)) => return Err(stuck(m)),
+            (Comp::To(first, x, n), frame) => {
+                stack.extend(frame);
+                stack.push(Frame::To(x, n, env.clone()));
+                (&**first, env)
+            }
+            (Comp::App(f, v), frame) => {
+                stack.extend(frame);
+                stack.push(Frame::Arg(env.eval(v)));
+                (&**f, env)
+            }
+            (Comp::Let(x, v, body), frame) => {
+                stack.extend(frame);
+                let v = env.eval(v);
+                (&**body, env.push(x, v))
+            }
+            (Comp::Force(v), frame) => {
+                stack.extend(frame);
+                let Closure(body, captured) = env.eval(v);
+                (body, captured)
+            }
+        };
+        steps += 1;
+        token.tick()?;
+    }
+}
+
+/// `m` with the values in `env` written as thunks, except for the variables in `bound`
+fn quote<'c>(m: &'c Comp, env: &Env<'c>, bound: &mut Vec<&'c str>) -> Comp {
+    let value = |v: &'c Value, bound: &mut Vec<&'c str>| match v {
+        Value::Var(x) if bound.contains(&&**x) => v.clone(),
+        Value::Var(x) => match env.get(x) {
+            Some(Closure(m, env)) => Value::Thunk(quote(m, env, &mut Vec::new()).into()),
+            None => v.clone(),
+        },
+        Value::Thunk(m) => Value::Thunk(quote(m, env, bound).into()),
+    };
+    let under = |x: &'c str, m: &'c Comp, bound: &mut Vec<&'c str>| {
+        bound.push(x);
+        let m = quote(m, env, bound);
+        bound.pop();
+        Box::new(m)
+    };
+    match m {
+        Comp::Return(v) => Comp::Return(value(v, bound)),
+        Comp::Force(v) => Comp::Force(value(v, bound)),
+        Comp::To(first, x, n) => {
+            let first = quote(first, env, bound).into();
+            Comp::To(first, x.clone(), under(x, n, bound))
+        }
+        Comp::Lam(x, body) => Comp::Lam(x.clone(), under(x, body, bound)),
+        Comp::App(f, v) => Comp::App(quote(f, env, bound).into(), value(v, bound)),
+        Comp::Let(x, v, body) => {
+            let v = value(v, bound);
+            Comp::Let(x.clone(), v, under(x, body, bound))
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::cancel::{CancelToken, Cancelled};
+    use crate::cbpv::{self, Error, Order};
+    use crate::session::Session;
+
+    fn run(src: &str) -> cbpv::Result<cbpv::Run> {
+        cbpv::run(
+            &cbpv::parse(src)?,
+            &mut CancelToken::new().with_budget(1_000),
+        )
+    }
+
+    #[test]
+    pub fn runs_computations() {
+        let r = run("let id = thunk (λx. return x) in force id thunk (return id) to y. force y")
+            .unwrap();
+        assert_eq!(r.result.to_string(), "return thunk (λx. return x)");
+        assert_eq!((r.steps, r.beta), (7, 1));
+        assert_eq!(
+            run("(λx. λy. force x) thunk (return x)")
+                .unwrap_err()
+                .to_string(),
+            "unknown variable `x`"
+        );
+        assert!(matches!(
+            run("return thunk (λy. return y) to x. x"),
+            Err(Error::Parser(..))
+        ));
+        assert!(matches!(
+            run("return thunk (λx. return x) thunk (λy. return y)"),
+            Err(Error::Stuck { .. })
+        ));
+    }
+
+    #[test]
+    pub fn translates_both_orders() {
+        let mut s = Session::new();
+        s.cancel.budget = Some(1_000);
+        let src = "(fn x y => y) ((fn x => x x) (fn x => x x)) (fn z => z)";
+        let (cbn, run) = s.cbpv_translate(Order::ByName, src).unwrap().unwrap();
+        assert_eq!(
+            cbn.to_string(),
+            "(λx. λy. force y) thunk ((λx. force x thunk (force x)) thunk (λx. force x thunk (force x))) thunk (λz. force z)"
+        );
+        assert_eq!(run.result.to_string(), "λz. force z");
+        // by value, the argument runs first, and never returns
+        assert!(matches!(
+            s.cbpv_translate(Order::ByValue, src),
+            Err(crate::session::Error::Cancelled(Cancelled::Budget(1_000)))
+        ));
+
+        let (_, run) = s
+            .cbpv_translate(Order::ByValue, "(fn x => x) (fn y => y)")
+            .unwrap()
+            .unwrap();
+        assert_eq!(run.result.to_string(), "return thunk (λy. return y)");
+    }
+}
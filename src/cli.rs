@@ -0,0 +1,260 @@
+use crate::repl::config::{self, Config};
+use crate::repl::{self, Error, Result};
+
+/// dispatches the command line arguments (without the program name)
+pub fn run(args: &[String]) -> Result<()> {
+    match args.first().map(String::as_str) {
+        None | Some("--output" | "--profile" | "--color" | "--config" | "--script") => {
+            let mut flags = Vec::new();
+            let mut args = args.iter();
+            while let Some(arg) = args.next() {
+                let value = args
+                    .next()
+                    .ok_or_else(|| Error::MissingArg(arg.trim_start_matches('-').to_string()))?;
+                flags.push((arg.as_str(), value.as_str()));
+            }
+            let mut r = repl::Repl::new()?;
+            // the config goes first, so the flags override it
+            let config = match flags.iter().find(|(flag, _)| *flag == "--config") {
+                Some((_, path)) => Some(path.into()),
+                None => config::default_path(),
+            };
+            if let Some(path) = config {
+                Config::load(&mut r, &path);
+            }
+            apply_flags(&mut r, &flags)?;
+            // run after the other flags, so it starts from their settings
+            if let Some((_, path)) = flags.iter().find(|(flag, _)| *flag == "--script") {
+                let content = std::fs::read_to_string(path).map_err(|e| Error::Io { e })?;
+                if r.script(&content) > 0 {
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
+            r.run()
+        }
+        Some("analyze") => analyze(&args[1..]),
+        Some("replay") => replay(&args[1..]),
+        Some("tune") => tune(&args[1..]),
+        Some("test") => test(&args[1..]),
+        Some("fmt") => fmt(&args[1..]),
+        Some("--explain") => explain(&args[1..]),
+        Some("--lsp") => qk::lsp::serve(&mut std::io::stdin().lock(), &mut std::io::stdout())
+            .map_err(|e| Error::Io { e }),
+        Some(cmd) => Err(Error::UnknownCommand(cmd.to_string())),
+    }
+}
+
+/// the REPL flags, over whatever the config set
+fn apply_flags(r: &mut repl::Repl, flags: &[(&str, &str)]) -> Result<()> {
+    for &(flag, value) in flags {
+        match flag {
+            "--output" => {
+                r.output = value
+                    .try_into()
+                    .map_err(|_| Error::InvalidValue("output".into(), value.into()))?
+            }
+            "--color" => {
+                r.theme.mode = value
+                    .try_into()
+                    .map_err(|_| Error::InvalidValue("color".into(), value.into()))?
+            }
+            "--profile" => {
+                // logged, so a replay starts from the same settings
+                let set = format!("set profile {value}");
+                r.cmd(&set)?;
+                r.session.log.record(&format!(":{set}"));
+            }
+            "--config" | "--script" => (),
+            _ => return Err(Error::UnknownCommand(flag.to_string())),
+        }
+    }
+    Ok(())
+}
+
+/// `qk analyze <dir> [--format json|csv]`
+fn analyze(args: &[String]) -> Result<()> {
+    let mut dir = None;
+    let mut format = "json";
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                format = args
+                    .next()
+                    .ok_or_else(|| Error::MissingArg("format".to_string()))?
+            }
+            _ => dir = Some(arg),
+        }
+    }
+    let dir = dir.ok_or_else(|| Error::MissingArg("directory".to_string()))?;
+    let report = qk::analyze::Report::from_dir(dir).map_err(|e| Error::Io { e })?;
+    match format {
+        "json" => println!("{}", report.to_json()),
+        "csv" => print!("{}", report.to_csv()),
+        _ => return Err(Error::InvalidValue("format".into(), format.into())),
+    }
+    Ok(())
+}
+
+/// `qk tune <dir> [--write] [--config path]`: times the expressions of a corpus with each
+/// strategy, and recommends the limits it needs. `--write` sets them in the config, keeping
+/// everything else in it
+fn tune(args: &[String]) -> Result<()> {
+    let mut dir = None;
+    let mut write = false;
+    let mut config = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--write" => write = true,
+            "--config" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| Error::MissingArg("config".to_string()))?;
+                config = Some(std::path::PathBuf::from(path));
+            }
+            _ => dir = Some(arg),
+        }
+    }
+    let dir = dir.ok_or_else(|| Error::MissingArg("directory".to_string()))?;
+    let tuning = qk::tune::Tuning::from_dir(dir).map_err(|e| Error::Io { e })?;
+    print!("{}", tuning.to_text());
+    if !write {
+        return Ok(());
+    }
+    let recommended = tuning.recommended();
+    if recommended.is_empty() {
+        eprintln!("nothing normalized, so the config was left as it was");
+        return Ok(());
+    }
+    let path = config
+        .or_else(config::default_path)
+        .ok_or_else(|| Error::MissingArg("config".to_string()))?;
+    let src = match std::fs::read_to_string(&path) {
+        Ok(src) => src,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(Error::Io { e }),
+    };
+    let merged = config::merge(&src, &recommended);
+    // what was there is kept as is, so it may be what's wrong
+    Config::parse(&merged)?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| Error::Io { e })?;
+    }
+    std::fs::write(&path, merged).map_err(|e| Error::Io { e })?;
+    eprintln!("wrote them to {}", path.display());
+    Ok(())
+}
+
+/// `qk test <dir>`: checks the `-- expect:` annotations of every `.qk` file, exiting with 1 if any
+/// fails
+fn test(args: &[String]) -> Result<()> {
+    let dir = args
+        .first()
+        .ok_or_else(|| Error::MissingArg("directory".to_string()))?;
+    let suite = qk::expect::Suite::from_dir(dir).map_err(|e| Error::Io { e })?;
+    print!("{}", suite.to_text());
+    if suite.failed().next().is_some() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// `qk fmt <file> [--width n] [--write]`: prints a file in the canonical style, or rewrites it
+/// with `--write`. The lines still too wide, and the ones which don't parse, are told on stderr
+fn fmt(args: &[String]) -> Result<()> {
+    let mut path = None;
+    let mut width = qk::formatter::WIDTH;
+    let mut write = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--width" => {
+                let n = args
+                    .next()
+                    .ok_or_else(|| Error::MissingArg("width".to_string()))?;
+                width = n
+                    .parse()
+                    .map_err(|_| Error::InvalidValue("width".into(), n.into()))?;
+            }
+            "--write" => write = true,
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.ok_or_else(|| Error::MissingArg("file".to_string()))?;
+    let content = std::fs::read_to_string(path).map_err(|e| Error::Io { e })?;
+    let frontends = qk::frontend::defaults();
+    let ext = std::path::Path::new(path)
+        .extension()
+        .map(|e| e.to_string_lossy());
+    let frontend = frontends
+        .iter()
+        .find(|f| ext.as_ref().is_some_and(|e| f.extensions().contains(&&**e)))
+        .unwrap_or(&frontends[0]);
+    let formatted = qk::formatter::format_file(frontend.as_ref(), &content, width);
+    for line in &formatted.unparsed {
+        eprintln!("{path}:{line}: doesn't parse, left as it was");
+    }
+    for line in &formatted.overlong {
+        eprintln!("{path}:{line}: wider than {width} columns");
+    }
+    match write {
+        true => std::fs::write(path, formatted.text).map_err(|e| Error::Io { e }),
+        false => {
+            print!("{}", formatted.text);
+            Ok(())
+        }
+    }
+}
+
+/// `qk --explain <code>`: what a diagnostic code means. Without a code, every one is printed as a
+/// JSON array
+fn explain(args: &[String]) -> Result<()> {
+    let mut entries = qk::diagnostics::catalog().iter().chain(repl::CATALOG);
+    let Some(code) = args.first() else {
+        let entries: Vec<_> = entries.collect();
+        println!(
+            "{}",
+            serde_json::to_string(&entries).expect("entries are serializable")
+        );
+        return Ok(());
+    };
+    let entry = entries
+        .find(|e| e.code == code)
+        .ok_or_else(|| Error::InvalidValue("explain".into(), code.into()))?;
+    print!("{}", entry.to_text());
+    Ok(())
+}
+
+/// `qk replay <log>`: runs a log written by `:log <path>` on a new session
+fn replay(args: &[String]) -> Result<()> {
+    let path = args
+        .first()
+        .ok_or_else(|| Error::MissingArg("log".to_string()))?;
+    let text = std::fs::read_to_string(path).map_err(|e| Error::Io { e })?;
+    repl::Repl::new()?.replay(&qk::session::log::EventLog::parse(&text));
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::cli::apply_flags;
+    use crate::repl::config::Config;
+    use crate::repl::theme::ColorMode;
+    use crate::repl::{OutputMode, Repl};
+
+    #[test]
+    pub fn flags_override_the_config() {
+        let mut r = Repl::new().unwrap();
+        let config = Config::parse("profile = \"server\"\ncolor = \"on\"").unwrap();
+        config.apply(&mut r);
+        assert_eq!((r.output, r.theme.mode), (OutputMode::Json, ColorMode::On));
+        apply_flags(&mut r, &[("--output", "human"), ("--color", "off")]).unwrap();
+        assert_eq!(
+            (r.output, r.theme.mode),
+            (OutputMode::Human, ColorMode::Off)
+        );
+        assert!(apply_flags(&mut r, &[("--color", "sometimes")]).is_err());
+    }
+}
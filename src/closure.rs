@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::arts::{CompArtifact, Const, Term, TermIdx};
+use crate::lift::free_variables;
+
+/// the body of a code block, where a variable is either its parameter or a field of its
+/// environment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Param,
+    Env(usize),
+    Const(Const),
+    /// calls the closure on the left with the right side
+    App(Box<Expr>, Box<Expr>),
+    /// the code `code` paired with the tuple of what it captures
+    Closure {
+        code: usize,
+        env: Vec<Expr>,
+    },
+}
+
+/// an abstraction without free variables: `#k = λ env x. body`, where `env` holds `captures`
+/// values
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Code {
+    pub captures: usize,
+    pub body: Expr,
+}
+
+/// a closed term, with every abstraction turned into a closure
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Converted {
+    pub codes: Vec<Code>,
+    pub main: Expr,
+}
+
+impl Converted {
+    /// closure converts `root`, if it's closed. An abstraction that's shared in the pool becomes
+    /// a single code block
+    pub fn new(art: &CompArtifact, root: TermIdx) -> Option<Self> {
+        let mut conv = Converter {
+            art,
+            free: free_variables(art, root),
+            converted: HashMap::new(),
+            codes: Vec::new(),
+        };
+        if !conv.free[&root].is_empty() {
+            return None;
+        }
+        let main = conv.expr(root, &[]);
+        Some(Self {
+            codes: conv.codes,
+            main,
+        })
+    }
+}
+
+struct Converter<'a> {
+    art: &'a CompArtifact,
+    free: HashMap<TermIdx, Vec<usize>>,
+    /// the code each abstraction became
+    converted: HashMap<TermIdx, usize>,
+    codes: Vec<Code>,
+}
+
+impl Converter<'_> {
+    /// `idx` inside a code block, where the variable `i` is `ctx[i]`
+    fn expr(&mut self, idx: TermIdx, ctx: &[Expr]) -> Expr {
+        match self.art.get(idx) {
+            Term::Var(v) => ctx[v.0].clone(),
+            Term::Const(c) => Expr::Const(c),
+            Term::App(l, r) => Expr::App(Box::new(self.expr(l, ctx)), Box::new(self.expr(r, ctx))),
+            Term::Abs { inner } => {
+                let code = self.code(idx, inner);
+                let env = self.free[&idx].iter().map(|v| ctx[*v].clone()).collect();
+                Expr::Closure { code, env }
+            }
+        }
+    }
+
+    fn code(&mut self, abs: TermIdx, inner: TermIdx) -> usize {
+        if let Some(code) = self.converted.get(&abs) {
+            return *code;
+        }
+        let free = self.free[&abs].clone();
+        // the variables that aren't free in the body are never looked up
+        let mut ctx = vec![Expr::Param; 1 + free.last().map_or(0, |f| f + 1)];
+        for (i, f) in free.iter().enumerate() {
+            ctx[1 + f] = Expr::Env(i);
+        }
+        let body = self.expr(inner, &ctx);
+        self.codes.push(Code {
+            captures: free.len(),
+            body,
+        });
+        let code = self.codes.len() - 1;
+        self.converted.insert(abs, code);
+        code
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Param => f.write_str("x"),
+            Self::Env(i) => write!(f, "env.{i}"),
+            Self::Const(c) => write!(f, "{c}"),
+            Self::App(l, r) if matches!(**r, Self::App(..)) => write!(f, "{l} ({r})"),
+            Self::App(l, r) => write!(f, "{l} {r}"),
+            Self::Closure { code, env } => {
+                write!(f, "⟨#{code}, (")?;
+                for (i, e) in env.iter().enumerate() {
+                    let sep = if i > 0 { ", " } else { "" };
+                    write!(f, "{sep}{e}")?;
+                }
+                f.write_str(")⟩")
+            }
+        }
+    }
+}
+
+/// a code block a line, then `main`
+impl fmt::Display for Converted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (k, code) in self.codes.iter().enumerate() {
+            writeln!(f, "#{k} = λ env x. {}", code.body)?;
+        }
+        write!(f, "main = {}", self.main)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::closure::Converted;
+    use crate::session::Session;
+
+    #[test]
+    pub fn converts_closures() {
+        let mut s = Session::new();
+        s.eval_str("two f x = f (f x)").unwrap();
+        let root = s
+            .compile_expr("fn y => two (fn z => z y)")
+            .unwrap()
+            .unwrap();
+        let converted = Converted::new(&s.art, root).unwrap();
+        assert_eq!(
+            converted.to_string(),
+            "#0 = λ env x. env.0 (env.0 x)\n\
+             #1 = λ env x. ⟨#0, (x)⟩\n\
+             #2 = λ env x. x env.0\n\
+             #3 = λ env x. ⟨#1, ()⟩ ⟨#2, (x)⟩\n\
+             main = ⟨#3, ()⟩"
+        );
+        assert_eq!(converted.codes[3].captures, 0);
+        assert_eq!(converted.codes[2].captures, 1);
+    }
+}
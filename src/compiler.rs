@@ -2,7 +2,7 @@ use miette::{Diagnostic, SourceSpan};
 use thiserror::Error;
 
 use crate::{
-    arts::{CompArtifact, OuterIdx, Term, TermIdx},
+    arts::{Blame, CompArtifact, Const, OuterIdx, Prim, Term, TermIdx},
     ir::{self, IrComponent, IrObj, Scope},
 };
 
@@ -39,7 +39,6 @@ impl<'a> CodeUnit<'a> {
     }
 
     pub fn with_artifacts(scope: &'a mut Scope, src: &'a str, art: CompArtifact) -> Result<Self> {
-        scope.check_for_pendings()?;
         let s = Self {
             art,
             scope,
@@ -50,47 +49,124 @@ impl<'a> CodeUnit<'a> {
     }
 
     pub fn compile(&mut self, ir: &IrObj) -> Result<()> {
+        self.art.spans.clear();
         let idx = self.compile_node(ir)?;
         self.art.root = Some(idx);
         Ok(())
     }
 
+    /// works through an explicit stack instead of recursion, so any nesting depth fits
     pub fn compile_node(&mut self, ir: &IrObj) -> Result<TermIdx> {
-        match &ir.item {
-            IrComponent::Pending => {
-                unreachable!("`Scope::check_for_pendings` wasn't executed or is bugged")
-            }
-            IrComponent::Binding => {
-                unreachable!("this `TermIdx` shouldn't be the entry point for compilation")
-            }
-            IrComponent::Abs(id, body) => {
-                self.layer_stack.push(*id);
-                let inner = self.compile_node(body)?;
-                self.layer_stack.pop();
-                Ok(self.art.push(Term::Abs { inner }))
-            }
-            IrComponent::App(l, r) => {
-                let l = self.compile_node(l)?;
-                let r = self.compile_node(r)?;
-                Ok(self.art.push(Term::App(l, r)))
-            }
-            IrComponent::Var(id) => match &self.scope.res_pool[id.0].item {
-                IrComponent::Def { .. } => self.cache_hit_or_compile(*id),
-                IrComponent::Binding => {
-                    let outer_idx = self
-                        .layer_stack
-                        .iter()
-                        .rev()
-                        .enumerate()
-                        .find(|(_, sid)| *sid == id)
-                        .unwrap()
-                        .0;
-                    Ok(self.art.push(Term::Var(OuterIdx(outer_idx))))
+        self.compile_in(ir, None)
+    }
+
+    /// `compile_node`, with `ir` being the body of the definition `def` if there's one
+    fn compile_in(&mut self, ir: &IrObj, def: Option<ir::Id>) -> Result<TermIdx> {
+        let scope = self.scope;
+        let mut tasks = vec![Task::Visit(ir)];
+        let mut done = Vec::new();
+        // where each definition being compiled was referred to. Their bodies are spans of
+        // another source
+        let mut entered = Vec::new();
+        let mut defs: Vec<ir::Id> = def.into_iter().collect();
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Visit(ir) => match &ir.item {
+                    IrComponent::Pending => {
+                        unreachable!("variables refer to definitions, not to their bodies")
+                    }
+                    IrComponent::Binding => {
+                        unreachable!("this `TermIdx` shouldn't be the entry point for compilation")
+                    }
+                    IrComponent::Const(c) => done.push(self.art.push(Term::Const(*c))),
+                    IrComponent::Abs(id, body) => {
+                        self.layer_stack.push(*id);
+                        tasks.push(Task::Abs);
+                        tasks.push(Task::Visit(body));
+                    }
+                    IrComponent::App(l, r) => {
+                        tasks.push(Task::App(ir.at));
+                        tasks.push(Task::Visit(r));
+                        tasks.push(Task::Visit(l));
+                    }
+                    IrComponent::Var(id) => match &scope.res_pool[id.0].item {
+                        IrComponent::Def { .. } => match self.art.obj_cache.get(id) {
+                            Some(&idx) => {
+                                if let Term::App(..) = self.art.arena()[idx.0] {
+                                    let at = entered.first().copied().unwrap_or(ir.at);
+                                    self.art.spans.entry(idx).or_insert(at);
+                                }
+                                done.push(idx);
+                            }
+                            None => {
+                                entered.push(ir.at);
+                                defs.push(*id);
+                                tasks.push(Task::Cache(*id));
+                                tasks.push(Task::Visit(&scope.res_pool[id.0]));
+                            }
+                        },
+                        // only reserved by a reference, and never defined
+                        IrComponent::Pending => {
+                            let at = entered.first().copied().unwrap_or(ir.at);
+                            return Err(ir::Error::UndeclaredVariable { at }.into());
+                        }
+                        IrComponent::Binding => {
+                            let outer_idx = self
+                                .layer_stack
+                                .iter()
+                                .rev()
+                                .enumerate()
+                                .find(|(_, sid)| *sid == id)
+                                .unwrap()
+                                .0;
+                            done.push(self.art.push(Term::Var(OuterIdx(outer_idx))));
+                        }
+                        _ => unreachable!(),
+                    },
+                    IrComponent::Def(obj) => tasks.push(Task::Visit(obj)),
+                    IrComponent::Cast(inner) => {
+                        tasks.push(Task::Cast(ir.at));
+                        tasks.push(Task::Visit(inner));
+                    }
+                },
+                Task::Abs => {
+                    self.layer_stack.pop();
+                    let inner = done.pop().expect("the body was compiled");
+                    done.push(self.art.push(Term::Abs { inner }));
                 }
-                _ => unreachable!(),
-            },
-            IrComponent::Def(obj) => self.compile_node(obj),
+                Task::App(at) => {
+                    let r = done.pop().expect("the argument was compiled");
+                    let l = done.pop().expect("the function was compiled");
+                    let app = self.art.push(Term::App(l, r));
+                    self.art
+                        .spans
+                        .insert(app, entered.first().copied().unwrap_or(at));
+                    done.push(app);
+                }
+                Task::Cast(at) => {
+                    let inner = done.pop().expect("the term cast was compiled");
+                    let blame = Blame {
+                        at,
+                        def: defs.last().copied(),
+                    };
+                    let cast = Prim::Cast(self.art.casts.len() as u32);
+                    self.art.casts.push(blame);
+                    let cast = self.art.push(Term::Const(Const::Prim(cast)));
+                    let app = self.art.push(Term::App(cast, inner));
+                    self.art
+                        .spans
+                        .insert(app, entered.first().copied().unwrap_or(at));
+                    done.push(app);
+                }
+                Task::Cache(id) => {
+                    entered.pop();
+                    defs.pop();
+                    let compiled = *done.last().expect("the definition was compiled");
+                    self.art.obj_cache.insert(id, compiled);
+                }
+            }
         }
+        Ok(done.pop().expect("the root was compiled"))
     }
 
     pub fn cache_hit_or_compile(&mut self, res_id: ir::Id) -> Result<TermIdx> {
@@ -98,9 +174,65 @@ impl<'a> CodeUnit<'a> {
             Ok(*idx)
         } else {
             let res = &self.scope.res_pool[res_id.0];
-            let compiled = self.compile_node(res)?;
+            let compiled = self.compile_in(res, Some(res_id))?;
             self.art.obj_cache.insert(res_id, compiled);
             Ok(compiled)
         }
     }
 }
+
+/// what's left to do in `CodeUnit::compile_node`
+enum Task<'a> {
+    Visit(&'a IrObj),
+    /// builds an abstraction out of the last compiled term, leaving its layer
+    Abs,
+    /// builds an application out of the last two compiled terms, written at the span
+    App(SourceSpan),
+    /// checks the last compiled term is a function, blaming the span if it isn't
+    Cast(SourceSpan),
+    /// caches the last compiled term as the definition
+    Cache(ir::Id),
+}
+
+#[cfg(test)]
+pub mod tests {
+    use miette::SourceSpan;
+
+    use crate::arts::{OuterIdx, Term};
+    use crate::ast::{Ast, Node};
+    use crate::compiler::CodeUnit;
+    use crate::ir::IrCompiler;
+    use crate::lexer::Trace;
+
+    const DEPTH: usize = 100_000;
+
+    /// compiles a tree built by `f` around `x`, where `x` is bound by the outermost abstraction
+    fn compile_deep(f: impl Fn(Node, SourceSpan) -> Node) -> Vec<Term> {
+        let src = "x";
+        let at = SourceSpan::from((0, 1));
+        let mut body = Ast::Var.at(at);
+        for _ in 0..DEPTH {
+            body = f(body, at);
+        }
+        let ast = Ast::Abs(at, body).at(at);
+        let mut irc = IrCompiler::default();
+        let ir = irc.compile(*ast, src).unwrap();
+        let mut cu = CodeUnit::new(&mut irc.scope, src).unwrap();
+        cu.compile(&ir).unwrap();
+        cu.art.arena().to_vec()
+    }
+
+    #[test]
+    pub fn deep_abstractions() {
+        let arena = compile_deep(|inner, at| Ast::Abs(at, inner).at(at));
+        assert_eq!(arena.len(), DEPTH + 2);
+        assert_eq!(arena[0], Term::Var(OuterIdx(0)));
+    }
+
+    #[test]
+    pub fn deep_applications() {
+        let arena = compile_deep(|l, at| Ast::App(l, Ast::Var.at(at)).at(at));
+        assert_eq!(arena.len(), 2 * DEPTH + 2);
+        assert!(matches!(arena.last(), Some(Term::Abs { .. })));
+    }
+}
@@ -0,0 +1,214 @@
+use miette::{Diagnostic, SourceSpan};
+use thiserror::Error;
+
+use crate::ast::{Ast, Node};
+use crate::lexer::{Meta, Trace, from_code};
+
+#[derive(Error, Diagnostic, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    #[error("{what} can't be written in continuation-passing style")]
+    #[diagnostic(
+        code(cps::unsupported),
+        help(
+            "it's lowered to a direct style term, which would be called the CPS way. Write it with abstractions and applications instead"
+        )
+    )]
+    Unsupported {
+        what: &'static str,
+        #[label("this one")]
+        at: SourceSpan,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// makes up the names the transform binds. They're appended to the source, as every name of a
+/// node is read from it, and written with subscripts, which no identifier can have
+pub struct Fresh<'s> {
+    src: &'s mut String,
+    next: usize,
+}
+
+impl<'s> Fresh<'s> {
+    pub fn new(src: &'s mut String) -> Self {
+        Self { src, next: 0 }
+    }
+
+    fn name(&mut self, prefix: char) -> SourceSpan {
+        const SUBSCRIPTS: [char; 10] = ['₀', '₁', '₂', '₃', '₄', '₅', '₆', '₇', '₈', '₉'];
+        self.src.push(' ');
+        let start = self.src.len();
+        self.src.push(prefix);
+        let digits = self.next.to_string();
+        self.src
+            .extend(digits.bytes().map(|d| SUBSCRIPTS[(d - b'0') as usize]));
+        self.next += 1;
+        (start, self.src.len() - start).into()
+    }
+}
+
+/// the call-by-value CPS form of `node`, after Plotkin: a function of the continuation, which
+/// it calls with the value of `node`. Abstractions take their continuation after their argument
+///
+/// ```text
+/// [x]       = λk. k x
+/// [λx. m]   = λk. k (λx. [m])
+/// [m n]     = λk. [m] (λf. [n] (λv. f v k))
+/// [let x = v in b] = λk. [v] (λx. [b] k)
+/// ```
+///
+/// Every other sugar is lowered to direct style terms, so it's left out
+pub fn transform(node: Node, fresh: &mut Fresh) -> Result<Node> {
+    let at = node.at;
+    let var = |name: SourceSpan| Ast::Var.at(name);
+    let abs = |name: SourceSpan, body: Node| Ast::Abs(name, body).at(at);
+    let app = |l: Node, r: Node| Ast::App(l, r).at(at);
+    let mut node = node;
+    let k = fresh.name('k');
+    let cps = match &mut node.item {
+        Ast::Var => app(var(k), node),
+        Ast::Abs(..) => app(var(k), value(node, fresh)?),
+        Ast::App(l, r) => {
+            let (l, r) = (Box::new(l.take()), Box::new(r.take()));
+            let (f, v) = (fresh.name('f'), fresh.name('v'));
+            let call = app(app(var(f), var(v)), var(k));
+            let (l, r) = (transform(l, fresh)?, transform(r, fresh)?);
+            app(l, abs(f, app(r, abs(v, call))))
+        }
+        Ast::Let {
+            rec: false,
+            ident,
+            value,
+            body,
+        } => {
+            let (value, body) = (Box::new(value.take()), Box::new(body.take()));
+            let body = app(transform(body, fresh)?, var(k));
+            app(transform(value, fresh)?, abs(*ident, body))
+        }
+        other => {
+            let what = match other {
+                Ast::Let { .. } => "`letrec`",
+                Ast::Unpack { .. } => "a pattern",
+                Ast::Annotated { .. } => "an annotation",
+                Ast::Tuple(..) => "a tuple",
+                Ast::List(..) => "a list",
+                Ast::Infix { .. } => "an operator",
+                _ => "a program",
+            };
+            return Err(Error::Unsupported { what, at });
+        }
+    };
+    Ok(abs(k, cps))
+}
+
+/// what a variable or an abstraction is passed to its continuation as
+fn value(mut node: Node, fresh: &mut Fresh) -> Result<Node> {
+    if let Ast::Abs(_, inner) = &mut node.item {
+        *inner = transform(Box::new(inner.take()), fresh)?;
+    }
+    Ok(node)
+}
+
+/// `node` as a compilation stage: an expression is run with the identity as its continuation,
+/// and each definition of a program is defined to the value of its body. The fresh names are
+/// appended to `src`
+pub fn stage(mut node: Node, src: &mut String) -> Result<Node> {
+    let mut fresh = Fresh::new(src);
+    let Ast::Program(steps) = &mut node.item else {
+        return run(node, &mut fresh);
+    };
+    for step in steps {
+        if let Ast::Def { params, body, .. } = &mut step.item {
+            // the parameters are abstractions of the body
+            let body = std::mem::take(params)
+                .into_iter()
+                .rev()
+                .fold(Box::new(body.take()), |body, p| Ast::Abs(p, body).at(p));
+            let body = run(body, &mut fresh)?;
+            if let Ast::Def { body: b, .. } = &mut step.item {
+                *b = body;
+            }
+        }
+    }
+    Ok(node)
+}
+
+fn run(node: Node, fresh: &mut Fresh) -> Result<Node> {
+    if matches!(node.item, Ast::Var | Ast::Abs(..)) {
+        return value(node, fresh);
+    }
+    let at = node.at;
+    let x = fresh.name('x');
+    let id = Ast::Abs(x, Ast::Var.at(x)).at(at);
+    Ok(Ast::App(transform(node, fresh)?, id).at(at))
+}
+
+/// the transformed term as source, an abstraction stretching as far right as it can
+pub fn display(node: &Meta<Ast>, src: &str) -> String {
+    match &node.item {
+        Ast::Var => node.from_code(src).to_string(),
+        Ast::Abs(v, inner) => format!("fn {} => {}", from_code(*v, src), display(inner, src)),
+        Ast::App(l, r) => {
+            let l = match l.item {
+                Ast::Abs(..) => format!("({})", display(l, src)),
+                _ => display(l, src),
+            };
+            match r.item {
+                Ast::Var => format!("{l} {}", display(r, src)),
+                _ => format!("{l} ({})", display(r, src)),
+            }
+        }
+        _ => unreachable!("the transform only makes abstractions and applications"),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::backend::{DeBruijn, Renderer};
+    use crate::cps::{self, Fresh};
+    use crate::padam::grammar;
+    use crate::session::{Output, Session};
+
+    #[test]
+    pub fn transforms() {
+        let mut src = String::from("fn x => x y");
+        let ast = grammar::tests::parse(grammar::EXPR, &src).unwrap();
+        let cps = cps::transform(ast, &mut Fresh::new(&mut src)).unwrap();
+        assert_eq!(
+            cps::display(&cps, &src),
+            "fn k₀ => k₀ (fn x => fn k₁ => (fn k₄ => k₄ x) (fn f₂ => (fn k₅ => k₅ y) (fn v₃ => f₂ v₃ k₁)))"
+        );
+    }
+
+    #[test]
+    pub fn evaluates_to_the_same_results() {
+        let mut s = Session::new();
+        for src in [
+            "(fn m n f x => m f (n f x)) (fn f x => f (f x)) (fn f x => f (f (f x)))",
+            "(fn x => x x) (fn f x => f (f x))",
+            "let two = fn f x => f (f x) in two two",
+            "(fn x y => y) (fn x => x x) (fn f x => f x)",
+        ] {
+            let Output::Normal(direct) = s.eval_str(src).unwrap().output else {
+                unreachable!("{src} is an expression")
+            };
+            // a numeral in CPS takes the successor and then the continuation, which gets the
+            // function adding it that many times
+            s.cps = true;
+            s.eval_str(&format!("n = {src}")).unwrap();
+            s.cps = false;
+            let observed = "fn s z => n (fn x k => k (s x)) (fn g => g z (fn r => r))";
+            let Output::Normal(cps) = s.eval_str(observed).unwrap().output else {
+                unreachable!("it's an expression")
+            };
+            let debruijn = |t| DeBruijn.render(&s.art, t, &Default::default());
+            assert_eq!(debruijn(cps), debruijn(direct), "{src}");
+        }
+
+        s.cps = true;
+        assert!(matches!(
+            s.eval_str("(fn (a, b) => a) (I, I)"),
+            Err(crate::session::Error::Cps(cps::Error::Unsupported { .. }))
+        ));
+    }
+}
@@ -1,6 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::arts::{CompArtifact, OuterIdx, Term, TermIdx};
+use crate::arts::{CompArtifact, Const, OuterIdx, Prim, Term, TermIdx};
+use crate::cancel::{self, CancelToken};
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct EffectReq;
@@ -10,6 +11,28 @@ pub enum Op {
     Normal,
     Reduced(TermIdx),
     Effect(EffectReq),
+    /// the cast of index `cast` in `CompArtifact::casts` found `value`, which isn't a function
+    Blame {
+        cast: u32,
+        value: TermIdx,
+    },
+}
+
+/// what a reduction cost
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// β-reductions done
+    pub beta: usize,
+    /// primitive operations computed, with the `primitives` mode
+    pub delta: usize,
+    /// terms pushed into the arena
+    pub allocations: usize,
+    /// subterms reused as they were by a substitution, instead of being copied
+    pub shared: usize,
+    /// the size of the biggest term the reduction went through
+    pub max_size: usize,
+    /// thunks found already forced, whose value was taken instead of reducing them again
+    pub forced: usize,
 }
 
 /// the actual machine reductor
@@ -23,6 +46,11 @@ pub struct Cpu {
 
     /// for a b -> c, maps (a, b) to c
     pub reductions: HashMap<(TermIdx, TermIdx), TermIdx>,
+    pub stats: Stats,
+    /// the last redex contracted
+    pub last_redex: Option<TermIdx>,
+    /// the arguments substituted by `Need`, which are updated with what they reduce to
+    thunks: HashSet<TermIdx>,
     // how many λ are we into. Should be always zero outside reduction methods
     // pub abs_layer: usize,
 }
@@ -37,6 +65,20 @@ pub trait Reductor {
         }
         idx
     }
+
+    /// same as `reduce`, but spends a step of `token` for each reduction
+    fn reduce_until(
+        s: &mut Cpu,
+        root: TermIdx,
+        token: &mut CancelToken,
+    ) -> cancel::Result<TermIdx> {
+        let mut idx = root;
+        while let Op::Reduced(i) = Self::step(s, idx) {
+            token.tick()?;
+            idx = i;
+        }
+        Ok(idx)
+    }
 }
 
 impl Cpu {
@@ -44,6 +86,175 @@ impl Cpu {
         Self {
             art,
             reductions: HashMap::new(),
+            stats: Stats::default(),
+            last_redex: None,
+            thunks: HashSet::new(),
+        }
+    }
+
+    /// takes `idx` into account for `Stats::max_size`
+    pub fn measure(&mut self, idx: TermIdx) {
+        let size = self.size(idx);
+        self.stats.max_size = self.stats.max_size.max(size);
+    }
+
+    /// how many terms are reachable from `idx`, each counted once however many parents share
+    /// it, so a definition used many times counts as much as one used once
+    pub fn size(&self, idx: TermIdx) -> usize {
+        let mut seen = HashSet::new();
+        let mut stack = vec![idx];
+        while let Some(idx) = stack.pop() {
+            if !seen.insert(idx) {
+                continue;
+            }
+            match self.art.get(idx) {
+                Term::Var(_) | Term::Const(_) => (),
+                Term::Abs { inner } => stack.push(inner),
+                Term::App(l, r) => stack.extend([l, r]),
+            }
+        }
+        seen.len()
+    }
+
+    fn push(&mut self, t: Term) -> TermIdx {
+        self.stats.allocations += 1;
+        self.art.push(t)
+    }
+
+    /// `new` if there's any, or else `old`, which is then shared
+    fn share(&mut self, new: Option<TermIdx>, old: TermIdx) -> TermIdx {
+        new.unwrap_or_else(|| {
+            self.stats.shared += 1;
+            old
+        })
+    }
+
+    /// the arguments the primitive applied at `idx` needs as numbers, with their position,
+    /// which aren't numbers yet. A cast needs its argument as a value
+    fn pending_args(&self, idx: TermIdx) -> Vec<usize> {
+        let Some((p, args)) = self.art.saturated(idx) else {
+            return Vec::new();
+        };
+        if let Prim::Cast(_) = p {
+            return match self.art.is_whnf(args[0]) {
+                true => Vec::new(),
+                false => vec![0],
+            };
+        }
+        (0..p.strict())
+            .filter(|i| !matches!(self.art.arena()[args[*i].0], Term::Const(Const::Int(_))))
+            .collect()
+    }
+
+    /// contracts the δ-redex at `idx`: a primitive applied to as many arguments as it takes,
+    /// with numbers where it needs them. Arithmetic wraps around, and a cast either passes its
+    /// argument on or blames it
+    pub fn delta(&mut self, idx: TermIdx) -> Option<Op> {
+        let (p, args) = self.art.saturated(idx)?;
+        let int = |c: &Self, i: usize| match c.art.arena()[args[i].0] {
+            Term::Const(Const::Int(n)) => Some(n),
+            _ => None,
+        };
+        let res = match p {
+            Prim::Add => self.push(Term::Const(Const::Int(
+                int(self, 0)?.wrapping_add(int(self, 1)?),
+            ))),
+            Prim::Sub => self.push(Term::Const(Const::Int(
+                int(self, 0)?.wrapping_sub(int(self, 1)?),
+            ))),
+            Prim::Mul => self.push(Term::Const(Const::Int(
+                int(self, 0)?.wrapping_mul(int(self, 1)?),
+            ))),
+            Prim::If0 if int(self, 0)? == 0 => args[1],
+            Prim::If0 => args[2],
+            Prim::Cast(cast) => match self.art.is_function(args[0])? {
+                true => args[0],
+                false => {
+                    let value = args[0];
+                    return Some(Op::Blame { cast, value });
+                }
+            },
+        };
+        self.stats.delta += 1;
+        self.last_redex = Some(idx);
+        Some(Op::Reduced(res))
+    }
+
+    /// steps the argument `i` of the application at `idx` with `step`, rebuilding the spine
+    /// above it
+    fn step_arg(&mut self, idx: TermIdx, i: usize, step: fn(&mut Self, TermIdx) -> Op) -> Op {
+        let (_, args) = self.art.spine(idx);
+        self.step_nth(idx, args.len() - 1 - i, step)
+    }
+
+    fn step_nth(
+        &mut self,
+        idx: TermIdx,
+        from_last: usize,
+        step: fn(&mut Self, TermIdx) -> Op,
+    ) -> Op {
+        let Term::App(l, r) = self.art.get(idx) else {
+            unreachable!("the spine has an application for each argument")
+        };
+        if from_last == 0 {
+            return match step(self, r) {
+                Op::Reduced(r) => Op::Reduced(self.push(Term::App(l, r))),
+                op => op,
+            };
+        }
+        match self.step_nth(l, from_last - 1, step) {
+            Op::Reduced(l) => Op::Reduced(self.push(Term::App(l, r))),
+            op => op,
+        }
+    }
+
+    /// steps the body under the abstractions at `idx` with `step`, rebuilding them in a loop
+    /// rather than a call each, so deep abstractions don't overflow
+    fn step_under(&mut self, idx: TermIdx, step: fn(&mut Self, TermIdx) -> Op) -> Op {
+        let mut body = idx;
+        let mut depth = 0;
+        while let Term::Abs { inner } = self.art.get(body) {
+            body = inner;
+            depth += 1;
+        }
+        match step(self, body) {
+            Op::Reduced(mut new) => {
+                for _ in 0..depth {
+                    new = self.push(Term::Abs { inner: new });
+                }
+                Op::Reduced(new)
+            }
+            op => op,
+        }
+    }
+
+    /// every term `idx` turns into by contracting a single redex, in no particular strategy
+    pub fn reducts(&mut self, idx: TermIdx) -> Vec<TermIdx> {
+        match self.art.get(idx) {
+            Term::Var(..) | Term::Const(..) => Vec::new(),
+            Term::Abs { inner } => self
+                .reducts(inner)
+                .into_iter()
+                .map(|inner| self.push(Term::Abs { inner }))
+                .collect(),
+            Term::App(l, r) => {
+                let mut reducts = Vec::new();
+                if let Term::Abs { inner } = self.art.get(l) {
+                    reducts.push(self.substitute(inner, r));
+                }
+                let delta = self.stats.delta;
+                if let Some(Op::Reduced(reduct)) = self.delta(idx) {
+                    reducts.push(reduct);
+                }
+                self.stats.delta = delta;
+                for l in self.reducts(l) {
+                    reducts.push(self.push(Term::App(l, r)));
+                }
+                for r in self.reducts(r) {
+                    reducts.push(self.push(Term::App(l, r)));
+                }
+                reducts
+            }
         }
     }
 
@@ -53,6 +264,9 @@ impl Cpu {
         // if let Some(idx) = self.reductions.get(&(inner, with)) {
         //     *idx
         // } else {
+        // `inner` loses its binder, so its free variables go down by one. `with` enters it and
+        // gets shifted up beforehand, keeping its own free variables the same in the end
+        let with = self.shift(with, 1);
         let t = self.substitute_inner(inner, with, 0).unwrap_or(inner);
         // self.reductions.insert((inner, with), t);
         self.shift(t, -1)
@@ -62,12 +276,14 @@ impl Cpu {
     fn substitute_inner(&mut self, abs: TermIdx, with: TermIdx, layer: usize) -> Option<TermIdx> {
         match self.art.get(abs) {
             Term::Var(o) if o.0 == layer => Some(with),
-            Term::Var(..) => None,
+            Term::Var(..) | Term::Const(..) => None,
             Term::App(l, r) => {
-                let new_l = self.substitute_inner(l, with, layer).unwrap_or(l);
-                let new_r = self.substitute_inner(r, with, layer).unwrap_or(r);
+                let new_l = self.substitute_inner(l, with, layer);
+                let new_l = self.share(new_l, l);
+                let new_r = self.substitute_inner(r, with, layer);
+                let new_r = self.share(new_r, r);
                 if new_l != l || new_r != r {
-                    Some(self.art.push(Term::App(new_l, new_r)))
+                    Some(self.push(Term::App(new_l, new_r)))
                 } else {
                     None
                 }
@@ -75,7 +291,7 @@ impl Cpu {
             Term::Abs { inner } => {
                 let with_shifted = self.shift(with, 1);
                 self.substitute_inner(inner, with_shifted, layer + 1)
-                    .and_then(|inner| Some(self.art.push(Term::Abs { inner })))
+                    .map(|inner| self.push(Term::Abs { inner }))
             }
         }
     }
@@ -91,19 +307,20 @@ impl Cpu {
         layers: isize,
     ) -> Option<TermIdx> {
         match self.art.get(term) {
-            Term::Var(o) if o.0 >= current_layer => Some(
-                self.art
-                    .push(Term::Var(OuterIdx(o.0.strict_add_signed(layers)))),
-            ),
-            Term::Var(..) => None,
+            Term::Var(o) if o.0 >= current_layer => {
+                Some(self.push(Term::Var(OuterIdx(o.0.strict_add_signed(layers)))))
+            }
+            Term::Var(..) | Term::Const(..) => None,
             Term::Abs { inner } => self
                 .shift_inner(inner, current_layer + 1, layers)
-                .and_then(|inner| Some(self.art.push(Term::Abs { inner }))),
+                .map(|inner| self.push(Term::Abs { inner })),
             Term::App(l, r) => {
-                let new_l = self.shift_inner(l, current_layer, layers).unwrap_or(l);
-                let new_r = self.shift_inner(r, current_layer, layers).unwrap_or(r);
+                let new_l = self.shift_inner(l, current_layer, layers);
+                let new_l = self.share(new_l, l);
+                let new_r = self.shift_inner(r, current_layer, layers);
+                let new_r = self.share(new_r, r);
                 if new_l != l || new_r != r {
-                    Some(self.art.push(Term::App(new_l, new_r)))
+                    Some(self.push(Term::App(new_l, new_r)))
                 } else {
                     None
                 }
@@ -117,22 +334,289 @@ pub struct Normal;
 impl Reductor for Normal {
     fn step(c: &mut Cpu, idx: TermIdx) -> Op {
         match c.art.arena()[idx.0] {
-            Term::Var(..) => Op::Normal,
+            Term::Var(..) | Term::Const(..) => Op::Normal,
+            Term::Abs { .. } => c.step_under(idx, Self::step),
+            Term::App(l, r) => {
+                if let Term::Abs { inner } = c.art.get(l) {
+                    c.stats.beta += 1;
+                    c.last_redex = Some(idx);
+                    return Op::Reduced(c.substitute(inner, r));
+                }
+                if let Some(op) = c.delta(idx) {
+                    return op;
+                }
+
+                match Self::step(c, l) {
+                    Op::Reduced(redex_l) => Op::Reduced(c.push(Term::App(redex_l, r))),
+                    Op::Normal => match Self::step(c, r) {
+                        Op::Reduced(redex_r) => Op::Reduced(c.push(Term::App(l, redex_r))),
+                        op => op,
+                    },
+                    op => op,
+                }
+            }
+        }
+    }
+}
+
+/// innermost first: a function and its argument are normalized before the application is
+/// contracted. Shorter than `Normal` when arguments get copied, but it loops on `K I Omega`
+pub struct Applicative;
+
+impl Reductor for Applicative {
+    fn step(c: &mut Cpu, idx: TermIdx) -> Op {
+        match c.art.arena()[idx.0] {
+            Term::Var(..) | Term::Const(..) => Op::Normal,
+            Term::Abs { .. } => c.step_under(idx, Self::step),
+            Term::App(l, r) => match Self::step(c, l) {
+                Op::Reduced(redex_l) => Op::Reduced(c.push(Term::App(redex_l, r))),
+                Op::Normal => match Self::step(c, r) {
+                    Op::Reduced(redex_r) => Op::Reduced(c.push(Term::App(l, redex_r))),
+                    Op::Normal => match c.art.get(l) {
+                        Term::Abs { inner } => {
+                            c.stats.beta += 1;
+                            c.last_redex = Some(idx);
+                            Op::Reduced(c.substitute(inner, r))
+                        }
+                        _ => c.delta(idx).unwrap_or(Op::Normal),
+                    },
+                    op => op,
+                },
+                op => op,
+            },
+        }
+    }
+}
+
+/// call-by-need: the normal order, but each argument substituted is a thunk, reduced at most
+/// once. A thunk is updated with each reduct it goes through, in `CompArtifact::forced`, so its
+/// other copies skip to the last one instead of reducing it again
+pub struct Need;
+
+impl Need {
+    /// the last value `thunk` was updated with, shortening the chain on the way
+    fn force(c: &mut Cpu, thunk: TermIdx) -> Option<TermIdx> {
+        let mut value = *c.art.forced.get(&thunk)?;
+        while let Some(&next) = c.art.forced.get(&value) {
+            value = next;
+        }
+        c.art.forced.insert(thunk, value);
+        Some(value)
+    }
+
+    /// one step of `idx` itself, as `Normal` would take it
+    fn contract(c: &mut Cpu, idx: TermIdx) -> Op {
+        match c.art.arena()[idx.0] {
+            Term::Var(..) | Term::Const(..) => Op::Normal,
             Term::Abs { inner } => match Self::step(c, inner) {
-                Op::Reduced(new_inner) => Op::Reduced(c.art.push(Term::Abs { inner: new_inner })),
+                Op::Reduced(new_inner) => Op::Reduced(c.push(Term::Abs { inner: new_inner })),
                 op => op,
             },
             Term::App(l, r) => {
                 if let Term::Abs { inner } = c.art.get(l) {
+                    c.stats.beta += 1;
+                    c.last_redex = Some(idx);
+                    c.thunks.insert(r);
                     return Op::Reduced(c.substitute(inner, r));
                 }
+                if let Some(op) = c.delta(idx) {
+                    return op;
+                }
+                match Self::step(c, l) {
+                    Op::Reduced(redex_l) => Op::Reduced(c.push(Term::App(redex_l, r))),
+                    Op::Normal => match Self::step(c, r) {
+                        Op::Reduced(redex_r) => Op::Reduced(c.push(Term::App(l, redex_r))),
+                        op => op,
+                    },
+                    op => op,
+                }
+            }
+        }
+    }
+}
 
+impl Reductor for Need {
+    fn step(c: &mut Cpu, idx: TermIdx) -> Op {
+        if let Some(value) = Self::force(c, idx) {
+            c.stats.forced += 1;
+            return Op::Reduced(value);
+        }
+        let op = Self::contract(c, idx);
+        // the update: whoever else holds the thunk gets the reduct from now on
+        if let Op::Reduced(reduct) = op
+            && c.thunks.contains(&idx)
+        {
+            c.art.forced.insert(idx, reduct);
+            c.thunks.insert(reduct);
+        }
+        op
+    }
+}
+
+/// only contracts the head redex, leaving the arguments as they are. It stops at a head normal
+/// form, `λx.. y M..`, which even some terms without a normal form have
+pub struct Head;
+
+impl Reductor for Head {
+    fn step(c: &mut Cpu, idx: TermIdx) -> Op {
+        match c.art.arena()[idx.0] {
+            Term::Var(..) | Term::Const(..) => Op::Normal,
+            Term::Abs { .. } => c.step_under(idx, Self::step),
+            Term::App(l, r) => {
+                if let Term::Abs { inner } = c.art.get(l) {
+                    c.stats.beta += 1;
+                    c.last_redex = Some(idx);
+                    return Op::Reduced(c.substitute(inner, r));
+                }
+                if let Some(op) = c.delta(idx) {
+                    return op;
+                }
+                // a primitive is the head only until it's computed, so the numbers it needs
+                // are reduced too
+                for i in c.pending_args(idx) {
+                    match c.step_arg(idx, i, Self::step) {
+                        Op::Normal => (),
+                        op => return op,
+                    }
+                }
                 match Self::step(c, l) {
-                    Op::Reduced(redex_l) => Op::Reduced(c.art.push(Term::App(redex_l, r))),
-                    Op::Normal => Self::step(c, r),
+                    Op::Reduced(redex_l) => Op::Reduced(c.push(Term::App(redex_l, r))),
                     op => op,
                 }
             }
         }
     }
 }
+
+/// the strategies a session can normalize its inputs with
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    #[default]
+    Normal,
+    Applicative,
+    Need,
+}
+
+impl Strategy {
+    pub const ALL: &[Self] = &[Self::Normal, Self::Applicative, Self::Need];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Applicative => "applicative",
+            Self::Need => "need",
+        }
+    }
+
+    /// a step of this strategy's reductor
+    pub fn step(self, c: &mut Cpu, idx: TermIdx) -> Op {
+        match self {
+            Self::Normal => Normal::step(c, idx),
+            Self::Applicative => Applicative::step(c, idx),
+            Self::Need => Need::step(c, idx),
+        }
+    }
+}
+
+impl TryFrom<&str> for Strategy {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|s| s.name() == value)
+            .ok_or(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::arts::{CompArtifact, OuterIdx, Term, TermIdx};
+    use crate::cancel::{CancelToken, Cancelled};
+    use crate::cpu::{Cpu, Need, Normal, Reductor};
+
+    fn var(art: &mut CompArtifact, i: usize) -> TermIdx {
+        art.push(Term::Var(OuterIdx(i)))
+    }
+
+    #[test]
+    pub fn free_vars_survive_substitution() {
+        // λf.(λx.x) f ~> λf.f
+        let mut art = CompArtifact::default();
+        let x = var(&mut art, 0);
+        let id = art.push(Term::Abs { inner: x });
+        let f = var(&mut art, 0);
+        let app = art.push(Term::App(id, f));
+        let root = art.push(Term::Abs { inner: app });
+
+        let mut cpu = Cpu::new(art);
+        let nf = Normal::reduce(&mut cpu, root);
+        let Term::Abs { inner } = cpu.art.get(nf) else {
+            panic!("expected an abstraction");
+        };
+        assert_eq!(cpu.art.get(inner), Term::Var(OuterIdx(0)));
+    }
+
+    #[test]
+    pub fn omega_runs_out_of_budget() {
+        // (λx.x x) (λx.x x)
+        let mut art = CompArtifact::default();
+        let x = var(&mut art, 0);
+        let xx = art.push(Term::App(x, x));
+        let omega = art.push(Term::Abs { inner: xx });
+        let root = art.push(Term::App(omega, omega));
+
+        let mut cpu = Cpu::new(art);
+        let mut token = CancelToken::new().with_budget(100).start();
+        let r = Normal::reduce_until(&mut cpu, root, &mut token);
+        assert_eq!(r, Err(Cancelled::Budget(100)));
+        assert_eq!(cpu.stats.beta, 101);
+    }
+
+    #[test]
+    pub fn reduces_inside_the_argument() {
+        // λf.f ((λx.x) f) ~> λf.f f
+        let mut art = CompArtifact::default();
+        let x = var(&mut art, 0);
+        let id = art.push(Term::Abs { inner: x });
+        let f = var(&mut art, 0);
+        let redex = art.push(Term::App(id, f));
+        let app = art.push(Term::App(f, redex));
+        let root = art.push(Term::Abs { inner: app });
+
+        let mut cpu = Cpu::new(art);
+        let nf = Normal::reduce(&mut cpu, root);
+        let Term::Abs { inner } = cpu.art.get(nf) else {
+            panic!("expected an abstraction");
+        };
+        let Term::App(l, r) = cpu.art.get(inner) else {
+            panic!("expected an application");
+        };
+        assert_eq!(cpu.art.get(l), Term::Var(OuterIdx(0)));
+        assert_eq!(cpu.art.get(r), Term::Var(OuterIdx(0)));
+        assert_eq!(cpu.stats.beta, 1);
+    }
+
+    #[test]
+    pub fn need_forces_an_argument_once() {
+        // (λx.x x) ((λy.y) (λy.y)) ~> λy.y
+        let mut art = CompArtifact::default();
+        let x = var(&mut art, 0);
+        let xx = art.push(Term::App(x, x));
+        let dup = art.push(Term::Abs { inner: xx });
+        let id = art.push(Term::Abs { inner: x });
+        let arg = art.push(Term::App(id, id));
+        let root = art.push(Term::App(dup, arg));
+
+        let mut normal = Cpu::new(art.clone());
+        let nf = Normal::reduce(&mut normal, root);
+        let mut need = Cpu::new(art);
+        let forced = Need::reduce(&mut need, root);
+        assert_eq!(normal.art.get(nf), need.art.get(forced));
+        assert_eq!(normal.stats.beta, 4);
+        assert_eq!(need.stats.beta, 3);
+        assert_eq!(need.stats.forced, 1);
+        assert_eq!(need.art.forced[&arg], id);
+    }
+}
@@ -0,0 +1,972 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use miette::{Diagnostic, LabeledSpan, Severity};
+
+use crate::lexer::{self, Columns};
+
+/// a diagnostic as plain text, which doesn't change with the terminal or with miette's version:
+/// no colors, no wrapping, and only ASCII around the source. The labels point into `src`
+///
+/// ```text
+/// error[ir::undeclared_variable]: undeclared variable
+///  --> 1:1
+///   |
+/// 1 | Z I
+///   | ^ this ident is unknown here
+///   = help: perhaps was a mistyping?
+/// ```
+pub fn render(diag: &dyn Diagnostic, src: &str) -> String {
+    let mut s = String::new();
+    write_diagnostic(&mut s, diag, None, src);
+    s
+}
+
+/// as `render`, with the positions prefixed by the name of `src`, as `--> lists.qk:3:1`
+pub fn render_named(diag: &dyn Diagnostic, name: &str, src: &str) -> String {
+    let mut s = String::new();
+    write_diagnostic(&mut s, diag, Some(name), src);
+    s
+}
+
+/// the source `diag` carries itself, as a definition's, which its spans are of rather than of
+/// the input that reported it
+pub fn own_source(diag: &dyn Diagnostic) -> Option<String> {
+    // an empty span with no end to its context reads the whole source
+    let span = diag
+        .source_code()?
+        .read_span(&(0, 0).into(), 0, usize::MAX)
+        .ok()?;
+    Some(String::from_utf8_lossy(span.data()).into_owned())
+}
+
+fn severity(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Advice => "advice",
+    }
+}
+
+fn write_diagnostic(s: &mut String, diag: &dyn Diagnostic, name: Option<&str>, src: &str) {
+    let severity = severity(diag.severity().unwrap_or_default());
+    match diag.code() {
+        Some(code) => writeln!(s, "{severity}[{code}]: {diag}").unwrap(),
+        None => writeln!(s, "{severity}: {diag}").unwrap(),
+    }
+    let mut labels: Vec<_> = diag.labels().into_iter().flatten().collect();
+    labels.sort_by_key(|l| (l.offset(), l.len()));
+    // the line numbers are right aligned, as wide as the last one
+    let gutter = labels
+        .iter()
+        .filter_map(|l| position(src, l.offset()))
+        .map(|(line, _)| (line + 1).to_string().len())
+        .max()
+        .unwrap_or(1);
+    for label in &labels {
+        write_label(s, label, name, src, gutter);
+    }
+    if let Some(help) = diag.help() {
+        writeln!(s, "{:gutter$} = help: {help}", "").unwrap();
+    }
+    if let Some(url) = diag.url() {
+        writeln!(s, "{:gutter$} = see: {url}", "").unwrap();
+    }
+    for related in diag.related().into_iter().flatten() {
+        write_diagnostic(s, related, name, src);
+    }
+}
+
+fn write_label(s: &mut String, label: &LabeledSpan, name: Option<&str>, src: &str, gutter: usize) {
+    let text = label.label().map_or(String::new(), |t| format!(" {t}"));
+    let Some((line, col)) = position(src, label.offset()) else {
+        // it points out of the source, so there's nothing to show but the label
+        writeln!(s, "{:gutter$}--> ?{text}", "").unwrap();
+        return;
+    };
+    let code = src.lines().nth(line).unwrap_or_default();
+    // up to the end of the line, and a single `^` for empty spans
+    let width = code
+        .char_indices()
+        .skip(col)
+        .take_while(|(i, _)| line_start(src, line) + i < label.offset() + label.len())
+        .count()
+        .max(1);
+    let name = name.map_or(String::new(), |n| format!("{n}:"));
+    writeln!(s, "{:gutter$}--> {name}{}:{}", "", line + 1, col + 1).unwrap();
+    writeln!(s, "{:gutter$} |", "").unwrap();
+    writeln!(s, "{:>gutter$} | {code}", line + 1).unwrap();
+    writeln!(s, "{:gutter$} | {:col$}{}{text}", "", "", "^".repeat(width)).unwrap();
+}
+
+/// the line and the column, in characters, of `offset`. `None` if it's out of `src`
+fn position(src: &str, offset: usize) -> Option<(usize, usize)> {
+    let pos = lexer::position(src, offset, Columns::Chars)?;
+    Some((pos.line, pos.column))
+}
+
+fn line_start(src: &str, line: usize) -> usize {
+    src.match_indices('\n')
+        .nth(line.wrapping_sub(1))
+        .map_or(0, |(i, _)| i + 1)
+}
+
+/// what a diagnostic is reported as, in place of its own severity
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// not reported at all
+    Allow,
+    #[default]
+    Warn,
+    Error,
+}
+
+impl Level {
+    pub const ALL: [Self; 3] = [Self::Allow, Self::Warn, Self::Error];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Warn => "warn",
+            Self::Error => "error",
+        }
+    }
+}
+
+impl TryFrom<&str> for Level {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, ()> {
+        Self::ALL.into_iter().find(|l| l.name() == value).ok_or(())
+    }
+}
+
+/// the levels warnings are reported with: one for every warning, and one for some codes, which
+/// wins over it. Errors are always reported as errors
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Severities {
+    pub warnings: Level,
+    pub codes: HashMap<Box<str>, Level>,
+}
+
+impl Severities {
+    /// parses `<code>=<level>`, or `<code>=default` to drop the override of `code`. `false` if
+    /// it's neither
+    pub fn set(&mut self, code_level: &str) -> bool {
+        let Some((code, level)) = code_level.split_once('=') else {
+            return false;
+        };
+        let code = code.trim();
+        match level.trim() {
+            "default" => {
+                self.codes.remove(code);
+            }
+            level => match Level::try_from(level) {
+                Ok(level) => {
+                    self.codes.insert(code.into(), level);
+                }
+                Err(()) => return false,
+            },
+        }
+        true
+    }
+
+    /// the level `diag` is reported with
+    pub fn level(&self, diag: &dyn Diagnostic) -> Level {
+        if diag.severity().unwrap_or_default() == Severity::Error {
+            return Level::Error;
+        }
+        let code = diag.code().map(|c| c.to_string());
+        code.and_then(|c| self.codes.get(&*c).copied())
+            .unwrap_or(self.warnings)
+    }
+
+    /// `diag`, with the severity its level gives. `None` if it's allowed
+    pub fn apply(&self, diag: Box<dyn Diagnostic + Send + Sync>) -> Option<Leveled> {
+        let severity = match self.level(&*diag) {
+            Level::Allow => return None,
+            Level::Warn => diag.severity().unwrap_or(Severity::Warning),
+            Level::Error => Severity::Error,
+        };
+        Some(Leveled { diag, severity })
+    }
+}
+
+/// a diagnostic reported with another severity
+#[derive(Debug)]
+pub struct Leveled {
+    diag: Box<dyn Diagnostic + Send + Sync>,
+    severity: Severity,
+}
+
+impl std::fmt::Display for Leveled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.diag, f)
+    }
+}
+
+impl std::error::Error for Leveled {}
+
+impl Diagnostic for Leveled {
+    fn code<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        self.diag.code()
+    }
+
+    fn severity(&self) -> Option<Severity> {
+        Some(self.severity)
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        self.diag.help()
+    }
+
+    fn url<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        self.diag.url()
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        self.diag.labels()
+    }
+
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+        self.diag.related()
+    }
+
+    fn diagnostic_source(&self) -> Option<&dyn Diagnostic> {
+        self.diag.diagnostic_source()
+    }
+}
+
+/// what's known about a diagnostic code without an instance of it. `message` and `help` are the
+/// templates they're written from, with the `{field}`s each instance fills in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Entry {
+    pub code: &'static str,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_severity"))]
+    pub severity: Severity,
+    pub message: &'static str,
+    pub help: Option<&'static str>,
+}
+
+impl Entry {
+    /// the entry as `render` would start a diagnostic of it
+    pub fn to_text(&self) -> String {
+        let mut s = format!(
+            "{}[{}]: {}\n",
+            severity(self.severity),
+            self.code,
+            self.message
+        );
+        if let Some(help) = self.help {
+            writeln!(s, "  = help: {help}").unwrap();
+        }
+        s
+    }
+}
+
+/// as `render` writes it, rather than as miette's `Severity` is serialized
+#[cfg(feature = "serde")]
+fn serialize_severity<S: serde::Serializer>(level: &Severity, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(severity(*level))
+}
+
+/// every code of the library, sorted. The REPL has its own
+pub fn catalog() -> &'static [Entry] {
+    CATALOG
+}
+
+/// the entry of `code`, if the library has it
+pub fn explain(code: &str) -> Option<&'static Entry> {
+    CATALOG.iter().find(|e| e.code == code)
+}
+
+const CATALOG: &[Entry] = &[
+    Entry {
+        code: "arts::forward_reference",
+        severity: Severity::Error,
+        message: "term {term} points to term {to}, which isn't before it",
+        help: Some("a term can only point to the ones pushed before it, so the pool has no cycles"),
+    },
+    Entry {
+        code: "arts::open_term",
+        severity: Severity::Error,
+        message: "{name} (term {term}) has a variable bound {needs} λ outside of it",
+        help: Some(
+            "the root and the definitions are closed: every variable is bound by a λ inside them",
+        ),
+    },
+    Entry {
+        code: "arts::unknown_term",
+        severity: Severity::Error,
+        message: "{name} is term {term}, but there are only {len} terms",
+        help: None,
+    },
+    Entry {
+        code: "builder::escaping_term",
+        severity: Severity::Error,
+        message: "term {term} needs {needs} λ around it, but there are only {binders}",
+        help: Some("a term built inside an `abs` can't be used outside of it"),
+    },
+    Entry {
+        code: "builder::unbound_index",
+        severity: Severity::Error,
+        message: "the variable {index} isn't bound, with only {binders} λ around it",
+        help: Some("indices count the λ from the innermost one, starting at 0"),
+    },
+    Entry {
+        code: "builder::unbound_name",
+        severity: Severity::Error,
+        message: "no λ around binds {0:?}",
+        help: None,
+    },
+    Entry {
+        code: "builder::unknown_term",
+        severity: Severity::Error,
+        message: "there's no term {0} in the pool",
+        help: None,
+    },
+    Entry {
+        code: "cancel::budget",
+        severity: Severity::Error,
+        message: "took more than {0} steps",
+        help: Some("maybe it has no normal form? The limit can be changed with `set budget`"),
+    },
+    Entry {
+        code: "cancel::interrupted",
+        severity: Severity::Error,
+        message: "interrupted",
+        help: None,
+    },
+    Entry {
+        code: "cancel::term_size",
+        severity: Severity::Error,
+        message: "the term grew to {size} nodes, over the limit of {max}",
+        help: Some(
+            "some terms keep growing instead of reaching a normal form. The limit can be changed with `set max-term-size`",
+        ),
+    },
+    Entry {
+        code: "cancel::timeout",
+        severity: Severity::Error,
+        message: "took longer than {0:?}",
+        help: Some("maybe it has no normal form? The limit can be changed with `set timeout`"),
+    },
+    Entry {
+        code: "cbpv::stuck",
+        severity: Severity::Error,
+        message: "the computation got stuck at `{at}`",
+        help: Some(
+            "only an abstraction pops an argument, and only a `return` passes a value to `to`",
+        ),
+    },
+    Entry {
+        code: "cbpv::unbound",
+        severity: Severity::Error,
+        message: "unknown variable `{name}`",
+        help: Some("a computation only sees its own binders"),
+    },
+    Entry {
+        code: "cbpv::unsupported",
+        severity: Severity::Error,
+        message: "{what} has no translation to call-by-push-value",
+        help: Some("only variables, abstractions, applications and `let` are translated"),
+    },
+    Entry {
+        code: "commands::duplicated",
+        severity: Severity::Error,
+        message: "there's already a command called {0:?}",
+        help: Some("remove the old one first, or pick another name"),
+    },
+    Entry {
+        code: "commands::plugin",
+        severity: Severity::Error,
+        message: "can't load the plugin: {0}",
+        help: Some(
+            "a plugin is a dynamic library exporting `qk_register`, built with the same compiler",
+        ),
+    },
+    Entry {
+        code: "compiler::pool::undeclared_variable",
+        severity: Severity::Error,
+        message: "undeclared variable",
+        help: Some("perhaps was a mistyping?"),
+    },
+    Entry {
+        code: "cps::unsupported",
+        severity: Severity::Error,
+        message: "{what} can't be written in continuation-passing style",
+        help: Some(
+            "it's lowered to a direct style term, which would be called the CPS way. Write it with abstractions and applications instead",
+        ),
+    },
+    Entry {
+        code: "engine::too_deep",
+        severity: Severity::Error,
+        message: "the closures nested over {0} calls",
+        help: Some(
+            "they run on the native stack. `set backend interpreter` reduces it in the pool instead",
+        ),
+    },
+    Entry {
+        code: "ir::bad_annotation",
+        severity: Severity::Error,
+        message: "{ty:?} isn't a type",
+        help: Some("a type is made of variables, `->`, `*`, `+`, `unit`, `void` and `dyn`"),
+    },
+    Entry {
+        code: "ir::duplicated_definition",
+        severity: Severity::Error,
+        message: "duplicated definition of {name:?}",
+        help: Some(
+            "a name is defined once in an input. A later input defining it again replaces it",
+        ),
+    },
+    Entry {
+        code: "ir::forbidden_expr_placement",
+        severity: Severity::Error,
+        message: "forbidden expression placement",
+        help: Some("if you want to execute this snippet, put inside a `main` entrypoint"),
+    },
+    Entry {
+        code: "ir::missing_operand",
+        severity: Severity::Error,
+        message: "{op:?} is missing an operand",
+        help: Some(
+            "an infix operator goes between two terms. Parenthesis, as `({op})`, take it as a term",
+        ),
+    },
+    Entry {
+        code: "ir::mixed_operators",
+        severity: Severity::Error,
+        message: "{first:?} and {second:?} can't be chained",
+        help: Some(
+            "operators of the same precedence only chain if both are `infixl` or both `infixr`. Add parenthesis",
+        ),
+    },
+    Entry {
+        code: "ir::recursive_definition",
+        severity: Severity::Error,
+        message: "{name:?} is defined in terms of itself: {cycle}",
+        help: Some(
+            "recursion needs a fixed-point combinator. `set recursive-defs y-combinator` inserts one where a definition refers to itself",
+        ),
+    },
+    Entry {
+        code: "ir::undeclared_variable",
+        severity: Severity::Error,
+        message: "undeclared variable",
+        help: Some("perhaps was a mistyping?"),
+    },
+    Entry {
+        code: "ir::undefined",
+        severity: Severity::Error,
+        message: "{name:?} isn't defined",
+        help: Some("`context` lists what's defined"),
+    },
+    Entry {
+        code: "lexer::invalid_char_seq",
+        severity: Severity::Error,
+        message: "invalid char sequence",
+        help: Some("these chars doesn't belong to this code. Haven't you mistyped?"),
+    },
+    Entry {
+        code: "lexer::other_error",
+        severity: Severity::Error,
+        message: "other error",
+        help: Some("this shouldn't happen. contact me"),
+    },
+    Entry {
+        code: "lint::over_application",
+        severity: Severity::Warning,
+        message: "`{name}` is applied to {args} arguments, but it takes {arity}",
+        help: Some(
+            "after {arity} arguments, `{name}` isn't an abstraction anymore. It's fine if what it returns is a function",
+        ),
+    },
+    Entry {
+        code: "lint::shadowed_definition",
+        severity: Severity::Warning,
+        message: "`{name}` is defined again",
+        help: Some(
+            "it was `{previous}`. What already refers to it keeps the old one. `set shadow-warnings off` silences this",
+        ),
+    },
+    Entry {
+        code: "lint::shadowed_variable",
+        severity: Severity::Warning,
+        message: "`{name}` hides an outer `{name}`",
+        help: Some(
+            "inside it, `{name}` only refers to the inner one. Rename one of them if that's not what you meant, or `set warn shadow off`",
+        ),
+    },
+    Entry {
+        code: "lint::unused_variable",
+        severity: Severity::Warning,
+        message: "`{name}` is never used",
+        help: Some(
+            "nothing in its body refers to it, which is often a typo. Start its name with `_` if it's on purpose, or `set warn unused off`",
+        ),
+    },
+    Entry {
+        code: "modules::alias_taken",
+        severity: Severity::Error,
+        message: "{alias:?} already names {path:?}",
+        help: Some("each module needs its own name. Import it `as` another one"),
+    },
+    Entry {
+        code: "modules::clash",
+        severity: Severity::Error,
+        message: "{name:?} is already defined",
+        help: Some("a definition can't have the qualified name of one in a module"),
+    },
+    Entry {
+        code: "parser::no_alternative",
+        severity: Severity::Error,
+        message: "no alternative to parser this snippet",
+        help: Some("the grammar has a choice that can't match anything, as `Parser::or(vec![])`"),
+    },
+    Entry {
+        code: "parser::no_enough_rep",
+        severity: Severity::Error,
+        message: "there was no enough symbols to repeat the sequence",
+        help: Some(
+            "the grammar repeats a pattern that can't match, as `Parser::plus(Parser::or(vec![]))`",
+        ),
+    },
+    Entry {
+        code: "parser::unexpected_eof",
+        severity: Severity::Error,
+        message: "unexpected end of input",
+        help: Some("the expression ended before it was complete"),
+    },
+    Entry {
+        code: "parser::unexpected_token",
+        severity: Severity::Error,
+        message: "unexpected token",
+        help: Some("maybe a missing parenthesis or `=>`?"),
+    },
+    Entry {
+        code: "separate::constants",
+        severity: Severity::Error,
+        message: "the terms have constants",
+        help: Some("Böhm's theorem is about pure λ-terms, so there's no telling a number apart"),
+    },
+    Entry {
+        code: "separate::equivalent",
+        severity: Severity::Error,
+        message: "the normal forms are the same, up to η",
+        help: Some("by Böhm's theorem, only βη-distinct normal forms can be told apart"),
+    },
+    Entry {
+        code: "separate::not_found",
+        severity: Severity::Error,
+        message: "no separating context was found",
+        help: Some("the terms may be too big to search through"),
+    },
+    Entry {
+        code: "session::cast_failed",
+        severity: Severity::Error,
+        message: "`{found}` isn't a function",
+        help: Some("it went through `dyn`, so it's only checked as it's used as a function"),
+    },
+    Entry {
+        code: "session::effect",
+        severity: Severity::Error,
+        message: "effects can't be stepped here",
+        help: Some("the reduction asked for an effect, which only a runner can perform"),
+    },
+    Entry {
+        code: "system_f::mismatch",
+        severity: Severity::Error,
+        message: "mismatched types",
+        help: Some("expected `{expected}`, but found `{found}`"),
+    },
+    Entry {
+        code: "system_f::not_a_function",
+        severity: Severity::Error,
+        message: "this isn't a function",
+        help: Some("it has type `{ty}`, and only arrows take an argument"),
+    },
+    Entry {
+        code: "system_f::not_polymorphic",
+        severity: Severity::Error,
+        message: "this isn't polymorphic",
+        help: Some("it has type `{ty}`, and only `∀` types take a type"),
+    },
+    Entry {
+        code: "system_f::shadowed_type",
+        severity: Severity::Error,
+        message: "the type variable `{name}` is already bound",
+        help: Some("types would mix up the two, so give this one another name"),
+    },
+    Entry {
+        code: "system_f::unbound",
+        severity: Severity::Error,
+        message: "unknown variable `{name}`",
+        help: Some("a System F term only sees its binders, and what was defined in `system-f`"),
+    },
+    Entry {
+        code: "system_f::unbound_type",
+        severity: Severity::Error,
+        message: "unknown type variable `{name}`",
+        help: Some("a type variable must be bound by a `Λ` or a `∀` around it"),
+    },
+    Entry {
+        code: "types::dyn_annotation",
+        severity: Severity::Error,
+        message: "`dyn` is only known to the gradual mode",
+        help: Some("`set typed gradual` allows it, checking the values when the term runs"),
+    },
+    Entry {
+        code: "types::lowered_mismatch",
+        severity: Severity::Error,
+        message: "mismatched types in this {sugar}",
+        help: Some("{}: expected `{l}`, but found `{r}`"),
+    },
+    Entry {
+        code: "types::mismatch",
+        severity: Severity::Error,
+        message: "mismatched types",
+        help: Some("expected `{l}`, but found `{r}`"),
+    },
+    Entry {
+        code: "types::no_inhabitant",
+        severity: Severity::Error,
+        message: "no inhabitant of {ty} was found",
+        help: Some("either the type is empty or its terms are bigger than the search bound"),
+    },
+    Entry {
+        code: "types::not_typable",
+        severity: Severity::Error,
+        message: "this term has no simple type",
+        help: Some(
+            "`{l}` would need to be the same as `{r}`. Self-application, like `x x`, is the usual suspect",
+        ),
+    },
+    Entry {
+        code: "types::signature_mismatch",
+        severity: Severity::Error,
+        message: "`{name}` doesn't match its signature",
+        help: Some("it was declared as `{declared}`, but its most general type is `{inferred}`"),
+    },
+    Entry {
+        code: "types::undefined_signature",
+        severity: Severity::Error,
+        message: "`{name}` is declared, but never defined",
+        help: None,
+    },
+];
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::BTreeSet;
+    use std::path::Path;
+    use std::time::Duration;
+
+    use miette::{Diagnostic, Severity};
+
+    use crate::arts::{CompArtifact, OuterIdx, Term, TermIdx};
+    use crate::builder::PoolBuilder;
+    use crate::cancel::Cancelled;
+    use crate::cbpv::Order;
+    use crate::diagnostics::{Level, Severities, catalog, explain, render};
+    use crate::engine::Backend;
+    use crate::infix::{Assoc, Fixity};
+    use crate::padam::Token;
+    use crate::padam::lexer::Lexer;
+    use crate::padam::parser::{NonTerminals, Parser};
+    use crate::session::commands::{self, CommandRegistry, UserCommand};
+    use crate::session::{self, Language, Session, modules};
+    use crate::types::{Type, synth};
+    use crate::{compiler, ir, lexer, padam, separate};
+
+    type Case = (&'static str, Box<dyn Diagnostic>);
+
+    const GOLDEN: &str = "tests/golden/diagnostics.txt";
+
+    fn session(prelude: bool, typed: bool) -> Session {
+        let mut s = Session::new();
+        s.set_prelude(prelude).unwrap();
+        s.set_typed(typed).unwrap();
+        s
+    }
+
+    fn eval(s: &mut Session, src: &'static str) -> Case {
+        (src, Box::new(s.eval_str(src).unwrap_err()))
+    }
+
+    /// how a grammar with `pattern` in it fails on `x`
+    fn malformed<T: 'static>(pattern: Parser<T>) -> padam::parser::Error {
+        let lexer = Lexer::default();
+        let tokens = lexer.lex("x").unwrap();
+        let nt = NonTerminals::new();
+        pattern.parse(&nt, &lexer, &tokens).err().unwrap()
+    }
+
+    /// an input for each error code, or the error itself when no input gets to it
+    fn cases() -> Vec<Case> {
+        let mut untyped = session(false, false);
+        let mut prelude = session(true, false);
+        let mut typed = session(true, true);
+        let mut gradual = session(false, true);
+        gradual.gradual = true;
+        gradual.irc.primitives = true;
+        let mut budget = session(true, false);
+        budget.cancel.budget = Some(10);
+        let mut sized = session(false, false);
+        sized.cancel.max_size = Some(20);
+        let mut closures = session(true, false);
+        closures.backend = Backend::Closures;
+        let mut cps = session(true, false);
+        cps.cps = true;
+        let mut system_f = session(false, false);
+        system_f.language = Language::SystemF;
+        let mut infix = session(false, false);
+        for (op, assoc, prec) in [("*", Assoc::Left, 7), ("==", Assoc::None, 4)] {
+            infix
+                .irc
+                .operators
+                .insert(op.into(), Fixity { assoc, prec });
+        }
+        let mut registry = CommandRegistry::default();
+        let nothing = |_: &mut Session, _: &str| Ok(String::new());
+        registry
+            .register(UserCommand::new("a", "", nothing))
+            .unwrap();
+        let mut art = CompArtifact::default();
+        let mut b = PoolBuilder::new(&mut art);
+        let mut escaped = TermIdx(0);
+        b.abs(|b| {
+            escaped = b.var(0)?;
+            Ok(escaped)
+        })
+        .unwrap();
+        let builder: Vec<Case> = vec![
+            ("", Box::new(b.var(0).unwrap_err())),
+            ("", Box::new(b.named("x").unwrap_err())),
+            ("", Box::new(b.app(TermIdx(100), TermIdx(100)).unwrap_err())),
+            ("", Box::new(b.app(escaped, escaped).unwrap_err())),
+        ];
+        let mut cases: Vec<Case> = vec![
+            eval(&mut untyped, "x $ y"),
+            ("", Box::new(lexer::Error::Other)),
+            eval(&mut untyped, "fn x => )"),
+            eval(&mut untyped, "fn x =>"),
+            ("x", Box::new(malformed(Parser::<Token>::or(Vec::new())))),
+            (
+                "x",
+                Box::new(malformed(Parser::plus(Parser::<Token>::or(Vec::new())))),
+            ),
+            eval(&mut untyped, "Z I"),
+            (
+                "x",
+                Box::new(ir::Error::ForbiddenExprPlacement { at: (0, 1).into() }),
+            ),
+            eval(&mut untyped, "fn (x : a ->) => x"),
+            eval(&mut untyped, "I x = x\nI y = y"),
+            eval(&mut untyped, "even = odd\nodd = even"),
+            ("", Box::new(untyped.undefine("nothing").unwrap_err())),
+            eval(&mut infix, "x + y *"),
+            eval(&mut infix, "x == y == z"),
+            (
+                "x y",
+                Box::new(compiler::Error::UndeclaredVariable { at: (2, 1).into() }),
+            ),
+            eval(&mut typed, "fn x => x x"),
+            eval(&mut typed, "fst (fn x => x)"),
+            eval(&mut typed, "if fst then I else K"),
+            eval(&mut typed, "fn (x : dyn) => x"),
+            eval(&mut gradual, "(fn (f : dyn) => f 1) 3"),
+            (
+                "a -> b",
+                Box::new(typed.declare("I", "a -> b").unwrap_err()),
+            ),
+            ("a", Box::new(typed.declare("nope", "a").unwrap_err())),
+            (
+                "",
+                Box::new(synth::one(&Type::parse("a").unwrap()).unwrap_err()),
+            ),
+            ("", Box::new(Cancelled::Interrupted)),
+            ("", Box::new(Cancelled::Timeout(Duration::from_millis(100)))),
+            eval(&mut budget, "Omega"),
+            eval(&mut sized, "(fn x => x x x) (fn x => x x x)"),
+            eval(&mut closures, "Omega"),
+            eval(&mut cps, "(fn (a, b) => a) (I, I)"),
+            eval(&mut system_f, "λx:a. x"),
+            eval(&mut system_f, "Λa. λx:a. y"),
+            eval(&mut system_f, "Λa. Λa. λx:a. x"),
+            eval(&mut system_f, "Λa. λx:a. x x"),
+            eval(&mut system_f, "Λa. λx:a. x [a]"),
+            eval(&mut system_f, "Λa. Λb. λx:a. λy:b. (λz:a. z) y"),
+            (
+                "λx. force y",
+                Box::new(untyped.cbpv("λx. force y").unwrap_err()),
+            ),
+            (
+                "return thunk (λx. return x) thunk (λy. return y)",
+                Box::new(
+                    untyped
+                        .cbpv("return thunk (λx. return x) thunk (λy. return y)")
+                        .unwrap_err(),
+                ),
+            ),
+            (
+                "fn (a, b) => a",
+                Box::new(
+                    untyped
+                        .cbpv_translate(Order::ByName, "fn (a, b) => a")
+                        .unwrap_err(),
+                ),
+            ),
+            (
+                "",
+                Box::new(prelude.separate("fn x => x", "fn y => y").unwrap_err()),
+            ),
+            ("", Box::new(separate::Error::NotFound)),
+            ("", Box::new(separate::Error::Constants)),
+            ("", Box::new(session::Error::Effect)),
+            (
+                "",
+                Box::new(modules::Error::AliasTaken {
+                    alias: "L".into(),
+                    path: "lists.qk".into(),
+                }),
+            ),
+            (
+                "",
+                Box::new(modules::Error::Clash {
+                    name: "L.map".into(),
+                }),
+            ),
+            (
+                "a",
+                Box::new(
+                    registry
+                        .register(UserCommand::new("a", "", nothing))
+                        .unwrap_err(),
+                ),
+            ),
+            (
+                "",
+                Box::new(commands::Error::Plugin("libfoo.so: no such file".into())),
+            ),
+        ];
+        let mut bad = CompArtifact::default();
+        let x = bad.push(Term::Var(OuterIdx(0)));
+        bad.push(Term::Abs { inner: TermIdx(1) });
+        bad.root = Some(x);
+        bad.obj_cache.insert(ir::Id(0), TermIdx(7));
+        cases.extend(
+            bad.validate()
+                .into_iter()
+                .map(|e| -> Case { ("", Box::new(e)) }),
+        );
+        let out = prelude.eval_str("K I I I").unwrap();
+        cases.push(("K I I I", Box::new(out.warnings[0].clone())));
+        let out = prelude.eval_str("true = fn x y => x").unwrap();
+        cases.push(("true = fn x y => x", Box::new(out.warnings[0].clone())));
+        let out = prelude.eval_str("fn x y => x").unwrap();
+        cases.push(("fn x y => x", Box::new(out.warnings[0].clone())));
+        let out = prelude.eval_str("fn x => fn x => x").unwrap();
+        cases.push(("fn x => fn x => x", Box::new(out.warnings[1].clone())));
+        cases.extend(builder);
+        cases
+    }
+
+    /// every `code(...)` of the library, but the REPL's
+    fn codes(dir: &Path, found: &mut BTreeSet<String>) {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.ends_with("repl") {
+                continue;
+            } else if path.is_dir() {
+                codes(&path, found);
+            } else if path.extension().is_some_and(|e| e == "rs") {
+                let src = std::fs::read_to_string(&path).unwrap();
+                for (i, _) in src.match_indices("code(") {
+                    let code: String = src[i + 5..]
+                        .chars()
+                        .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == ':')
+                        .collect();
+                    if code.contains("::") && src[i + 5 + code.len()..].starts_with(')') {
+                        found.insert(code);
+                    }
+                }
+            }
+        }
+    }
+
+    /// the rendered diagnostics are compared to `GOLDEN`. `QK_BLESS=1` writes them there instead
+    #[test]
+    pub fn golden() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut rendered = String::new();
+        let mut covered = BTreeSet::new();
+        for (src, diag) in cases() {
+            covered.insert(diag.code().unwrap().to_string());
+            rendered.push_str(&format!("=== {src:?}\n{}\n", render(&*diag, src)));
+        }
+        let mut all = BTreeSet::new();
+        codes(&root.join("src"), &mut all);
+        let missing: Vec<_> = all.difference(&covered).collect();
+        assert!(missing.is_empty(), "no snapshot of {missing:?}");
+
+        let path = root.join(GOLDEN);
+        if std::env::var_os("QK_BLESS").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, rendered).unwrap();
+            return;
+        }
+        let golden = std::fs::read_to_string(&path).unwrap_or_default();
+        for (old, new) in golden.lines().zip(rendered.lines()) {
+            assert_eq!(old, new, "the diagnostics changed. `QK_BLESS=1` accepts it");
+        }
+        assert_eq!(golden.lines().count(), rendered.lines().count());
+    }
+
+    /// the catalog has every code, with the severity and the help their diagnostics have
+    #[test]
+    pub fn catalog_is_complete() {
+        let mut all = BTreeSet::new();
+        codes(&Path::new(env!("CARGO_MANIFEST_DIR")).join("src"), &mut all);
+        let listed: Vec<_> = catalog().iter().map(|e| e.code.to_string()).collect();
+        assert!(listed.is_sorted());
+        assert_eq!(listed.into_iter().collect::<BTreeSet<_>>(), all);
+        for (_, diag) in cases() {
+            let entry = explain(&diag.code().unwrap().to_string()).unwrap();
+            assert_eq!(
+                Some(entry.severity),
+                diag.severity().or(Some(Severity::Error))
+            );
+            if let Some(help) = entry.help.filter(|h| !h.contains('{')) {
+                assert_eq!(diag.help().map(|h| h.to_string()).as_deref(), Some(help));
+            }
+        }
+    }
+
+    #[test]
+    pub fn severity_overrides() {
+        let mut prelude = session(true, false);
+        let over = prelude.eval_str("K I I I").unwrap().warnings[0].clone();
+        let warning = || -> Box<dyn Diagnostic + Send + Sync> { Box::new(over.clone()) };
+        let mut severities = Severities::default();
+        let severity = |s: &Severities, d| s.apply(d).map(|l| l.severity());
+        assert_eq!(
+            severity(&severities, warning()),
+            Some(Some(Severity::Warning))
+        );
+        severities.warnings = Level::Error;
+        assert_eq!(
+            severity(&severities, warning()),
+            Some(Some(Severity::Error))
+        );
+        assert!(severities.set("lint::over_application=allow"));
+        assert_eq!(severity(&severities, warning()), None);
+        assert!(!severities.set("lint::over_application"));
+        // errors stay errors
+        assert!(severities.set("cancel::budget=allow"));
+        let budget = Box::new(Cancelled::Budget(1));
+        assert_eq!(severity(&severities, budget), Some(Some(Severity::Error)));
+    }
+}
@@ -0,0 +1,390 @@
+use std::cell::{Cell, OnceCell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::arts::{CompArtifact, Const, OuterIdx, Prim, Term, TermIdx};
+use crate::cancel::{CancelToken, Cancelled};
+use crate::cpu::Stats;
+
+/// how deep the closures may call each other. They run on the native stack, which is much
+/// smaller than the interpreters' arenas
+pub const MAX_DEPTH: usize = 1_000;
+
+#[derive(Error, Diagnostic, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Cancelled(#[from] Cancelled),
+
+    #[error("the closures nested over {0} calls")]
+    #[diagnostic(
+        code(engine::too_deep),
+        help(
+            "they run on the native stack. `set backend interpreter` reduces it in the pool instead"
+        )
+    )]
+    TooDeep(usize),
+
+    /// the cast of index `cast` in `CompArtifact::casts` found `found`, which isn't a function.
+    /// The session tells where it was written
+    #[error("`{found}` isn't a function")]
+    Blame { cast: u32, found: String },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// what reduces the inputs to their normal forms
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// a `cpu::Strategy` rewriting the pool, step by step
+    #[default]
+    Interpreter,
+    /// `closures`: the pool compiled to Rust closures and run at once
+    Closures,
+    /// `jit`: the lambda lifted program compiled to native code, when it only computes a number.
+    /// Anything else is left to the interpreter
+    #[cfg(feature = "jit")]
+    Jit,
+}
+
+impl Backend {
+    pub const ALL: &[Self] = &[
+        Self::Interpreter,
+        Self::Closures,
+        #[cfg(feature = "jit")]
+        Self::Jit,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Interpreter => "interpreter",
+            Self::Closures => "closures",
+            #[cfg(feature = "jit")]
+            Self::Jit => "jit",
+        }
+    }
+}
+
+impl TryFrom<&str> for Backend {
+    type Error = ();
+
+    fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|b| b.name() == value)
+            .ok_or(())
+    }
+}
+
+/// an argument, computed the first time it's needed
+pub struct Thunk {
+    value: OnceCell<Value>,
+    delayed: Cell<Option<(Code, Env)>>,
+}
+
+type Arg = Rc<Thunk>;
+
+type Code = Rc<dyn Fn(&Env, &Ctx) -> Result<Value>>;
+
+type Fun = Rc<dyn Fn(Arg, &Ctx) -> Result<Value>>;
+
+#[derive(Clone)]
+pub enum Value {
+    Fun(Fun),
+    Int(i64),
+    /// something that can't be applied: a variable of the readback, by its level, or a constant,
+    /// with its arguments
+    Neutral(Head, Vec<Arg>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Head {
+    Var(usize),
+    Const(Const),
+}
+
+/// the arguments in scope, the innermost first
+#[derive(Clone, Default)]
+pub struct Env(Option<Rc<(Arg, Env)>>);
+
+impl Env {
+    fn push(&self, arg: Arg) -> Self {
+        Self(Some(Rc::new((arg, self.clone()))))
+    }
+
+    fn get(&self, mut idx: usize) -> &Arg {
+        let mut env = self;
+        loop {
+            let (arg, outer) = &**env.0.as_ref().expect("the term is closed");
+            if idx == 0 {
+                return arg;
+            }
+            (env, idx) = (outer, idx - 1);
+        }
+    }
+}
+
+/// what every closure shares while running
+pub struct Ctx {
+    token: RefCell<CancelToken>,
+    depth: Cell<usize>,
+    /// how many abstractions were applied
+    pub beta: Cell<usize>,
+    /// how many primitives were computed
+    pub delta: Cell<usize>,
+}
+
+impl Ctx {
+    pub fn new(token: CancelToken) -> Self {
+        Self {
+            token: RefCell::new(token),
+            depth: Cell::new(0),
+            beta: Cell::new(0),
+            delta: Cell::new(0),
+        }
+    }
+
+    /// runs `f` a call deeper
+    fn nested<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        if self.depth.get() >= MAX_DEPTH {
+            return Err(Error::TooDeep(MAX_DEPTH));
+        }
+        self.depth.set(self.depth.get() + 1);
+        let r = f();
+        self.depth.set(self.depth.get() - 1);
+        r
+    }
+}
+
+impl Thunk {
+    fn value(value: Value) -> Arg {
+        Rc::new(Self {
+            value: OnceCell::from(value),
+            delayed: Cell::new(None),
+        })
+    }
+
+    fn delayed(code: Code, env: Env) -> Arg {
+        Rc::new(Self {
+            value: OnceCell::new(),
+            delayed: Cell::new(Some((code, env))),
+        })
+    }
+
+    fn force(&self, ctx: &Ctx) -> Result<Value> {
+        if let Some(v) = self.value.get() {
+            return Ok(v.clone());
+        }
+        let (code, env) = self
+            .delayed
+            .take()
+            .expect("a thunk can't need itself without recursion");
+        let v = ctx.nested(|| code(&env, ctx))?;
+        Ok(self.value.get_or_init(|| v).clone())
+    }
+}
+
+/// compiles the terms of a pool to closures, once for each term even if it's shared
+#[derive(Default)]
+pub struct Compiler {
+    compiled: HashMap<TermIdx, Code>,
+}
+
+impl Compiler {
+    pub fn compile(&mut self, art: &CompArtifact, root: TermIdx) -> Code {
+        // the arena is in order, so the children are compiled first
+        for t in art.reachable(&[root]) {
+            if self.compiled.contains_key(&t) {
+                continue;
+            }
+            let code: Code = match art.get(t) {
+                Term::Var(v) => Rc::new(move |env, ctx| env.get(v.0).force(ctx)),
+                Term::Const(Const::Int(n)) => Rc::new(move |_, _| Ok(Value::Int(n))),
+                Term::Const(c) => {
+                    Rc::new(move |_, _| Ok(Value::Neutral(Head::Const(c), Vec::new())))
+                }
+                Term::Abs { inner } => {
+                    let body = self.compiled[&inner].clone();
+                    Rc::new(move |env, _| {
+                        let (body, env) = (body.clone(), env.clone());
+                        Ok(Value::Fun(Rc::new(move |arg, ctx| {
+                            body(&env.push(arg), ctx)
+                        })))
+                    })
+                }
+                Term::App(l, r) => {
+                    let l = self.compiled[&l].clone();
+                    match art.get(r) {
+                        // no need for a thunk of a thunk
+                        Term::Var(v) => {
+                            Rc::new(move |env, ctx| apply(l(env, ctx)?, env.get(v.0).clone(), ctx))
+                        }
+                        _ => {
+                            let r = self.compiled[&r].clone();
+                            Rc::new(move |env, ctx| {
+                                let arg = Thunk::delayed(r.clone(), env.clone());
+                                apply(l(env, ctx)?, arg, ctx)
+                            })
+                        }
+                    }
+                }
+            };
+            self.compiled.insert(t, code);
+        }
+        self.compiled[&root].clone()
+    }
+}
+
+fn apply(f: Value, arg: Arg, ctx: &Ctx) -> Result<Value> {
+    match f {
+        Value::Fun(f) => {
+            ctx.token.borrow_mut().tick()?;
+            ctx.beta.set(ctx.beta.get() + 1);
+            ctx.nested(|| f(arg, ctx))
+        }
+        Value::Int(n) => Ok(Value::Neutral(Head::Const(Const::Int(n)), vec![arg])),
+        Value::Neutral(head, mut args) => {
+            args.push(arg);
+            match head {
+                Head::Const(Const::Prim(p)) if args.len() == p.arity() => compute(p, args, ctx),
+                _ => Ok(Value::Neutral(head, args)),
+            }
+        }
+    }
+}
+
+/// `p` on `args`, or `p` stuck on them if the ones it needs aren't numbers
+fn compute(p: Prim, args: Vec<Arg>, ctx: &Ctx) -> Result<Value> {
+    if let Prim::Cast(cast) = p {
+        return match args[0].force(ctx)? {
+            Value::Int(n) | Value::Neutral(Head::Const(Const::Int(n)), _) => {
+                let found = n.to_string();
+                Err(Error::Blame { cast, found })
+            }
+            // stuck on a variable, so it's left for the readback to drop
+            Value::Neutral(Head::Var(_), _) => {
+                Ok(Value::Neutral(Head::Const(Const::Prim(p)), args))
+            }
+            value => {
+                ctx.delta.set(ctx.delta.get() + 1);
+                Ok(value)
+            }
+        };
+    }
+    let strict = p.strict();
+    let mut ints = Vec::with_capacity(strict);
+    for arg in &args[..strict] {
+        match arg.force(ctx)? {
+            Value::Int(n) => ints.push(n),
+            _ => return Ok(Value::Neutral(Head::Const(Const::Prim(p)), args)),
+        }
+    }
+    ctx.token.borrow_mut().tick()?;
+    ctx.delta.set(ctx.delta.get() + 1);
+    match p {
+        Prim::Add => Ok(Value::Int(ints[0].wrapping_add(ints[1]))),
+        Prim::Sub => Ok(Value::Int(ints[0].wrapping_sub(ints[1]))),
+        Prim::Mul => Ok(Value::Int(ints[0].wrapping_mul(ints[1]))),
+        Prim::If0 if ints[0] == 0 => args[1].force(ctx),
+        Prim::If0 => args[2].force(ctx),
+        Prim::Cast(_) => unreachable!("casts are checked before"),
+    }
+}
+
+/// the normal form of `value` in `art`, `level` abstractions deep. Functions are applied to a
+/// fresh variable, and read back under a new abstraction
+fn quote(art: &mut CompArtifact, value: Value, level: usize, ctx: &Ctx) -> Result<TermIdx> {
+    ctx.nested(|| match value {
+        Value::Fun(f) => {
+            let var = Thunk::value(Value::Neutral(Head::Var(level), Vec::new()));
+            let body = f(var, ctx)?;
+            let inner = quote(art, body, level + 1, ctx)?;
+            Ok(art.push(Term::Abs { inner }))
+        }
+        Value::Int(n) => Ok(art.push(Term::Const(Const::Int(n)))),
+        // a cast stuck on a variable says nothing about its value
+        Value::Neutral(Head::Const(Const::Prim(Prim::Cast(_))), mut args) if args.len() == 1 => {
+            let arg = args.pop().expect("it has an argument");
+            quote(art, arg.force(ctx)?, level, ctx)
+        }
+        Value::Neutral(head, args) => {
+            let mut t = match head {
+                Head::Var(l) => art.push(Term::Var(OuterIdx(level - l - 1))),
+                Head::Const(c) => art.push(Term::Const(c)),
+            };
+            for arg in args {
+                let arg = quote(art, arg.force(ctx)?, level, ctx)?;
+                t = art.push(Term::App(t, arg));
+            }
+            Ok(t)
+        }
+    })
+}
+
+/// the normal form of the closed term `root`, compiled to closures and read back into `art`,
+/// and what it cost. Only the β and δ-steps are counted. Each of them spends a step of `token`
+pub fn normalize(
+    art: &mut CompArtifact,
+    root: TermIdx,
+    token: &CancelToken,
+) -> Result<(TermIdx, Stats)> {
+    let code = Compiler::default().compile(art, root);
+    let ctx = Ctx::new(token.clone());
+    let value = code(&Env::default(), &ctx)?;
+    let nf = quote(art, value, 0, &ctx)?;
+    let stats = Stats {
+        beta: ctx.beta.get(),
+        delta: ctx.delta.get(),
+        ..Default::default()
+    };
+    Ok((nf, stats))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::backend::{DeBruijn, Renderer};
+    use crate::cancel::{CancelToken, Cancelled};
+    use crate::engine::{self, Backend};
+    use crate::session::{Output, Session};
+
+    #[test]
+    pub fn agrees_with_the_interpreter() {
+        let mut s = Session::new();
+        s.set_prelude(true).unwrap();
+        s.irc.primitives = true;
+        for src in [
+            "(fn f x => f (f x)) (fn f x => f (f x))",
+            "K I Omega",
+            "fn y => (fn x z => x) y",
+            "if0 (- 2 2) (+ 1 2) Omega",
+            "+ K 1",
+        ] {
+            let out = s.eval_str(src).unwrap();
+            let Output::Normal(expected) = out.output else {
+                unreachable!("{src} is an expression")
+            };
+            let root = out.compiled.unwrap();
+            let (nf, _) = engine::normalize(&mut s.art, root, &CancelToken::new()).unwrap();
+            let debruijn = |t| DeBruijn.render(&s.art, t, &Default::default());
+            assert_eq!(debruijn(nf), debruijn(expected), "{src}");
+        }
+
+        s.backend = Backend::Closures;
+        s.bench = crate::session::BENCH_SETTING
+            .parse_inspired("eval")
+            .unwrap();
+        let out = s
+            .eval_str("(fn f x => f (f x)) (fn f x => f (f x))")
+            .unwrap();
+        assert_eq!(out.stats.unwrap().beta, 5);
+        s.cancel.budget = Some(10);
+        assert!(matches!(
+            s.eval_str("Omega"),
+            Err(crate::session::Error::Cancelled(Cancelled::Budget(10)))
+        ));
+    }
+}
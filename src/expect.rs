@@ -0,0 +1,234 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use miette::Diagnostic;
+
+use crate::analyze::collect_files;
+use crate::session::{self, EvalOutput, Output, Session};
+
+/// starts a line telling what the line run before it should give
+pub const EXPECT: &str = "-- expect:";
+
+/// the steps each line may take, so a divergent one fails instead of hanging the suite
+pub const BUDGET: usize = 1_000_000;
+
+/// what an `-- expect:` annotation asks for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expectation {
+    /// `-- expect: <expr>`, a normal form α-equivalent to the one of `expr`
+    Normal(Box<str>),
+    /// `-- expect: defined`, for a program
+    Defined,
+    /// `-- expect: error <code>`
+    Error(Box<str>),
+    /// `-- expect: warning <code>`, which the line may have along with others
+    Warning(Box<str>),
+}
+
+impl Expectation {
+    pub fn parse(annotation: &str) -> Self {
+        let annotation = annotation.trim();
+        let code = |prefix| {
+            annotation
+                .strip_prefix(prefix)
+                .map(|c: &str| c.trim().into())
+        };
+        if annotation == "defined" {
+            Self::Defined
+        } else if let Some(code) = code("error ") {
+            Self::Error(code)
+        } else if let Some(code) = code("warning ") {
+            Self::Warning(code)
+        } else {
+            Self::Normal(annotation.into())
+        }
+    }
+}
+
+/// an annotation, with the line it's about
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case {
+    pub path: PathBuf,
+    /// of the annotation, counted from 1
+    pub line: usize,
+    pub src: Box<str>,
+    pub expected: Box<str>,
+    /// what the line gave, when it's not what was expected
+    pub failure: Option<String>,
+}
+
+/// the annotations of every `.qk` file of a directory, checked
+#[derive(Debug, Default, Clone)]
+pub struct Suite {
+    pub cases: Vec<Case>,
+    /// the files with annotations
+    pub files: usize,
+}
+
+impl Suite {
+    /// runs every `.qk` file inside `dir` with an annotation, recursively
+    pub fn from_dir(dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        let mut paths = Vec::new();
+        collect_files(dir.as_ref(), &mut paths)?;
+        paths.sort();
+        let mut suite = Self::default();
+        for path in paths {
+            let src = std::fs::read_to_string(&path)?;
+            if src.lines().any(|l| l.trim_start().starts_with(EXPECT)) {
+                suite.push(path, &src);
+            }
+        }
+        Ok(suite)
+    }
+
+    /// runs a file a line at a time, on a new session, checking each annotation against the last
+    /// line run before it
+    pub fn push(&mut self, path: PathBuf, src: &str) {
+        self.files += 1;
+        let mut s = Session::new();
+        s.cancel.budget = Some(BUDGET);
+        let mut last: Option<(&str, session::Result<EvalOutput>)> = None;
+        for (line, l) in src.lines().enumerate() {
+            let trimmed = l.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let Some(annotation) = trimmed.strip_prefix(EXPECT) else {
+                last = Some((l, s.eval_str(l)));
+                continue;
+            };
+            let (run, failure) = match &last {
+                Some((run, result)) => (
+                    *run,
+                    failure(&mut s, result, &Expectation::parse(annotation)),
+                ),
+                None => ("", Some("nothing was run before it".to_string())),
+            };
+            self.cases.push(Case {
+                path: path.clone(),
+                line: line + 1,
+                src: run.into(),
+                expected: annotation.trim().into(),
+                failure,
+            });
+        }
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &Case> {
+        self.cases.iter().filter(|c| c.failure.is_some())
+    }
+
+    /// each failure with what was expected and what was got, then the totals
+    pub fn to_text(&self) -> String {
+        let mut s = String::new();
+        for case in self.failed() {
+            let _ = writeln!(
+                s,
+                "FAIL {}:{}: {}",
+                case.path.display(),
+                case.line,
+                case.src
+            );
+            let _ = writeln!(s, "  - {}", case.expected);
+            let _ = writeln!(s, "  + {}", case.failure.as_deref().unwrap_or_default());
+        }
+        let failed = self.failed().count();
+        let _ = writeln!(
+            s,
+            "{} passed, {failed} failed, in {} files",
+            self.cases.len() - failed,
+            self.files
+        );
+        s
+    }
+}
+
+/// what `result` is, when it isn't what's `expected`
+fn failure(
+    s: &mut Session,
+    result: &session::Result<EvalOutput>,
+    expected: &Expectation,
+) -> Option<String> {
+    let code = |e: &dyn Diagnostic| e.code().map_or_else(|| "unknown".into(), |c| c.to_string());
+    let out = match (result, expected) {
+        (Err(e), Expectation::Error(c)) if code(e) == **c => return None,
+        (Err(e), _) => return Some(format!("error {}", code(e))),
+        (Ok(out), _) => out,
+    };
+    let got = match out.output {
+        Output::Normal(nf) => s.art.pretty(nf, &s.irc.scope.get_aliases()),
+        _ => "defined".to_string(),
+    };
+    match (expected, out.output) {
+        (Expectation::Defined, Output::Normal(_)) => Some(got),
+        (Expectation::Defined, _) => None,
+        (Expectation::Error(_), _) => Some(got),
+        (Expectation::Warning(c), _) => {
+            let codes: Vec<_> = out.warnings.iter().map(|w| code(w)).collect();
+            match codes.iter().any(|w| w == &**c) {
+                true => None,
+                false if codes.is_empty() => Some(format!("{got}, without warnings")),
+                false => Some(format!("{got}, with warning {}", codes.join(", "))),
+            }
+        }
+        (Expectation::Normal(expr), Output::Normal(nf)) => match s.eval_str(expr) {
+            Ok(EvalOutput {
+                output: Output::Normal(expected),
+                ..
+            }) if s.art.alpha_eq(nf, expected) => None,
+            Ok(_) => Some(got),
+            Err(e) => Some(format!("{got}, as the expectation gave error {}", code(&e))),
+        },
+        (Expectation::Normal(_), _) => Some(got),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::expect::{Expectation, Suite};
+
+    #[test]
+    pub fn parses_annotations() {
+        assert_eq!(Expectation::parse(" defined"), Expectation::Defined);
+        assert_eq!(
+            Expectation::parse("error ir::undeclared_variable"),
+            Expectation::Error("ir::undeclared_variable".into())
+        );
+        assert_eq!(
+            Expectation::parse("fn x => x"),
+            Expectation::Normal("fn x => x".into())
+        );
+    }
+
+    #[test]
+    pub fn checks_a_file() {
+        let mut suite = Suite::default();
+        suite.push(
+            "a.qk".into(),
+            "I x = x\n-- expect: defined\nK x y = x\n\
+             K I I\n-- expect: fn a => a\n-- expect: I I\n\
+             K I\n-- expect: K\n\
+             y\n-- expect: error ir::undeclared_variable\n\
+             -- expect: y\n\
+             (fn x => x x) (fn x => x x)\n-- expect: error cancel::budget\n",
+        );
+        let failed: Vec<_> = suite.failed().map(|c| c.line).collect();
+        assert_eq!(failed, [8, 11]);
+        assert_eq!(suite.cases[3].failure.as_deref(), Some("λn.I"));
+        assert_eq!(
+            suite.cases[5].failure.as_deref(),
+            Some("error ir::undeclared_variable")
+        );
+        assert!(
+            suite
+                .to_text()
+                .ends_with("5 passed, 2 failed, in 1 files\n")
+        );
+    }
+
+    #[test]
+    pub fn runs_the_suite() {
+        let suite = Suite::from_dir("tests/suite").unwrap();
+        assert_eq!(suite.failed().next(), None, "{}", suite.to_text());
+    }
+}
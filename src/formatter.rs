@@ -0,0 +1,235 @@
+use crate::ast::{Ast, Node};
+use crate::expect::EXPECT;
+use crate::frontend::Frontend;
+use crate::lexer::from_code;
+use crate::padam::lexer::TriviaKind;
+use crate::padam::parser;
+use crate::printer::{self, Notation, Piece, View};
+use crate::provenance::Sugar;
+
+/// the width lines are kept under, when they can be
+pub const WIDTH: usize = 80;
+
+/// what `n` is to the printer. `if` is lowered to applications, but kept apart by its sugar
+fn view<'a>(n: &'a Node, src: &'a str) -> View<'a, &'a Node> {
+    let text = |at| Piece::Text(from_code(at, src).into());
+    let kw = |kw: &'static str| Piece::Text(kw.into());
+    match &n.item {
+        Ast::App(l, e) if n.sugar == Some(Sugar::If) && l.sugar == Some(Sugar::If) => {
+            let Ast::App(c, t) = &l.item else {
+                return View::App(l, e);
+            };
+            let pieces = vec![
+                kw("if "),
+                Piece::Term(c),
+                kw(" then "),
+                Piece::Term(t),
+                kw(" else "),
+            ];
+            View::Open(pieces, e)
+        }
+        Ast::Var => View::Atom(n.from_code(src).into()),
+        Ast::Abs(v, inner) => View::Abs(from_code(*v, src).into(), inner),
+        Ast::Unpack { names, body } => {
+            let names: Vec<_> = names.iter().map(|v| from_code(*v, src)).collect();
+            View::Abs(format!("({})", names.join(", ")).into(), body)
+        }
+        Ast::Annotated { name, ty, body } => {
+            let (name, ty) = (from_code(*name, src), from_code(*ty, src));
+            View::Abs(format!("({name} : {ty})").into(), body)
+        }
+        Ast::App(l, r) => View::App(l, r),
+        Ast::Infix { items, operators } => View::Infix(
+            items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| match operators.contains(&i) {
+                    true => text(item.at),
+                    false => Piece::Term(item),
+                })
+                .collect(),
+        ),
+        Ast::Let {
+            rec,
+            ident,
+            value,
+            body,
+        } => {
+            let mut pieces = vec![kw(if *rec { "letrec " } else { "let " }), text(*ident)];
+            // the parameters written after the name, whose abstractions start at them instead
+            // of at a `fn`
+            let mut value = value;
+            while let Ast::Abs(v, inner) = &value.item
+                && value.at.offset() == v.offset()
+            {
+                pieces.extend([kw(" "), text(*v)]);
+                value = inner;
+            }
+            pieces.extend([kw(" = "), Piece::Term(value), kw(" in ")]);
+            View::Open(pieces, body)
+        }
+        Ast::Def {
+            ident,
+            params,
+            body,
+        } => {
+            let mut pieces = vec![text(*ident)];
+            for p in params {
+                pieces.extend([kw(" "), text(*p)]);
+            }
+            pieces.push(kw(" = "));
+            View::Open(pieces, body)
+        }
+        Ast::Tuple(items) | Ast::List(items) | Ast::Program(items) => {
+            let (open, sep, close) = match &n.item {
+                Ast::Tuple(_) => ("(", ", ", ")"),
+                Ast::List(_) => ("[", ", ", "]"),
+                _ => ("", "\n", ""),
+            };
+            let mut pieces = vec![kw(open)];
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    pieces.push(kw(sep));
+                }
+                pieces.push(Piece::Term(item));
+            }
+            pieces.push(kw(close));
+            View::Group(pieces)
+        }
+    }
+}
+
+/// `n` in the canonical style: abstractions written with `notation`, nested ones merged, and only
+/// the parenthesis needed to parse it back the same
+pub fn print(n: &Node, src: &str, notation: Notation) -> String {
+    printer::print(n, notation, |n| view(n, src))
+}
+
+/// a line of code in the canonical style, with its comments moved after it
+pub fn format_line(frontend: &dyn Frontend, line: &str) -> parser::Result<String> {
+    let lexer = frontend.lexer();
+    let layout = lexer.lex_with_trivia(line)?;
+    let comments: Vec<_> = layout
+        .trivia()
+        .filter(|t| t.kind == TriviaKind::Comment)
+        .map(|t| from_code(t.at, line).trim_end())
+        .collect();
+    let mut s = match layout.tokens.is_empty() {
+        true => String::new(),
+        false => {
+            let tokens: Vec<_> = layout.tokens.into_iter().map(|t| t.token).collect();
+            let node = frontend.parse(&lexer, &tokens)?;
+            print(&node, line, frontend.notation())
+        }
+    };
+    for c in comments {
+        if !s.is_empty() {
+            s.push(' ');
+        }
+        s.push_str(c);
+    }
+    Ok(s)
+}
+
+/// a file, formatted
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Formatted {
+    pub text: String,
+    /// the lines still wider than asked, counted from 1. Neither definitions nor expressions
+    /// can span more than one, so they aren't broken
+    pub overlong: Vec<usize>,
+    /// the lines which don't parse, and were left as they were
+    pub unparsed: Vec<usize>,
+}
+
+/// formats every line of code of a file. Commands, imports, signatures and `-- expect:`
+/// annotations are left as they are, but for the whitespace at their end
+pub fn format_file(frontend: &dyn Frontend, content: &str, width: usize) -> Formatted {
+    let mut formatted = Formatted::default();
+    let mut in_signature = false;
+    for (i, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+        let verbatim = match trimmed {
+            "sig" => {
+                in_signature = true;
+                true
+            }
+            "end" if in_signature => {
+                in_signature = false;
+                true
+            }
+            _ => {
+                in_signature
+                    || trimmed.starts_with(':')
+                    || trimmed.starts_with("import ")
+                    || trimmed.starts_with(EXPECT)
+            }
+        };
+        let out = match verbatim {
+            true => line.trim_end().to_string(),
+            false => format_line(frontend, line).unwrap_or_else(|_| {
+                formatted.unparsed.push(i + 1);
+                line.trim_end().to_string()
+            }),
+        };
+        if out.chars().count() > width {
+            formatted.overlong.push(i + 1);
+        }
+        formatted.text.push_str(&out);
+        formatted.text.push('\n');
+    }
+    formatted
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::formatter::{WIDTH, format_file, format_line};
+    use crate::frontend::{Classic, Frontend, Qk};
+    use crate::padam::grammar::tests::sexpr;
+
+    fn sexp(f: &dyn Frontend, src: &str) -> String {
+        let lexer = f.lexer();
+        let tokens = lexer.lex(src).unwrap();
+        sexpr(&f.parse(&lexer, &tokens).unwrap(), src)
+    }
+
+    #[test]
+    pub fn canonical_and_parsed_back_the_same() {
+        let cases = [
+            ("fn x => (fn y => (x y))", "fn x y => x y"),
+            ("(f x) (g y)", "f x (g y)"),
+            ("f (fn x => x) (fn y => y)", "f (fn x => x) fn y => y"),
+            ("(fn x => x) y", "(fn x => x) y"),
+            ("f (if c then (a) else b) z", "f (if c then a else b) z"),
+            ("K   x y =   x # the first", "K x y = x # the first"),
+            (
+                "let f x = (x) in let g = fn y => y in f g",
+                "let f x = x in let g = fn y => y in f g",
+            ),
+            ("(a + b) c", "(a + b) c"),
+            ("map (+) [ (x,y) , z]", "map (+) [(x, y), z]"),
+            ("fn (a, b) c => a", "fn (a, b) c => a"),
+        ];
+        for (src, canonical) in cases {
+            let formatted = format_line(&Qk, src).unwrap();
+            assert_eq!(formatted, canonical);
+            assert_eq!(format_line(&Qk, &formatted).unwrap(), formatted);
+            let code = |s: &str| s.split_once('#').map_or(s, |(c, _)| c).to_string();
+            assert_eq!(sexp(&Qk, &code(&formatted)), sexp(&Qk, &code(src)), "{src}");
+        }
+        assert_eq!(format_line(&Classic, "\\x.λy. x").unwrap(), "λx y.x");
+    }
+
+    #[test]
+    pub fn formats_a_file() {
+        let content =
+            "# ids\nI  x = x  \n:set prelude on\n-- expect:  I\nI (\n\nsig\n  I : a -> a\nend\n";
+        let formatted = format_file(&Qk, content, WIDTH);
+        assert_eq!(
+            formatted.text,
+            "# ids\nI x = x\n:set prelude on\n-- expect:  I\nI (\n\nsig\n  I : a -> a\nend\n"
+        );
+        assert_eq!(formatted.unparsed, [5]);
+        assert_eq!(format_file(&Qk, content, 5).overlong, [2, 3, 4, 8]);
+    }
+}
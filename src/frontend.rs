@@ -0,0 +1,242 @@
+use crate::{
+    ast::{Ast, Node},
+    lexer::{self, Trace, over},
+    padam::{
+        Token, grammar,
+        lexer::{
+            ASSIGN_TY, CLOSE_BRACKET_TY, CLOSE_PAREN_TY, COLON_TY, COMMA_TY, ELSE_KW_TY, EOL_TY,
+            FN_IMPL_TY, FN_KW_TY, IDENT_TY, IF_KW_TY, IN_KW_TY, LET_KW_TY, LETREC_KW_TY, Lexer,
+            OPEN_BRACKET_TY, OPEN_PAREN_TY, OPERATOR_TY, THEN_KW_TY, Tokenizer, comment, ident,
+            literal, number, operator, single_char,
+        },
+        parser::{self, TokenStream},
+    },
+    printer::Notation,
+};
+
+/// a surface syntax: turns source code into the AST the rest of the pipeline works with
+pub trait Frontend: std::fmt::Debug {
+    /// the name used by `:set syntax`
+    fn name(&self) -> &'static str;
+
+    /// file extensions that are loaded with this syntax
+    fn extensions(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    fn lexer(&self) -> Lexer;
+
+    /// how abstractions are written back, as by the formatter
+    fn notation(&self) -> Notation {
+        Notation::Fn
+    }
+
+    /// whether terms written with `notation` can be parsed
+    fn reads(&self, notation: Notation) -> bool {
+        notation == self.notation()
+    }
+
+    fn parse(&self, lexer: &Lexer, tokens: &[Token]) -> parser::Result<Node>;
+
+    /// parses the tokens as they're lexed. `None` if there are none. By default, they're all
+    /// collected before parsing
+    fn parse_stream(
+        &self,
+        lexer: &Lexer,
+        tokens: &mut dyn Iterator<Item = lexer::Result<Token>>,
+    ) -> parser::Result<Option<Node>> {
+        let tokens = tokens.collect::<lexer::Result<Vec<_>>>()?;
+        if tokens.is_empty() {
+            return Ok(None);
+        }
+        self.parse(lexer, &tokens).map(Some)
+    }
+}
+
+/// every syntax qk knows by default. The first one is the default syntax
+pub fn defaults() -> Vec<Box<dyn Frontend>> {
+    vec![Box::new(Qk), Box::new(Classic)]
+}
+
+/// parses with `grammar::lambda`, as a program if there's any definition
+pub fn parse_lambda(lexer: &Lexer, tokens: &[Token]) -> parser::Result<Node> {
+    parse_lambda_stream(lexer, &mut tokens.iter().cloned().map(Ok))?
+        .ok_or(parser::Error::UnexpectedEof)
+}
+
+/// whether a line of tokens is a definition rather than an expression
+pub fn is_decl(lexer: &Lexer, line: &[Token]) -> bool {
+    // TODO: This is not ideal. But since we don't have namespaces yet, it's the only way that
+    // declarations can exist. The `=` of a `let` doesn't make one
+    line.iter()
+        .map(|t| lexer.get_type(t.item))
+        .take_while(|ty| *ty != LET_KW_TY && *ty != LETREC_KW_TY)
+        .any(|ty| ty == ASSIGN_TY)
+}
+
+/// same as `parse_lambda`, a line at a time. Neither definitions nor expressions span more than
+/// one, so only a line of tokens is kept around
+pub fn parse_lambda_stream(
+    lexer: &Lexer,
+    tokens: &mut dyn Iterator<Item = lexer::Result<Token>>,
+) -> parser::Result<Option<Node>> {
+    let nt = grammar::lambda();
+    let is_eol = |t: &Token| lexer.get_type(t.item) == EOL_TY;
+    let mut stream = TokenStream::new(tokens);
+    let Some(first) = stream.next_nonempty_line(is_eol)? else {
+        return Ok(None);
+    };
+    if !is_decl(lexer, &first.0) {
+        let expr = parser::run_line(&nt, lexer, grammar::EXPR, &first)?;
+        return match (first.1, stream.next_nonempty_line(is_eol)?) {
+            (Some(eol), Some(_)) => Err(parser::Error::UnexpectedToken { at: eol.at }),
+            _ => Ok(Some(expr)),
+        };
+    }
+    let mut defs = vec![parser::run_line(&nt, lexer, grammar::DEF, &first)?];
+    while let Some(line) = stream.next_nonempty_line(is_eol)? {
+        defs.push(parser::run_line(&nt, lexer, grammar::DEF, &line)?);
+    }
+    let at = over(defs[0].at, defs[defs.len() - 1].at);
+    Ok(Some(Ast::Program(defs).at(at)))
+}
+
+/// `fn x y => x`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Qk;
+
+impl Frontend for Qk {
+    fn name(&self) -> &'static str {
+        "qk"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["qk"]
+    }
+
+    fn lexer(&self) -> Lexer {
+        Lexer::default()
+    }
+
+    fn parse(&self, lexer: &Lexer, tokens: &[Token]) -> parser::Result<Node> {
+        parse_lambda(lexer, tokens)
+    }
+
+    fn parse_stream(
+        &self,
+        lexer: &Lexer,
+        tokens: &mut dyn Iterator<Item = lexer::Result<Token>>,
+    ) -> parser::Result<Option<Node>> {
+        parse_lambda_stream(lexer, tokens)
+    }
+}
+
+/// `λx y.x`, or `\x y.x` for who doesn't have a λ key
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Classic;
+
+impl Frontend for Classic {
+    fn name(&self) -> &'static str {
+        "classic"
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["lc", "lambda"]
+    }
+
+    fn notation(&self) -> Notation {
+        Notation::Lambda
+    }
+
+    fn reads(&self, notation: Notation) -> bool {
+        matches!(notation, Notation::Lambda | Notation::Backslash)
+    }
+
+    fn lexer(&self) -> Lexer {
+        // same token types as `qk`, so the grammar doesn't change
+        let tokenizers = [
+            Tokenizer::new(FN_KW_TY, single_char('λ')),
+            Tokenizer::new(FN_KW_TY, single_char('\\')),
+            Tokenizer::new(LET_KW_TY, literal("let")),
+            Tokenizer::new(LETREC_KW_TY, literal("letrec")),
+            Tokenizer::new(IN_KW_TY, literal("in")),
+            Tokenizer::new(IF_KW_TY, literal("if")),
+            Tokenizer::new(THEN_KW_TY, literal("then")),
+            Tokenizer::new(ELSE_KW_TY, literal("else")),
+            Tokenizer::new(FN_IMPL_TY, single_char('.')),
+            Tokenizer::new(OPEN_PAREN_TY, single_char('(')),
+            Tokenizer::new(CLOSE_PAREN_TY, single_char(')')),
+            Tokenizer::new(COMMA_TY, single_char(',')),
+            Tokenizer::new(COLON_TY, single_char(':')),
+            Tokenizer::new(OPEN_BRACKET_TY, single_char('[')),
+            Tokenizer::new(CLOSE_BRACKET_TY, single_char(']')),
+            Tokenizer::new(EOL_TY, single_char('\n')),
+            Tokenizer::ignore(single_char(' ')),
+            Tokenizer::ignore(single_char('\t')),
+            Tokenizer::new(IDENT_TY, ident()),
+            Tokenizer::new(ASSIGN_TY, single_char('=')),
+            Tokenizer::new(OPERATOR_TY, operator()),
+            Tokenizer::new(IDENT_TY, number()),
+            Tokenizer::ignore(comment()),
+        ];
+        Lexer::new(tokenizers.into_iter())
+    }
+
+    fn parse(&self, lexer: &Lexer, tokens: &[Token]) -> parser::Result<Node> {
+        parse_lambda(lexer, tokens)
+    }
+
+    fn parse_stream(
+        &self,
+        lexer: &Lexer,
+        tokens: &mut dyn Iterator<Item = lexer::Result<Token>>,
+    ) -> parser::Result<Option<Node>> {
+        parse_lambda_stream(lexer, tokens)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::frontend::{Classic, Frontend, Qk};
+    use crate::padam::{
+        grammar::{self, tests::sexpr},
+        parser::Error,
+    };
+
+    fn sexp(f: &dyn Frontend, src: &str) -> String {
+        let lexer = f.lexer();
+        let tokens = lexer.lex(src).unwrap();
+        sexpr(&f.parse(&lexer, &tokens).unwrap(), src)
+    }
+
+    #[test]
+    pub fn classic_matches_qk() {
+        assert_eq!(
+            sexp(&Classic, "λx y.x (\\z.z)"),
+            sexp(&Qk, "fn x y => x (fn z => z)")
+        );
+        assert_eq!(sexp(&Classic, "K x y = x"), sexp(&Qk, "K x y = x"));
+    }
+
+    #[test]
+    pub fn streams_a_line_at_a_time() {
+        let lexer = Qk.lexer();
+        let src = "\nK x y = x\n\nI x = x\n";
+        let mut pulled = 0;
+        let mut tokens = lexer.tokens(src).inspect(|_| pulled += 1);
+        let node = Qk.parse_stream(&lexer, &mut tokens).unwrap().unwrap();
+        let whole = grammar::tests::parse(grammar::PROGRAM, src).unwrap();
+        assert_eq!(sexpr(&node, src), sexpr(&whole, src));
+        assert_eq!(pulled, lexer.lex(src).unwrap().len());
+
+        // the first line is parsed before the second is lexed
+        let src = "K x y = (x\n$";
+        let e = Qk.parse_stream(&lexer, &mut lexer.tokens(src)).unwrap_err();
+        assert!(matches!(e, Error::UnexpectedToken { at } if at.offset() == 10));
+        assert!(
+            Qk.parse_stream(&lexer, &mut lexer.tokens("  "))
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use miette::Diagnostic;
+
+use crate::arts::{CompArtifact, Term, TermIdx};
+use crate::frontend::{self, Frontend, Qk};
+use crate::session::Session;
+use crate::tree::SyntaxTree;
+
+/// the reductions an input may take, so the fuzzer doesn't stop at the first divergent term
+pub const BUDGET: usize = 1_000;
+
+/// the pieces `tokens` builds sources of: a token of each type, and some near misses
+const PIECES: &[&str] = &[
+    "x", "y", "K", "fn", "=>", "=", "(", ")", "\n", " ", "λ", "\\", ".", "-- c\n", "$", "=>=",
+    "let", "letrec", "in", ",", "[", "]", "if", "then", "else", "+", "*", "<=",
+];
+
+/// a source made of `PIECES`, so most inputs get past the lexer
+pub fn tokens(data: &[u8]) -> String {
+    data.iter()
+        .map(|b| PIECES[*b as usize % PIECES.len()])
+        .collect()
+}
+
+/// panics if a label of `diag`, or of its related diagnostics, isn't a range of `src`
+pub fn assert_spans(diag: &dyn Diagnostic, src: &str) {
+    for label in diag.labels().into_iter().flatten() {
+        let range = label.offset()..label.offset() + label.len();
+        assert!(src.get(range).is_some(), "{diag:?} points out of {src:?}");
+    }
+    for related in diag.related().into_iter().flatten() {
+        assert_spans(related, src);
+    }
+}
+
+/// panics if a variable of the term at `idx` isn't bound in it, or if it reaches out of `art`
+pub fn assert_closed(art: &CompArtifact, idx: TermIdx) {
+    let mut stack = vec![(idx, 0)];
+    while let Some((idx, binders)) = stack.pop() {
+        assert!(idx.0 < art.arena().len(), "{idx:?} is out of the pool");
+        match art.get(idx) {
+            Term::Var(v) => assert!(v.0 < binders, "{idx:?} is unbound"),
+            Term::Const(_) => (),
+            Term::Abs { inner } => stack.push((inner, binders + 1)),
+            Term::App(l, r) => stack.extend([(l, binders), (r, binders)]),
+        }
+    }
+}
+
+/// lexes `data` with every syntax. The tokens, or the error, must point into it
+pub fn lex(data: &[u8]) {
+    let Ok(src) = std::str::from_utf8(data) else {
+        return;
+    };
+    for f in frontend::defaults() {
+        match f.lexer().lex(src) {
+            Ok(tokens) => {
+                for t in tokens {
+                    let range = t.at.offset()..t.at.offset() + t.at.len();
+                    assert!(src.get(range).is_some(), "{t:?} points out of {src:?}");
+                }
+            }
+            Err(e) => assert_spans(&e, src),
+        }
+    }
+}
+
+/// parses the tokens `data` picks. Every node, or the error, must point into the source
+pub fn parse(data: &[u8]) {
+    let src = tokens(data);
+    let lexer = Qk.lexer();
+    let Ok(tokens) = lexer.lex(&src) else {
+        return;
+    };
+    match Qk.parse(&lexer, &tokens) {
+        Ok(ast) => {
+            for node in SyntaxTree::new(&ast).iter() {
+                let at = node.span();
+                let range = at.offset()..at.offset() + at.len();
+                assert!(src.get(range).is_some(), "{at:?} points out of {src:?}");
+            }
+        }
+        Err(e) => assert_spans(&e, &src),
+    }
+}
+
+/// compiles and runs the tokens `data` picks in a new session. The pool must stay closed, and the
+/// errors must point into the source
+pub fn compile(data: &[u8]) {
+    let src = tokens(data);
+    let mut session = Session::new();
+    session.cancel.budget = Some(BUDGET);
+    match session.compile_expr(&src) {
+        Ok(Some(root)) => {
+            assert_closed(&session.art, root);
+            let errors = session.art.validate();
+            assert!(errors.is_empty(), "{errors:?} in the pool of {src:?}");
+        }
+        Ok(None) => (),
+        Err(e) => assert_spans(&e, &src),
+    }
+    let mut session = Session::new();
+    session.cancel.budget = Some(BUDGET);
+    match session.evaluate(&src) {
+        Ok(ev) => {
+            if let Some(nf) = ev.normal() {
+                assert_closed(&ev.session.art, nf);
+            }
+        }
+        Err(e) => assert_spans(&e, &src),
+    }
+}
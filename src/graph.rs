@@ -0,0 +1,164 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+
+use crate::arts::{CompArtifact, TermIdx};
+use crate::backend::{Aliases, DeBruijn, Renderer};
+use crate::cancel::{self, CancelToken};
+use crate::cpu::{Cpu, Normal, Op, Reductor};
+
+/// past it, the graph is cut short, since some terms have a lot of reducts
+pub const MAX_TERMS: usize = 500;
+
+/// a term of the graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Node {
+    pub term: TermIdx,
+    /// the fewest reductions from the root to it
+    pub depth: usize,
+    /// it has no reducts. Only known for the terms that were explored
+    pub normal: bool,
+}
+
+/// a single step reduction, from a node to another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    /// it's the step the normal order strategy takes
+    pub leftmost: bool,
+}
+
+/// every term reachable from a root up to some depth, with the reductions between them. Terms
+/// are the same node if they're α-equivalent, so confluence shows up as diamonds
+#[derive(Debug, Default, Clone)]
+pub struct Graph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    /// there were more terms than `MAX_TERMS`
+    pub truncated: bool,
+}
+
+impl Graph {
+    /// explores the reducts of `root` breadth first, `depth` reductions deep at most. Each term
+    /// explored spends a step of `token`
+    pub fn explore(
+        cpu: &mut Cpu,
+        root: TermIdx,
+        depth: usize,
+        token: &mut CancelToken,
+    ) -> cancel::Result<Self> {
+        let mut g = Self::default();
+        let mut seen = HashMap::new();
+        let key = |cpu: &Cpu, t| DeBruijn.render(&cpu.art, t, &Aliases::new());
+        seen.insert(key(cpu, root), 0);
+        g.nodes.push(Node {
+            term: root,
+            depth: 0,
+            normal: false,
+        });
+        let mut queue = VecDeque::from([0]);
+        while let Some(from) = queue.pop_front() {
+            let node = g.nodes[from];
+            if node.depth >= depth {
+                continue;
+            }
+            token.tick()?;
+            let leftmost = match Normal::step(cpu, node.term) {
+                Op::Reduced(t) => Some(key(cpu, t)),
+                _ => None,
+            };
+            let reducts = cpu.reducts(node.term);
+            g.nodes[from].normal = reducts.is_empty();
+            for term in reducts {
+                let k = key(cpu, term);
+                let to = match seen.get(&k) {
+                    Some(to) => *to,
+                    None if g.nodes.len() >= MAX_TERMS => {
+                        g.truncated = true;
+                        continue;
+                    }
+                    None => {
+                        g.nodes.push(Node {
+                            term,
+                            depth: node.depth + 1,
+                            normal: false,
+                        });
+                        queue.push_back(g.nodes.len() - 1);
+                        g.nodes.len() - 1
+                    }
+                };
+                let is_leftmost = leftmost.as_ref() == Some(&k);
+                seen.entry(k).or_insert(to);
+                // two redexes may lead to the same term
+                match g.edges.iter_mut().find(|e| e.from == from && e.to == to) {
+                    Some(e) => e.leftmost |= is_leftmost,
+                    None => g.edges.push(Edge {
+                        from,
+                        to,
+                        leftmost: is_leftmost,
+                    }),
+                }
+            }
+        }
+        Ok(g)
+    }
+
+    /// as a Graphviz graph. The root is boxed, normal forms are doubled and the normal order
+    /// path is bold
+    pub fn to_dot(&self, art: &CompArtifact, aliases: &Aliases) -> String {
+        let mut s = String::from("digraph reductions {\n");
+        for (i, n) in self.nodes.iter().enumerate() {
+            let label = art.pretty(n.term, aliases);
+            let shape = match (i, n.normal) {
+                (0, _) => "box",
+                (_, true) => "doublecircle",
+                _ => "ellipse",
+            };
+            let _ = writeln!(s, "  r{i} [label={label:?}, shape={shape}];");
+        }
+        for e in &self.edges {
+            let style = if e.leftmost { " [style=bold]" } else { "" };
+            let _ = writeln!(s, "  r{} -> r{}{style};", e.from, e.to);
+        }
+        s.push_str("}\n");
+        s
+    }
+
+    /// `{ nodes: [{ term, depth, normal }], edges: [{ from, to, leftmost }], truncated }`
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self, art: &CompArtifact, aliases: &Aliases) -> serde_json::Value {
+        use serde_json::json;
+
+        let nodes: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|n| json!({ "term": art.pretty(n.term, aliases), "depth": n.depth, "normal": n.normal }))
+            .collect();
+        let edges: Vec<_> = self
+            .edges
+            .iter()
+            .map(|e| json!({ "from": e.from, "to": e.to, "leftmost": e.leftmost }))
+            .collect();
+        json!({ "nodes": nodes, "edges": edges, "truncated": self.truncated })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::session::Session;
+
+    #[test]
+    pub fn diamonds() {
+        let mut s = Session::new();
+        s.eval_str("I x = x").unwrap();
+        // both redexes can go first, but they meet again at `fn y => I`
+        let g = s.graph("(fn x y => x) (I I)", 4).unwrap().unwrap();
+        assert_eq!(g.nodes.len(), 4);
+        assert_eq!(g.edges.len(), 4);
+        assert_eq!(g.edges.iter().filter(|e| e.leftmost).count(), 3);
+        assert_eq!(g.nodes.iter().filter(|n| n.normal).count(), 1);
+
+        let g = s.graph("(fn x => x x) (fn x => x x)", 10).unwrap().unwrap();
+        assert_eq!((g.nodes.len(), g.edges.len()), (1, 1));
+    }
+}
@@ -0,0 +1,267 @@
+use std::ops::Range;
+
+use crate::{
+    ast::{Ast, Node},
+    frontend,
+    lexer::{Trace, over, shift},
+    padam::{
+        Token, grammar,
+        lexer::{EOL_TY, Lexer},
+        parser::{self, NonTerminals},
+    },
+};
+
+/// replaces `range` of the source with `text`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+impl Edit {
+    pub fn new(range: Range<usize>, text: impl Into<String>) -> Self {
+        Self {
+            range,
+            text: text.into(),
+        }
+    }
+}
+
+/// a line of the source, lexed and parsed on its own. Its spans start at the line, not at the
+/// source, so the lines after an edit only have to be moved, not recomputed
+#[derive(Debug)]
+struct Line {
+    start: usize,
+    /// with the `\n` ending it, if there's any
+    len: usize,
+    tokens: Vec<Token>,
+    eol: Option<Token>,
+    is_decl: bool,
+    /// `None` if there's nothing on it
+    parsed: Option<parser::Result<Node>>,
+}
+
+/// a source kept parsed as it's edited, for editors. Tokens never cross a line, and neither
+/// definitions nor expressions span more than one, so an edit only lexes and parses again the
+/// lines it touches. It parses as `frontend::parse_lambda` does
+pub struct Document {
+    lexer: Lexer,
+    nt: NonTerminals,
+    src: String,
+    lines: Vec<Line>,
+}
+
+impl Document {
+    pub fn new(lexer: Lexer, src: impl Into<String>) -> Self {
+        let mut doc = Self {
+            lexer,
+            nt: grammar::lambda(),
+            src: src.into(),
+            lines: Vec::new(),
+        };
+        doc.lines = doc.build_lines(0..doc.src.len());
+        doc
+    }
+
+    pub fn src(&self) -> &str {
+        &self.src
+    }
+
+    pub fn lexer(&self) -> &Lexer {
+        &self.lexer
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// applies `edit` and returns the lines that were lexed and parsed again. Panics if the range
+    /// isn't in the source, as `String::replace_range` does
+    pub fn edit(&mut self, edit: &Edit) -> Range<usize> {
+        let line_of = |pos| self.lines.partition_point(|l| l.start <= pos) - 1;
+        let (first, last) = (line_of(edit.range.start), line_of(edit.range.end));
+        let start = self.lines[first].start;
+        let end = self.lines[last].start + self.lines[last].len;
+        self.src.replace_range(edit.range.clone(), &edit.text);
+        let new_end = end + edit.text.len() - edit.range.len();
+
+        let lines = self.build_lines(start..new_end);
+        let count = lines.len();
+        self.lines.splice(first..=last, lines);
+        for l in &mut self.lines[first + count..] {
+            l.start = l.start + edit.text.len() - edit.range.len();
+        }
+        first..first + count
+    }
+
+    /// lexes and parses the lines of `range`. The last line of the source is always there, even
+    /// if empty
+    fn build_lines(&self, range: Range<usize>) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut start = range.start;
+        for text in self.src[range.start..range.end].split_inclusive('\n') {
+            lines.push(self.line(start, text));
+            start += text.len();
+        }
+        let is_last = range.end == self.src.len();
+        if is_last && (lines.is_empty() || self.src[..range.end].ends_with('\n')) {
+            lines.push(self.line(range.end, ""));
+        }
+        lines
+    }
+
+    fn line(&self, start: usize, text: &str) -> Line {
+        let mut line = Line {
+            start,
+            len: text.len(),
+            tokens: Vec::new(),
+            eol: None,
+            is_decl: false,
+            parsed: None,
+        };
+        for tk in self.lexer.tokens(text) {
+            match tk {
+                Ok(tk) if self.lexer.get_type(tk.item) == EOL_TY => line.eol = Some(tk),
+                Ok(tk) => line.tokens.push(tk),
+                Err(e) => {
+                    line.parsed = Some(Err(e.into()));
+                    return line;
+                }
+            }
+        }
+        if line.tokens.is_empty() {
+            return line;
+        }
+        line.is_decl = frontend::is_decl(&self.lexer, &line.tokens);
+        let nt = if line.is_decl {
+            grammar::DEF
+        } else {
+            grammar::EXPR
+        };
+        line.parsed = Some(self.run_line(&line, nt));
+        line
+    }
+
+    fn run_line(&self, line: &Line, nt: &str) -> parser::Result<Node> {
+        parser::run_line(
+            &self.nt,
+            &self.lexer,
+            nt,
+            &(line.tokens.clone(), line.eol.clone()),
+        )
+    }
+
+    /// every token lexed, with its span in the source
+    pub fn tokens(&self) -> impl Iterator<Item = Token> + '_ {
+        self.lines.iter().flat_map(|l| {
+            let eol = l.eol.iter();
+            l.tokens.iter().chain(eol).map(|t| Token {
+                item: t.item,
+                at: shift(t.at, l.start),
+                sugar: None,
+            })
+        })
+    }
+
+    /// the error of each line that doesn't lex or parse on its own
+    pub fn errors(&self) -> impl Iterator<Item = parser::Error> + '_ {
+        self.lines.iter().filter_map(|l| match &l.parsed {
+            Some(Err(e)) => Some(e.clone().shift(l.start)),
+            _ => None,
+        })
+    }
+
+    /// the whole source, as `frontend::parse_lambda_stream` parses it, out of the parsed lines
+    pub fn parse(&self) -> parser::Result<Option<Node>> {
+        let mut lines = self.lines.iter().filter(|l| l.parsed.is_some());
+        let Some(first) = lines.next() else {
+            return Ok(None);
+        };
+        let parsed = |l: &Line, r: &parser::Result<Node>| match r {
+            Ok(n) => {
+                let mut n = n.clone();
+                n.shift(l.start);
+                Ok(n)
+            }
+            Err(e) => Err(e.clone().shift(l.start)),
+        };
+        let result = first.parsed.as_ref().expect("it's not empty");
+        if !first.is_decl {
+            let expr = parsed(first, result)?;
+            return match (&first.eol, lines.next()) {
+                (
+                    _,
+                    Some(Line {
+                        parsed: Some(Err(e @ parser::Error::Lexer(_))),
+                        start,
+                        ..
+                    }),
+                ) => Err(e.clone().shift(*start)),
+                (Some(eol), Some(_)) => Err(parser::Error::UnexpectedToken {
+                    at: shift(eol.at, first.start),
+                }),
+                _ => Ok(Some(expr)),
+            };
+        }
+        let mut defs = vec![parsed(first, result)?];
+        for l in lines {
+            let def = match l.parsed.as_ref().expect("it's not empty") {
+                r @ Err(parser::Error::Lexer(_)) => parsed(l, r),
+                r if l.is_decl => parsed(l, r),
+                // an expression amid definitions
+                _ => parsed(l, &self.run_line(l, grammar::DEF)),
+            };
+            defs.push(def?);
+        }
+        let at = over(defs[0].at, defs[defs.len() - 1].at);
+        Ok(Some(Ast::Program(defs).at(at)))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::frontend::parse_lambda;
+    use crate::incremental::{Document, Edit};
+    use crate::padam::lexer::Lexer;
+
+    fn same_as_parsing_again(doc: &Document) {
+        let lexer = Lexer::default();
+        let tokens = lexer.lex(doc.src()).unwrap();
+        assert_eq!(doc.tokens().collect::<Vec<_>>(), tokens);
+        match (doc.parse(), parse_lambda(&lexer, &tokens)) {
+            (Ok(Some(l)), Ok(r)) => assert_eq!(l, r),
+            (Err(l), Err(r)) => assert_eq!(l.to_string(), r.to_string()),
+            (l, r) => panic!("{l:?} isn't {r:?}"),
+        }
+    }
+
+    #[test]
+    pub fn edits() {
+        let mut doc = Document::new(
+            Lexer::default(),
+            "I x = x\nK x y = x\n\nS x y z = x z (y z)",
+        );
+        assert_eq!(doc.line_count(), 4);
+        same_as_parsing_again(&doc);
+
+        // renaming a parameter only touches its line
+        assert_eq!(doc.edit(&Edit::new(10..11, "a")), 1..2);
+        assert_eq!(doc.src(), "I x = x\nK a y = x\n\nS x y z = x z (y z)");
+        same_as_parsing_again(&doc);
+        assert_eq!(doc.edit(&Edit::new(16..17, "a")), 1..2);
+        same_as_parsing_again(&doc);
+
+        // splitting and joining lines
+        assert_eq!(doc.edit(&Edit::new(18..18, "T x = x\n")), 2..4);
+        assert_eq!(doc.line_count(), 5);
+        same_as_parsing_again(&doc);
+        assert_eq!(doc.edit(&Edit::new(7..8, " ")), 0..1);
+        assert_eq!(doc.line_count(), 4);
+        assert!(doc.errors().next().is_some());
+        same_as_parsing_again(&doc);
+
+        doc.edit(&Edit::new(0..doc.src().len(), "fn x => x"));
+        assert_eq!(doc.line_count(), 1);
+        same_as_parsing_again(&doc);
+    }
+}
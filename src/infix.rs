@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::{Ast, Node},
+    ir::{Error, Result},
+    lexer::{Trace, from_code, over},
+    provenance::Sugar,
+};
+
+/// how operators of the same precedence group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assoc {
+    /// `infixl`: `a - b - c` is `(a - b) - c`
+    Left,
+    /// `infixr`: `a : b : c` is `a : (b : c)`
+    Right,
+    /// `infix`: `a == b == c` is an error
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fixity {
+    pub assoc: Assoc,
+    /// from 0 to 9. Higher binds tighter, and applications tighter than any
+    pub prec: u8,
+}
+
+impl Fixity {
+    pub const MAX_PREC: u8 = 9;
+
+    /// the command declaring it: `infixl`, `infixr` or `infix`
+    pub fn keyword(self) -> &'static str {
+        match self.assoc {
+            Assoc::Left => "infixl",
+            Assoc::Right => "infixr",
+            Assoc::None => "infix",
+        }
+    }
+}
+
+/// the operators declared infix, by name
+pub type Operators = HashMap<Box<str>, Fixity>;
+
+/// a term between operators, or an operator
+enum Item {
+    Operand(Node),
+    Operator(Fixity, Node),
+}
+
+/// turns `items` into applications: the terms between infix operators are applied to each other,
+/// and each operator to the groups around it, as `fixities` say. Operators not declared infix are
+/// terms like any other
+pub fn resolve(
+    items: Vec<Node>,
+    operators: &[usize],
+    fixities: &Operators,
+    src: &str,
+) -> Result<Node> {
+    let mut seq: Vec<Item> = Vec::new();
+    for (i, item) in items.into_iter().enumerate() {
+        let fixity = match operators.contains(&i) {
+            true => fixities.get(from_code(item.at, src)).copied(),
+            false => None,
+        };
+        if let Some(f) = fixity {
+            seq.push(Item::Operator(f, item));
+            continue;
+        }
+        // the terms in a row are applied to each other before any operator
+        match seq.pop() {
+            Some(Item::Operand(l)) => {
+                let at = over(l.at, item.at);
+                seq.push(Item::Operand(Ast::App(l, item).at(at)));
+            }
+            last => {
+                seq.extend(last);
+                seq.push(Item::Operand(item));
+            }
+        }
+    }
+
+    let mut operands: Vec<Node> = Vec::new();
+    let mut pending: Vec<(Fixity, Node)> = Vec::new();
+    let mut expect_operand = true;
+    for item in seq {
+        match (item, expect_operand) {
+            (Item::Operand(t), true) => {
+                operands.push(t);
+                expect_operand = false;
+            }
+            (Item::Operator(f, op), false) => {
+                while let Some((top, top_op)) = pending.last() {
+                    let tighter = top.prec > f.prec
+                        || (top.prec == f.prec
+                            && top.assoc == Assoc::Left
+                            && f.assoc == Assoc::Left);
+                    if tighter {
+                        apply(&mut operands, &mut pending);
+                    } else if top.prec == f.prec
+                        && !(top.assoc == Assoc::Right && f.assoc == Assoc::Right)
+                    {
+                        return Err(Error::MixedOperators {
+                            first: from_code(top_op.at, src).into(),
+                            second: from_code(op.at, src).into(),
+                            first_at: top_op.at,
+                            second_at: op.at,
+                        });
+                    } else {
+                        break;
+                    }
+                }
+                pending.push((f, op));
+                expect_operand = true;
+            }
+            (Item::Operator(_, op), true) => {
+                return Err(Error::MissingOperand {
+                    op: from_code(op.at, src).into(),
+                    at: op.at,
+                });
+            }
+            (Item::Operand(_), false) => unreachable!("the terms in a row were applied"),
+        }
+    }
+    if expect_operand && let Some((_, op)) = pending.pop() {
+        return Err(Error::MissingOperand {
+            op: from_code(op.at, src).into(),
+            at: op.at,
+        });
+    }
+    while !pending.is_empty() {
+        apply(&mut operands, &mut pending);
+    }
+    Ok(operands
+        .pop()
+        .expect("there's an operand around each operator"))
+}
+
+/// `op l r`, out of the last operator and the last two operands
+fn apply(operands: &mut Vec<Node>, pending: &mut Vec<(Fixity, Node)>) {
+    let (_, op) = pending.pop().expect("only called with an operator pending");
+    let r = operands.pop().expect("an operator has a right operand");
+    let l = operands.pop().expect("an operator has a left operand");
+    let (l_at, at) = (over(l.at, op.at), over(l.at, r.at));
+    let op_l = Ast::App(op, l).lowered(l_at, Sugar::Infix);
+    operands.push(Ast::App(op_l, r).lowered(at, Sugar::Infix));
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::infix::{Assoc, Fixity, Operators};
+    use crate::padam::grammar::{self, tests::sexpr};
+
+    fn resolve(src: &str, fixities: &Operators) -> Result<String, crate::ir::Error> {
+        let mut ast = grammar::tests::parse(grammar::EXPR, src).unwrap();
+        let crate::ast::Ast::Infix { items, operators } = &mut ast.item else {
+            return Ok(sexpr(&ast, src));
+        };
+        let items = std::mem::take(items);
+        let node = crate::infix::resolve(items, operators, fixities, src)?;
+        Ok(sexpr(&node, src))
+    }
+
+    #[test]
+    pub fn precedence_and_associativity() {
+        let mut ops = Operators::new();
+        let mut declare = |op: &str, assoc, prec| ops.insert(op.into(), Fixity { assoc, prec });
+        declare("+", Assoc::Left, 6);
+        declare("*", Assoc::Left, 7);
+        declare("++", Assoc::Right, 5);
+        declare("==", Assoc::None, 4);
+        assert_eq!(resolve("a + b * c", &ops).unwrap(), "((+ a) ((* b) c))");
+        assert_eq!(
+            resolve("f a + b + c", &ops).unwrap(),
+            "((+ ((+ (f a)) b)) c)"
+        );
+        assert_eq!(resolve("a ++ b ++ c", &ops).unwrap(), "((++ a) ((++ b) c))");
+        assert_eq!(resolve("a + b == c", &ops).unwrap(), "((== ((+ a) b)) c)");
+        // not declared, so it's applied as any other term
+        assert_eq!(resolve("- a b + c", &ops).unwrap(), "((+ ((- a) b)) c)");
+        assert!(resolve("a == b == c", &ops).is_err());
+        assert!(resolve("a + b ++ c + d", &ops).is_ok());
+        assert!(resolve("a +", &ops).is_err());
+        assert!(resolve("* a", &ops).is_err());
+    }
+}
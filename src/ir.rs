@@ -1,22 +1,27 @@
-use std::{
-    collections::{HashMap, hash_map::Entry},
-    sync::LazyLock,
-};
+use std::{borrow::Cow, collections::HashMap, ops::Index, sync::LazyLock};
 
 use miette::{Diagnostic, SourceSpan};
 use thiserror::Error;
 
 use crate::{
-    ast::{Ast, Node},
+    arts::Const,
+    ast::Ast,
+    infix,
     lexer::{Meta, Trace},
+    printer::{self, Notation, View},
+    provenance::Sugar,
+    symbol::{Interner, Symbol},
+    types::Type,
 };
 
 pub type IrObj = Box<Meta<IrComponent>>;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Id(pub usize);
 
-#[derive(Default, Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+#[derive(Default, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum IrComponent {
     Var(Id),
     App(IrObj, IrObj),
@@ -34,6 +39,99 @@ pub enum IrComponent {
     /// i. e, indicates it's a variable from a lambda expr
     /// e. g, \y.x, where y is a variable binding
     Binding,
+
+    /// a number or a primitive operation, with `IrCompiler::primitives` on
+    Const(Const),
+
+    /// a `dyn` value used as a function, checked to be one when it runs. Inserted by the type
+    /// inference, at what's blamed if it isn't
+    Cast(IrObj),
+}
+
+/// the trees are as deep as the source is nested, so they're taken apart without recursion
+impl Drop for IrComponent {
+    fn drop(&mut self) {
+        fn take_children(c: &mut IrComponent, stack: &mut Vec<IrComponent>) {
+            match c {
+                IrComponent::App(l, r) => {
+                    stack.push(std::mem::take(&mut l.item));
+                    stack.push(std::mem::take(&mut r.item));
+                }
+                IrComponent::Abs(_, inner) | IrComponent::Def(inner) | IrComponent::Cast(inner) => {
+                    stack.push(std::mem::take(&mut inner.item))
+                }
+                _ => (),
+            }
+        }
+
+        let mut stack = Vec::new();
+        take_children(self, &mut stack);
+        while let Some(mut c) = stack.pop() {
+            take_children(&mut c, &mut stack);
+        }
+    }
+}
+
+impl IrComponent {
+    fn children(&self) -> Vec<&IrObj> {
+        match self {
+            IrComponent::App(l, r) => vec![l, r],
+            IrComponent::Abs(_, inner) | IrComponent::Def(inner) | IrComponent::Cast(inner) => {
+                vec![inner]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// as deep as `Drop`, so it's cloned without recursion too
+impl Clone for IrComponent {
+    fn clone(&self) -> Self {
+        enum Task<'a> {
+            Visit(&'a IrComponent),
+            /// rebuilds the node out of its last cloned children
+            Build(&'a IrComponent),
+        }
+
+        let mut tasks = vec![Task::Visit(self)];
+        let mut done: Vec<IrComponent> = Vec::new();
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Visit(ir) => {
+                    tasks.push(Task::Build(ir));
+                    tasks.extend(
+                        ir.children()
+                            .into_iter()
+                            .rev()
+                            .map(|c| Task::Visit(&c.item)),
+                    );
+                }
+                Task::Build(ir) => {
+                    let children = done.split_off(done.len() - ir.children().len());
+                    let mut children =
+                        ir.children().into_iter().zip(children).map(|(old, item)| {
+                            Box::new(Meta {
+                                item,
+                                at: old.at,
+                                sugar: old.sugar,
+                            })
+                        });
+                    let mut next = || children.next().expect("every child was cloned");
+                    done.push(match ir {
+                        IrComponent::Var(id) => IrComponent::Var(*id),
+                        IrComponent::App(..) => IrComponent::App(next(), next()),
+                        IrComponent::Abs(v, _) => IrComponent::Abs(*v, next()),
+                        IrComponent::Def(_) => IrComponent::Def(next()),
+                        IrComponent::Pending => IrComponent::Pending,
+                        IrComponent::Binding => IrComponent::Binding,
+                        IrComponent::Const(c) => IrComponent::Const(*c),
+                        IrComponent::Cast(_) => IrComponent::Cast(next()),
+                    });
+                }
+            }
+        }
+        done.pop().expect("the root was cloned")
+    }
 }
 
 #[derive(Error, Debug, Diagnostic)]
@@ -58,7 +156,7 @@ pub enum Error {
     #[error("duplicated definition of {name:?}")]
     #[diagnostic(
         code(ir::duplicated_definition),
-        help("shadowing is only allow in function scopes")
+        help("a name is defined once in an input. A later input defining it again replaces it")
     )]
     DuplicatedDefinition {
         name: Box<str>,
@@ -69,36 +167,322 @@ pub enum Error {
         #[label("afterwards, it's again defined here")]
         second: SourceSpan,
     },
+
+    #[error("{name:?} is defined in terms of itself: {cycle}")]
+    #[diagnostic(
+        code(ir::recursive_definition),
+        help(
+            "recursion needs a fixed-point combinator. `set recursive-defs y-combinator` inserts one where a definition refers to itself"
+        )
+    )]
+    RecursiveDefinition {
+        name: Box<str>,
+        cycle: String,
+
+        #[label("this definition goes back to itself")]
+        at: SourceSpan,
+    },
+
+    #[error("{name:?} isn't defined")]
+    #[diagnostic(code(ir::undefined), help("`context` lists what's defined"))]
+    Undefined { name: Box<str> },
+
+    #[error("{op:?} is missing an operand")]
+    #[diagnostic(
+        code(ir::missing_operand),
+        help(
+            "an infix operator goes between two terms. Parenthesis, as `({op})`, take it as a term"
+        )
+    )]
+    MissingOperand {
+        op: Box<str>,
+
+        #[label("there's nothing on one of its sides")]
+        at: SourceSpan,
+    },
+
+    #[error("{first:?} and {second:?} can't be chained")]
+    #[diagnostic(
+        code(ir::mixed_operators),
+        help(
+            "operators of the same precedence only chain if both are `infixl` or both `infixr`. Add parenthesis"
+        )
+    )]
+    MixedOperators {
+        first: Box<str>,
+        second: Box<str>,
+
+        #[label("this one")]
+        first_at: SourceSpan,
+
+        #[label("and this one")]
+        second_at: SourceSpan,
+    },
+
+    #[error("{ty:?} isn't a type")]
+    #[diagnostic(
+        code(ir::bad_annotation),
+        help("a type is made of variables, `->`, `*`, `+`, `unit`, `void` and `dyn`")
+    )]
+    BadAnnotation {
+        ty: Box<str>,
+
+        #[label("this annotation")]
+        at: SourceSpan,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+/// what becomes of a definition referring to itself
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Recursion {
+    /// it's an error, as a term can't contain itself
+    #[default]
+    Reject,
+    /// `f = .. f ..` is read as `f = Y (λf. .. f ..)`. Cycles through other definitions are still
+    /// rejected
+    YCombinator,
+}
+
+impl TryFrom<&str> for Recursion {
+    type Error = ();
+
+    fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
+        match value {
+            "reject" => Ok(Self::Reject),
+            "y-combinator" => Ok(Self::YCombinator),
+            _ => Err(()),
+        }
+    }
+}
+
 /// the first
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct IrCompiler {
     pub scope: Scope,
+    pub recursion: Recursion,
+    /// reads numbers and `+ - * if0` as native constants, unless they're defined
+    pub primitives: bool,
+    /// the fixities of the operators declared infix
+    pub operators: infix::Operators,
+    /// the `fix` primitive, which recursion is lowered to in place of the Y combinator, as it's
+    /// typed while the Y combinator isn't
+    pub fix: Option<Id>,
 }
 
 impl IrCompiler {
-    pub fn compile(&mut self, ast: Node, src: &str) -> Result<IrObj> {
-        match ast.item {
-            Ast::Var => {
-                Ok(IrComponent::Var(self.scope.get_or_reserve(ast.from_code(src))?).at(ast.at))
+    /// works through an explicit stack instead of recursion, so any nesting depth fits
+    pub fn compile(&mut self, ast: Meta<Ast>, src: &str) -> Result<IrObj> {
+        let mut tasks = vec![Task::Visit(ast)];
+        let mut done: Vec<IrObj> = Vec::new();
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Visit(mut ast) => match &mut ast.item {
+                    Ast::Var => match self.constant(ast.from_code(src)) {
+                        Some(c) => done.push(IrComponent::Const(c).at(ast.at)),
+                        None => match self.scope.get_or_reserve(ast.from_code(src)) {
+                            Ok(id) => done.push(IrComponent::Var(id).at(ast.at)),
+                            Err(e) => {
+                                self.unwind(tasks);
+                                return Err(e);
+                            }
+                        },
+                    },
+                    Ast::App(l, r) => {
+                        tasks.push(Task::App(ast.at, ast.sugar));
+                        tasks.push(Task::Visit(r.take()));
+                        tasks.push(Task::Visit(l.take()));
+                    }
+                    Ast::Abs(v, inner) => {
+                        let name = self
+                            .scope
+                            .definitions
+                            .intern(crate::lexer::from_code(*v, src));
+                        let (id, old_id) = self.bind(name, ast.at);
+                        tasks.push(Task::Abs {
+                            at: ast.at,
+                            sugar: ast.sugar,
+                            id,
+                            name,
+                            old_id,
+                        });
+                        tasks.push(Task::Visit(inner.take()));
+                    }
+                    Ast::Annotated { name, ty, body } => {
+                        let text = crate::lexer::from_code(*ty, src);
+                        let Ok(annotated) = Type::parse(text) else {
+                            self.unwind(tasks);
+                            return Err(Error::BadAnnotation {
+                                ty: text.into(),
+                                at: *ty,
+                            });
+                        };
+                        let name = self
+                            .scope
+                            .definitions
+                            .intern(crate::lexer::from_code(*name, src));
+                        let (id, old_id) = self.bind(name, ast.at);
+                        self.scope.annotations.insert(
+                            id,
+                            Annotation {
+                                ty: annotated,
+                                at: *ty,
+                            },
+                        );
+                        tasks.push(Task::Abs {
+                            at: ast.at,
+                            sugar: ast.sugar,
+                            id,
+                            name,
+                            old_id,
+                        });
+                        tasks.push(Task::Visit(body.take()));
+                    }
+                    // `fn p => p (fn x q => q (fn y z => body))`, for `(x, y, z)`
+                    Ast::Unpack { names, body } => {
+                        let last = names.len() - 1;
+                        for (i, v) in names.iter().enumerate() {
+                            if i < last {
+                                let pair = self.scope.push_res(IrComponent::Binding.at(ast.at));
+                                tasks.push(Task::Unpack { at: ast.at, pair });
+                            }
+                            let name = self
+                                .scope
+                                .definitions
+                                .intern(crate::lexer::from_code(*v, src));
+                            let (id, old_id) = self.bind(name, ast.at);
+                            tasks.push(Task::Abs {
+                                at: ast.at,
+                                sugar: Some(Sugar::Pattern),
+                                id,
+                                name,
+                                old_id,
+                            });
+                        }
+                        tasks.push(Task::Visit(body.take()));
+                    }
+                    // `(fn f => body) value`, with `value` as `Y (fn f => value)` if it's recursive
+                    Ast::Let {
+                        rec,
+                        ident,
+                        value,
+                        body,
+                    } => {
+                        let sugar = if *rec { Sugar::LetRec } else { Sugar::Let };
+                        let body = Ast::Abs(*ident, Box::new(body.take())).lowered(ast.at, sugar);
+                        let value = match rec {
+                            true => Ast::Abs(*ident, Box::new(value.take())).lowered(ast.at, sugar),
+                            false => Box::new(value.take()),
+                        };
+                        tasks.push(Task::App(ast.at, Some(sugar)));
+                        if *rec {
+                            tasks.push(Task::Fix(ast.at));
+                        }
+                        tasks.push(Task::Visit(*value));
+                        tasks.push(Task::Visit(*body));
+                    }
+                    Ast::Tuple(items) | Ast::List(items) => {
+                        let items = std::mem::take(items);
+                        let list = matches!(ast.item, Ast::List(..));
+                        let (at, len) = (ast.at, items.len());
+                        tasks.push(Task::Data { at, len, list });
+                        tasks.extend(items.into_iter().rev().map(|i| Task::Visit(*i)));
+                    }
+                    Ast::Infix { items, operators } => {
+                        let items = std::mem::take(items);
+                        match infix::resolve(items, operators, &self.operators, src) {
+                            Ok(node) => tasks.push(Task::Visit(*node)),
+                            Err(e) => {
+                                self.unwind(tasks);
+                                return Err(e);
+                            }
+                        }
+                    }
+                    Ast::Def { .. } | Ast::Program(..) => unimplemented!(),
+                },
+                Task::App(at, sugar) => {
+                    let r = done.pop().expect("the argument was compiled");
+                    let l = done.pop().expect("the function was compiled");
+                    done.push(lower(IrComponent::App(l, r), at, sugar));
+                }
+                Task::Fix(at) => {
+                    let f = done.pop().expect("the function was compiled");
+                    let y = self.y_combinator(at);
+                    done.push(IrComponent::App(y, f).lowered(at, Sugar::LetRec));
+                }
+                Task::Data {
+                    len: 0,
+                    list: false,
+                    at,
+                } => done.push(self.unit(at)),
+                Task::Data { at, len, list } => {
+                    let mut items = done.split_off(done.len() - len);
+                    let sugar = if list { Sugar::List } else { Sugar::Tuple };
+                    let last = match list {
+                        true => self.nil(at),
+                        false => items.pop().expect("a tuple has two elements or more"),
+                    };
+                    let data = items
+                        .into_iter()
+                        .rev()
+                        .fold(last, |rest, item| self.pair(at, sugar, item, rest));
+                    done.push(data);
+                }
+                Task::Unpack { at, pair } => {
+                    let f = done.pop().expect("the function was compiled");
+                    let applied = IrComponent::App(IrComponent::Var(pair).at(at), f);
+                    let applied = applied.lowered(at, Sugar::Pattern);
+                    done.push(IrComponent::Abs(pair, applied).lowered(at, Sugar::Pattern));
+                }
+                Task::Abs {
+                    at,
+                    sugar,
+                    id,
+                    name,
+                    old_id,
+                } => {
+                    self.unbind(name, old_id);
+                    let inner = done.pop().expect("the body was compiled");
+                    done.push(lower(IrComponent::Abs(id, inner), at, sugar));
+                }
             }
-            Ast::App(l, r) => {
-                Ok(IrComponent::App(self.compile(l, src)?, self.compile(r, src)?).at(ast.at))
+        }
+        Ok(done.pop().expect("the root was compiled"))
+    }
+
+    /// the constant `name` is read as, if primitives are on and nothing else is called so. A
+    /// name only referred to, and never defined, doesn't count
+    fn constant(&self, name: &str) -> Option<Const> {
+        let defined = |id: &Id| self.scope.res_pool[id.0].item != IrComponent::Pending;
+        if !self.primitives || self.scope.definitions.get(name).is_some_and(defined) {
+            return None;
+        }
+        Const::parse(name)
+    }
+
+    /// gives back the names bound by the abstractions that were left halfway
+    fn unwind(&mut self, tasks: Vec<Task>) {
+        for task in tasks.into_iter().rev() {
+            if let Task::Abs { name, old_id, .. } = task {
+                self.unbind(name, old_id);
             }
-            Ast::Abs(v, inner) => self.guard(crate::lexer::from_code(v, src), ast.at, |s, id| {
-                Ok(IrComponent::Abs(id, s.compile(inner, src)?).at(ast.at))
-            }),
-            Ast::Def { .. } | Ast::Program(..) => unimplemented!(),
         }
     }
 
-    pub fn compile_program(&mut self, ast: Node, src: &str) -> Result<()> {
-        if let Ast::Program(steps) = ast.item {
+    pub fn compile_program(&mut self, mut ast: Meta<Ast>, src: &str) -> Result<()> {
+        if let Ast::Program(steps) = &mut ast.item {
             for step in steps {
-                match step.item {
-                    Ast::Var | Ast::App(..) | Ast::Abs(..) => {
+                match &mut step.item {
+                    Ast::Var
+                    | Ast::App(..)
+                    | Ast::Abs(..)
+                    | Ast::Unpack { .. }
+                    | Ast::Annotated { .. }
+                    | Ast::Let { .. }
+                    | Ast::Tuple(..)
+                    | Ast::List(..)
+                    | Ast::Infix { .. } => {
                         return Err(Error::ForbiddenExprPlacement { at: step.at });
                     }
                     Ast::Def {
@@ -106,18 +490,50 @@ impl IrCompiler {
                         params,
                         body,
                     } => {
-                        let reorganized_abs = params.into_iter().rev().fold(body, |abs, param| {
+                        let body = Box::new(body.take());
+                        let reorganized_abs = params.iter().rev().fold(body, |abs, &param| {
                             let abs_at = abs.at;
                             crate::lexer::Meta {
                                 item: Ast::Abs(param, abs),
                                 at: crate::lexer::over(param, abs_at),
+                                sugar: None,
                             }
                             .into()
                         });
-                        let inner = self.compile(reorganized_abs, src)?;
+                        let name = crate::lexer::from_code(*ident, src);
+                        let reserved = self.scope.definitions.contains_key(name);
+                        let inner = match self.recursion {
+                            Recursion::Reject => self.compile(*reorganized_abs, src)?,
+                            Recursion::YCombinator => {
+                                let (id, inner) = self.guard(name, *ident, |s, id| {
+                                    Ok((id, s.compile(*reorganized_abs, src)?))
+                                })?;
+                                if refers_to(&inner, id) {
+                                    let y = self.y_combinator(step.at);
+                                    let f = IrComponent::Abs(id, inner).at(step.at);
+                                    IrComponent::App(y, f).at(step.at)
+                                } else {
+                                    inner
+                                }
+                            }
+                        };
                         let obj = IrComponent::Def(inner).at(step.at);
-                        self.scope
-                            .push(crate::lexer::from_code(ident, src).into(), obj)?;
+                        let id = self.scope.push(name, obj)?;
+                        if let Some(cycle) = self.scope.cycle(id) {
+                            let aliases = self.scope.get_aliases();
+                            let names: Vec<_> = cycle.iter().map(|c| &*aliases[c]).collect();
+                            // what referred to it before keeps waiting for a definition
+                            if reserved {
+                                self.scope.res_pool[id.0] = IrComponent::Pending.generated();
+                            } else {
+                                self.scope.definitions.remove(name);
+                            }
+                            return Err(Error::RecursiveDefinition {
+                                name: name.into(),
+                                cycle: names.join(" -> "),
+                                at: step.at,
+                            });
+                        }
                     }
                     Ast::Program(..) => unreachable!(),
                 }
@@ -128,58 +544,243 @@ impl IrCompiler {
         }
     }
 
+    /// `λu. (λv. u (v v)) (λv. u (v v))`, or `fix` if it's defined
+    fn y_combinator(&mut self, at: SourceSpan) -> IrObj {
+        if let Some(fix) = self.fix {
+            return IrComponent::Var(fix).at(at);
+        }
+        let u = self.scope.push_res(IrComponent::Binding.at(at));
+        let mut half = || {
+            let v = self.scope.push_res(IrComponent::Binding.at(at));
+            let vv = IrComponent::App(IrComponent::Var(v).at(at), IrComponent::Var(v).at(at));
+            let body = IrComponent::App(IrComponent::Var(u).at(at), vv.at(at));
+            IrComponent::Abs(v, body.at(at)).at(at)
+        };
+        let (l, r) = (half(), half());
+        IrComponent::Abs(u, IrComponent::App(l, r).at(at)).at(at)
+    }
+
+    /// `λp. p a b`
+    fn pair(&mut self, at: SourceSpan, sugar: Sugar, a: IrObj, b: IrObj) -> IrObj {
+        let p = self.scope.push_res(IrComponent::Binding.at(at));
+        let pa = IrComponent::App(IrComponent::Var(p).at(at), a).lowered(at, sugar);
+        let pab = IrComponent::App(pa, b).lowered(at, sugar);
+        IrComponent::Abs(p, pab).lowered(at, sugar)
+    }
+
+    /// `λp. p`, what the `unit` primitive is, but typed as `unit` and printed back as `()`
+    fn unit(&mut self, at: SourceSpan) -> IrObj {
+        let p = self.scope.push_res(IrComponent::Binding.at(at));
+        let body = IrComponent::Var(p).lowered(at, Sugar::Unit);
+        IrComponent::Abs(p, body).lowered(at, Sugar::Unit)
+    }
+
+    /// `λp. λt. λf. t`, so `l (λh. λt. false)` is `true` only for it
+    fn nil(&mut self, at: SourceSpan) -> IrObj {
+        let [p, t, f] = [(); 3].map(|_| self.scope.push_res(IrComponent::Binding.at(at)));
+        let body = IrComponent::Abs(f, IrComponent::Var(t).at(at)).lowered(at, Sugar::List);
+        let body = IrComponent::Abs(t, body).lowered(at, Sugar::List);
+        IrComponent::Abs(p, body).lowered(at, Sugar::List)
+    }
+
     pub fn guard<T>(
         &mut self,
         name: &str,
         binding_span: SourceSpan,
         f: impl FnOnce(&mut Self, Id) -> T,
     ) -> T {
-        let id = self.scope.push_res(IrComponent::Binding.at(binding_span));
-        let old_id = if let Some(old_id) = self.scope.definitions.get_mut(name) {
-            let mut new_id = id;
-            std::mem::swap(&mut new_id, old_id);
-            Some(new_id)
-        } else {
-            self.scope.definitions.insert(name.into(), id);
-            None
-        };
+        let name = self.scope.definitions.intern(name);
+        let (id, old_id) = self.bind(name, binding_span);
         let r = f(self, id);
+        self.unbind(name, old_id);
+        r
+    }
+
+    /// makes `name` a new binding, returning it and whatever `name` was before
+    fn bind(&mut self, name: Symbol, binding_span: SourceSpan) -> (Id, Option<Id>) {
+        let id = self.scope.push_res(IrComponent::Binding.at(binding_span));
+        let old_id = self.scope.definitions.ids.insert(name, id);
+        (id, old_id)
+    }
+
+    fn unbind(&mut self, name: Symbol, old_id: Option<Id>) {
         if let Some(old_id) = old_id {
-            *self.scope.definitions.get_mut(name).unwrap() = old_id;
+            self.scope.definitions.ids.insert(name, old_id);
         } else {
-            self.scope.definitions.remove(name);
+            self.scope.definitions.ids.remove(&name);
         }
-        r
     }
 }
 
-#[derive(Default, Debug)]
+/// what's left to do in `IrCompiler::compile`
+enum Task {
+    Visit(Meta<Ast>),
+    /// builds an application out of the last two compiled terms
+    App(SourceSpan, Option<Sugar>),
+    /// applies a fixed-point combinator to the last compiled term
+    Fix(SourceSpan),
+    /// nests the last `len` compiled terms in pairs, the last one ending a list if `list`
+    Data {
+        at: SourceSpan,
+        len: usize,
+        list: bool,
+    },
+    /// `λp. p f`, with `f` the last compiled term
+    Unpack {
+        at: SourceSpan,
+        pair: Id,
+    },
+    /// builds an abstraction out of the last compiled term, unbinding its variable
+    Abs {
+        at: SourceSpan,
+        sugar: Option<Sugar>,
+        id: Id,
+        name: Symbol,
+        old_id: Option<Id>,
+    },
+}
+
+/// `item` at `at`, marked as lowered from `sugar` if there's one
+fn lower(item: IrComponent, at: SourceSpan, sugar: Option<Sugar>) -> IrObj {
+    match sugar {
+        Some(sugar) => item.lowered(at, sugar),
+        None => item.at(at),
+    }
+}
+
+/// whether `ir` has a variable of `id`
+pub fn refers_to(ir: &IrObj, id: Id) -> bool {
+    let mut stack = vec![ir];
+    while let Some(ir) = stack.pop() {
+        match &ir.item {
+            IrComponent::Var(v) if *v == id => return true,
+            IrComponent::App(l, r) => stack.extend([l, r]),
+            IrComponent::Abs(_, inner) | IrComponent::Def(inner) | IrComponent::Cast(inner) => {
+                stack.push(inner)
+            }
+            _ => (),
+        }
+    }
+    false
+}
+
+/// how many nodes `ir` has
+pub fn size(ir: &IrObj) -> usize {
+    let mut size = 0;
+    let mut stack = vec![ir];
+    while let Some(ir) = stack.pop() {
+        size += 1;
+        match &ir.item {
+            IrComponent::App(l, r) => stack.extend([l, r]),
+            IrComponent::Abs(_, inner) | IrComponent::Def(inner) | IrComponent::Cast(inner) => {
+                stack.push(inner)
+            }
+            _ => (),
+        }
+    }
+    size
+}
+
+/// what each name in scope stands for. Names are interned, so the compiler looks them up by
+/// `Symbol`, while everyone else may keep using strings
+#[derive(Default, Debug, Clone)]
+pub struct Definitions {
+    pub symbols: Interner,
+    ids: HashMap<Symbol, Id>,
+}
+
+impl Definitions {
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        self.symbols.intern(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Id> {
+        self.ids.get(&self.symbols.get(name)?)
+    }
+
+    pub fn get_symbol(&self, sym: Symbol) -> Option<&Id> {
+        self.ids.get(&sym)
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn insert(&mut self, name: &str, id: Id) -> Option<Id> {
+        let sym = self.intern(name);
+        self.ids.insert(sym, id)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Id> {
+        self.ids.remove(&self.symbols.get(name)?)
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(&str, &mut Id) -> bool) {
+        let symbols = &self.symbols;
+        self.ids.retain(|sym, id| f(symbols.resolve(*sym), id));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Id)> {
+        self.ids
+            .iter()
+            .map(|(sym, id)| (self.symbols.resolve(*sym), id))
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Id> {
+        self.ids.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+impl Index<&str> for Definitions {
+    type Output = Id;
+
+    fn index(&self, name: &str) -> &Id {
+        self.get(name)
+            .unwrap_or_else(|| panic!("`{name}` isn't defined"))
+    }
+}
+
+/// the type a binder was annotated with, as in `fn (x : dyn) => x x`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    pub ty: Type,
+    pub at: SourceSpan,
+}
+
+#[derive(Default, Debug, Clone)]
 pub struct Scope {
     pub res_pool: Vec<IrObj>,
-    pub definitions: HashMap<Box<str>, Id>,
+    pub definitions: Definitions,
+    /// the annotated bindings, by their id
+    pub annotations: HashMap<Id, Annotation>,
 }
 
 impl Scope {
-    pub fn push(&mut self, name: Box<str>, res: IrObj) -> Result<Id> {
-        let id = self.push_res(res);
-        match self.definitions.entry(name) {
-            Entry::Vacant(e) => {
-                e.insert(id);
-            }
-            Entry::Occupied(mut e) => {
-                let old = &self.res_pool[e.get().0];
-                if !matches!(old.item, IrComponent::Pending) {
-                    let e = Error::DuplicatedDefinition {
-                        name: e.key().clone(),
-                        first: old.at,
-                        second: self.res_pool[id.0].at,
-                    };
-                    return Err(e);
-                } else {
-                    e.insert(id);
-                }
+    pub fn push(&mut self, name: &str, res: IrObj) -> Result<Id> {
+        let sym = self.definitions.intern(name);
+        if let Some(old) = self.definitions.get_symbol(sym).copied() {
+            let first = &self.res_pool[old.0];
+            if !matches!(first.item, IrComponent::Pending) {
+                return Err(Error::DuplicatedDefinition {
+                    name: name.into(),
+                    first: first.at,
+                    second: res.at,
+                });
             }
+            // whatever referred to it before it was defined now finds it
+            self.res_pool[old.0] = res;
+            return Ok(old);
         }
+        let id = self.push_res(res);
+        self.definitions.ids.insert(sym, id);
         Ok(id)
     }
 
@@ -192,10 +793,11 @@ impl Scope {
     /// if `Scope` doesn't have a resource, it doesn't mean the resource doesn't exist
     /// it only means it hasn't been evaluated yet
     pub fn get_or_reserve(&mut self, s: &str) -> Result<Id> {
-        if let Some(id) = self.definitions.get(s) {
+        let sym = self.definitions.intern(s);
+        if let Some(id) = self.definitions.get_symbol(sym) {
             Ok(*id)
         } else {
-            self.push(s.into(), IrComponent::Pending.generated())
+            self.push(s, IrComponent::Pending.generated())
         }
     }
 
@@ -210,63 +812,138 @@ impl Scope {
         Ok(())
     }
 
-    pub fn pretty_print(&self, ir: &IrObj) {
-        let mut binding_stack = Vec::new();
+    /// the definitions `id` refers to, in the order they show up
+    pub fn dependencies(&self, id: Id) -> Vec<Id> {
+        self.references(&self.res_pool[id.0])
+    }
+
+    /// the definitions `ir` refers to, in the order they show up: its free variables, before
+    /// they're replaced by what they stand for
+    pub fn references(&self, ir: &IrObj) -> Vec<Id> {
+        let mut deps = Vec::new();
+        let mut stack = vec![ir];
+        while let Some(ir) = stack.pop() {
+            match &ir.item {
+                IrComponent::Var(v)
+                    if matches!(self.res_pool[v.0].item, IrComponent::Def(..))
+                        && !deps.contains(v) =>
+                {
+                    deps.push(*v);
+                }
+                IrComponent::App(l, r) => stack.extend([r, l]),
+                IrComponent::Abs(_, inner) | IrComponent::Def(inner) | IrComponent::Cast(inner) => {
+                    stack.push(inner)
+                }
+                _ => (),
+            }
+        }
+        deps
+    }
+
+    /// a chain of definitions going from `id` back to itself, each one referring to the next
+    pub fn cycle(&self, id: Id) -> Option<Vec<Id>> {
+        let mut visited = vec![id];
+        let mut stack = vec![(id, self.dependencies(id))];
+        while let Some((_, deps)) = stack.last_mut() {
+            match deps.pop() {
+                Some(d) if d == id => {
+                    let mut path: Vec<_> = stack.iter().map(|(d, _)| *d).collect();
+                    path.push(id);
+                    return Some(path);
+                }
+                Some(d) if !visited.contains(&d) => {
+                    visited.push(d);
+                    stack.push((d, self.dependencies(d)));
+                }
+                Some(_) => (),
+                None => {
+                    stack.pop();
+                }
+            }
+        }
+        None
+    }
+
+    /// the definitions that refer to `id`
+    pub fn dependents(&self, id: Id) -> Vec<Id> {
+        let mut deps: Vec<_> = self
+            .definitions
+            .values()
+            .filter(|d| self.dependencies(**d).contains(&id))
+            .copied()
+            .collect();
+        deps.sort();
+        deps
+    }
+
+    /// `ir` with the names of its variables, and of the definitions it uses
+    pub fn pretty(&self, ir: &IrObj, notation: Notation) -> String {
         let aliases = self.get_aliases();
-        self.buff_pretty_print(&aliases, &mut binding_stack, ir);
-        println!();
+        printer::print(ir, notation, |ir| self.view(&aliases, ir, &Self::id_to_str))
     }
 
     pub fn get_aliases(&self) -> HashMap<Id, Box<str>> {
         self.definitions
             .iter()
-            .map(|(l, r)| (*r, l.clone()))
+            .map(|(l, r)| (*r, l.into()))
             .collect()
     }
 
-    pub fn buff_pretty_print(
-        &self,
-        aliases: &HashMap<Id, Box<str>>,
-        binding_stack: &mut Vec<Id>,
-        ir: &IrObj,
-    ) {
-        match &ir.item {
-            IrComponent::Pending => print!("..."),
-            IrComponent::Binding => {
-                unreachable!()
-            }
-            IrComponent::Def(def) => self.buff_pretty_print(aliases, binding_stack, def),
-            IrComponent::Var(id) => {
-                if let Some(alias) = aliases.get(id) {
-                    print!("{alias}")
-                } else if let Some(v) = binding_stack.iter().find(|i| id == *i) {
-                    print!("{}", Self::id_to_str(v));
-                } else {
-                    self.buff_pretty_print(aliases, binding_stack, &self.res_pool[id.0])
-                }
+    /// prints `ir` back in a source syntax, so it can be parsed again: `fn` for `qk`, and `λ` or
+    /// `\` for `classic`. Bound variables are named so they don't capture a definition
+    pub fn to_source(&self, ir: &IrObj, notation: Notation) -> String {
+        let aliases = self.get_aliases();
+        let name = |id: &Id| self.binding_name(&aliases, id);
+        printer::print(ir, notation, |ir| self.view(&aliases, ir, &name))
+    }
+
+    /// what `ir` is to the printer, with the variables bound called after `name`. A definition
+    /// without an alias is printed in place
+    fn view<'a>(
+        &'a self,
+        aliases: &'a HashMap<Id, Box<str>>,
+        mut ir: &'a IrObj,
+        name: &dyn Fn(&Id) -> String,
+    ) -> View<'a, &'a IrObj> {
+        loop {
+            if ir.sugar == Some(Sugar::Unit) {
+                return View::Atom("()".into());
             }
-            IrComponent::App(l, r) => {
-                let r_is_app = matches!(r.item, IrComponent::App(..));
-                if r_is_app {
-                    print!("(");
+            match &ir.item {
+                IrComponent::Pending => return View::Atom("...".into()),
+                IrComponent::Binding => unreachable!(),
+                IrComponent::Const(c) => return View::Atom(c.to_string().into()),
+                IrComponent::Def(def) | IrComponent::Cast(def) => ir = def,
+                IrComponent::Var(id) => {
+                    if let Some(alias) = aliases.get(id) {
+                        return View::Atom(Cow::Borrowed(alias));
+                    }
+                    if self.res_pool[id.0].item == IrComponent::Binding {
+                        return View::Atom(name(id).into());
+                    }
+                    ir = &self.res_pool[id.0];
                 }
-                self.buff_pretty_print(aliases, binding_stack, l);
-                print!(" ");
-                self.buff_pretty_print(aliases, binding_stack, r);
-                if r_is_app {
-                    print!(")");
+                IrComponent::App(l, r) => return View::App(l, r),
+                IrComponent::Abs(v, inner) => {
+                    let binder = match self.annotations.get(v) {
+                        Some(a) => format!("({} : {})", name(v), a.ty),
+                        None => name(v),
+                    };
+                    return View::Abs(binder.into(), inner);
                 }
             }
-            IrComponent::Abs(v, inner) => {
-                binding_stack.push(*v);
-                let id_str = Self::id_to_str(v);
-                print!("λ{id_str}.");
-                self.buff_pretty_print(aliases, binding_stack, inner);
-                binding_stack.pop();
-            }
         }
     }
 
+    /// `id_to_str`, but avoiding to capture a definition with the same name
+    fn binding_name(&self, aliases: &HashMap<Id, Box<str>>, id: &Id) -> String {
+        let mut name = Self::id_to_str(id);
+        while aliases.values().any(|a| **a == *name) {
+            name.push('_');
+        }
+        name
+    }
+
     pub fn id_to_str(id: &Id) -> String {
         const ALPHABET: &str = "abcdefghijklmnopqrstuvwxyz";
         static CHARS: LazyLock<Vec<char>> = LazyLock::new(|| ALPHABET.chars().collect());
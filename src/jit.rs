@@ -0,0 +1,214 @@
+use cranelift_codegen::ir::{AbiParam, InstBuilder, Signature, Value, types::I64};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{FuncId, Linkage, Module, default_libcall_names};
+
+use crate::arts::{Const, Prim};
+use crate::lift::{Expr, Lifted};
+
+/// which supercombinators only compute numbers: their parameters are numbers, and so is their
+/// body, with every primitive and supercombinator in it given all it takes. Those are the ones
+/// compiled to native code
+pub fn first_order(lifted: &Lifted) -> Vec<bool> {
+    let mut ok = Vec::with_capacity(lifted.combinators.len());
+    // a supercombinator only refers to the ones lifted before it
+    for comb in &lifted.combinators {
+        let body = numeric(lifted, &ok, &comb.body);
+        ok.push(body);
+    }
+    ok
+}
+
+/// whether `e` computes a number, out of the supercombinators in `ok`
+fn numeric(lifted: &Lifted, ok: &[bool], e: &Expr) -> bool {
+    let (head, args) = spine(e);
+    let saturated = match head {
+        Expr::Param(_) | Expr::Const(Const::Int(_)) => args.is_empty(),
+        // what's cast is a function, if it doesn't fail
+        Expr::Const(Const::Prim(Prim::Cast(_))) => false,
+        Expr::Const(Const::Prim(p)) => args.len() == p.arity(),
+        Expr::Comb(k) => ok.get(*k) == Some(&true) && args.len() == lifted.combinators[*k].arity,
+        Expr::App(..) => unreachable!("the head of a spine isn't an application"),
+    };
+    saturated && args.iter().all(|a| numeric(lifted, ok, a))
+}
+
+fn spine(mut e: &Expr) -> (&Expr, Vec<&Expr>) {
+    let mut args = Vec::new();
+    while let Expr::App(l, r) = e {
+        args.push(&**r);
+        e = l;
+    }
+    args.reverse();
+    (e, args)
+}
+
+/// compiles `main` and the first-order supercombinators to native code, and runs it. `None` if
+/// `main` isn't a number, or cranelift doesn't support this machine, so it's left to the
+/// interpreters
+pub fn run(lifted: &Lifted) -> Option<i64> {
+    let ok = first_order(lifted);
+    if !numeric(lifted, &ok, &lifted.main) {
+        return None;
+    }
+    let mut flags = settings::builder();
+    flags.set("use_colocated_libcalls", "false").ok()?;
+    flags.set("is_pic", "false").ok()?;
+    let isa = cranelift_native::builder()
+        .ok()?
+        .finish(settings::Flags::new(flags))
+        .ok()?;
+    let mut jit = Jit {
+        module: JITModule::new(JITBuilder::with_isa(isa, default_libcall_names())),
+        lifted,
+        funcs: Vec::new(),
+    };
+    let main = jit.compile(&ok);
+    let n = main.map(|main| {
+        let code = jit.module.get_finalized_function(main);
+        // SAFETY: `main` was defined with no parameters and an `i64` result
+        let main = unsafe { std::mem::transmute::<*const u8, extern "C" fn() -> i64>(code) };
+        main()
+    });
+    // SAFETY: nothing compiled is used from now on
+    unsafe { jit.module.free_memory() };
+    n
+}
+
+struct Jit<'l> {
+    module: JITModule,
+    lifted: &'l Lifted,
+    /// the function of each supercombinator, if it's first-order
+    funcs: Vec<Option<FuncId>>,
+}
+
+impl Jit<'_> {
+    fn signature(&self, params: usize) -> Signature {
+        let mut sig = self.module.make_signature();
+        sig.params.extend((0..params).map(|_| AbiParam::new(I64)));
+        sig.returns.push(AbiParam::new(I64));
+        sig
+    }
+
+    /// defines every first-order supercombinator, then `main`, which it returns
+    fn compile(&mut self, ok: &[bool]) -> Option<FuncId> {
+        let lifted = self.lifted;
+        for (k, comb) in lifted.combinators.iter().enumerate() {
+            let mut id = None;
+            if ok[k] {
+                let sig = self.signature(comb.arity);
+                let name = format!("sc{k}");
+                id = Some(
+                    self.module
+                        .declare_function(&name, Linkage::Local, &sig)
+                        .ok()?,
+                );
+            }
+            self.funcs.push(id);
+        }
+        let main_sig = self.signature(0);
+        let main = (self.module)
+            .declare_function("main", Linkage::Local, &main_sig)
+            .ok()?;
+        let mut fctx = FunctionBuilderContext::new();
+        for (k, comb) in lifted.combinators.iter().enumerate() {
+            if let Some(id) = self.funcs[k] {
+                self.define(id, comb.arity, &comb.body, &mut fctx)?;
+            }
+        }
+        self.define(main, 0, &lifted.main, &mut fctx)?;
+        self.module.finalize_definitions().ok()?;
+        Some(main)
+    }
+
+    fn define(
+        &mut self,
+        id: FuncId,
+        params: usize,
+        body: &Expr,
+        fctx: &mut FunctionBuilderContext,
+    ) -> Option<()> {
+        let mut ctx = self.module.make_context();
+        ctx.func.signature = self.signature(params);
+        let mut b = FunctionBuilder::new(&mut ctx.func, fctx);
+        let entry = b.create_block();
+        b.append_block_params_for_function_params(entry);
+        b.switch_to_block(entry);
+        b.seal_block(entry);
+        let params = b.block_params(entry).to_vec();
+        let result = self.expr(&mut b, &params, body);
+        b.ins().return_(&[result]);
+        b.finalize();
+        self.module.define_function(id, &mut ctx).ok()?;
+        self.module.clear_context(&mut ctx);
+        Some(())
+    }
+
+    /// the code of a `numeric` expression
+    fn expr(&mut self, b: &mut FunctionBuilder, params: &[Value], e: &Expr) -> Value {
+        let (head, args) = spine(e);
+        match head {
+            Expr::Param(i) => params[*i],
+            Expr::Const(Const::Int(n)) => b.ins().iconst(I64, *n),
+            // only the branch taken is computed
+            Expr::Const(Const::Prim(Prim::If0)) => {
+                let cond = self.expr(b, params, args[0]);
+                let (zero, other, merge) = (b.create_block(), b.create_block(), b.create_block());
+                b.append_block_param(merge, I64);
+                b.ins().brif(cond, other, &[], zero, &[]);
+                for (block, branch) in [(zero, args[1]), (other, args[2])] {
+                    b.switch_to_block(block);
+                    b.seal_block(block);
+                    let v = self.expr(b, params, branch);
+                    b.ins().jump(merge, &[v]);
+                }
+                b.switch_to_block(merge);
+                b.seal_block(merge);
+                b.block_params(merge)[0]
+            }
+            Expr::Const(Const::Prim(p)) => {
+                let (l, r) = (self.expr(b, params, args[0]), self.expr(b, params, args[1]));
+                match p {
+                    Prim::Add => b.ins().iadd(l, r),
+                    Prim::Sub => b.ins().isub(l, r),
+                    Prim::Mul => b.ins().imul(l, r),
+                    Prim::If0 => unreachable!("`if0` is matched before"),
+                    Prim::Cast(_) => unreachable!("a cast isn't numeric"),
+                }
+            }
+            Expr::Comb(k) => {
+                let args: Vec<_> = args.iter().map(|a| self.expr(b, params, a)).collect();
+                let id = self.funcs[*k].expect("a numeric expression only calls first-order ones");
+                let func = self.module.declare_func_in_func(id, b.func);
+                let call = b.ins().call(func, &args);
+                b.inst_results(call)[0]
+            }
+            Expr::App(..) => unreachable!("the head of a spine isn't an application"),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::jit;
+    use crate::lift::Lifted;
+    use crate::session::Session;
+
+    #[test]
+    pub fn runs_first_order_code() {
+        let mut s = Session::new();
+        s.irc.primitives = true;
+        s.eval_str("sq x = * x x").unwrap();
+        let mut run = |src| {
+            let root = s.compile_expr(src).unwrap().unwrap();
+            jit::run(&Lifted::new(&s.art, root).unwrap())
+        };
+        assert_eq!(run("(fn x y => + (sq x) (sq y)) 3 4"), Some(25));
+        assert_eq!(run("if0 (- 1 1) (sq 7) (sq 8)"), Some(49));
+        assert_eq!(run("- 0 9223372036854775807"), Some(-9223372036854775807));
+        // functions aren't numbers
+        assert_eq!(run("fn x => sq x"), None);
+        assert_eq!(run("(fn f => f 2) sq"), None);
+    }
+}
@@ -1,16 +1,95 @@
 use miette::{Diagnostic, SourceSpan};
 use thiserror::Error;
 
+use crate::provenance::Sugar;
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Meta<T> {
     pub item: T,
     pub at: SourceSpan,
+    /// the sugar it was lowered from, if it wasn't written as it is
+    pub sugar: Option<Sugar>,
 }
 
 impl<T> Meta<T> {
     pub fn from_code<'a>(&self, src: &'a str) -> &'a str {
         from_code(self.at, src)
     }
+
+    /// where it starts in `src`, in characters
+    pub fn start(&self, src: &str) -> Option<Position> {
+        position(src, self.at.offset(), Columns::Chars)
+    }
+
+    /// where it ends in `src`, in characters
+    pub fn end(&self, src: &str) -> Option<Position> {
+        position(src, self.at.offset() + self.at.len(), Columns::Chars)
+    }
+}
+
+/// a place in a source, both counted from 0. Shown from 1, as `3:7`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line + 1, self.column + 1)
+    }
+}
+
+/// what a column counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Columns {
+    /// characters, as they're read
+    Chars,
+    /// UTF-16 code units, as LSP counts them
+    Utf16,
+}
+
+impl Columns {
+    fn width(self, c: char) -> usize {
+        match self {
+            Self::Chars => 1,
+            Self::Utf16 => c.len_utf16(),
+        }
+    }
+}
+
+/// the position of the byte `offset` of `src`. `None` if it's out of it
+pub fn position(src: &str, offset: usize, columns: Columns) -> Option<Position> {
+    let before = src.get(..offset)?;
+    let start = before.rfind('\n').map_or(0, |i| i + 1);
+    Some(Position {
+        line: before.matches('\n').count(),
+        column: before[start..].chars().map(|c| columns.width(c)).sum(),
+    })
+}
+
+/// the byte offset of `pos` in `src`. A column past the end of its line is the end of it, and
+/// `None` is for a line past the end of `src`
+pub fn offset(src: &str, pos: Position, columns: Columns) -> Option<usize> {
+    let start = match pos.line {
+        0 => 0,
+        line => src.match_indices('\n').nth(line - 1)?.0 + 1,
+    };
+    let mut units = 0;
+    for (i, c) in src[start..].char_indices() {
+        if units >= pos.column || c == '\n' {
+            return Some(start + i);
+        }
+        units += columns.width(c);
+    }
+    Some(src.len())
+}
+
+/// where `span` starts and ends in `src`
+pub fn positions(span: SourceSpan, src: &str, columns: Columns) -> Option<(Position, Position)> {
+    let start = position(src, span.offset(), columns)?;
+    Some((start, position(src, span.offset() + span.len(), columns)?))
 }
 
 pub trait Trace
@@ -21,6 +100,17 @@ where
         Meta {
             item: self,
             at: span,
+            sugar: None,
+        }
+        .into()
+    }
+
+    /// a node out of `sugar`, which was written at `span`
+    fn lowered(self, span: SourceSpan, sugar: Sugar) -> Box<Meta<Self>> {
+        Meta {
+            item: self,
+            at: span,
+            sugar: Some(sugar),
         }
         .into()
     }
@@ -41,6 +131,11 @@ pub fn over(l: SourceSpan, r: SourceSpan) -> SourceSpan {
     SourceSpan::new(l.offset().into(), r.offset() + r.len() - l.offset())
 }
 
+/// the same span, `by` bytes further
+pub fn shift(s: SourceSpan, by: usize) -> SourceSpan {
+    SourceSpan::new((s.offset() + by).into(), s.len())
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Error, Debug, Diagnostic, Default, Clone, PartialEq)]
@@ -60,3 +155,32 @@ pub enum Error {
     #[diagnostic(code(lexer::other_error), help("this shouldn't happen. contact me"))]
     Other,
 }
+
+#[cfg(test)]
+pub mod tests {
+    use crate::lexer::{Columns, Position, Trace, offset, position, positions};
+
+    #[test]
+    pub fn positions_round_trip() {
+        let src = "I x = x\nλ𝕩. 𝕩 y\n";
+        let at = |line, column| Position { line, column };
+        assert_eq!(position(src, 0, Columns::Chars), Some(at(0, 0)));
+        // `y`, after characters of 2 and 4 bytes, and 2 UTF-16 units for `𝕩`
+        let y = src.rfind('y').unwrap();
+        assert_eq!(position(src, y, Columns::Chars), Some(at(1, 6)));
+        assert_eq!(position(src, y, Columns::Utf16), Some(at(1, 8)));
+        assert_eq!(offset(src, at(1, 6), Columns::Chars), Some(y));
+        assert_eq!(offset(src, at(1, 8), Columns::Utf16), Some(y));
+        assert_eq!(offset(src, at(0, 99), Columns::Chars), Some(7));
+        assert_eq!(offset(src, at(5, 0), Columns::Chars), None);
+        assert_eq!(position(src, 99, Columns::Chars), None);
+
+        let node = ().at((y, 1).into());
+        assert_eq!(node.start(src).unwrap().to_string(), "2:7");
+        assert_eq!(node.end(src), Some(at(1, 7)));
+        assert_eq!(
+            positions((0, 1).into(), src, Columns::Chars),
+            Some((at(0, 0), at(0, 1)))
+        );
+    }
+}
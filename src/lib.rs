@@ -4,11 +4,60 @@
 // https://github.com/rust-lang/rust/issues/147648
 #![allow(unused_assignments)]
 
+pub mod analyze;
 pub mod arts;
 pub mod ast;
+pub mod backend;
+pub mod bohm;
+pub mod builder;
+pub mod cancel;
+pub mod cbpv;
+pub mod closure;
 pub mod compiler;
+pub mod cps;
 pub mod cpu;
+pub mod diagnostics;
+pub mod engine;
+pub mod expect;
+pub mod formatter;
+pub mod frontend;
+pub mod graph;
+pub mod incremental;
+pub mod infix;
 pub mod ir;
 pub mod lexer;
+pub mod lift;
+pub mod lint;
+pub mod nets;
+pub mod predicates;
+pub mod printer;
+pub mod provenance;
+pub mod random;
+pub mod readback;
+pub mod separate;
+pub mod session;
+pub mod source_map;
+pub mod symbol;
+pub mod tree;
+pub mod tune;
+pub mod types;
 
 pub mod padam;
+
+/// invariants the fuzz targets in `fuzz/` check the pipeline against
+#[doc(hidden)]
+pub mod fuzz;
+
+#[cfg(feature = "serde")]
+pub mod lsp;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+/// native code for the supercombinators that only compute numbers
+#[cfg(feature = "jit")]
+pub mod jit;
+
+/// properties every closed term should have, checked on random ones
+#[cfg(test)]
+pub mod properties;
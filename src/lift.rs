@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::arts::{CompArtifact, Const, Prim, Term, TermIdx};
+use crate::cancel::{self, CancelToken};
+
+/// the body of a supercombinator: there are no abstractions left, only its parameters and the
+/// other supercombinators
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Param(usize),
+    Comb(usize),
+    Const(Const),
+    App(Box<Expr>, Box<Expr>),
+}
+
+/// a top-level function, `$k x0 x1.. = body`. The free variables of the abstraction it was lifted
+/// from come first, then its own parameters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Supercombinator {
+    pub arity: usize,
+    pub body: Expr,
+}
+
+/// a closed term, with every abstraction lifted to a supercombinator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lifted {
+    pub combinators: Vec<Supercombinator>,
+    pub main: Expr,
+}
+
+impl Lifted {
+    /// lambda lifts `root`, if it's closed. A maximal chain of abstractions becomes a single
+    /// supercombinator, and an abstraction that's shared in the pool is lifted once
+    pub fn new(art: &CompArtifact, root: TermIdx) -> Option<Self> {
+        let mut lifter = Lifter {
+            art,
+            free: free_variables(art, root),
+            lifted: HashMap::new(),
+            combinators: Vec::new(),
+        };
+        if !lifter.free[&root].is_empty() {
+            return None;
+        }
+        let main = lifter.expr(root, &[]);
+        Some(Self {
+            combinators: lifter.combinators,
+            main,
+        })
+    }
+}
+
+struct Lifter<'a> {
+    art: &'a CompArtifact,
+    /// the variables each term needs from outside, as de Bruijn indices
+    free: HashMap<TermIdx, Vec<usize>>,
+    /// the supercombinator each abstraction became
+    lifted: HashMap<TermIdx, usize>,
+    combinators: Vec<Supercombinator>,
+}
+
+impl Lifter<'_> {
+    /// `idx` inside a supercombinator, where the variable `i` is its parameter `env[i]`
+    fn expr(&mut self, idx: TermIdx, env: &[usize]) -> Expr {
+        match self.art.get(idx) {
+            Term::Var(v) => Expr::Param(env[v.0]),
+            Term::Const(c) => Expr::Const(c),
+            Term::App(l, r) => Expr::App(Box::new(self.expr(l, env)), Box::new(self.expr(r, env))),
+            Term::Abs { .. } => {
+                let comb = self.combinator(idx);
+                // what it closes over is passed first
+                self.free[&idx].iter().fold(Expr::Comb(comb), |f, v| {
+                    Expr::App(Box::new(f), Box::new(Expr::Param(env[*v])))
+                })
+            }
+        }
+    }
+
+    fn combinator(&mut self, abs: TermIdx) -> usize {
+        if let Some(comb) = self.lifted.get(&abs) {
+            return *comb;
+        }
+        let (mut body, mut params) = (abs, 0);
+        while let Term::Abs { inner } = self.art.get(body) {
+            body = inner;
+            params += 1;
+        }
+        let free = self.free[&abs].clone();
+        // the variables that aren't free in the body are never looked up
+        let mut env = vec![usize::MAX; params + free.last().map_or(0, |f| f + 1)];
+        for (i, slot) in env[..params].iter_mut().enumerate() {
+            *slot = free.len() + params - 1 - i;
+        }
+        for (i, f) in free.iter().enumerate() {
+            env[params + f] = i;
+        }
+        let body = self.expr(body, &env);
+        self.combinators.push(Supercombinator {
+            arity: free.len() + params,
+            body,
+        });
+        let comb = self.combinators.len() - 1;
+        self.lifted.insert(abs, comb);
+        comb
+    }
+}
+
+/// the free variables of each term reachable from `root`, sorted. The arena is in order, so the
+/// children are done first
+pub(crate) fn free_variables(art: &CompArtifact, root: TermIdx) -> HashMap<TermIdx, Vec<usize>> {
+    let mut free: HashMap<TermIdx, Vec<usize>> = HashMap::new();
+    for t in art.reachable(&[root]) {
+        let vars = match art.get(t) {
+            Term::Var(v) => vec![v.0],
+            Term::Const(_) => Vec::new(),
+            Term::Abs { inner } => free[&inner]
+                .iter()
+                .filter_map(|v| v.checked_sub(1))
+                .collect(),
+            Term::App(l, r) => {
+                let mut vars = [&free[&l][..], &free[&r][..]].concat();
+                vars.sort_unstable();
+                vars.dedup();
+                vars
+            }
+        };
+        free.insert(t, vars);
+    }
+    free
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Param(i) => write!(f, "x{i}"),
+            Self::Comb(k) => write!(f, "${k}"),
+            Self::Const(c) => write!(f, "{c}"),
+            Self::App(l, r) if matches!(**r, Self::App(..)) => write!(f, "{l} ({r})"),
+            Self::App(l, r) => write!(f, "{l} {r}"),
+        }
+    }
+}
+
+/// a supercombinator a line, then `main`
+impl fmt::Display for Lifted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (k, comb) in self.combinators.iter().enumerate() {
+            write!(f, "${k}")?;
+            for i in 0..comb.arity {
+                write!(f, " x{i}")?;
+            }
+            writeln!(f, " = {}", comb.body)?;
+        }
+        write!(f, "main = {}", self.main)
+    }
+}
+
+/// what `Machine::eval` stops at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Whnf {
+    Int(i64),
+    /// a supercombinator or a primitive applied to fewer arguments than it takes
+    Function,
+    /// a number applied to something, or a primitive given something else than a number
+    Stuck,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Node {
+    App(usize, usize),
+    Comb(usize),
+    Const(Const),
+    /// a redex that was already reduced, to what it points to
+    Ind(usize),
+}
+
+/// a template-instantiation evaluator: the program is a graph, and a saturated supercombinator
+/// is reduced by building an instance of its body, which overwrites the application. So every
+/// argument is shared, and reduced once
+pub struct Machine<'l> {
+    lifted: &'l Lifted,
+    heap: Vec<Node>,
+    /// how many supercombinators were instantiated and primitives computed
+    pub steps: usize,
+}
+
+impl<'l> Machine<'l> {
+    pub fn new(lifted: &'l Lifted) -> Self {
+        // a single node for each supercombinator, shared by every reference
+        let heap = (0..lifted.combinators.len()).map(Node::Comb).collect();
+        Self {
+            lifted,
+            heap,
+            steps: 0,
+        }
+    }
+
+    /// reduces `main` to a weak head normal form, spending a step of `token` for each reduction
+    pub fn eval(&mut self, token: &mut CancelToken) -> cancel::Result<Whnf> {
+        let main = self.instantiate(&self.lifted.main, &[]);
+        let whnf = self.whnf(main, token)?;
+        Ok(self.classify(whnf))
+    }
+
+    fn classify(&self, addr: usize) -> Whnf {
+        let (mut head, mut args) = (addr, 0);
+        loop {
+            head = match self.heap[head] {
+                Node::App(l, _) => {
+                    args += 1;
+                    l
+                }
+                Node::Ind(to) => to,
+                _ => break,
+            };
+        }
+        match self.heap[head] {
+            Node::Const(Const::Int(n)) if args == 0 => Whnf::Int(n),
+            Node::Comb(k) if args < self.lifted.combinators[k].arity => Whnf::Function,
+            Node::Const(Const::Prim(p)) if args < p.arity() => Whnf::Function,
+            _ => Whnf::Stuck,
+        }
+    }
+
+    /// unwinds the spine of `addr` until its head can't be reduced, and returns where the result is
+    fn whnf(&mut self, addr: usize, token: &mut CancelToken) -> cancel::Result<usize> {
+        let mut spine = vec![addr];
+        loop {
+            let top = *spine.last().unwrap();
+            let args = spine.len() - 1;
+            let (arity, redex) = match self.heap[top] {
+                Node::Ind(to) => {
+                    *spine.last_mut().unwrap() = to;
+                    continue;
+                }
+                Node::App(l, _) => {
+                    spine.push(l);
+                    continue;
+                }
+                Node::Comb(k) if args >= self.lifted.combinators[k].arity => {
+                    let arity = self.lifted.combinators[k].arity;
+                    let params = self.args(&spine, arity);
+                    let body = &self.lifted.combinators[k].body;
+                    (arity, self.instantiate(body, &params))
+                }
+                Node::Const(Const::Prim(p)) if args >= p.arity() => {
+                    let params = self.args(&spine, p.arity());
+                    match self.compute(p, &params, token)? {
+                        Some(res) => (p.arity(), res),
+                        None => return Ok(spine[0]),
+                    }
+                }
+                _ => return Ok(spine[0]),
+            };
+            token.tick()?;
+            self.steps += 1;
+            // the application is updated, so whoever else points to it gets the result
+            spine.truncate(spine.len() - arity);
+            let at = *spine.last().unwrap();
+            self.heap[at] = Node::Ind(redex);
+            *spine.last_mut().unwrap() = redex;
+        }
+    }
+
+    /// the right side of the `n` applications above the head of `spine`, the first one first
+    fn args(&self, spine: &[usize], n: usize) -> Vec<usize> {
+        (spine[spine.len() - 1 - n..spine.len() - 1].iter().rev())
+            .map(|app| match self.heap[*app] {
+                Node::App(_, r) => r,
+                _ => unreachable!("the spine has an application above the head"),
+            })
+            .collect()
+    }
+
+    /// the result of `p` on `args`, if the ones it needs are numbers
+    fn compute(
+        &mut self,
+        p: Prim,
+        args: &[usize],
+        token: &mut CancelToken,
+    ) -> cancel::Result<Option<usize>> {
+        if let Prim::Cast(_) = p {
+            // a failed cast is left stuck, for the interpreter to report it
+            let arg = self.whnf(args[0], token)?;
+            return Ok((self.classify(arg) == Whnf::Function).then_some(args[0]));
+        }
+        let strict = p.strict();
+        let mut ints = Vec::with_capacity(strict);
+        for arg in &args[..strict] {
+            let arg = self.whnf(*arg, token)?;
+            match self.heap[arg] {
+                Node::Const(Const::Int(n)) => ints.push(n),
+                _ => return Ok(None),
+            }
+        }
+        let mut int = |n| Some(self.push(Node::Const(Const::Int(n))));
+        Ok(match p {
+            Prim::Add => int(ints[0].wrapping_add(ints[1])),
+            Prim::Sub => int(ints[0].wrapping_sub(ints[1])),
+            Prim::Mul => int(ints[0].wrapping_mul(ints[1])),
+            Prim::If0 if ints[0] == 0 => Some(args[1]),
+            Prim::If0 => Some(args[2]),
+            Prim::Cast(_) => unreachable!("casts are checked before"),
+        })
+    }
+
+    /// a new instance of `body` in the heap, with `params` in place of the parameters
+    fn instantiate(&mut self, body: &Expr, params: &[usize]) -> usize {
+        match body {
+            Expr::Param(i) => params[*i],
+            Expr::Comb(k) => *k,
+            Expr::Const(c) => self.push(Node::Const(*c)),
+            Expr::App(l, r) => {
+                let (l, r) = (self.instantiate(l, params), self.instantiate(r, params));
+                self.push(Node::App(l, r))
+            }
+        }
+    }
+
+    fn push(&mut self, node: Node) -> usize {
+        self.heap.push(node);
+        self.heap.len() - 1
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::cancel::CancelToken;
+    use crate::lift::{Lifted, Machine, Whnf};
+    use crate::session::Session;
+
+    #[test]
+    pub fn lifts_and_instantiates() {
+        let mut s = Session::new();
+        s.irc.primitives = true;
+        s.eval_str("two f x = f (f x)").unwrap();
+        let root = s
+            .compile_expr("fn y => two (fn z => + y z)")
+            .unwrap()
+            .unwrap();
+        let lifted = Lifted::new(&s.art, root).unwrap();
+        assert_eq!(
+            lifted.to_string(),
+            "$0 x0 x1 = x0 (x0 x1)\n$1 x0 x1 = + x0 x1\n$2 x0 = $0 ($1 x0)\nmain = $2"
+        );
+
+        let mut run = |src| {
+            let root = s.compile_expr(src).unwrap().unwrap();
+            let lifted = Lifted::new(&s.art, root).unwrap();
+            Machine::new(&lifted).eval(&mut CancelToken::new())
+        };
+        assert_eq!(run("(fn y => two (fn z => + y z)) 3 1"), Ok(Whnf::Int(7)));
+        assert_eq!(
+            run("if0 (- 2 2) 1 ((fn x => x x) (fn x => x x))"),
+            Ok(Whnf::Int(1))
+        );
+        assert_eq!(run("two (+ 1)"), Ok(Whnf::Function));
+        assert_eq!(run("+ two 1"), Ok(Whnf::Stuck));
+    }
+}
@@ -0,0 +1,354 @@
+use miette::{Diagnostic, SourceSpan};
+use thiserror::Error;
+
+use crate::ast::{Ast, Node};
+use crate::ir::{Id, IrComponent, IrObj, Scope};
+use crate::lexer::from_code;
+use crate::provenance::Sugar;
+
+#[derive(Error, Diagnostic, Debug, Clone)]
+pub enum Warning {
+    #[error("`{name}` is applied to {args} arguments, but it takes {arity}")]
+    #[diagnostic(
+        code(lint::over_application),
+        severity(Warning),
+        help(
+            "after {arity} arguments, `{name}` isn't an abstraction anymore. It's fine if what it returns is a function"
+        )
+    )]
+    OverApplication {
+        #[label("applied to {args} arguments here")]
+        at: SourceSpan,
+        name: Box<str>,
+        arity: usize,
+        args: usize,
+    },
+
+    #[error("`{name}` is defined again")]
+    #[diagnostic(
+        code(lint::shadowed_definition),
+        severity(Warning),
+        help(
+            "it was `{previous}`. What already refers to it keeps the old one. `set shadow-warnings off` silences this"
+        )
+    )]
+    ShadowedDefinition {
+        #[label("the new definition")]
+        at: SourceSpan,
+        name: Box<str>,
+        previous: Box<str>,
+    },
+
+    #[error("`{name}` is never used")]
+    #[diagnostic(
+        code(lint::unused_variable),
+        severity(Warning),
+        help(
+            "nothing in its body refers to it, which is often a typo. Start its name with `_` if it's on purpose, or `set warn unused off`"
+        )
+    )]
+    UnusedVariable {
+        #[label("bound here")]
+        at: SourceSpan,
+        name: Box<str>,
+    },
+
+    #[error("`{name}` hides an outer `{name}`")]
+    #[diagnostic(
+        code(lint::shadowed_variable),
+        severity(Warning),
+        help(
+            "inside it, `{name}` only refers to the inner one. Rename one of them if that's not what you meant, or `set warn shadow off`"
+        )
+    )]
+    ShadowedVariable {
+        #[label("this `{name}`")]
+        at: SourceSpan,
+        #[label("hides this one")]
+        outer: SourceSpan,
+        name: Box<str>,
+    },
+}
+
+/// how many arguments the definition `id` takes before its body stops being an abstraction.
+/// A body that partially applies another definition takes what's left of that one's arity:
+/// `plus = fn m n => ..` and `inc = plus one` take 2 and 1
+pub fn arity(scope: &Scope, id: Id) -> usize {
+    match &scope.res_pool[id.0].item {
+        IrComponent::Def(body) => arity_of(scope, body),
+        _ => 0,
+    }
+}
+
+fn arity_of(scope: &Scope, ir: &IrObj) -> usize {
+    match &ir.item {
+        IrComponent::Abs(_, inner) => 1 + arity_of(scope, inner),
+        IrComponent::Def(body) => arity_of(scope, body),
+        _ => {
+            let (head, args) = spine(ir);
+            match head.item {
+                IrComponent::Var(id) => arity(scope, id).saturating_sub(args.len()),
+                _ => 0,
+            }
+        }
+    }
+}
+
+/// `f a b c` as `f` and `[a, b, c]`. `if c then t else e` is `c t e`, but the arguments written to
+/// `c` aren't in its spine: `if is_zero n then a else b` doesn't apply `is_zero` to three
+fn spine(ir: &IrObj) -> (&IrObj, Vec<&IrObj>) {
+    let is_if = |ir: &IrObj| ir.sugar == Some(Sugar::If);
+    if is_if(ir)
+        && let IrComponent::App(ct, e) = &ir.item
+        && let IrComponent::App(c, t) = &ct.item
+    {
+        return (c, vec![t, e]);
+    }
+    let mut args = Vec::new();
+    let mut head = ir;
+    while let IrComponent::App(l, r) = &head.item
+        && (std::ptr::eq(head, ir) || !is_if(head))
+    {
+        args.push(r);
+        head = l;
+    }
+    args.reverse();
+    (head, args)
+}
+
+/// every application of a definition to more arguments than its arity
+pub fn over_applications(scope: &Scope, ir: &IrObj) -> Vec<Warning> {
+    let aliases = scope.get_aliases();
+    let mut warnings = Vec::new();
+    let mut stack = vec![ir];
+    while let Some(ir) = stack.pop() {
+        match &ir.item {
+            IrComponent::Abs(_, inner) | IrComponent::Def(inner) => stack.push(inner),
+            IrComponent::App(..) => {
+                let (head, args) = spine(ir);
+                // a name only reserved by a forward reference has no arity yet
+                if let IrComponent::Var(id) = head.item
+                    && let Some(name) = aliases.get(&id)
+                    && matches!(scope.res_pool[id.0].item, IrComponent::Def(..))
+                {
+                    let arity = arity(scope, id);
+                    if args.len() > arity {
+                        warnings.push(Warning::OverApplication {
+                            at: ir.at,
+                            name: name.clone(),
+                            arity,
+                            args: args.len(),
+                        });
+                    }
+                }
+                stack.push(head);
+                stack.extend(args);
+            }
+            _ => (),
+        }
+    }
+    warnings
+}
+
+/// a variable bound by an abstraction, a parameter, a pattern or a `let`
+struct Binder<'a> {
+    name: &'a str,
+    at: SourceSpan,
+    /// whether its body refers to it
+    used: bool,
+    /// the binder of the same name it hides, if it's inside one
+    shadows: Option<SourceSpan>,
+}
+
+/// every binder of `ast`, in the order they're written
+fn binders<'a>(ast: &'a Node, src: &'a str) -> Vec<Binder<'a>> {
+    enum Task<'a> {
+        Visit(&'a Node),
+        Bind(&'a [SourceSpan]),
+        /// takes the last `n` binders out of scope
+        Unbind(usize),
+    }
+
+    let mut all = Vec::new();
+    let mut scope: Vec<Binder> = Vec::new();
+    let mut tasks = vec![Task::Visit(ast)];
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Visit(n) => match &n.item {
+                Ast::Var => {
+                    let name = n.from_code(src);
+                    if let Some(b) = scope.iter_mut().rev().find(|b| b.name == name) {
+                        b.used = true;
+                    }
+                }
+                Ast::Abs(v, inner)
+                | Ast::Annotated {
+                    name: v,
+                    body: inner,
+                    ..
+                } => {
+                    let v = std::slice::from_ref(v);
+                    tasks.extend([Task::Unbind(1), Task::Visit(inner), Task::Bind(v)]);
+                }
+                Ast::Unpack { names: vs, body }
+                | Ast::Def {
+                    params: vs, body, ..
+                } => {
+                    tasks.extend([Task::Unbind(vs.len()), Task::Visit(body), Task::Bind(vs)]);
+                }
+                Ast::Let {
+                    rec,
+                    ident,
+                    value,
+                    body,
+                } => {
+                    let v = std::slice::from_ref(ident);
+                    tasks.extend([Task::Unbind(1), Task::Visit(body)]);
+                    match rec {
+                        true => tasks.extend([Task::Visit(value), Task::Bind(v)]),
+                        false => tasks.extend([Task::Bind(v), Task::Visit(value)]),
+                    }
+                }
+                Ast::App(l, r) => tasks.extend([Task::Visit(r), Task::Visit(l)]),
+                Ast::Tuple(items)
+                | Ast::List(items)
+                | Ast::Infix { items, .. }
+                | Ast::Program(items) => tasks.extend(items.iter().rev().map(Task::Visit)),
+            },
+            Task::Bind(vs) => {
+                for &at in vs {
+                    let name = from_code(at, src);
+                    let shadows = scope.iter().rev().find(|b| b.name == name).map(|b| b.at);
+                    scope.push(Binder {
+                        name,
+                        at,
+                        used: false,
+                        shadows,
+                    });
+                }
+            }
+            Task::Unbind(n) => all.extend(scope.split_off(scope.len() - n)),
+        }
+    }
+    all.sort_by_key(|b| b.at.offset());
+    all
+}
+
+/// every binder that its body never refers to. Names starting with `_` are left out
+pub fn unused_variables(ast: &Node, src: &str) -> Vec<Warning> {
+    binders(ast, src)
+        .into_iter()
+        .filter(|b| !b.used && !b.name.starts_with('_'))
+        .map(|b| Warning::UnusedVariable {
+            at: b.at,
+            name: b.name.into(),
+        })
+        .collect()
+}
+
+/// every binder inside another of the same name, as the inner `x` of `fn x => fn x => x`. Names
+/// starting with `_` are left out
+pub fn shadowed_variables(ast: &Node, src: &str) -> Vec<Warning> {
+    binders(ast, src)
+        .into_iter()
+        .filter(|b| !b.name.starts_with('_'))
+        .filter_map(|b| {
+            Some(Warning::ShadowedVariable {
+                at: b.at,
+                outer: b.shadows?,
+                name: b.name.into(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::lint::{Warning, arity, over_applications, shadowed_variables, unused_variables};
+    use crate::session::Session;
+
+    #[test]
+    pub fn arities() {
+        let mut s = Session::new();
+        s.eval_str("plus m n f x = m f (n f x)\ninc = plus one\none f x = f x")
+            .unwrap();
+        let scope = &s.irc.scope;
+        assert_eq!(arity(scope, scope.definitions["plus"]), 4);
+        assert_eq!(arity(scope, scope.definitions["inc"]), 3);
+
+        let src = "fn y => inc one y y y";
+        let tokens = s.lexer(src).unwrap();
+        let ast = s.parse(&tokens, src).unwrap();
+        let ir = s.ir(ast, src).unwrap().unwrap();
+        let warnings = over_applications(&s.irc.scope, &ir);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].to_string(),
+            "`inc` is applied to 4 arguments, but it takes 3"
+        );
+    }
+
+    #[test]
+    pub fn conditions() {
+        let mut s = Session::new();
+        s.eval_str("T a b = a\nZ n = n (fn x a b => b) T").unwrap();
+        let warnings = |s: &mut Session, src: &str| {
+            let tokens = s.lexer(src).unwrap();
+            let ast = s.parse(&tokens, src).unwrap();
+            let ir = s.ir(ast, src).unwrap().unwrap();
+            over_applications(&s.irc.scope, &ir).len()
+        };
+        assert_eq!(warnings(&mut s, "fn n => if Z n then n else n"), 0);
+        assert_eq!(warnings(&mut s, "fn n => (if Z n then Z else T) n n"), 0);
+        assert_eq!(warnings(&mut s, "fn n => if Z n n then n else n"), 1);
+        assert_eq!(warnings(&mut s, "fn n => Z n n n"), 1);
+    }
+
+    #[test]
+    pub fn unused() {
+        let mut s = Session::new();
+        let unused = |s: &mut Session, src: &str| {
+            let tokens = s.lexer(src).unwrap();
+            let ast = s.parse(&tokens, src).unwrap();
+            let names: Vec<_> = unused_variables(&ast, src)
+                .into_iter()
+                .map(|w| match w {
+                    Warning::UnusedVariable { name, .. } => name,
+                    _ => unreachable!(),
+                })
+                .collect();
+            names.join(" ")
+        };
+        assert_eq!(unused(&mut s, "fn x => y"), "x");
+        assert_eq!(unused(&mut s, "K x y = x\nF _x y = y"), "y");
+        assert_eq!(unused(&mut s, "fn x x => x"), "x");
+        assert_eq!(unused(&mut s, "fn (a, b) => let c = a in b"), "c");
+        assert_eq!(unused(&mut s, "letrec f x = f in f"), "x");
+        assert_eq!(unused(&mut s, "let f = f in I"), "f");
+
+        s.set_prelude(true).unwrap();
+        assert_eq!(s.eval_str("fn x y => x").unwrap().warnings.len(), 1);
+        s.unused_warnings = false;
+        assert!(s.eval_str("fn x y => x").unwrap().warnings.is_empty());
+    }
+
+    #[test]
+    pub fn shadowing() {
+        let mut s = Session::new();
+        let src = "fn x y => (fn x => x) (let y = x in fn _y _y => y)";
+        let tokens = s.lexer(src).unwrap();
+        let ast = s.parse(&tokens, src).unwrap();
+        let spans: Vec<_> = shadowed_variables(&ast, src)
+            .into_iter()
+            .map(|w| match w {
+                Warning::ShadowedVariable { at, outer, .. } => (at.offset(), outer.offset()),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(spans, [(14, 3), (27, 5)]);
+
+        s.shadowed_binder_warnings = false;
+        let out = s.eval_str("fn x => fn x => x").unwrap();
+        assert!((out.warnings.iter()).all(|w| !matches!(w, Warning::ShadowedVariable { .. })));
+    }
+}
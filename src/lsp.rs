@@ -0,0 +1,484 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::time::Duration;
+
+use miette::{Diagnostic, SourceSpan};
+use serde_json::{Value, json};
+
+use crate::arts::{CompArtifact, Term, TermIdx};
+use crate::ast::{Ast, Node};
+use crate::incremental::{Document, Edit};
+use crate::ir::{self, IrComponent};
+use crate::lexer::{self, Columns, Position, from_code};
+use crate::padam::lexer::{IDENT_TY, OPERATOR_TY};
+use crate::session::{self, Session};
+use crate::tree::{Cursor, SyntaxTree};
+
+/// the reductions a hover may take to find a normal form
+pub const HOVER_BUDGET: usize = 10_000;
+/// how long a hover may take to find a normal form, whatever the budget
+pub const HOVER_TIMEOUT: Duration = Duration::from_millis(200);
+/// the nodes of the largest normal form a hover shows
+pub const HOVER_SIZE: usize = 500;
+/// shown in place of a normal form that's cancelled or bigger than `HOVER_SIZE`
+pub const TOO_LARGE: &str = "too large to evaluate here";
+
+/// an open file: its source, kept parsed, and a session with its definitions
+struct Entry {
+    doc: Document,
+    session: Session,
+}
+
+impl Entry {
+    fn new(uri: &str, text: &str) -> Self {
+        let mut session = Session::new();
+        if let Some(f) = session.frontends.iter().find(|f| {
+            f.extensions()
+                .iter()
+                .any(|e| uri.ends_with(&format!(".{e}")))
+        }) {
+            session.set_syntax(f.name());
+        }
+        session.cancel.budget = Some(HOVER_BUDGET);
+        session.cancel.timeout = Some(HOVER_TIMEOUT);
+        Self {
+            doc: Document::new(session.frontend().lexer(), text),
+            session,
+        }
+    }
+
+    /// the errors of every line that doesn't parse or, if they all do, the ones of compiling
+    /// them. The session gets the document's definitions
+    fn diagnostics(&mut self) -> Vec<Value> {
+        let src = self.doc.src();
+        let errors: Vec<_> = self.doc.errors().map(|e| diagnostic(src, &e)).collect();
+        if !errors.is_empty() {
+            return errors;
+        }
+        self.session = {
+            let mut s = Session::new();
+            s.set_syntax(self.session.frontend().name());
+            s.cancel = self.session.cancel.clone();
+            s
+        };
+        // the session only finds undeclared names once they're needed, and without a span
+        let mut errors = Vec::new();
+        if let Ok(Some(ast)) = self.doc.parse() {
+            let scope = &self.session.irc.scope;
+            resolve(&ast, src, |at, binder| {
+                let declared = scope.definitions.contains_key(from_code(at, src));
+                if binder.is_none() && !declared {
+                    errors.push(diagnostic(src, &ir::Error::UndeclaredVariable { at }));
+                }
+            });
+        }
+        if !errors.is_empty() {
+            return errors;
+        }
+        match self.session.compile_expr(src) {
+            Ok(_) => Vec::new(),
+            Err(e) => vec![diagnostic(src, &e)],
+        }
+    }
+
+    /// the identifier under `offset`
+    fn ident_at(&self, offset: usize) -> Option<SourceSpan> {
+        self.doc
+            .tokens()
+            .find(|t| {
+                t.at.offset() <= offset
+                    && offset <= t.at.offset() + t.at.len()
+                    && [IDENT_TY, OPERATOR_TY].contains(&self.doc.lexer().get_type(t.item))
+            })
+            .map(|t| t.at)
+    }
+
+    /// the type and normal form of the definition under `offset`
+    fn hover(&mut self, offset: usize) -> Option<Value> {
+        let at = self.ident_at(offset)?;
+        let ast = self.doc.parse().ok()??;
+        let src = self.doc.src().to_string();
+        if binder(&ast, &src, at).is_some_and(|b| !is_definition(&ast, b)) {
+            return None;
+        }
+        let name = from_code(at, &src);
+        let scope = &self.session.irc.scope;
+        let id = *scope
+            .definitions
+            .get(name)
+            .filter(|id| scope.res_pool[id.0].item != IrComponent::Pending)?;
+        let ty = self
+            .session
+            .definition_type(id)
+            .map_or_else(|| "no simple type".to_string(), |t| t.to_string());
+        // a divergent or exploding definition mustn't keep the editor waiting
+        let normal = match self.session.evaluate(name) {
+            Ok(ev) => ev
+                .normal()
+                .map(|nf| match fits(&ev.session.art, nf, HOVER_SIZE) {
+                    // without aliases, or the definition would be printed as its own name
+                    true => format!("`{}`", ev.session.art.pretty(nf, &HashMap::new())),
+                    false => TOO_LARGE.to_string(),
+                }),
+            Err(session::Error::Cancelled(_)) => Some(TOO_LARGE.to_string()),
+            Err(e) => Some(e.to_string()),
+        };
+        let value = format!(
+            "```qk\n{name} : {ty}\n```\nnormal form: {}",
+            normal.unwrap_or_default()
+        );
+        Some(json!({
+            "contents": { "kind": "markdown", "value": value },
+            "range": range(&src, at),
+        }))
+    }
+
+    /// where the identifier under `offset` is bound
+    fn definition(&self, offset: usize) -> Option<SourceSpan> {
+        let at = self.ident_at(offset)?;
+        binder(&self.doc.parse().ok()??, self.doc.src(), at)
+    }
+}
+
+/// a language server over JSON-RPC: diagnostics of the lexer, parser and compiler, hovering a
+/// definition for its type and normal form, and going to where a name is bound
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, Entry>,
+    /// `exit` was received
+    pub exited: bool,
+}
+
+impl Server {
+    /// answers a message, with the responses and notifications to send back
+    pub fn handle(&mut self, msg: &Value) -> Vec<Value> {
+        let method = msg["method"].as_str().unwrap_or_default();
+        let params = &msg["params"];
+        let Some(id) = msg.get("id") else {
+            return self.notification(method, params);
+        };
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    // incremental
+                    "textDocumentSync": 2,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                },
+                "serverInfo": { "name": "qk", "version": env!("CARGO_PKG_VERSION") },
+            }),
+            "shutdown" => Value::Null,
+            "textDocument/hover" => self
+                .documents
+                .get_mut(uri)
+                .and_then(|e| {
+                    let offset = offset(e.doc.src(), &params["position"])?;
+                    e.hover(offset)
+                })
+                .unwrap_or(Value::Null),
+            "textDocument/definition" => self
+                .documents
+                .get(uri)
+                .and_then(|e| {
+                    let at = e.definition(offset(e.doc.src(), &params["position"])?)?;
+                    Some(json!({ "uri": uri, "range": range(e.doc.src(), at) }))
+                })
+                .unwrap_or(Value::Null),
+            _ => {
+                return vec![json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": -32601, "message": format!("unknown method {method}") },
+                })];
+            }
+        };
+        vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })]
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let doc = &params["textDocument"];
+        let uri = doc["uri"].as_str().unwrap_or_default().to_string();
+        let diagnostics = match method {
+            "exit" => {
+                self.exited = true;
+                return Vec::new();
+            }
+            "textDocument/didOpen" => {
+                let text = doc["text"].as_str().unwrap_or_default();
+                let entry = self
+                    .documents
+                    .entry(uri.clone())
+                    .insert_entry(Entry::new(&uri, text));
+                entry.into_mut().diagnostics()
+            }
+            "textDocument/didChange" => {
+                let Some(entry) = self.documents.get_mut(&uri) else {
+                    return Vec::new();
+                };
+                let changes = params["contentChanges"].as_array();
+                for change in changes.into_iter().flatten() {
+                    let text = change["text"].as_str().unwrap_or_default();
+                    let src = entry.doc.src();
+                    let range = match change.get("range") {
+                        Some(r) => offset(src, &r["start"]).zip(offset(src, &r["end"])),
+                        None => Some((0, src.len())),
+                    };
+                    if let Some((start, end)) = range.filter(|(s, e)| s <= e) {
+                        entry.doc.edit(&Edit::new(start..end, text));
+                    }
+                }
+                entry.diagnostics()
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                Vec::new()
+            }
+            _ => return Vec::new(),
+        };
+        vec![json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        })]
+    }
+}
+
+/// serves `input` until `exit`, or until it ends. Messages are framed by a `Content-Length` header
+pub fn serve(input: &mut impl BufRead, output: &mut impl Write) -> io::Result<()> {
+    let mut server = Server::default();
+    while let Some(body) = read_message(input)? {
+        let replies = match serde_json::from_slice::<Value>(&body) {
+            Ok(msg) => server.handle(&msg),
+            Err(e) => vec![json!({
+                "jsonrpc": "2.0",
+                "id": Value::Null,
+                "error": { "code": -32700, "message": e.to_string() },
+            })],
+        };
+        for reply in replies {
+            let body = reply.to_string();
+            write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        }
+        output.flush()?;
+        if server.exited {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            len = value.trim().parse().ok();
+        }
+    }
+    let len = len.ok_or_else(|| io::Error::other("a message without Content-Length"))?;
+    let mut body = vec![0; len];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+/// the byte offset of a position, whose `character` counts UTF-16 code units
+fn offset(src: &str, pos: &Value) -> Option<usize> {
+    let line = pos["line"].as_u64()? as usize;
+    let column = pos["character"].as_u64()? as usize;
+    lexer::offset(src, Position { line, column }, Columns::Utf16)
+}
+
+fn position(src: &str, offset: usize) -> Value {
+    let pos = lexer::position(src, offset, Columns::Utf16).unwrap_or_default();
+    json!({ "line": pos.line, "character": pos.column })
+}
+
+fn range(src: &str, at: SourceSpan) -> Value {
+    let end = (at.offset() + at.len()).min(src.len());
+    json!({ "start": position(src, at.offset().min(end)), "end": position(src, end) })
+}
+
+/// an error as an LSP diagnostic, at its first label
+fn diagnostic(src: &str, e: &dyn Diagnostic) -> Value {
+    let at = e
+        .labels()
+        .and_then(|mut l| l.next())
+        .map_or(SourceSpan::from(0..0), |l| *l.inner());
+    let mut message = e.to_string();
+    if let Some(help) = e.help() {
+        message = format!("{message}\n{help}");
+    }
+    json!({
+        "range": range(src, at),
+        "severity": 1,
+        "code": e.code().map(|c| c.to_string()),
+        "source": "qk",
+        "message": message,
+    })
+}
+
+/// whether the term at `idx`, as a tree, has at most `limit` nodes. Shared subterms count once
+/// for each parent, as they're printed, but it stops counting past `limit`
+fn fits(art: &CompArtifact, idx: TermIdx, limit: usize) -> bool {
+    let mut stack = vec![idx];
+    let mut nodes = 0;
+    while let Some(idx) = stack.pop() {
+        nodes += 1;
+        if nodes > limit {
+            return false;
+        }
+        match art.get(idx) {
+            Term::Var(_) | Term::Const(_) => (),
+            Term::Abs { inner } => stack.push(inner),
+            Term::App(l, r) => stack.extend([l, r]),
+        }
+    }
+    true
+}
+
+/// the span of the name binding the variable at `at`: a parameter or a definition
+fn binder(ast: &Node, src: &str, at: SourceSpan) -> Option<SourceSpan> {
+    let tree = SyntaxTree::new(ast);
+    let var = tree
+        .find_node_at(at.offset())
+        .filter(|c| matches!(c.ast(), Ast::Var) && c.span() == at)?;
+    binding(&var, src, &globals(&tree, src))
+}
+
+/// calls `f` with every variable and what binds it, if anything in `ast` does
+fn resolve(ast: &Node, src: &str, mut f: impl FnMut(SourceSpan, Option<SourceSpan>)) {
+    let tree = SyntaxTree::new(ast);
+    let globals = globals(&tree, src);
+    for var in tree.iter().filter(|c| matches!(c.ast(), Ast::Var)) {
+        f(var.span(), binding(&var, src, &globals));
+    }
+}
+
+/// the name of each definition, at its first one
+fn globals<'s>(tree: &SyntaxTree, src: &'s str) -> HashMap<&'s str, SourceSpan> {
+    let mut globals = HashMap::new();
+    for def in tree.root().children() {
+        if let Ast::Def { ident, .. } = def.ast() {
+            globals.entry(from_code(*ident, src)).or_insert(*ident);
+        }
+    }
+    globals
+}
+
+fn binding(var: &Cursor, src: &str, globals: &HashMap<&str, SourceSpan>) -> Option<SourceSpan> {
+    let name = var.text(src);
+    // the innermost binder wins, and the last of a definition's parameters
+    let local = var
+        .ancestors()
+        .flat_map(|a| a.binders().iter().rev())
+        .find(|b| from_code(**b, src) == name);
+    local.or_else(|| globals.get(name)).copied()
+}
+
+fn is_definition(ast: &Node, at: SourceSpan) -> bool {
+    match &ast.item {
+        Ast::Program(defs) => defs
+            .iter()
+            .any(|d| matches!(d.item, Ast::Def { ident, .. } if ident == at)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use serde_json::{Value, json};
+
+    use crate::lsp::{Server, TOO_LARGE};
+
+    fn open(server: &mut Server, text: &str) -> Value {
+        let msg = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": "file:///a.qk", "text": text } },
+        });
+        server.handle(&msg).remove(0)
+    }
+
+    fn request(server: &mut Server, method: &str, line: u64, character: u64) -> Value {
+        let msg = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": {
+                "textDocument": { "uri": "file:///a.qk" },
+                "position": { "line": line, "character": character },
+            },
+        });
+        server.handle(&msg).remove(0)["result"].take()
+    }
+
+    #[test]
+    pub fn language_server() {
+        let mut server = Server::default();
+        let published = open(&mut server, "I x = x\nK x y = z\n");
+        let diagnostics = &published["params"]["diagnostics"];
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+        assert_eq!(
+            diagnostics[0]["range"]["start"],
+            json!({ "line": 1, "character": 8 })
+        );
+
+        let change = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": { "uri": "file:///a.qk" },
+                "contentChanges": [{
+                    "range": {
+                        "start": { "line": 1, "character": 8 },
+                        "end": { "line": 1, "character": 9 },
+                    },
+                    "text": "I x",
+                }],
+            },
+        });
+        let published = server.handle(&change).remove(0);
+        assert_eq!(published["params"]["diagnostics"], json!([]));
+
+        let hover = request(&mut server, "textDocument/hover", 1, 8);
+        let contents = hover["contents"]["value"].as_str().unwrap();
+        assert!(contents.contains("I : "), "{contents}");
+        assert!(contents.contains("normal form: `λ"), "{contents}");
+        assert_eq!(
+            request(&mut server, "textDocument/hover", 1, 10),
+            Value::Null
+        );
+
+        let def = request(&mut server, "textDocument/definition", 1, 8);
+        assert_eq!(def["range"]["start"], json!({ "line": 0, "character": 0 }));
+        let def = request(&mut server, "textDocument/definition", 1, 10);
+        assert_eq!(def["range"]["start"], json!({ "line": 1, "character": 2 }));
+    }
+
+    #[test]
+    pub fn hover_degrades() {
+        let mut server = Server::default();
+        open(
+            &mut server,
+            "W x = x x\nO = W W\nT f x = f (f (f (f x)))\nN = T T\n",
+        );
+        for line in [1, 3] {
+            let hover = request(&mut server, "textDocument/hover", line, 0);
+            let contents = hover["contents"]["value"].as_str().unwrap();
+            assert!(contents.ends_with(TOO_LARGE), "{contents}");
+        }
+        let hover = request(&mut server, "textDocument/hover", 2, 0);
+        let contents = hover["contents"]["value"].as_str().unwrap();
+        assert!(contents.contains("normal form: `λ"), "{contents}");
+    }
+}
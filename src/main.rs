@@ -1,6 +1,7 @@
+pub mod cli;
 pub mod repl;
 
 fn main() -> repl::Result<()> {
-    let mut r = repl::Repl::new()?;
-    r.run()
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    cli::run(&args)
 }
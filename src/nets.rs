@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+
+use crate::arts::{CompArtifact, OuterIdx, Term, TermIdx};
+use crate::cancel::{self, CancelToken};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// where the whole term goes. It never interacts
+    Root,
+    /// `λ`: its principal port is the abstraction, then the variable and the body
+    Lam,
+    /// an application: its principal port is the function, then the argument and the result
+    App,
+    /// shares what's at its principal port between the other two. Fans of the same label
+    /// annihilate, any other pair commutes
+    Dup(usize),
+    /// an unused value, erasing what it meets
+    Era,
+}
+
+/// a port of a node: 0 is the principal one, 1 and 2 the auxiliary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Port(usize);
+
+impl Port {
+    fn new(node: usize, slot: usize) -> Self {
+        Self(node * 3 + slot)
+    }
+
+    fn node(self) -> usize {
+        self.0 / 3
+    }
+
+    fn slot(self) -> usize {
+        self.0 % 3
+    }
+}
+
+/// what a reduction cost
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// applications annihilated with an abstraction
+    pub beta: usize,
+    /// every rewrite, the β-steps among them
+    pub interactions: usize,
+}
+
+/// an interaction net for a λ-term, reduced by the abstract algorithm of Lamping: each
+/// abstraction copies its argument with fans of its own label, and no subterm is ever copied
+/// before it's needed, so no redex is reduced twice.
+///
+/// It has no brackets, so it's only right when a copy of a term never duplicates with another
+/// copy of itself, unlike in `(fn x => x x) two`. Otherwise the normal form may not read back
+#[derive(Debug, Clone)]
+pub struct Net {
+    kinds: Vec<Kind>,
+    /// the port each port is wired to
+    wires: Vec<Port>,
+    labels: usize,
+    pub stats: Stats,
+}
+
+const ROOT: Port = Port(0);
+
+impl Net {
+    /// the net of the closed term `root`, if it has no constants. Shared subterms of the pool
+    /// are copied, each abstraction getting its own label
+    pub fn new(art: &CompArtifact, root: TermIdx) -> Option<Self> {
+        let mut net = Self {
+            kinds: Vec::new(),
+            wires: Vec::new(),
+            labels: 0,
+            stats: Stats::default(),
+        };
+        net.node(Kind::Root);
+        let top = net.compile(art, root, &mut Vec::new())?;
+        net.link(ROOT, top);
+        Some(net)
+    }
+
+    fn node(&mut self, kind: Kind) -> usize {
+        self.kinds.push(kind);
+        let node = self.kinds.len() - 1;
+        self.wires.extend((0..3).map(|slot| Port::new(node, slot)));
+        node
+    }
+
+    fn link(&mut self, a: Port, b: Port) {
+        self.wires[a.0] = b;
+        self.wires[b.0] = a;
+    }
+
+    fn target(&self, p: Port) -> Port {
+        self.wires[p.0]
+    }
+
+    /// the port giving the value of `idx`. `binders` has, for each abstraction around it, the
+    /// port its next occurrence takes
+    fn compile(
+        &mut self,
+        art: &CompArtifact,
+        idx: TermIdx,
+        binders: &mut Vec<Binder>,
+    ) -> Option<Port> {
+        match art.get(idx) {
+            Term::Var(v) => {
+                let b = binders.iter_mut().rev().nth(v.0)?;
+                let (label, src) = (b.label, b.src);
+                b.uses += 1;
+                if b.uses == 1 {
+                    return Some(src);
+                }
+                // the occurrences before it take one side of a new fan, and this one the other
+                let before = self.target(src);
+                let dup = self.node(Kind::Dup(label));
+                self.link(Port::new(dup, 0), src);
+                self.link(Port::new(dup, 1), before);
+                binders.iter_mut().rev().nth(v.0)?.src = Port::new(dup, 2);
+                Some(Port::new(dup, 2))
+            }
+            Term::Abs { inner } => {
+                let lam = self.node(Kind::Lam);
+                self.labels += 1;
+                binders.push(Binder {
+                    label: self.labels,
+                    src: Port::new(lam, 1),
+                    uses: 0,
+                });
+                let body = self.compile(art, inner, binders);
+                let b = binders.pop()?;
+                self.link(Port::new(lam, 2), body?);
+                if b.uses == 0 {
+                    let era = self.node(Kind::Era);
+                    self.link(Port::new(lam, 1), Port::new(era, 0));
+                }
+                Some(Port::new(lam, 0))
+            }
+            Term::App(l, r) => {
+                let app = self.node(Kind::App);
+                let f = self.compile(art, l, binders)?;
+                self.link(Port::new(app, 0), f);
+                let arg = self.compile(art, r, binders)?;
+                self.link(Port::new(app, 1), arg);
+                Some(Port::new(app, 2))
+            }
+            Term::Const(_) => None,
+        }
+    }
+
+    fn interacts(&self, a: usize, b: usize) -> bool {
+        self.kinds[a] != Kind::Root && self.kinds[b] != Kind::Root
+    }
+
+    fn interact(&mut self, a: usize, b: usize) {
+        self.stats.interactions += 1;
+        match (self.kinds[a], self.kinds[b]) {
+            (Kind::Lam, Kind::App) | (Kind::App, Kind::Lam) => {
+                self.stats.beta += 1;
+                self.annihilate(a, b);
+            }
+            (Kind::Dup(l), Kind::Dup(m)) if l == m => self.annihilate(a, b),
+            (Kind::Era, Kind::Era) => (),
+            (Kind::Era, _) => self.erase(b),
+            (_, Kind::Era) => self.erase(a),
+            (Kind::Lam | Kind::App, Kind::Lam | Kind::App) => self.annihilate(a, b),
+            _ => self.commute(a, b),
+        }
+    }
+
+    /// wires what was at each side of `a` to what was at the same side of `b`
+    fn annihilate(&mut self, a: usize, b: usize) {
+        for slot in 1..3 {
+            // read again, as the first link may have moved the second
+            let (x, y) = (
+                self.target(Port::new(a, slot)),
+                self.target(Port::new(b, slot)),
+            );
+            self.link(x, y);
+        }
+    }
+
+    fn erase(&mut self, node: usize) {
+        for slot in 1..3 {
+            let t = self.target(Port::new(node, slot));
+            let era = self.node(Kind::Era);
+            self.link(Port::new(era, 0), t);
+        }
+    }
+
+    /// `a` goes through `b`: each is copied to the other's sides
+    fn commute(&mut self, a: usize, b: usize) {
+        let (ka, kb) = (self.kinds[a], self.kinds[b]);
+        let a_copies = [self.node(ka), self.node(ka)];
+        let b_copies = [self.node(kb), self.node(kb)];
+        for (i, &bc) in b_copies.iter().enumerate() {
+            for (j, &ac) in a_copies.iter().enumerate() {
+                self.link(Port::new(bc, j + 1), Port::new(ac, i + 1));
+            }
+        }
+        // a side wired to another side of the pair goes to the copy that replaced it
+        let replaced = |p: Port| match p {
+            p if p.node() == a && p.slot() > 0 => Some(Port::new(b_copies[p.slot() - 1], 0)),
+            p if p.node() == b && p.slot() > 0 => Some(Port::new(a_copies[p.slot() - 1], 0)),
+            _ => None,
+        };
+        for (node, copies) in [(a, b_copies), (b, a_copies)] {
+            for (slot, copy) in (1..3).zip(copies) {
+                let t = self.target(Port::new(node, slot));
+                let t = replaced(t).unwrap_or(t);
+                self.link(Port::new(copy, 0), t);
+            }
+        }
+    }
+
+    /// reduces what's wired to `from` until its head can't be: it's an abstraction, or a
+    /// variable, maybe applied or copied. Only the pairs on the way are reduced. `None` if the
+    /// way goes round in a circle, which no term does
+    fn whnf(&mut self, from: Port, token: &mut CancelToken) -> cancel::Result<Option<Port>> {
+        let mut stack = Vec::new();
+        let mut cur = from;
+        loop {
+            let next = self.target(cur);
+            if next.slot() == 0 && cur.slot() == 0 && self.interacts(cur.node(), next.node()) {
+                token.tick()?;
+                self.interact(cur.node(), next.node());
+                cur = stack.pop().unwrap_or(from);
+            } else if next.slot() > 0 && !(self.kinds[next.node()] == Kind::Lam && next.slot() == 1)
+            {
+                // a result or a copy: what it comes from is needed first
+                if stack.len() > self.kinds.len() {
+                    return Ok(None);
+                }
+                stack.push(cur);
+                cur = Port::new(next.node(), 0);
+            } else {
+                return Ok(Some(self.target(from)));
+            }
+        }
+    }
+
+    /// reduces the net to its normal form, and reads it back into `art`. `None` if it doesn't
+    /// read back as a term
+    pub fn normalize(
+        &mut self,
+        art: &mut CompArtifact,
+        token: &mut CancelToken,
+    ) -> cancel::Result<Option<TermIdx>> {
+        let mut reader = Reader {
+            levels: HashMap::new(),
+            fans: Vec::new(),
+        };
+        self.read(art, ROOT, 0, &mut reader, token)
+    }
+
+    /// the term wired to `from`, `depth` abstractions deep
+    fn read(
+        &mut self,
+        art: &mut CompArtifact,
+        from: Port,
+        depth: usize,
+        r: &mut Reader,
+        token: &mut CancelToken,
+    ) -> cancel::Result<Option<TermIdx>> {
+        let Some(p) = self.whnf(from, token)? else {
+            return Ok(None);
+        };
+        token.check()?;
+        let (node, slot) = (p.node(), p.slot());
+        Ok(match (self.kinds[node], slot) {
+            (Kind::Lam, 0) => {
+                r.levels.insert(node, depth);
+                let body = self.read(art, Port::new(node, 2), depth + 1, r, token)?;
+                body.map(|inner| art.push(Term::Abs { inner }))
+            }
+            (Kind::Lam, _) => r
+                .levels
+                .get(&node)
+                .map(|level| art.push(Term::Var(OuterIdx(depth - level - 1)))),
+            (Kind::App, 2) => {
+                let Some(f) = self.read(art, Port::new(node, 0), depth, r, token)? else {
+                    return Ok(None);
+                };
+                let arg = self.read(art, Port::new(node, 1), depth, r, token)?;
+                arg.map(|arg| art.push(Term::App(f, arg)))
+            }
+            // a copy: what's being copied, remembering which side it's read from
+            (Kind::Dup(label), 1 | 2) => {
+                r.fans.push((label, slot));
+                let value = self.read(art, Port::new(node, 0), depth, r, token)?;
+                r.fans.pop();
+                value
+            }
+            // two values in one: the side of the copy being read
+            (Kind::Dup(label), 0) => {
+                let Some(i) = r.fans.iter().rposition(|(l, _)| *l == label) else {
+                    return Ok(None);
+                };
+                let fan = r.fans.remove(i);
+                let value = self.read(art, Port::new(node, fan.1), depth, r, token)?;
+                r.fans.insert(i, fan);
+                value
+            }
+            _ => None,
+        })
+    }
+}
+
+struct Binder {
+    label: usize,
+    src: Port,
+    uses: usize,
+}
+
+struct Reader {
+    /// how many abstractions are around each one being read
+    levels: HashMap<usize, usize>,
+    /// the copies being read, and from which side
+    fans: Vec<(usize, usize)>,
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::backend::{DeBruijn, Renderer};
+    use crate::cancel::CancelToken;
+    use crate::cpu::Normal;
+    use crate::nets::Net;
+    use crate::session::Session;
+
+    #[test]
+    pub fn shares_what_normal_order_copies() {
+        let mut s = Session::new();
+        s.set_prelude(true).unwrap();
+        for (src, beta, normal_order) in [
+            ("K I Omega", 2, 2),
+            ("mul three (add two two)", 34, 72),
+            ("(fn x => x x) (I I)", 3, 4),
+            ("fn y => (fn x z => x) y", 1, 1),
+            ("two two", 17, 39),
+        ] {
+            let root = s.compile_expr(src).unwrap().unwrap();
+            let mut net = Net::new(&s.art, root).unwrap();
+            let nf = net
+                .normalize(&mut s.art, &mut CancelToken::new())
+                .unwrap()
+                .unwrap();
+            let steps = s
+                .reduction::<Normal>(root, &mut CancelToken::new())
+                .unwrap();
+            let debruijn = |t| DeBruijn.render(&s.art, t, &Default::default());
+            assert_eq!(debruijn(nf), debruijn(*steps.last().unwrap()), "{src}");
+            assert_eq!(net.stats.beta, beta, "{src}");
+            assert_eq!(steps.len() - 1, normal_order, "{src}");
+        }
+
+        // a copy of `two` copying itself needs brackets
+        let root = s.compile_expr("(fn x => x x) two").unwrap().unwrap();
+        let mut net = Net::new(&s.art, root).unwrap();
+        let nf = net.normalize(&mut s.art, &mut CancelToken::new()).unwrap();
+        assert_eq!(nf, None);
+    }
+}
@@ -0,0 +1,754 @@
+// Program =
+//      Eol* Def (Eol+ Def)* Eol*
+// Def =
+//      <Ident> <Ident>* "=" Expr
+// Expr = App
+// App =
+//      (Atom | <Operator>)+
+// Atom =
+//      "(" Expr ")"
+//      "(" ")"
+//      "(" Expr ("," Expr)+ ")"
+//      "[" (Expr ("," Expr)*)? "]"
+//      Abs
+//      Let
+//      If
+//      Var
+// Abs =
+//      "fn" Param+ "=>" Expr
+// Param =
+//      <Ident>
+//      "(" <Ident> ("," <Ident>)* ")"
+//      "(" <Ident> ":" Type ")"
+// Let =
+//      ("let" | "letrec") <Ident> <Ident>* "=" Expr "in" Expr
+// If =
+//      "if" Expr "then" Expr "else" Expr
+// Var = <Ident> | "(" <Operator> ")"
+//
+// `App` is parsed in a single loop rather than through `Atom`, so deep nesting doesn't overflow
+// the stack. An `App` with bare operators is kept as `Ast::Infix`, since how they group is only
+// known with the fixities declared. A `Type` is any tokens with balanced parenthesis, read by
+// `Type::parse` when the term is typed
+
+use miette::SourceSpan;
+
+use crate::{
+    ast::{Ast, Node},
+    lexer::{Trace, over},
+    padam::{
+        Token,
+        lexer::{
+            ASSIGN_TY, CLOSE_BRACKET_TY, CLOSE_PAREN_TY, COLON_TY, COMMA_TY, ELSE_KW_TY, EOL_TY,
+            FN_IMPL_TY, FN_KW_TY, IDENT_TY, IF_KW_TY, IN_KW_TY, LET_KW_TY, LETREC_KW_TY, Lexer,
+            OPEN_BRACKET_TY, OPEN_PAREN_TY, OPERATOR_TY, THEN_KW_TY,
+        },
+        parser::{Error, NonTerminals, Parser, Result},
+    },
+    provenance::Sugar,
+};
+
+pub const PROGRAM: &str = "Program";
+pub const DEF: &str = "Def";
+pub const EXPR: &str = "Expr";
+pub const APP: &str = "App";
+pub const ATOM: &str = "Atom";
+pub const ABS: &str = "Abs";
+pub const VAR: &str = "Var";
+
+/// the untyped lambda calculus, with top-level definitions
+pub fn lambda() -> NonTerminals {
+    let mut nt = NonTerminals::new();
+    nt.insert(PROGRAM.into(), program());
+    nt.insert(DEF.into(), def());
+    nt.insert(EXPR.into(), Parser::external(APP));
+    nt.insert(APP.into(), app());
+    nt.insert(ATOM.into(), atom());
+    nt.insert(ABS.into(), abs());
+    nt.insert(VAR.into(), var());
+    nt
+}
+
+fn program() -> Parser<Node> {
+    let eols = Parser::any(Parser::token(EOL_TY));
+    let sep = Parser::plus(Parser::token(EOL_TY));
+    let def = Parser::external(DEF);
+    Parser::new(move |nt, lex, tks| {
+        let (_, mut tks) = eols.parse(nt, lex, tks)?;
+        let mut defs = Vec::new();
+        loop {
+            let (d, rem) = def.parse(nt, lex, tks)?;
+            defs.push(d);
+            // any leftover is reported by `parser::run`
+            let (rem, more) = match sep.parse(nt, lex, rem) {
+                Ok((_, rem)) => (rem, !rem.is_empty()),
+                Err(_) => (rem, false),
+            };
+            tks = rem;
+            if !more {
+                break;
+            }
+        }
+        let at = over(defs[0].at, defs[defs.len() - 1].at);
+        Ok((Ast::Program(defs).at(at), tks))
+    })
+}
+
+fn def() -> Parser<Node> {
+    let ident = Parser::or(vec![Parser::token(IDENT_TY), Parser::token(OPERATOR_TY)]);
+    let params = Parser::any(Parser::token(IDENT_TY));
+    let assign = Parser::token(ASSIGN_TY);
+    let body = Parser::external(EXPR);
+    Parser::new(move |nt, lex, tks| {
+        let (ident, tks) = ident.parse(nt, lex, tks)?;
+        let (params, tks) = params.parse(nt, lex, tks)?;
+        let (_, tks) = assign.parse(nt, lex, tks)?;
+        let (body, tks) = body.parse(nt, lex, tks)?;
+        let at = over(ident.at, body.at);
+        let def = Ast::Def {
+            ident: ident.at,
+            params: params.into_iter().map(|p| p.at).collect(),
+            body,
+        };
+        Ok((def.at(at), tks))
+    })
+}
+
+/// an expression still being parsed, waiting for what closes it
+enum Frame {
+    Root,
+    /// with the elements before each comma, if it's a tuple
+    Parens {
+        open: SourceSpan,
+        items: Vec<Node>,
+    },
+    List {
+        open: SourceSpan,
+        items: Vec<Node>,
+    },
+    Abs {
+        kw: SourceSpan,
+        params: Vec<Param>,
+    },
+    /// a `let` waiting for the `in` that ends its value
+    LetValue {
+        kw: SourceSpan,
+        rec: bool,
+        ident: Token,
+        params: Vec<Token>,
+    },
+    /// an `if` waiting for the `then` that ends its condition
+    IfCond(SourceSpan),
+    /// an `if` waiting for the `else` that ends its first branch
+    IfThen {
+        kw: SourceSpan,
+        cond: Node,
+    },
+    /// an `if` waiting for the end of its last branch, which extends as far right as an
+    /// abstraction's. It becomes `c t e`, applying the Church boolean, so `applied` is `c t`
+    IfElse {
+        kw: SourceSpan,
+        applied: Node,
+    },
+    /// a `let` waiting for the end of its body, which extends as far right as an abstraction's
+    LetBody {
+        kw: SourceSpan,
+        rec: bool,
+        ident: Token,
+        value: Node,
+    },
+}
+
+/// the terms of an expression so far, and which of them are bare operators
+#[derive(Default)]
+struct Terms {
+    items: Vec<Node>,
+    operators: Vec<usize>,
+}
+
+impl Terms {
+    fn push(&mut self, term: Node, operator: bool) {
+        if operator {
+            self.operators.push(self.items.len());
+        }
+        self.items.push(term);
+    }
+
+    /// the terms applied to each other, or `Ast::Infix` if there's an operator among them. A lone
+    /// operator, as in `(+)`, is just a variable
+    fn finish(mut self) -> Option<Node> {
+        if self.operators.is_empty() || self.items.len() == 1 {
+            let mut items = self.items.into_iter();
+            let first = items.next()?;
+            return Some(items.fold(first, |l, r| {
+                let at = over(l.at, r.at);
+                Ast::App(l, r).at(at)
+            }));
+        }
+        let at = over(self.items[0].at, self.items[self.items.len() - 1].at);
+        let items = std::mem::take(&mut self.items);
+        let operators = self.operators;
+        Some(Ast::Infix { items, operators }.at(at))
+    }
+}
+
+/// what an abstraction binds: a name, the sides of a pair or an annotated name
+enum Param {
+    Name(Token),
+    Pair {
+        names: Vec<SourceSpan>,
+        at: SourceSpan,
+    },
+    Annotated {
+        name: SourceSpan,
+        ty: SourceSpan,
+        at: SourceSpan,
+    },
+}
+
+/// a name, or a pattern of names in parenthesis. `(x)` is just `x`
+fn param() -> Parser<Param> {
+    let name = Parser::token(IDENT_TY);
+    let open = Parser::token(OPEN_PAREN_TY);
+    let comma = Parser::token(COMMA_TY);
+    let colon = Parser::token(COLON_TY);
+    let close = Parser::token(CLOSE_PAREN_TY);
+    Parser::new(move |nt, lex, tks| {
+        let Ok((open, tks)) = open.parse(nt, lex, tks) else {
+            return name
+                .parse(nt, lex, tks)
+                .map(|(n, tks)| (Param::Name(n), tks));
+        };
+        let (first, mut tks) = name.parse(nt, lex, tks)?;
+        if let Ok((_, rem)) = colon.parse(nt, lex, tks) {
+            let (ty, close, tks) = annotation(lex, rem)?;
+            let param = Param::Annotated {
+                name: first.at,
+                ty,
+                at: over(open.at, close),
+            };
+            return Ok((param, tks));
+        }
+        let mut names = vec![first.at];
+        while let Ok((_, rem)) = comma.parse(nt, lex, tks) {
+            let (n, rem) = name.parse(nt, lex, rem)?;
+            names.push(n.at);
+            tks = rem;
+        }
+        let (close, tks) = close.parse(nt, lex, tks)?;
+        let param = match names.len() {
+            1 => Param::Name(first),
+            _ => Param::Pair {
+                names,
+                at: over(open.at, close.at),
+            },
+        };
+        Ok((param, tks))
+    })
+}
+
+/// the tokens of a type up to the parenthesis closing the parameter: their span, the one of the
+/// parenthesis and the tokens after it
+fn annotation<'a>(lex: &Lexer, tks: &'a [Token]) -> Result<(SourceSpan, SourceSpan, &'a [Token])> {
+    let mut depth = 0usize;
+    for (i, tk) in tks.iter().enumerate() {
+        match lex.get_type(tk.item) {
+            OPEN_PAREN_TY => depth += 1,
+            CLOSE_PAREN_TY if depth > 0 => depth -= 1,
+            CLOSE_PAREN_TY if i == 0 => return Err(Error::UnexpectedToken { at: tk.at }),
+            CLOSE_PAREN_TY => return Ok((over(tks[0].at, tks[i - 1].at), tk.at, &tks[i + 1..])),
+            EOL_TY | FN_IMPL_TY | COMMA_TY | COLON_TY => {
+                return Err(Error::UnexpectedToken { at: tk.at });
+            }
+            _ => (),
+        }
+    }
+    Err(Error::UnexpectedEof)
+}
+
+/// `body` under an abstraction of each of `params`, binding a name or unpacking a pair
+fn abstract_params(params: Vec<Param>, body: Node) -> Node {
+    params.into_iter().rev().fold(body, |inner, p| match p {
+        Param::Name(p) => {
+            let at = over(p.at, inner.at);
+            Ast::Abs(p.at, inner).at(at)
+        }
+        Param::Pair { names, at } => {
+            let at = over(at, inner.at);
+            Ast::Unpack { names, body: inner }.at(at)
+        }
+        Param::Annotated { name, ty, at } => {
+            let at = over(at, inner.at);
+            Ast::Annotated {
+                name,
+                ty,
+                body: inner,
+            }
+            .at(at)
+        }
+    })
+}
+
+/// `body` under an abstraction of each of `params`
+fn abstract_over(params: Vec<Token>, body: Node) -> Node {
+    params.into_iter().rev().fold(body, |inner, p| {
+        let at = over(p.at, inner.at);
+        Ast::Abs(p.at, inner).at(at)
+    })
+}
+
+/// parses `App` through an explicit stack of the enclosing parenthesis and abstractions instead of
+/// recursing into `Atom`, so any nesting depth fits
+fn app() -> Parser<Node> {
+    let params = Parser::plus(param());
+    let arrow = Parser::token(FN_IMPL_TY);
+    let ident = Parser::token(IDENT_TY);
+    let let_params = Parser::any(Parser::token(IDENT_TY));
+    let assign = Parser::token(ASSIGN_TY);
+    Parser::new(move |nt, lex, mut tks| {
+        // every frame with the application built so far
+        let mut stack: Vec<(Frame, Terms)> = vec![(Frame::Root, Terms::default())];
+        loop {
+            let next = tks.first();
+            let ty = next.map(|tk| lex.get_type(tk.item));
+            let atom = match ty {
+                Some(OPEN_PAREN_TY) => {
+                    let open = next.unwrap().at;
+                    let items = Vec::new();
+                    stack.push((Frame::Parens { open, items }, Terms::default()));
+                    tks = &tks[1..];
+                    continue;
+                }
+                Some(OPEN_BRACKET_TY) => {
+                    let open = next.unwrap().at;
+                    let items = Vec::new();
+                    stack.push((Frame::List { open, items }, Terms::default()));
+                    tks = &tks[1..];
+                    continue;
+                }
+                // `()`, the unit, which is the tuple of no elements
+                Some(CLOSE_PAREN_TY) if matches!(stack.last(), Some((Frame::Parens { items, .. }, terms)) if items.is_empty() && terms.items.is_empty()) =>
+                {
+                    let Some((Frame::Parens { open, .. }, _)) = stack.pop() else {
+                        unreachable!("the frame was just matched")
+                    };
+                    tks = &tks[1..];
+                    Ast::Tuple(Vec::new()).at(over(open, next.unwrap().at))
+                }
+                // `[]`, the only list without an element before its end
+                Some(CLOSE_BRACKET_TY) if matches!(stack.last(), Some((Frame::List { items, .. }, terms)) if items.is_empty() && terms.items.is_empty()) =>
+                {
+                    let Some((Frame::List { open, .. }, _)) = stack.pop() else {
+                        unreachable!("the frame was just matched")
+                    };
+                    tks = &tks[1..];
+                    Ast::List(Vec::new()).at(over(open, next.unwrap().at))
+                }
+                Some(FN_KW_TY) => {
+                    let kw = next.unwrap().at;
+                    let (params, rem) = params.parse(nt, lex, &tks[1..])?;
+                    let (_, rem) = arrow.parse(nt, lex, rem)?;
+                    stack.push((Frame::Abs { kw, params }, Terms::default()));
+                    tks = rem;
+                    continue;
+                }
+                Some(LET_KW_TY | LETREC_KW_TY) => {
+                    let kw = next.unwrap().at;
+                    let (ident, rem) = ident.parse(nt, lex, &tks[1..])?;
+                    let (params, rem) = let_params.parse(nt, lex, rem)?;
+                    let (_, rem) = assign.parse(nt, lex, rem)?;
+                    let rec = ty == Some(LETREC_KW_TY);
+                    let frame = Frame::LetValue {
+                        kw,
+                        rec,
+                        ident,
+                        params,
+                    };
+                    stack.push((frame, Terms::default()));
+                    tks = rem;
+                    continue;
+                }
+                Some(IF_KW_TY) => {
+                    stack.push((Frame::IfCond(next.unwrap().at), Terms::default()));
+                    tks = &tks[1..];
+                    continue;
+                }
+                Some(IDENT_TY | OPERATOR_TY) => {
+                    tks = &tks[1..];
+                    let (_, terms) = stack.last_mut().expect("the root is only popped to return");
+                    terms.push(Ast::Var.at(next.unwrap().at), ty == Some(OPERATOR_TY));
+                    continue;
+                }
+                // anything else ends the innermost expression, and the abstractions around it
+                _ => {
+                    let unexpected = || match next {
+                        Some(tk) => Error::UnexpectedToken { at: tk.at },
+                        None => Error::UnexpectedEof,
+                    };
+                    let (frame, terms) = stack.pop().expect("the root is never closed here");
+                    let body = terms.finish().ok_or_else(unexpected)?;
+                    match frame {
+                        Frame::Root => return Ok((body, tks)),
+                        Frame::Abs { kw, params } => {
+                            let mut abs = abstract_params(params, body);
+                            abs.at = over(kw, abs.at);
+                            abs
+                        }
+                        Frame::LetValue {
+                            kw,
+                            rec,
+                            ident,
+                            params,
+                        } if ty == Some(IN_KW_TY) => {
+                            let value = abstract_over(params, body);
+                            let frame = Frame::LetBody {
+                                kw,
+                                rec,
+                                ident,
+                                value,
+                            };
+                            stack.push((frame, Terms::default()));
+                            tks = &tks[1..];
+                            continue;
+                        }
+                        Frame::LetValue { .. } => return Err(unexpected()),
+                        Frame::IfCond(kw) if ty == Some(THEN_KW_TY) => {
+                            stack.push((Frame::IfThen { kw, cond: body }, Terms::default()));
+                            tks = &tks[1..];
+                            continue;
+                        }
+                        Frame::IfThen { kw, cond } if ty == Some(ELSE_KW_TY) => {
+                            // the span of `if c then t`, so `c` applied to `t` points there
+                            let at = over(kw, body.at);
+                            let applied = Ast::App(cond, body).lowered(at, Sugar::If);
+                            stack.push((Frame::IfElse { kw, applied }, Terms::default()));
+                            tks = &tks[1..];
+                            continue;
+                        }
+                        Frame::IfCond(_) | Frame::IfThen { .. } => return Err(unexpected()),
+                        Frame::IfElse { kw, applied } => {
+                            let at = over(kw, body.at);
+                            Ast::App(applied, body).lowered(at, Sugar::If)
+                        }
+                        Frame::LetBody {
+                            kw,
+                            rec,
+                            ident,
+                            value,
+                        } => {
+                            let at = over(kw, body.at);
+                            let ident = ident.at;
+                            Ast::Let {
+                                rec,
+                                ident,
+                                value,
+                                body,
+                            }
+                            .at(at)
+                        }
+                        // the element is done, and the next one starts
+                        Frame::Parens { open, mut items } if ty == Some(COMMA_TY) => {
+                            items.push(body);
+                            stack.push((Frame::Parens { open, items }, Terms::default()));
+                            tks = &tks[1..];
+                            continue;
+                        }
+                        Frame::List { open, mut items } if ty == Some(COMMA_TY) => {
+                            items.push(body);
+                            stack.push((Frame::List { open, items }, Terms::default()));
+                            tks = &tks[1..];
+                            continue;
+                        }
+                        Frame::Parens { open, mut items } if ty == Some(CLOSE_PAREN_TY) => {
+                            let close = next.unwrap().at;
+                            tks = &tks[1..];
+                            if items.is_empty() {
+                                let mut e = body;
+                                // so the spans of the terms around it include the parenthesis. A
+                                // variable's span is also its name, so it must stay the same
+                                if !matches!(e.item, Ast::Var) {
+                                    e.at = over(open, close);
+                                }
+                                e
+                            } else {
+                                items.push(body);
+                                Ast::Tuple(items).at(over(open, close))
+                            }
+                        }
+                        Frame::List { open, mut items } if ty == Some(CLOSE_BRACKET_TY) => {
+                            items.push(body);
+                            let close = next.unwrap().at;
+                            tks = &tks[1..];
+                            Ast::List(items).at(over(open, close))
+                        }
+                        Frame::Parens { .. } | Frame::List { .. } => return Err(unexpected()),
+                    }
+                }
+            };
+            let (_, terms) = stack.last_mut().expect("the root is only popped to return");
+            terms.push(atom, false);
+        }
+    })
+}
+
+fn atom() -> Parser<Node> {
+    let open = Parser::token(OPEN_PAREN_TY);
+    let expr = Parser::external(EXPR);
+    let close = Parser::token(CLOSE_PAREN_TY);
+    let parens = Parser::new(move |nt, lex, tks| {
+        let (open, tks) = open.parse(nt, lex, tks)?;
+        let (mut e, tks) = expr.parse(nt, lex, tks)?;
+        let (close, tks) = close.parse(nt, lex, tks)?;
+        // so the spans of the terms around it include the parenthesis. A variable's span is
+        // also its name, so it must stay the same
+        if !matches!(e.item, Ast::Var) {
+            e.at = over(open.at, close.at);
+        }
+        Ok((e, tks))
+    });
+    Parser::or(vec![parens, Parser::external(ABS), Parser::external(VAR)])
+}
+
+fn abs() -> Parser<Node> {
+    let kw = Parser::token(FN_KW_TY);
+    let params = Parser::plus(param());
+    let arrow = Parser::token(FN_IMPL_TY);
+    let body = Parser::external(EXPR);
+    Parser::new(move |nt, lex, tks| {
+        let (kw, tks) = kw.parse(nt, lex, tks)?;
+        let (params, tks) = params.parse(nt, lex, tks)?;
+        let (_, tks) = arrow.parse(nt, lex, tks)?;
+        let (body, tks) = body.parse(nt, lex, tks)?;
+        let mut abs = abstract_params(params, body);
+        abs.at = over(kw.at, abs.at);
+        Ok((abs, tks))
+    })
+}
+
+fn var() -> Parser<Node> {
+    Parser::token(IDENT_TY).map(|tk| Ast::Var.at(tk.at))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::{
+        ast::{Ast, Node},
+        padam::{
+            grammar::{EXPR, PROGRAM, lambda},
+            lexer::Lexer,
+            parser::{self, Error},
+        },
+    };
+
+    pub fn parse(start: &str, source: &str) -> parser::Result<Node> {
+        let lexer = Lexer::default();
+        let tokens = lexer.lex(source).unwrap();
+        parser::run(&lambda(), &lexer, start, &tokens)
+    }
+
+    /// renders the tree as a s-expression, using the source for the variables
+    pub fn sexpr(n: &Node, source: &str) -> String {
+        match &n.item {
+            Ast::Var => n.from_code(source).to_string(),
+            Ast::Abs(v, inner) => format!(
+                "(fn {} {})",
+                crate::lexer::from_code(*v, source),
+                sexpr(inner, source)
+            ),
+            Ast::Annotated { name, ty, body } => format!(
+                "(fn ({} : {}) {})",
+                crate::lexer::from_code(*name, source),
+                crate::lexer::from_code(*ty, source),
+                sexpr(body, source)
+            ),
+            Ast::Unpack { names, body } => {
+                let names: Vec<_> = names
+                    .iter()
+                    .map(|v| crate::lexer::from_code(*v, source))
+                    .collect();
+                format!("(fn ({}) {})", names.join(" "), sexpr(body, source))
+            }
+            Ast::App(l, r) => format!("({} {})", sexpr(l, source), sexpr(r, source)),
+            Ast::Let {
+                rec,
+                ident,
+                value,
+                body,
+            } => format!(
+                "({} {} {} {})",
+                if *rec { "letrec" } else { "let" },
+                crate::lexer::from_code(*ident, source),
+                sexpr(value, source),
+                sexpr(body, source)
+            ),
+            Ast::Def {
+                ident,
+                params,
+                body,
+            } => {
+                let params: Vec<_> = params
+                    .iter()
+                    .map(|p| crate::lexer::from_code(*p, source))
+                    .collect();
+                format!(
+                    "(def {} [{}] {})",
+                    crate::lexer::from_code(*ident, source),
+                    params.join(" "),
+                    sexpr(body, source)
+                )
+            }
+            Ast::Tuple(items) => {
+                let items: Vec<_> = items.iter().map(|i| sexpr(i, source)).collect();
+                format!("(tuple {})", items.join(" "))
+            }
+            Ast::List(items) => {
+                let items: Vec<_> = items.iter().map(|i| sexpr(i, source)).collect();
+                format!("[{}]", items.join(" "))
+            }
+            Ast::Infix { items, .. } => {
+                let items: Vec<_> = items.iter().map(|i| sexpr(i, source)).collect();
+                format!("(infix {})", items.join(" "))
+            }
+            Ast::Program(defs) => {
+                let defs: Vec<_> = defs.iter().map(|d| sexpr(d, source)).collect();
+                defs.join(" ")
+            }
+        }
+    }
+
+    pub fn expected(start: &str, source: &str, sexp: &str) {
+        let node = parse(start, source).unwrap();
+        assert_eq!(sexpr(&node, source), sexp);
+    }
+
+    pub mod exprs {
+        use super::{EXPR, expected};
+
+        #[test]
+        pub fn left_assoc_app() {
+            expected(EXPR, "a b c", "((a b) c)");
+        }
+
+        #[test]
+        pub fn parens() {
+            expected(EXPR, "a (b c)", "(a (b c))");
+        }
+
+        #[test]
+        pub fn patterns() {
+            expected(EXPR, "fn (x, y) => y x", "(fn (x y) (y x))");
+            expected(
+                EXPR,
+                "fn a (x, y, z) (b) => a",
+                "(fn a (fn (x y z) (fn b a)))",
+            );
+        }
+
+        #[test]
+        pub fn annotations() {
+            expected(EXPR, "fn (x : dyn) => x x", "(fn (x : dyn) (x x))");
+            expected(
+                EXPR,
+                "fn f (x : (a -> b) -> a) => f x",
+                "(fn f (fn (x : (a -> b) -> a) (f x)))",
+            );
+            assert!(super::parse(EXPR, "fn (x :) => x").is_err());
+            assert!(super::parse(EXPR, "fn (x : a => x").is_err());
+        }
+
+        #[test]
+        pub fn operators() {
+            expected(EXPR, "f a + b", "(infix f a + b)");
+            expected(EXPR, "(+) a (b * c)", "((+ a) (infix b * c))");
+            expected(EXPR, "fn x => x <= y", "(fn x (infix x <= y))");
+        }
+
+        #[test]
+        pub fn abs_body_extends_right() {
+            expected(EXPR, "fn x y => x y", "(fn x (fn y (x y)))");
+            expected(EXPR, "(fn x => x) fn y => y", "((fn x x) (fn y y))");
+        }
+
+        #[test]
+        pub fn lets() {
+            expected(EXPR, "let f x = x in f f", "(let f (fn x x) (f f))");
+            expected(
+                EXPR,
+                "a (letrec f = g (f) in let x = (f) in x) b",
+                "((a (letrec f (g f) (let x f x))) b)",
+            );
+        }
+
+        #[test]
+        pub fn ifs() {
+            expected(EXPR, "if c then a else b", "((c a) b)");
+            expected(
+                EXPR,
+                "f (if if c then d else e then fn x => x else g h) i",
+                "((f ((((c d) e) (fn x x)) (g h))) i)",
+            );
+            assert!(super::parse(EXPR, "if c then a").is_err());
+            assert!(super::parse(EXPR, "if c else a").is_err());
+        }
+
+        #[test]
+        pub fn tuples_and_lists() {
+            expected(EXPR, "(a, b c)", "(tuple a (b c))");
+            expected(EXPR, "f () (())", "((f (tuple )) (tuple ))");
+            expected(EXPR, "f (fn x => x, [])", "(f (tuple (fn x x) []))");
+            expected(EXPR, "[a, (b), [c, d]] e", "([a b [c d]] e)");
+            assert!(super::parse(EXPR, "(a,)").is_err());
+            assert!(super::parse(EXPR, "[a b").is_err());
+        }
+    }
+
+    pub mod deep {
+        use super::{EXPR, parse};
+        use crate::ast::Ast;
+
+        const DEPTH: usize = 100_000;
+
+        #[test]
+        pub fn nested_parens() {
+            let src = format!("{}a{}", "(".repeat(DEPTH), ")".repeat(DEPTH));
+            assert!(matches!(parse(EXPR, &src).unwrap().item, Ast::Var));
+        }
+
+        #[test]
+        pub fn nested_terms() {
+            let src = format!("{}a{}", "a (fn x => ".repeat(DEPTH), ")".repeat(DEPTH));
+            let node = parse(EXPR, &src).unwrap();
+            assert_eq!(node.at.len(), src.len());
+            let copy = node.clone();
+            assert!(matches!(copy.item, Ast::App(..)));
+        }
+    }
+
+    pub mod programs {
+        use super::{PROGRAM, expected};
+
+        #[test]
+        pub fn multiple_defs() {
+            expected(
+                PROGRAM,
+                "\nK x y = x\n\nI = fn x => x\n",
+                "(def K [x y] x) (def I [] (fn x x))",
+            );
+        }
+    }
+
+    pub mod errors {
+        use super::{EXPR, Error, parse};
+
+        #[test]
+        pub fn unclosed_paren() {
+            assert!(matches!(parse(EXPR, "(a b"), Err(Error::UnexpectedEof)));
+        }
+
+        #[test]
+        pub fn leftover() {
+            let err = parse(EXPR, "a b)").unwrap_err();
+            assert!(matches!(err, Error::UnexpectedToken { at } if at.offset() == 3));
+        }
+    }
+}
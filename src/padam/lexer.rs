@@ -1,5 +1,8 @@
-use crate::lexer::Meta;
+use miette::SourceSpan;
+
+use crate::lexer::{self, Meta};
 use crate::padam::{Error, Result, Token};
+use crate::symbol::Interner;
 
 /// A raw component from the source-code
 pub trait Lexeme {
@@ -21,16 +24,19 @@ pub struct FnToken {
 
 impl Lexeme for FnToken {
     fn parse<'a>(&self, tokens: &'a str) -> Result<&'a str> {
-        let end = tokens
-            .char_indices()
+        // `f` receives the char index, while the slice needs the byte one
+        let (count, end) = tokens
+            .chars()
+            .enumerate()
             .take_while(|(i, c)| (self.f)(*i, *c))
-            .count();
-
-        if end >= self.min_amount {
-            let end = if self.greedy && tokens.len() > end {
-                end + 1
-            } else {
-                end
+            .fold((0, 0), |(count, end), (_, c)| {
+                (count + 1, end + c.len_utf8())
+            });
+
+        if count >= self.min_amount {
+            let end = match tokens[end..].chars().next() {
+                Some(c) if self.greedy => end + c.len_utf8(),
+                _ => end,
             };
             Ok(&tokens[..end])
         } else {
@@ -41,12 +47,41 @@ impl Lexeme for FnToken {
 
 pub fn ident() -> FnToken {
     FnToken {
-        f: Box::new(|i, c| {
-            c == '_'
-                || (c >= 'a' && c <= 'z')
-                || (c >= 'A' && c <= 'Z')
-                || (i > 0 && c >= '0' && c <= '9')
-        }),
+        f: Box::new(|i, c| c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit())),
+        greedy: false,
+        min_amount: 1,
+    }
+}
+
+/// a name inside a module, as `L.map`: names joined by dots
+pub struct Qualified;
+
+impl Lexeme for Qualified {
+    fn parse<'a>(&self, tokens: &'a str) -> Result<&'a str> {
+        let ident = ident();
+        let mut end = ident.parse(tokens)?.len();
+        while let Some(rest) = tokens[end..].strip_prefix('.')
+            && let Ok(name) = ident.parse(rest)
+        {
+            end += 1 + name.len();
+        }
+        Ok(&tokens[..end])
+    }
+}
+
+/// an integer, as `12` or `-3`. A `-` alone is an operator, which must be pushed first to win
+pub fn number() -> FnToken {
+    FnToken {
+        f: Box::new(|i, c| c.is_ascii_digit() || (i == 0 && c == '-')),
+        greedy: false,
+        min_amount: 1,
+    }
+}
+
+/// a name made of symbols, as `+` or `<=`. `=` and `=>` alone must be pushed first to win
+pub fn operator() -> FnToken {
+    FnToken {
+        f: Box::new(|_, c| "+-*/<>!&|^%~=".contains(c)),
         greedy: false,
         min_amount: 1,
     }
@@ -122,25 +157,65 @@ impl Lexer {
         self.tokenizers.push(tokenizer);
     }
 
-    pub fn lex(&self, src: &str) -> Result<Vec<Token>> {
-        let mut tokens = Vec::new();
-        let mut start = 0;
-        while start < src.len() {
-            let offset = &src[start..];
-            let (i, lexeme) = self.single_lex(offset)?;
-            let span = (start, lexeme.len()).into();
-            start += lexeme.len();
-            if !self.tokenizers[i].ignore {
-                tokens.push(Meta { item: i, at: span });
-            }
+    pub fn lex(&self, src: &str) -> lexer::Result<Vec<Token>> {
+        self.tokens(src).collect()
+    }
+
+    /// as `lex`, also interning every name in `symbols`, so whoever compiles the tokens finds
+    /// them there already
+    pub fn lex_interned(&self, src: &str, symbols: &mut Interner) -> lexer::Result<Vec<Token>> {
+        self.tokens(src)
+            .inspect(|t| {
+                if let Ok(t) = t {
+                    self.intern(t, src, symbols);
+                }
+            })
+            .collect()
+    }
+
+    /// interns `token` if it's a name, which a number isn't
+    pub fn intern(&self, token: &Token, src: &str, symbols: &mut Interner) {
+        let name = token.from_code(src);
+        if [IDENT_TY, OPERATOR_TY].contains(&self.get_type(token.item))
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+        {
+            symbols.intern(name);
+        }
+    }
+
+    /// lexes `src` lazily, a token at a time. It stops after the first error
+    pub fn tokens<'a>(&'a self, src: &'a str) -> Tokens<'a> {
+        Tokens {
+            lexer: self,
+            src,
+            start: 0,
+            trivia: None,
+        }
+    }
+
+    /// lexes `src` keeping what's skipped, so the source can be rebuilt from the tokens
+    pub fn lex_with_trivia(&self, src: &str) -> lexer::Result<Layout> {
+        let mut tokens = self.tokens(src).with_trivia();
+        let mut spaced = Vec::new();
+        while let Some(token) = tokens.next() {
+            let token = token?;
+            spaced.push(Spaced {
+                leading: tokens.take_trivia(),
+                token,
+            });
         }
-        Ok(tokens)
+        Ok(Layout {
+            tokens: spaced,
+            trailing: tokens.take_trivia(),
+        })
     }
 
+    /// on a tie, the tokenizer pushed first wins. Keywords must come before `ident`
     pub fn single_lex<'a>(&'a self, src: &'a str) -> Result<(usize, &'a str)> {
         self.tokenizers
             .iter()
             .enumerate()
+            .rev()
             .filter_map(|(i, t)| t.toker.parse(src).ok().map(|tk| (i, tk)))
             .max_by_key(|(_, span)| span.chars().count())
             .map_or_else(|| Err(Error::Impossible), Ok)
@@ -151,27 +226,175 @@ impl Lexer {
     }
 }
 
-const FN_KW_TY: &str = "FnKw";
-const FN_IMPL_TY: &str = "FnImpl";
-const OPEN_PAREN_TY: &str = "OpenParen";
-const CLOSE_PAREN_TY: &str = "CloseParen";
-const EOL_TY: &str = "Eol";
-const IDENT_TY: &str = "Ident";
-const ASSIGN_TY: &str = "Assign";
+/// what an ignored tokenizer matched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    Newline,
+    Comment,
+}
+
+impl TriviaKind {
+    pub fn of(text: &str) -> Self {
+        match text.chars().next() {
+            Some('#') => Self::Comment,
+            Some('\n') => Self::Newline,
+            _ => Self::Whitespace,
+        }
+    }
+}
+
+/// a run of skipped source of the same kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub at: SourceSpan,
+}
+
+/// a token, with the trivia between it and the one before
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spaced {
+    pub leading: Vec<Trivia>,
+    pub token: Token,
+}
+
+/// every token of a source with its trivia, as `Lexer::lex_with_trivia` finds them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Layout {
+    pub tokens: Vec<Spaced>,
+    /// after the last token
+    pub trailing: Vec<Trivia>,
+}
+
+impl Layout {
+    pub fn trivia(&self) -> impl Iterator<Item = &Trivia> {
+        self.tokens
+            .iter()
+            .flat_map(|t| &t.leading)
+            .chain(&self.trailing)
+    }
+
+    /// the source it was lexed from, rebuilt piece by piece
+    pub fn to_source(&self, src: &str) -> String {
+        let text = |at: SourceSpan| &src[at.offset()..at.offset() + at.len()];
+        let mut s = String::with_capacity(src.len());
+        for t in &self.tokens {
+            t.leading.iter().for_each(|tr| s.push_str(text(tr.at)));
+            s.push_str(text(t.token.at));
+        }
+        self.trailing.iter().for_each(|tr| s.push_str(text(tr.at)));
+        s
+    }
+}
+
+/// the tokens of a source, as `Lexer::tokens` finds them
+pub struct Tokens<'a> {
+    lexer: &'a Lexer,
+    src: &'a str,
+    start: usize,
+    /// what was skipped since the last `take_trivia`, if it's kept
+    trivia: Option<Vec<Trivia>>,
+}
+
+impl Tokens<'_> {
+    /// keeps what ignored tokenizers match instead of dropping it
+    pub fn with_trivia(mut self) -> Self {
+        self.trivia = Some(Vec::new());
+        self
+    }
+
+    /// the trivia skipped since the last call. Empty if it isn't kept
+    pub fn take_trivia(&mut self) -> Vec<Trivia> {
+        self.trivia.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn skip(&mut self, at: SourceSpan) {
+        let Some(trivia) = &mut self.trivia else {
+            return;
+        };
+        let kind = TriviaKind::of(&self.src[at.offset()..]);
+        match trivia.last_mut() {
+            Some(last) if last.kind == kind && last.at.offset() + last.at.len() == at.offset() => {
+                last.at = (last.at.offset(), last.at.len() + at.len()).into();
+            }
+            _ => trivia.push(Trivia { kind, at }),
+        }
+    }
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = lexer::Result<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.start < self.src.len() {
+            let offset = &self.src[self.start..];
+            let Ok((i, lexeme)) = self.lexer.single_lex(offset) else {
+                let len = offset.chars().next().map_or(1, char::len_utf8);
+                let at = (self.start, len).into();
+                self.start = self.src.len();
+                return Some(Err(lexer::Error::InvalidCharSeq { at }));
+            };
+            let span = (self.start, lexeme.len()).into();
+            self.start += lexeme.len();
+            if self.lexer.tokenizers[i].ignore {
+                self.skip(span);
+            } else {
+                return Some(Ok(Meta {
+                    item: i,
+                    at: span,
+                    sugar: None,
+                }));
+            }
+        }
+        None
+    }
+}
+
+pub const FN_KW_TY: &str = "FnKw";
+pub const FN_IMPL_TY: &str = "FnImpl";
+pub const OPEN_PAREN_TY: &str = "OpenParen";
+pub const CLOSE_PAREN_TY: &str = "CloseParen";
+pub const EOL_TY: &str = "Eol";
+pub const IDENT_TY: &str = "Ident";
+pub const ASSIGN_TY: &str = "Assign";
+pub const LET_KW_TY: &str = "LetKw";
+pub const LETREC_KW_TY: &str = "LetRecKw";
+pub const IN_KW_TY: &str = "InKw";
+pub const IF_KW_TY: &str = "IfKw";
+pub const THEN_KW_TY: &str = "ThenKw";
+pub const ELSE_KW_TY: &str = "ElseKw";
+pub const OPERATOR_TY: &str = "Operator";
+pub const COMMA_TY: &str = "Comma";
+pub const OPEN_BRACKET_TY: &str = "OpenBracket";
+pub const CLOSE_BRACKET_TY: &str = "CloseBracket";
+pub const COLON_TY: &str = "Colon";
 
 impl Default for Lexer {
     fn default() -> Self {
         let tokenizers = [
             Tokenizer::new(FN_KW_TY, literal("fn")),
+            Tokenizer::new(LET_KW_TY, literal("let")),
+            Tokenizer::new(LETREC_KW_TY, literal("letrec")),
+            Tokenizer::new(IN_KW_TY, literal("in")),
+            Tokenizer::new(IF_KW_TY, literal("if")),
+            Tokenizer::new(THEN_KW_TY, literal("then")),
+            Tokenizer::new(ELSE_KW_TY, literal("else")),
             Tokenizer::new(FN_IMPL_TY, literal("=>")),
             Tokenizer::new(OPEN_PAREN_TY, single_char('(')),
             Tokenizer::new(CLOSE_PAREN_TY, single_char(')')),
+            Tokenizer::new(COMMA_TY, single_char(',')),
+            Tokenizer::new(COLON_TY, single_char(':')),
+            Tokenizer::new(OPEN_BRACKET_TY, single_char('[')),
+            Tokenizer::new(CLOSE_BRACKET_TY, single_char(']')),
             Tokenizer::new(EOL_TY, single_char('\n')),
             Tokenizer::ignore(single_char(' ')),
             Tokenizer::ignore(single_char('\t')),
             Tokenizer::new(IDENT_TY, ident()),
-            Tokenizer::ignore(comment()),
+            Tokenizer::new(IDENT_TY, Qualified),
             Tokenizer::new(ASSIGN_TY, single_char('=')),
+            Tokenizer::new(OPERATOR_TY, operator()),
+            Tokenizer::new(IDENT_TY, number()),
+            Tokenizer::ignore(comment()),
         ]
         .into_iter();
         Self::new(tokenizers)
@@ -214,17 +437,90 @@ pub mod tests {
         pub fn aggregate() {
             expected(
                 &Lexer::default(),
-                "plain snake_case PascalCase UPPER_SNAKE_CASE MiXeD WithNumb3r _123IsValid s O Nice",
-                &[IDENT_TY; 10],
+                "plain snake_case PascalCase UPPER_SNAKE_CASE MiXeD WithNumb3r _123IsValid s O Nice L.map",
+                &[IDENT_TY; 11],
             );
         }
     }
 
+    pub mod interning {
+        use crate::padam::lexer::Lexer;
+        use crate::symbol::Interner;
+
+        #[test]
+        pub fn names_only() {
+            let mut symbols = Interner::default();
+            let tokens = Lexer::default()
+                .lex_interned("f = fn x => x + 12 f", &mut symbols)
+                .unwrap();
+            assert_eq!(tokens.len(), 9);
+            assert_eq!(symbols.len(), 3);
+            assert!(["f", "x", "+"].iter().all(|n| symbols.get(n).is_some()));
+        }
+    }
+
     pub mod snippets {
-        use crate::padam::lexer::{FN_IMPL_TY, IDENT_TY};
+        use crate::padam::lexer::{
+            ASSIGN_TY, ELSE_KW_TY, FN_IMPL_TY, FN_KW_TY, IDENT_TY, IF_KW_TY, IN_KW_TY,
+            LETREC_KW_TY, OPERATOR_TY, THEN_KW_TY,
+        };
 
         use super::{Lexer, expected};
 
+        #[test]
+        pub fn abs() {
+            expected(
+                &Lexer::default(),
+                "fn x y => x",
+                &[FN_KW_TY, IDENT_TY, IDENT_TY, FN_IMPL_TY, IDENT_TY],
+            );
+        }
+
+        #[test]
+        pub fn let_in() {
+            expected(
+                &Lexer::default(),
+                "letrec f = inner in letter",
+                &[
+                    LETREC_KW_TY,
+                    IDENT_TY,
+                    ASSIGN_TY,
+                    IDENT_TY,
+                    IN_KW_TY,
+                    IDENT_TY,
+                ],
+            );
+        }
+
+        #[test]
+        pub fn if_then_else() {
+            expected(
+                &Lexer::default(),
+                "if if0 then elsewhere else iffy",
+                &[
+                    IF_KW_TY, IDENT_TY, THEN_KW_TY, IDENT_TY, ELSE_KW_TY, IDENT_TY,
+                ],
+            );
+        }
+
+        #[test]
+        pub fn operators() {
+            expected(
+                &Lexer::default(),
+                "<= x = y => -1 - z",
+                &[
+                    OPERATOR_TY,
+                    IDENT_TY,
+                    ASSIGN_TY,
+                    IDENT_TY,
+                    FN_IMPL_TY,
+                    IDENT_TY,
+                    OPERATOR_TY,
+                    IDENT_TY,
+                ],
+            );
+        }
+
         #[test]
         pub fn fn_decl() {
             expected(
@@ -236,7 +532,7 @@ pub mod tests {
     }
 
     pub mod comments {
-        use crate::padam::lexer::{FN_IMPL_TY, IDENT_TY};
+        use crate::padam::lexer::{FN_IMPL_TY, IDENT_TY, TriviaKind};
 
         use super::{Lexer, expected};
 
@@ -257,5 +553,29 @@ pub mod tests {
                 &[IDENT_TY, IDENT_TY, FN_IMPL_TY, IDENT_TY],
             );
         }
+
+        #[test]
+        pub fn kept_as_trivia() {
+            let src = "  id \t # the identity\n\tx => x # done";
+            let layout = Lexer::default().lex_with_trivia(src).unwrap();
+            assert_eq!(layout.to_source(src), src);
+            let kinds: Vec<_> = layout.trivia().map(|t| t.kind).collect();
+            assert_eq!(
+                kinds,
+                [
+                    TriviaKind::Whitespace,
+                    TriviaKind::Whitespace,
+                    TriviaKind::Comment,
+                    TriviaKind::Whitespace,
+                    TriviaKind::Whitespace,
+                    TriviaKind::Whitespace,
+                    TriviaKind::Whitespace,
+                    TriviaKind::Comment,
+                ]
+            );
+            assert_eq!(layout.tokens[0].leading[0].at, (0, 2).into());
+            assert_eq!(layout.trailing.len(), 2);
+            assert_eq!(Lexer::default().lex(src).unwrap().len(), 4);
+        }
     }
 }
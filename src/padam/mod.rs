@@ -5,6 +5,7 @@ use crate::lexer::Meta;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+pub mod grammar;
 pub mod lexer;
 pub mod parser;
 
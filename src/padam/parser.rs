@@ -1,46 +1,143 @@
-// Expr = App
-// App =
-//      Atom+
-// Atom =
-//      "(" Expr ")"
-//      Abs
-//      Var
-// Abs =
-//      "fn" <Ident>+ "=>" Expr
-// Var = <Ident>
-
 use std::collections::HashMap;
 
-use miette::Diagnostic;
+use miette::{Diagnostic, SourceSpan};
 use thiserror::Error;
 
 use crate::{
-    ast::Ast,
+    ast::Node,
     padam::{Token, lexer::Lexer},
 };
 
-#[derive(Error, Debug, Diagnostic)]
+#[derive(Error, Debug, Diagnostic, Clone)]
 pub enum Error {
     #[error("no alternative to parser this snippet")]
-    #[diagnostic(code(parser::no_alternative))]
+    #[diagnostic(
+        code(parser::no_alternative),
+        help("the grammar has a choice that can't match anything, as `Parser::or(vec![])`")
+    )]
     NoAlternative,
 
     #[error("there was no enough symbols to repeat the sequence")]
-    #[diagnostic(code(parser::no_enough_rep))]
+    #[diagnostic(
+        code(parser::no_enough_rep),
+        help(
+            "the grammar repeats a pattern that can't match, as `Parser::plus(Parser::or(vec![]))`"
+        )
+    )]
     NoEnoughRep { tks_consumed: usize },
+
+    #[error("unexpected token")]
+    #[diagnostic(
+        code(parser::unexpected_token),
+        help("maybe a missing parenthesis or `=>`?")
+    )]
+    UnexpectedToken {
+        #[label("this wasn't expected here")]
+        at: SourceSpan,
+    },
+
+    #[error("unexpected end of input")]
+    #[diagnostic(
+        code(parser::unexpected_eof),
+        help("the expression ended before it was complete")
+    )]
+    UnexpectedEof,
+
+    /// when the tokens are streamed, lexing fails while parsing
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Lexer(#[from] crate::lexer::Error),
 }
 
 impl Error {
     pub fn tokens_consumed(&self) -> Option<usize> {
         match self {
-            Self::NoAlternative => None,
             Self::NoEnoughRep { tks_consumed } => Some(*tks_consumed),
+            _ => None,
+        }
+    }
+
+    /// the same error, for a source that starts `by` bytes later
+    pub fn shift(self, by: usize) -> Self {
+        use crate::lexer::shift;
+        match self {
+            Self::UnexpectedToken { at } => Self::UnexpectedToken { at: shift(at, by) },
+            Self::Lexer(crate::lexer::Error::InvalidCharSeq { at }) => {
+                Self::Lexer(crate::lexer::Error::InvalidCharSeq { at: shift(at, by) })
+            }
+            e => e,
+        }
+    }
+
+    /// how far in the source the parser went before failing
+    pub fn reach(&self) -> Option<usize> {
+        match self {
+            Self::UnexpectedToken { at } => Some(at.offset()),
+            Self::UnexpectedEof => Some(usize::MAX),
+            _ => None,
         }
     }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
-pub type NonTerminals = HashMap<Box<str>, Parser<Ast>>;
+pub type NonTerminals = HashMap<Box<str>, Parser<Node>>;
+
+/// parses all the tokens as the `start` non-terminal
+pub fn run(nt: &NonTerminals, lex: &Lexer, start: &str, tks: &[Token]) -> Result<Node> {
+    let (node, remaining) = nt[start].parse(nt, lex, tks)?;
+    match remaining.first() {
+        Some(tk) => Err(Error::UnexpectedToken { at: tk.at }),
+        None => Ok(node),
+    }
+}
+
+/// the tokens of a line and the one ending it
+pub type Line = (Vec<Token>, Option<Token>);
+
+/// parses a line as the `start` non-terminal. Running out of tokens before the line's end is
+/// reported at the end, just as if the whole source was parsed at once
+pub fn run_line(nt: &NonTerminals, lex: &Lexer, start: &str, line: &Line) -> Result<Node> {
+    match (run(nt, lex, start, &line.0), &line.1) {
+        (Err(Error::UnexpectedEof), Some(eol)) => Err(Error::UnexpectedToken { at: eol.at }),
+        (r, _) => r,
+    }
+}
+
+/// pulls tokens from a lexer as they're needed, a line at a time. The combinators backtrack, so
+/// they parse a line from a buffer, but a whole source never has to be lexed up front
+pub struct TokenStream<I> {
+    tokens: I,
+}
+
+impl<I: Iterator<Item = crate::lexer::Result<Token>>> TokenStream<I> {
+    pub fn new(tokens: I) -> Self {
+        Self { tokens }
+    }
+
+    /// the tokens until the next one that `is_eol`, which is consumed and returned apart, if
+    /// there's any. `None` once there's nothing left
+    pub fn next_line(&mut self, is_eol: impl Fn(&Token) -> bool) -> Result<Option<Line>> {
+        let mut line = Vec::new();
+        for tk in self.tokens.by_ref() {
+            let tk = tk?;
+            if is_eol(&tk) {
+                return Ok(Some((line, Some(tk))));
+            }
+            line.push(tk);
+        }
+        Ok((!line.is_empty()).then_some((line, None)))
+    }
+
+    /// skips the empty lines
+    pub fn next_nonempty_line(&mut self, is_eol: impl Fn(&Token) -> bool) -> Result<Option<Line>> {
+        while let Some(line) = self.next_line(&is_eol)? {
+            if !line.0.is_empty() {
+                return Ok(Some(line));
+            }
+        }
+        Ok(None)
+    }
+}
 
 pub type CombFn<T> =
     Box<dyn for<'a> Fn(&'a NonTerminals, &'a Lexer, &'a [Token]) -> Result<(T, &'a [Token])>>;
@@ -82,6 +179,13 @@ impl<T: 'static> Parser<T> {
         })
     }
 
+    pub fn map<U: 'static>(self, f: impl Fn(T) -> U + 'static) -> Parser<U> {
+        Parser::new(move |nt, lex, tks| {
+            self.parse(nt, lex, tks)
+                .map(|(item, remaining)| (f(item), remaining))
+        })
+    }
+
     pub fn or(alternatives: Vec<Self>) -> Self {
         Self::new(move |nt, lex, tks| {
             let mut biggest_err = Error::NoAlternative;
@@ -89,7 +193,9 @@ impl<T: 'static> Parser<T> {
                 match alt.parse(nt, lex, tks) {
                     Ok(v) => return Ok(v),
                     Err(e) => {
-                        if e.tokens_consumed() > biggest_err.tokens_consumed() {
+                        if e.reach() > biggest_err.reach()
+                            || e.tokens_consumed() > biggest_err.tokens_consumed()
+                        {
                             biggest_err = e;
                         }
                     }
@@ -103,21 +209,31 @@ impl<T: 'static> Parser<T> {
         Parser::new(move |nt, lex, tks| {
             let mut remaining_tokens = tks;
             let mut v = Vec::new();
+            let mut last_err = None;
 
-            while v.len() < max
-                && let Ok((ast, rem)) = parser.parse(nt, lex, remaining_tokens)
-            {
-                remaining_tokens = rem;
-                v.push(ast);
+            while v.len() < max {
+                match parser.parse(nt, lex, remaining_tokens) {
+                    Ok((ast, rem)) => {
+                        remaining_tokens = rem;
+                        v.push(ast);
+                    }
+                    Err(e) => {
+                        last_err = Some(e);
+                        break;
+                    }
+                }
             }
 
             if v.len() < min {
-                return Err(Error::NoEnoughRep {
-                    tks_consumed: tks.len() - remaining_tokens.len(),
+                return Err(match last_err {
+                    Some(e) if e.reach().is_some() => e,
+                    _ => Error::NoEnoughRep {
+                        tks_consumed: tks.len() - remaining_tokens.len(),
+                    },
                 });
             }
 
-            return Ok((v, remaining_tokens));
+            Ok((v, remaining_tokens))
         })
     }
 
@@ -132,9 +248,23 @@ impl<T: 'static> Parser<T> {
     pub fn option(parser: Self) -> Parser<Vec<T>> {
         Self::rep(parser, 0, 1)
     }
+}
 
-    pub fn external(name: &str) -> Parser<Ast> {
+impl Parser<Node> {
+    pub fn external(name: &str) -> Self {
         let name: Box<str> = name.into();
         Parser::new(move |nt, lex, tks| nt[&name].parse(nt, lex, tks))
     }
 }
+
+impl Parser<Token> {
+    /// matches a single token of the `ty` type
+    pub fn token(ty: &str) -> Self {
+        let ty: Box<str> = ty.into();
+        Parser::new(move |_, lex, tks| match tks.split_first() {
+            Some((tk, rem)) if lex.get_type(tk.item) == &*ty => Ok((tk.clone(), rem)),
+            Some((tk, _)) => Err(Error::UnexpectedToken { at: tk.at }),
+            None => Err(Error::UnexpectedEof),
+        })
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use crate::arts::{CompArtifact, Const, Prim, Term, TermIdx};
+
+impl CompArtifact {
+    /// the head of the application at `idx` and its arguments, the first one first
+    pub fn spine(&self, mut idx: TermIdx) -> (TermIdx, Vec<TermIdx>) {
+        let mut args = Vec::new();
+        while let Term::App(l, r) = self.arena()[idx.0] {
+            args.push(r);
+            idx = l;
+        }
+        args.reverse();
+        (idx, args)
+    }
+
+    /// the primitive applied at `idx` to as many arguments as it takes, if any
+    pub fn saturated(&self, idx: TermIdx) -> Option<(Prim, Vec<TermIdx>)> {
+        let (head, args) = self.spine(idx);
+        match self.arena()[head.0] {
+            Term::Const(Const::Prim(p)) if args.len() == p.arity() => Some((p, args)),
+            _ => None,
+        }
+    }
+
+    /// whether `idx` itself can be contracted: an abstraction applied to something, or a
+    /// primitive applied to all it takes, with numbers where it needs them
+    pub fn is_redex(&self, idx: TermIdx) -> bool {
+        let Term::App(l, _) = self.arena()[idx.0] else {
+            return false;
+        };
+        if let Term::Abs { .. } = self.arena()[l.0] {
+            return true;
+        }
+        self.saturated(idx).is_some_and(|(p, args)| match p {
+            Prim::Cast(_) => self.is_function(args[0]).is_some(),
+            p => (args[..p.strict()].iter())
+                .all(|a| matches!(self.arena()[a.0], Term::Const(Const::Int(_)))),
+        })
+    }
+
+    /// whether `idx` is a function: an abstraction, or a primitive short of arguments. `None` if
+    /// that isn't known yet, as it isn't a weak head normal form or it's stuck on a variable
+    pub fn is_function(&self, idx: TermIdx) -> Option<bool> {
+        let (head, args) = self.spine(idx);
+        match self.arena()[head.0] {
+            Term::Abs { .. } if args.is_empty() => Some(true),
+            Term::Const(Const::Prim(p)) if args.len() < p.arity() => Some(true),
+            Term::Const(Const::Int(_)) => Some(false),
+            _ => None,
+        }
+    }
+
+    /// whether there's no redex anywhere in `idx`, even under abstractions
+    pub fn is_normal_form(&self, idx: TermIdx) -> bool {
+        self.reachable(&[idx])
+            .into_iter()
+            .all(|t| !self.is_redex(t))
+    }
+
+    /// whether there's no redex at the head of `idx`: it's an abstraction, or a variable or a
+    /// constant applied to anything. A primitive applied to all it takes still has to be computed
+    pub fn is_whnf(&self, idx: TermIdx) -> bool {
+        let (head, args) = self.spine(idx);
+        match self.arena()[head.0] {
+            Term::Abs { .. } => args.is_empty(),
+            Term::Var(_) => true,
+            Term::Const(Const::Prim(p)) => args.len() < p.arity(),
+            Term::Const(Const::Int(_)) => true,
+            Term::App(..) => unreachable!("the head of a spine isn't an application"),
+        }
+    }
+
+    /// whether every variable of `idx` is bound by an abstraction inside it
+    pub fn is_closed(&self, idx: TermIdx) -> bool {
+        // how many λ outside of each term its variables need
+        let needs = self.fold(idx, |t, child| match *t {
+            Term::Var(v) => v.0 + 1,
+            Term::Abs { inner } => child(inner).saturating_sub(1),
+            Term::App(l, r) => child(l).max(child(r)),
+            Term::Const(_) => 0,
+        });
+        needs == 0
+    }
+
+    /// how many nodes `idx` has, as a tree. Shared subterms count once for each parent
+    pub fn size(&self, idx: TermIdx) -> usize {
+        self.fold(idx, |t, child| match *t {
+            Term::Var(_) | Term::Const(_) => 1,
+            Term::Abs { inner } => child(inner).saturating_add(1),
+            Term::App(l, r) => child(l).saturating_add(child(r)).saturating_add(1),
+        })
+    }
+
+    /// how many nodes there are in the longest path from `idx` to a leaf
+    pub fn depth(&self, idx: TermIdx) -> usize {
+        self.fold(idx, |t, child| match *t {
+            Term::Var(_) | Term::Const(_) => 1,
+            Term::Abs { inner } => child(inner) + 1,
+            Term::App(l, r) => child(l).max(child(r)) + 1,
+        })
+    }
+
+    /// how many abstractions `idx` has, counted as `size` does
+    pub fn binders(&self, idx: TermIdx) -> usize {
+        self.fold(idx, |t, child| match *t {
+            Term::Var(_) | Term::Const(_) => 0,
+            Term::Abs { inner } => child(inner).saturating_add(1),
+            Term::App(l, r) => child(l).saturating_add(child(r)),
+        })
+    }
+
+    /// computes `f` for each term reachable from `idx`, out of what it gave for its children.
+    /// A term only points to the ones before it, so going through the arena in order visits the
+    /// children first, without recursion
+    fn fold(&self, idx: TermIdx, f: impl Fn(&Term, &dyn Fn(TermIdx) -> usize) -> usize) -> usize {
+        let mut done: HashMap<TermIdx, usize> = HashMap::new();
+        for t in self.reachable(&[idx]) {
+            let value = f(&self.arena()[t.0], &|c| done[&c]);
+            done.insert(t, value);
+        }
+        done[&idx]
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::arts::{OuterIdx, Term};
+    use crate::session::Session;
+
+    #[test]
+    pub fn predicates() {
+        let mut s = Session::new();
+        s.eval_str("I x = x\nK x y = x").unwrap();
+        s.irc.primitives = true;
+        let mut compile = |src| s.compile_expr(src).unwrap().unwrap();
+        let (k, ki, redex) = (compile("K"), compile("K I"), compile("fn y => I y"));
+        let int = compile("+ 1 (* 2 3)");
+        let y = s.art.push(Term::Var(OuterIdx(1)));
+        let open = s.art.push(Term::Abs { inner: y });
+        let art = &s.art;
+
+        assert!(art.is_normal_form(k) && art.is_whnf(k));
+        assert!(!art.is_normal_form(ki) && !art.is_whnf(ki));
+        assert!(!art.is_normal_form(redex) && art.is_whnf(redex));
+        assert!(!art.is_whnf(int) && !art.is_normal_form(int));
+        assert!(art.is_closed(redex) && !art.is_closed(open));
+
+        // `λy. (λx. x) y`
+        assert_eq!(art.size(redex), 5);
+        assert_eq!(art.depth(redex), 4);
+        assert_eq!(art.binders(redex), 2);
+        assert_eq!(art.size(int), 9);
+        assert_eq!(art.binders(int), 0);
+    }
+}
@@ -0,0 +1,333 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use crate::ir;
+
+/// how an abstraction is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Notation {
+    /// `fn x y => x`
+    Fn,
+    /// `λx y.x`
+    #[default]
+    Lambda,
+    /// `\x y.x`
+    Backslash,
+    /// `λ λ 1`, with each variable replaced by how many abstractions are between it and the one
+    /// binding it. It can't be parsed back
+    DeBruijn,
+}
+
+impl Notation {
+    pub const ALL: [Self; 4] = [Self::Lambda, Self::Backslash, Self::Fn, Self::DeBruijn];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Fn => "fn",
+            Self::Lambda => "lambda",
+            Self::Backslash => "backslash",
+            Self::DeBruijn => "debruijn",
+        }
+    }
+}
+
+impl TryFrom<&str> for Notation {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Self::ALL.into_iter().find(|n| n.name() == value).ok_or(())
+    }
+}
+
+/// a term, as far as the printer needs to know to place parenthesis
+#[derive(Debug, Clone)]
+pub enum View<'a, T> {
+    /// a name or a constant. An operator is written `(+)`
+    Atom(Cow<'a, str>),
+    /// the variable bound by the `n`th abstraction around it, counting from 0
+    Bound(usize),
+    /// an abstraction binding a name, or a pattern as `(x, y)`
+    Abs(Cow<'a, str>, T),
+    /// an abstraction named after how many abstractions are around it, `a` for the outermost, so
+    /// a term is printed the same wherever it's stored
+    Fresh(T),
+    App(T, T),
+    /// terms inside delimiters of their own, as a tuple
+    Group(Vec<Piece<'a, T>>),
+    /// a term led by a keyword, as `let`, which extends to the right as far as an abstraction:
+    /// its pieces, then the term it ends with
+    Open(Vec<Piece<'a, T>>, T),
+    /// terms with operators between them, separated by spaces. It can't be applied without
+    /// parenthesis, as `a + b c` has `c` as one more operand
+    Infix(Vec<Piece<'a, T>>),
+}
+
+/// text written as is, or a term printed as if it was alone
+#[derive(Debug, Clone)]
+pub enum Piece<'a, T> {
+    Text(Cow<'a, str>),
+    Term(T),
+}
+
+/// where a term is printed, to know if it needs parenthesis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pos {
+    /// nothing comes after it, or a keyword or a delimiter closes it
+    Free,
+    /// applied to something
+    Head,
+    /// an argument with more after it
+    Arg,
+    /// the last argument, which an abstraction may extend from up to the end
+    LastArg,
+}
+
+enum Task<'a, T> {
+    Text(Cow<'a, str>),
+    Term(T, Pos),
+    /// the body of an abstraction was printed, so its binders are out of scope
+    Unbind(usize),
+    /// where the term marked starts or ends
+    Mark,
+}
+
+/// whether `name` is an operator, which is written `(+)` when it isn't between its operands
+pub fn is_operator(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| "+-*/<>!&|^%~=".contains(c))
+}
+
+/// `root` with the least parenthesis it needs to be parsed back the same, and nested abstractions
+/// merged as `fn x y => x`. `view` tells what each term is, and is asked again for the body of an
+/// abstraction
+pub fn print<'a, T: Copy>(
+    root: T,
+    notation: Notation,
+    view: impl FnMut(T) -> View<'a, T>,
+) -> String {
+    write(root, notation, |_| false, view).0
+}
+
+/// as `print`, also giving where the first `target` was printed, without its parenthesis.
+/// `None` if it wasn't
+pub fn print_marked<'a, T: Copy + PartialEq>(
+    root: T,
+    notation: Notation,
+    target: T,
+    view: impl FnMut(T) -> View<'a, T>,
+) -> (String, Option<Range<usize>>) {
+    write(root, notation, |t| t == target, view)
+}
+
+/// `v`, with a `Fresh` abstraction named for `depth` abstractions around it
+fn fresh<'a, T>(v: View<'a, T>, depth: usize) -> View<'a, T> {
+    match v {
+        View::Fresh(body) => View::Abs(ir::Scope::id_to_str(&ir::Id(depth)).into(), body),
+        v => v,
+    }
+}
+
+fn write<'a, T: Copy>(
+    root: T,
+    notation: Notation,
+    mut marked: impl FnMut(T) -> bool,
+    mut view: impl FnMut(T) -> View<'a, T>,
+) -> (String, Option<Range<usize>>) {
+    let (lambda, arrow) = match notation {
+        Notation::Fn => ("fn ", " => "),
+        Notation::Lambda => ("λ", "."),
+        Notation::Backslash => ("\\", "."),
+        Notation::DeBruijn => ("λ ", ""),
+    };
+    let indices = notation == Notation::DeBruijn;
+    let mut s = String::new();
+    // the names of the abstractions around the term printed, the innermost last
+    let mut binders: Vec<Cow<'a, str>> = Vec::new();
+    let mut marks = Vec::new();
+    let mut tasks = vec![Task::Term(root, Pos::Free)];
+    while let Some(task) = tasks.pop() {
+        let (t, pos) = match task {
+            Task::Text(text) => {
+                s.push_str(&text);
+                continue;
+            }
+            Task::Unbind(n) => {
+                binders.truncate(binders.len() - n);
+                continue;
+            }
+            Task::Mark => {
+                marks.push(s.len());
+                continue;
+            }
+            Task::Term(t, pos) => (t, pos),
+        };
+        let mark = marks.is_empty() && marked(t);
+        let v = match fresh(view(t), binders.len()) {
+            // terms written with names have their variables bound by name
+            View::Atom(name) if indices => match binders.iter().rev().position(|b| *b == name) {
+                Some(n) => View::Bound(n),
+                None => View::Atom(name),
+            },
+            v => v,
+        };
+        let bare = match &v {
+            View::Atom(_) | View::Bound(_) | View::Group(_) => true,
+            View::App(..) => matches!(pos, Pos::Free | Pos::Head),
+            View::Infix(_) => pos == Pos::Free,
+            View::Abs(..) | View::Fresh(_) | View::Open(..) => {
+                matches!(pos, Pos::Free | Pos::LastArg)
+            }
+        };
+        let pos = if bare { pos } else { Pos::Free };
+        let mut parts = Vec::new();
+        if !bare {
+            parts.push(Task::Text("(".into()));
+        }
+        if mark {
+            parts.push(Task::Mark);
+        }
+        let piece = |p| match p {
+            Piece::Text(text) => Task::Text(text),
+            Piece::Term(t) => Task::Term(t, Pos::Free),
+        };
+        match v {
+            View::Atom(name) if is_operator(&name) => parts.extend([
+                Task::Text("(".into()),
+                Task::Text(name),
+                Task::Text(")".into()),
+            ]),
+            View::Atom(name) => parts.push(Task::Text(name)),
+            View::Bound(n) if indices => parts.push(Task::Text(n.to_string().into())),
+            View::Bound(n) => {
+                let name = binders
+                    .len()
+                    .checked_sub(n + 1)
+                    .map_or(Cow::Borrowed("?"), |i| binders[i].clone());
+                parts.push(Task::Text(name));
+            }
+            View::App(l, r) => {
+                let last = match pos {
+                    Pos::Free => Pos::LastArg,
+                    _ => Pos::Arg,
+                };
+                parts.extend([
+                    Task::Term(l, Pos::Head),
+                    Task::Text(" ".into()),
+                    Task::Term(r, last),
+                ]);
+            }
+            View::Abs(name, mut body) => {
+                parts.push(Task::Text(lambda.into()));
+                if !indices {
+                    parts.push(Task::Text(name.clone()));
+                }
+                binders.push(name);
+                let mut bound = 1;
+                while let View::Abs(name, inner) = fresh(view(body), binders.len()) {
+                    match indices {
+                        true => parts.push(Task::Text(lambda.into())),
+                        false => parts.extend([Task::Text(" ".into()), Task::Text(name.clone())]),
+                    }
+                    binders.push(name);
+                    bound += 1;
+                    body = inner;
+                }
+                parts.extend([
+                    Task::Text(arrow.into()),
+                    Task::Term(body, Pos::Free),
+                    Task::Unbind(bound),
+                ]);
+            }
+            View::Fresh(_) => unreachable!(),
+            View::Group(pieces) => parts.extend(pieces.into_iter().map(piece)),
+            View::Open(pieces, last) => {
+                parts.extend(pieces.into_iter().map(piece));
+                parts.push(Task::Term(last, Pos::Free));
+            }
+            View::Infix(pieces) => {
+                let last = pieces.iter().rposition(|p| matches!(p, Piece::Term(_)));
+                for (i, p) in pieces.into_iter().enumerate() {
+                    if i > 0 {
+                        parts.push(Task::Text(" ".into()));
+                    }
+                    parts.push(match p {
+                        Piece::Text(text) => Task::Text(text),
+                        Piece::Term(t) if Some(i) == last => Task::Term(t, Pos::LastArg),
+                        Piece::Term(t) => Task::Term(t, Pos::Arg),
+                    });
+                }
+            }
+        }
+        if mark {
+            // after the `Unbind` of an abstraction, which prints nothing
+            parts.push(Task::Mark);
+        }
+        if !bare {
+            parts.push(Task::Text(")".into()));
+        }
+        tasks.extend(parts.into_iter().rev());
+    }
+    let marked = match marks[..] {
+        [start, end] => Some(start..end),
+        _ => None,
+    };
+    (s, marked)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::printer::{Notation, Piece, View, print, print_marked};
+
+    const DEPTH: usize = 100_000;
+
+    #[test]
+    pub fn only_the_parenthesis_needed() {
+        // `f (f (.. x))`: each term is the number of applications left, and `usize::MAX` is `f`
+        let nested = print(DEPTH, Notation::Fn, |n| match n {
+            0 => View::Atom("x".into()),
+            usize::MAX => View::Atom("f".into()),
+            n => View::App(usize::MAX, n - 1),
+        });
+        assert!(nested.starts_with("f (f (f ("));
+        assert!(nested.ends_with(&format!("f x{}", ")".repeat(DEPTH - 1))));
+        assert_eq!(nested.len(), 4 * DEPTH - 1);
+
+        // `(fn x => x) (a + b) fn y => y`
+        let printed = print(0, Notation::Lambda, |n| match n {
+            0 => View::App(1, 2),
+            1 => View::App(3, 4),
+            2 => View::Abs("y".into(), 8),
+            3 => View::Abs("x".into(), 9),
+            4 => View::Infix(vec![
+                Piece::Term(5),
+                Piece::Text("+".into()),
+                Piece::Term(6),
+            ]),
+            5 => View::Atom("a".into()),
+            6 => View::Atom("b".into()),
+            8 | 9 => View::Bound(0),
+            _ => unreachable!(),
+        });
+        assert_eq!(printed, "(λx.x) (a + b) λy.y");
+
+        // `f (g x)`, marking `g x`
+        let (printed, marked) = print_marked(0, Notation::Fn, 2, |n| match n {
+            0 => View::App(1, 2),
+            1 => View::Atom("f".into()),
+            2 => View::App(3, 4),
+            3 => View::Atom("g".into()),
+            _ => View::Atom("x".into()),
+        });
+        assert_eq!(&printed[marked.unwrap()], "g x");
+
+        // `fn a b => a fn c => c`, with its binders named after their depth
+        let printed = print(0, Notation::Fn, |n| match n {
+            0 => View::Fresh(1),
+            1 => View::Fresh(2),
+            2 => View::App(3, 4),
+            3 => View::Bound(1),
+            4 => View::Fresh(5),
+            _ => View::Bound(0),
+        });
+        assert_eq!(printed, "fn a b => a fn c => c");
+    }
+}
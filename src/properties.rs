@@ -0,0 +1,272 @@
+use proptest::prelude::*;
+
+use crate::arts::{CompArtifact, OuterIdx, Term, TermIdx};
+use crate::formatter;
+use crate::fuzz;
+use crate::printer::Notation;
+use crate::session::Session;
+
+/// a de Bruijn term, small enough to be walked recursively
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lam {
+    Var(usize),
+    Abs(Box<Lam>),
+    App(Box<Lam>, Box<Lam>),
+}
+
+impl Arbitrary for Lam {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    /// closed terms: the indices are taken modulo the binders around them, and there's always a
+    /// λ at the root
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        let leaf = any::<usize>().prop_map(Lam::Var);
+        let raw = leaf.prop_recursive(5, 24, 2, |inner| {
+            prop_oneof![
+                inner.clone().prop_map(|t| Lam::Abs(Box::new(t))),
+                (inner.clone(), inner).prop_map(|(l, r)| Lam::App(Box::new(l), Box::new(r))),
+            ]
+        });
+        raw.prop_map(|t| Lam::Abs(Box::new(t)).closed(0)).boxed()
+    }
+}
+
+impl Lam {
+    fn closed(self, binders: usize) -> Self {
+        match self {
+            Self::Var(i) => Self::Var(i % binders),
+            Self::Abs(t) => Self::Abs(Box::new(t.closed(binders + 1))),
+            Self::App(l, r) => Self::App(Box::new(l.closed(binders)), Box::new(r.closed(binders))),
+        }
+    }
+
+    /// in the classic syntax, naming the binder at depth `d` after `name(d)`
+    fn source(&self, name: &dyn Fn(usize) -> String) -> String {
+        fn go(t: &Lam, depth: usize, name: &dyn Fn(usize) -> String, s: &mut String) {
+            match t {
+                Lam::Var(i) => s.push_str(&name(depth - 1 - i)),
+                Lam::Abs(t) => {
+                    s.push_str(&format!("(λ{}.", name(depth)));
+                    go(t, depth + 1, name, s);
+                    s.push(')');
+                }
+                Lam::App(l, r) => {
+                    s.push('(');
+                    go(l, depth, name, s);
+                    s.push(' ');
+                    go(r, depth, name, s);
+                    s.push(')');
+                }
+            }
+        }
+        let mut s = String::new();
+        go(self, 0, name, &mut s);
+        s
+    }
+
+    fn read(art: &CompArtifact, idx: TermIdx) -> Self {
+        match art.get(idx) {
+            Term::Var(OuterIdx(i)) => Self::Var(i),
+            Term::Abs { inner } => Self::Abs(Box::new(Self::read(art, inner))),
+            Term::App(l, r) => {
+                Self::App(Box::new(Self::read(art, l)), Box::new(Self::read(art, r)))
+            }
+            Term::Const(_) => unreachable!("the terms generated are pure"),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Self::Var(_) => 1,
+            Self::Abs(t) => 1 + t.size(),
+            Self::App(l, r) => 1 + l.size() + r.size(),
+        }
+    }
+
+    /// adds `by` to the indices of the variables free under `cutoff` binders
+    fn shift(&self, by: isize, cutoff: usize) -> Self {
+        match self {
+            Self::Var(i) if *i >= cutoff => Self::Var(i.checked_add_signed(by).unwrap()),
+            Self::Var(i) => Self::Var(*i),
+            Self::Abs(t) => Self::Abs(Box::new(t.shift(by, cutoff + 1))),
+            Self::App(l, r) => {
+                Self::App(Box::new(l.shift(by, cutoff)), Box::new(r.shift(by, cutoff)))
+            }
+        }
+    }
+
+    fn subst(&self, j: usize, with: &Self) -> Self {
+        match self {
+            Self::Var(i) if *i == j => with.clone(),
+            Self::Var(i) => Self::Var(*i),
+            Self::Abs(t) => Self::Abs(Box::new(t.subst(j + 1, &with.shift(1, 0)))),
+            Self::App(l, r) => Self::App(Box::new(l.subst(j, with)), Box::new(r.subst(j, with))),
+        }
+    }
+
+    fn contract(body: &Self, arg: &Self) -> Self {
+        body.subst(0, &arg.shift(1, 0)).shift(-1, 0)
+    }
+
+    fn redexes(&self) -> usize {
+        match self {
+            Self::Var(_) => 0,
+            Self::Abs(t) => t.redexes(),
+            Self::App(l, r) => usize::from(matches!(**l, Self::Abs(_))) + l.redexes() + r.redexes(),
+        }
+    }
+
+    /// contracts the `n`th redex, counting from the left. `Err` with how many were left to skip
+    /// if there aren't that many
+    fn step_at(&self, n: usize) -> Result<Self, usize> {
+        match self {
+            Self::Var(_) => Err(n),
+            Self::Abs(t) => t.step_at(n).map(|t| Self::Abs(Box::new(t))),
+            Self::App(l, r) => {
+                let n = match (&**l, n) {
+                    (Self::Abs(body), 0) => return Ok(Self::contract(body, r)),
+                    (Self::Abs(_), n) => n - 1,
+                    (_, n) => n,
+                };
+                match l.step_at(n) {
+                    Ok(l) => Ok(Self::App(Box::new(l), r.clone())),
+                    Err(n) => r.step_at(n).map(|r| Self::App(l.clone(), Box::new(r))),
+                }
+            }
+        }
+    }
+
+    /// the normal form through the leftmost redexes, if it's reached within `fuel` steps and
+    /// without growing too much
+    fn normalize(mut self, fuel: usize) -> Option<Self> {
+        for _ in 0..fuel {
+            match self.step_at(0) {
+                Ok(t) if t.size() < 2_000 => self = t,
+                Ok(_) => return None,
+                Err(_) => return Some(self),
+            }
+        }
+        None
+    }
+}
+
+const FUEL: usize = 200;
+
+fn compile(s: &mut Session, src: &str) -> Lam {
+    let idx = s.compile_expr(src).unwrap().unwrap();
+    Lam::read(&s.art, idx)
+}
+
+fn classic() -> Session {
+    let mut s = Session::new();
+    assert!(s.set_syntax("classic"));
+    s
+}
+
+proptest! {
+    #[test]
+    fn compiles_as_generated(t: Lam) {
+        let mut s = classic();
+        prop_assert_eq!(compile(&mut s, &t.source(&|d| format!("x{d}"))), t);
+    }
+
+    #[test]
+    fn renaming_keeps_alpha_equivalence(t: Lam) {
+        let mut s = classic();
+        let x = compile(&mut s, &t.source(&|d| format!("x{d}")));
+        let y = compile(&mut s, &t.source(&|d| format!("long_name_{}", 100 - d)));
+        prop_assert_eq!(x, y);
+    }
+
+    #[test]
+    fn parse_print_parse(t: Lam) {
+        let mut s = classic();
+        let idx = s.compile_expr(&t.source(&|d| format!("x{d}"))).unwrap().unwrap();
+        let printed = s.art.pretty(idx, &Default::default());
+        prop_assert_eq!(compile(&mut s, &printed), t, "printed as {}", printed);
+    }
+
+    #[test]
+    fn formatted_parses_back(t: Lam) {
+        let mut s = classic();
+        let src = t.source(&|d| format!("x{d}"));
+        let node = s.parse_stream(&src).unwrap().unwrap();
+        let printed = formatter::print(&node, &src, Notation::Lambda);
+        prop_assert_eq!(compile(&mut s, &printed), t, "printed as {}", printed);
+    }
+
+    #[test]
+    fn ir_printed_parses_back(t: Lam) {
+        let mut s = classic();
+        let src = t.source(&|d| format!("x{d}"));
+        let node = s.parse_stream(&src).unwrap().unwrap();
+        let ir = s.ir(node, &src).unwrap().unwrap();
+        let pretty = s.irc.scope.pretty(&ir, Notation::Lambda);
+        let source = s.irc.scope.to_source(&ir, Notation::Fn);
+        prop_assert_eq!(compile(&mut s, &pretty), t.clone(), "printed as {}", pretty);
+        let mut qk = Session::new();
+        prop_assert_eq!(compile(&mut qk, &source), t, "printed as {}", source);
+    }
+
+    #[test]
+    fn single_steps_are_confluent(t: Lam) {
+        let Some(nf) = t.clone().normalize(FUEL) else {
+            return Ok(());
+        };
+        for n in 0..t.redexes() {
+            let reduct = t.step_at(n).unwrap();
+            // normal order finds the normal form from any reduct that has one
+            if let Some(other) = reduct.normalize(FUEL) {
+                prop_assert_eq!(&other, &nf, "after contracting redex {}", n);
+            }
+        }
+    }
+
+    #[test]
+    fn agrees_with_the_cpu(t: Lam) {
+        let Some(nf) = t.clone().normalize(FUEL) else {
+            return Ok(());
+        };
+        let mut s = classic();
+        // the cpu counts its steps otherwise, so it gets some room
+        s.cancel.budget = Some(FUEL * 100);
+        let out = s.eval_str(&t.source(&|d| format!("x{d}"))).unwrap();
+        let crate::session::Output::Normal(idx) = out.output else {
+            panic!("{:?} has no normal form", t);
+        };
+        prop_assert_eq!(Lam::read(&s.art, idx), nf);
+    }
+
+    #[test]
+    fn call_by_need_agrees(t: Lam) {
+        let Some(nf) = t.clone().normalize(FUEL) else {
+            return Ok(());
+        };
+        let mut s = classic();
+        s.strategy = crate::cpu::Strategy::Need;
+        s.cancel.budget = Some(FUEL * 100);
+        let out = s.eval_str(&t.source(&|d| format!("x{d}"))).unwrap();
+        let crate::session::Output::Normal(idx) = out.output else {
+            panic!("{:?} has no normal form", t);
+        };
+        prop_assert_eq!(Lam::read(&s.art, idx), nf);
+    }
+
+    #[test]
+    fn lexes_any_input(data: Vec<u8>) {
+        fuzz::lex(&data);
+        fuzz::lex(String::from_utf8_lossy(&data).as_bytes());
+    }
+
+    // short, as the longer a random sequence of tokens, the likelier it's rejected early
+    #[test]
+    fn parses_any_tokens(data in proptest::collection::vec(any::<u8>(), 0..16)) {
+        fuzz::parse(&data);
+    }
+
+    #[test]
+    fn compiles_any_tokens(data in proptest::collection::vec(any::<u8>(), 0..16)) {
+        fuzz::compile(&data);
+    }
+}
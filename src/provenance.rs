@@ -0,0 +1,47 @@
+use std::fmt;
+
+/// the syntax a node was lowered from into the plain calculus. Its span is still the one of the
+/// whole sugar, so diagnostics point at what was written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Sugar {
+    Let,
+    LetRec,
+    If,
+    Infix,
+    Tuple,
+    List,
+    Pattern,
+    Unit,
+}
+
+impl Sugar {
+    /// what it's read as, to explain errors in terms that aren't in the source
+    pub fn lowering(self) -> &'static str {
+        match self {
+            Sugar::Let => "`let x = v in b` is `(fn x => b) v`",
+            Sugar::LetRec => "`letrec x = v in b` is `(fn x => b) (Y (fn x => v))`",
+            Sugar::If => "`if c then t else e` is `c t e`, so `c` must be a Church boolean",
+            Sugar::Infix => "`l op r` is `op l r`",
+            Sugar::Tuple => "`(a, b)` is `fn p => p a b`",
+            Sugar::List => "`[a, b]` is `(a, (b, nil))`",
+            Sugar::Pattern => "`fn (x, y) => b` is `fn p => p (fn x y => b)`",
+            Sugar::Unit => "`()` is `fn p => p`, the only value of `unit`",
+        }
+    }
+}
+
+impl fmt::Display for Sugar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Sugar::Let => "`let`",
+            Sugar::LetRec => "`letrec`",
+            Sugar::If => "`if`",
+            Sugar::Infix => "operator",
+            Sugar::Tuple => "tuple",
+            Sugar::List => "list",
+            Sugar::Pattern => "pattern",
+            Sugar::Unit => "`()`",
+        })
+    }
+}
@@ -0,0 +1,188 @@
+use crate::arts::{CompArtifact, TermIdx};
+use crate::ast::Node;
+use crate::builder::{self, PoolBuilder};
+use crate::frontend::{Frontend, Qk};
+use crate::padam::parser;
+
+/// how likely each kind of node is, relative to the others. Only variables are picked at the
+/// maximum depth, or when every weight is 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Weights {
+    pub abs: u32,
+    pub app: u32,
+    pub var: u32,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            abs: 1,
+            app: 1,
+            var: 1,
+        }
+    }
+}
+
+/// SplitMix64: tiny, and the same numbers for the same seed on any platform
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Shape {
+    /// a de Bruijn index
+    Var(usize),
+    Abs(Box<Shape>),
+    App(Box<Shape>, Box<Shape>),
+}
+
+/// makes random closed terms, the same ones for the same seed. Each term is at most `depth` nodes
+/// deep, besides the λ always at its root
+#[derive(Debug, Clone)]
+pub struct Generator {
+    rng: Rng,
+    pub depth: usize,
+    pub weights: Weights,
+}
+
+impl Generator {
+    pub fn new(depth: usize, seed: u64) -> Self {
+        Self {
+            rng: Rng(seed),
+            depth,
+            weights: Weights::default(),
+        }
+    }
+
+    pub fn with_weights(mut self, weights: Weights) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    /// under `binders` λ, at least one, so there's always a variable to pick
+    fn shape(&mut self, depth: usize, binders: usize) -> Shape {
+        let Weights { abs, app, var } = self.weights;
+        let (abs, app) = if depth == 0 { (0, 0) } else { (abs, app) };
+        let pick = match abs + app + var {
+            0 => u32::MAX,
+            total => self.rng.below(u64::from(total)) as u32,
+        };
+        if pick < abs {
+            Shape::Abs(Box::new(self.shape(depth - 1, binders + 1)))
+        } else if pick < abs + app {
+            let l = self.shape(depth - 1, binders);
+            let r = self.shape(depth - 1, binders);
+            Shape::App(Box::new(l), Box::new(r))
+        } else {
+            Shape::Var(self.rng.below(binders as u64) as usize)
+        }
+    }
+
+    fn closed(&mut self) -> Shape {
+        Shape::Abs(Box::new(self.shape(self.depth, 1)))
+    }
+
+    /// a term built with `b`
+    pub fn term(&mut self, b: &mut PoolBuilder) -> builder::Result<TermIdx> {
+        fn build(b: &mut PoolBuilder, shape: &Shape) -> builder::Result<TermIdx> {
+            match shape {
+                Shape::Var(i) => b.var(*i),
+                Shape::Abs(inner) => b.abs(|b| build(b, inner)),
+                Shape::App(l, r) => {
+                    let l = build(b, l)?;
+                    let r = build(b, r)?;
+                    b.app(l, r)
+                }
+            }
+        }
+        let shape = self.closed();
+        build(b, &shape)
+    }
+
+    /// a term in the `qk` syntax, with the variable bound at depth `d` called `xd`
+    pub fn source(&mut self) -> String {
+        fn write(s: &mut String, shape: &Shape, binders: usize) {
+            match shape {
+                Shape::Var(i) => s.push_str(&format!("x{}", binders - 1 - i)),
+                Shape::Abs(inner) => {
+                    s.push_str(&format!("(fn x{binders} => "));
+                    write(s, inner, binders + 1);
+                    s.push(')');
+                }
+                Shape::App(l, r) => {
+                    s.push('(');
+                    write(s, l, binders);
+                    s.push(' ');
+                    write(s, r, binders);
+                    s.push(')');
+                }
+            }
+        }
+        let mut s = String::new();
+        write(&mut s, &self.closed(), 0);
+        s
+    }
+
+    /// a term as an AST, with the source its spans point into
+    pub fn node(&mut self) -> parser::Result<(String, Node)> {
+        let src = self.source();
+        let lexer = Qk.lexer();
+        let tokens = lexer.lex(&src)?;
+        let node = Qk.parse(&lexer, &tokens)?;
+        Ok((src, node))
+    }
+}
+
+/// a random closed term, as the root of its own pool
+pub fn random_term(depth: usize, seed: u64) -> CompArtifact {
+    let mut art = CompArtifact::default();
+    let mut b = PoolBuilder::new(&mut art);
+    let root = Generator::new(depth, seed)
+        .term(&mut b)
+        .and_then(|t| b.finish(t));
+    // every variable is picked under a λ binding it
+    root.expect("generated terms are closed");
+    art
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::random::{Generator, Weights, random_term};
+    use crate::session::Session;
+
+    #[test]
+    pub fn generates() {
+        let a = random_term(6, 42);
+        let b = random_term(6, 42);
+        assert_eq!(a.arena(), b.arena());
+        assert_ne!(Generator::new(8, 1).source(), Generator::new(8, 2).source());
+
+        let mut s = Session::new();
+        let mut g = Generator::new(5, 7);
+        for _ in 0..50 {
+            let (src, _) = g.node().unwrap();
+            assert!(s.compile_expr(&src).unwrap().is_some(), "{src}");
+        }
+
+        let only_vars = Weights {
+            abs: 0,
+            app: 0,
+            var: 1,
+        };
+        let mut g = Generator::new(10, 3).with_weights(only_vars);
+        assert_eq!(g.source(), "(fn x0 => x0)");
+    }
+}
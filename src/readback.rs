@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use crate::arts::{CompArtifact, OuterIdx, Term, TermIdx};
+use crate::ir;
+
+/// a normal form read back as the data it Church-encodes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    /// `fn f x => f (.. (f x))`
+    Numeral(usize),
+    /// `fn t f => t` or `fn t f => f`
+    Bool(bool),
+    /// `fn p => p a b`
+    Pair(Box<Value>, Box<Value>),
+    /// pairs nested to the right, ended by `fn p t f => t`
+    List(Vec<Value>),
+    /// a pair's side that encodes nothing known
+    Term(TermIdx),
+}
+
+impl Value {
+    pub fn display(&self, art: &CompArtifact, aliases: &HashMap<ir::Id, Box<str>>) -> String {
+        match self {
+            Self::Numeral(n) => n.to_string(),
+            Self::Bool(b) => b.to_string(),
+            Self::Pair(a, b) => {
+                format!("({}, {})", a.display(art, aliases), b.display(art, aliases))
+            }
+            Self::List(items) => {
+                let items: Vec<_> = items.iter().map(|i| i.display(art, aliases)).collect();
+                format!("[{}]", items.join(", "))
+            }
+            Self::Term(idx) => art.pretty(*idx, aliases),
+        }
+    }
+}
+
+/// every way the normal form at `idx` can be read. `fn f x => x` is both `0` and `false`, so
+/// there may be more than one
+pub fn readback(art: &CompArtifact, idx: TermIdx) -> Vec<Value> {
+    [
+        numeral(art, idx),
+        boolean(art, idx),
+        list(art, idx),
+        pair(art, idx),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+fn numeral(art: &CompArtifact, idx: TermIdx) -> Option<Value> {
+    let Term::Abs { inner } = art.get(idx) else {
+        return None;
+    };
+    let Term::Abs { inner } = art.get(inner) else {
+        return None;
+    };
+    let mut n = 0;
+    let mut body = inner;
+    loop {
+        match art.get(body) {
+            Term::Var(OuterIdx(0)) => return Some(Value::Numeral(n)),
+            Term::App(f, x) if art.get(f) == Term::Var(OuterIdx(1)) => {
+                n += 1;
+                body = x;
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn boolean(art: &CompArtifact, idx: TermIdx) -> Option<Value> {
+    let Term::Abs { inner } = art.get(idx) else {
+        return None;
+    };
+    let Term::Abs { inner } = art.get(inner) else {
+        return None;
+    };
+    match art.get(inner) {
+        Term::Var(OuterIdx(1)) => Some(Value::Bool(true)),
+        Term::Var(OuterIdx(0)) => Some(Value::Bool(false)),
+        _ => None,
+    }
+}
+
+/// the sides of `fn p => p a b`, if they're closed
+fn sides(art: &CompArtifact, idx: TermIdx) -> Option<(TermIdx, TermIdx)> {
+    let Term::Abs { inner } = art.get(idx) else {
+        return None;
+    };
+    let Term::App(l, b) = art.get(inner) else {
+        return None;
+    };
+    let Term::App(p, a) = art.get(l) else {
+        return None;
+    };
+    if art.get(p) != Term::Var(OuterIdx(0)) || !closed(art, a, 0) || !closed(art, b, 0) {
+        return None;
+    }
+    Some((a, b))
+}
+
+/// what `idx` reads as first, or the term itself
+fn side(art: &CompArtifact, idx: TermIdx) -> Value {
+    readback(art, idx)
+        .into_iter()
+        .next()
+        .unwrap_or(Value::Term(idx))
+}
+
+fn pair(art: &CompArtifact, idx: TermIdx) -> Option<Value> {
+    let (a, b) = sides(art, idx)?;
+    Some(Value::Pair(side(art, a).into(), side(art, b).into()))
+}
+
+fn list(art: &CompArtifact, mut idx: TermIdx) -> Option<Value> {
+    let mut items = Vec::new();
+    loop {
+        if let Term::Abs { inner } = art.get(idx)
+            && let Term::Abs { inner } = art.get(inner)
+            && let Term::Abs { inner } = art.get(inner)
+            && art.get(inner) == Term::Var(OuterIdx(1))
+        {
+            return Some(Value::List(items));
+        }
+        let (head, tail) = sides(art, idx)?;
+        items.push(side(art, head));
+        idx = tail;
+    }
+}
+
+/// whether the term doesn't use any variable bound outside of it, given it's under `depth`
+/// abstractions of its own
+fn closed(art: &CompArtifact, idx: TermIdx, depth: usize) -> bool {
+    match art.get(idx) {
+        Term::Var(v) => v.0 < depth,
+        Term::Const(_) => true,
+        Term::Abs { inner } => closed(art, inner, depth + 1),
+        Term::App(l, r) => closed(art, l, depth) && closed(art, r, depth),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::readback::readback;
+    use crate::session::{Output, Session};
+
+    fn read(s: &mut Session, src: &str) -> Vec<String> {
+        let Output::Normal(nf) = s.eval_str(src).unwrap().output else {
+            panic!("expected a normal form");
+        };
+        let aliases = s.irc.scope.get_aliases();
+        readback(&s.art, nf)
+            .iter()
+            .map(|v| v.display(&s.art, &aliases))
+            .collect()
+    }
+
+    #[test]
+    pub fn church_data() {
+        let mut s = Session::new();
+        s.set_prelude(true).unwrap();
+        assert_eq!(read(&mut s, "mul two three"), ["6"]);
+        assert_eq!(read(&mut s, "sub one one"), ["0", "false"]);
+        assert_eq!(read(&mut s, "not false"), ["true"]);
+        assert_eq!(read(&mut s, "pair (add one one) true"), ["(2, true)"]);
+        assert!(read(&mut s, "fn x => x x").is_empty());
+    }
+
+    #[test]
+    pub fn tuples_and_lists() {
+        let mut s = Session::new();
+        s.set_prelude(true).unwrap();
+        assert_eq!(read(&mut s, "(two, not false)"), ["(2, true)"]);
+        assert_eq!(read(&mut s, "snd (one, two, three)"), ["(2, 3)"]);
+        // a list is also the pairs it's made of
+        assert_eq!(read(&mut s, "[one, [two]]"), ["[1, [2]]", "(1, [[2]])"]);
+        assert_eq!(read(&mut s, "tail [one]"), ["[]"]);
+        assert_eq!(read(&mut s, "is_nil []"), ["true"]);
+        assert_eq!(read(&mut s, "is_nil (cons one nil)"), ["0", "false"]);
+    }
+}
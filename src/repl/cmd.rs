@@ -1,3 +1,24 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use qk::ast::{Ast, TreeStyle, display_node};
+use qk::cbpv;
+use qk::formatter;
+use qk::infix::{Assoc, Fixity};
+use qk::ir::{self, IrComponent};
+use qk::lint;
+use qk::padam::lexer::{self, Lexeme};
+use qk::session::{
+    self as session, Output, Session, bench,
+    evaluated::Section,
+    exercise, modules,
+    prelude::PRELUDE,
+    profiles::{self, PROFILES},
+};
+use qk::source_map::{self, Located};
+use qk::types::{self, Type, primitives::PRIMITIVES, synth};
+
 use crate::repl::Repl;
 use crate::repl::{Error, Result};
 
@@ -19,14 +40,15 @@ pub const COMMANDS: &[Command] = &[
         cmd: "quit",
         alias: "q",
         desc: "quits the terminal",
-        func: &|_r: &mut Repl, _input: &str| -> Result<()> {
+        func: &|r: &mut Repl, _input: &str| -> Result<()> {
+            r.save_history()?;
             std::process::exit(0);
         },
     },
     Command {
         cmd: "set",
         alias: "s",
-        desc: "manual settings",
+        desc: "manual settings: `set <setting> <value>`. `set profile <teaching|benchmark|server>` sets many at once, and `set color <on|off|auto>` or `set color <part> <color>` colors the output. `set warn <unused|shadow> [on|off]` turns a lint on or off, or toggles it. `set warnings <allow|warn|error>` reports every warning so, and `set diag <code>=<allow|warn|error|default>` a single one. `set notation <lambda|backslash|fn|debruijn>` chooses how terms are printed",
         func: &|r: &mut Repl, input: &str| -> Result<()> {
             fn set<T: for<'a> TryFrom<&'a str>>(
                 prop: &mut T,
@@ -44,16 +66,149 @@ pub const COMMANDS: &[Command] = &[
                 .ok_or_else(|| Error::MissingArg("setting".to_string()))?;
             match setting {
                 "prompt" => set(&mut r.prompt, "prompt", value)?,
+                "output" => set(&mut r.output, "output", value)?,
+                "strategy" => set(&mut r.session.strategy, "strategy", value)?,
+                "notation" => set(&mut r.session.art.notation, "notation", value)?,
+                "backend" => set(&mut r.session.backend, "backend", value)?,
+                "language" => set(&mut r.session.language, "language", value)?,
+                "recursive-defs" => set(&mut r.session.irc.recursion, "recursive-defs", value)?,
+                "color" => match value.split_once(' ') {
+                    Some((part, color)) => {
+                        if !r.theme.set(part, color) {
+                            return Err(Error::InvalidValue(
+                                format!("color {part}"),
+                                color.to_string(),
+                            ));
+                        }
+                    }
+                    None => set(&mut r.theme.mode, "color", value)?,
+                },
                 "bench" => {
-                    r.runner.bench = crate::repl::runner::BENCH_SETTING
+                    r.session.bench = qk::session::BENCH_SETTING
                         .parse_inspired(value)
                         .map_err(|v| Error::InvalidValue(setting.to_string(), v.to_string()))?
                 }
+                "typed" => match value {
+                    "on" | "gradual" => {
+                        r.session.set_typed(true)?;
+                        r.session.gradual = value == "gradual";
+                    }
+                    "off" => {
+                        r.session.set_typed(false)?;
+                        r.session.gradual = false;
+                    }
+                    _ => return Err(Error::InvalidValue(setting.to_string(), value.to_string())),
+                },
+                "cps" => match value {
+                    "on" => r.session.cps = true,
+                    "off" => r.session.cps = false,
+                    _ => return Err(Error::InvalidValue(setting.to_string(), value.to_string())),
+                },
+                "warnings" => set(&mut r.severities.warnings, "warnings", value)?,
+                "diag" => {
+                    if let Some((code, _)) = value.split_once('=')
+                        && qk::diagnostics::explain(code.trim()).is_none()
+                        && !crate::repl::CATALOG.iter().any(|e| e.code == code.trim())
+                    {
+                        return Err(Error::UnknownCode(code.trim().to_string()));
+                    }
+                    if !r.severities.set(value) {
+                        return Err(Error::InvalidValue(setting.to_string(), value.to_string()));
+                    }
+                }
+                "readback" => match value {
+                    "on" => r.session.readback = true,
+                    "off" => r.session.readback = false,
+                    _ => return Err(Error::InvalidValue(setting.to_string(), value.to_string())),
+                },
+                "shadow-warnings" => match value {
+                    "on" => r.session.shadow_warnings = true,
+                    "off" => r.session.shadow_warnings = false,
+                    _ => return Err(Error::InvalidValue(setting.to_string(), value.to_string())),
+                },
+                "warn" => {
+                    let (lint, on) = match value.split_once(' ') {
+                        Some((lint, "on")) => (lint, Some(true)),
+                        Some((lint, "off")) => (lint, Some(false)),
+                        Some(_) => {
+                            return Err(Error::InvalidValue(
+                                setting.to_string(),
+                                value.to_string(),
+                            ));
+                        }
+                        None => (value, None),
+                    };
+                    let flag = match lint {
+                        "unused" => &mut r.session.unused_warnings,
+                        "shadow" => &mut r.session.shadowed_binder_warnings,
+                        _ => {
+                            return Err(Error::InvalidValue(setting.to_string(), lint.to_string()));
+                        }
+                    };
+                    // alone, it toggles
+                    *flag = on.unwrap_or(!*flag);
+                }
+                "primitives" => match value {
+                    "on" => r.session.irc.primitives = true,
+                    "off" => r.session.irc.primitives = false,
+                    _ => return Err(Error::InvalidValue(setting.to_string(), value.to_string())),
+                },
+                "fix" => match value {
+                    "on" => r.session.set_fix(true)?,
+                    "off" => r.session.set_fix(false)?,
+                    _ => return Err(Error::InvalidValue(setting.to_string(), value.to_string())),
+                },
+                "prelude" => match value {
+                    "on" => r.session.set_prelude(true)?,
+                    "off" => r.session.set_prelude(false)?,
+                    _ => return Err(Error::InvalidValue(setting.to_string(), value.to_string())),
+                },
+                "syntax" => {
+                    if !r.session.set_syntax(value) {
+                        return Err(Error::InvalidValue(setting.to_string(), value.to_string()));
+                    }
+                }
+                "timeout" => {
+                    r.session.cancel.timeout = match value {
+                        "off" => None,
+                        ms => Some(Duration::from_millis(ms.parse().map_err(|_| {
+                            Error::InvalidValue(setting.to_string(), value.to_string())
+                        })?)),
+                    }
+                }
+                "budget" => {
+                    r.session.cancel.budget = match value {
+                        "off" => None,
+                        steps => Some(steps.parse().map_err(|_| {
+                            Error::InvalidValue(setting.to_string(), value.to_string())
+                        })?),
+                    }
+                }
+                "max-term-size" => {
+                    r.session.cancel.max_size = match value {
+                        "off" => None,
+                        nodes => Some(nodes.parse().map_err(|_| {
+                            Error::InvalidValue(setting.to_string(), value.to_string())
+                        })?),
+                    }
+                }
                 "show" => {
-                    r.runner.show = crate::repl::runner::SHOW_SETTING
+                    r.session.show = qk::session::SHOW_SETTING
                         .parse_inspired(value)
                         .map_err(|v| Error::InvalidValue(setting.to_string(), v.to_string()))?
                 }
+                "profile" => {
+                    let Some(profile) = profiles::get(value) else {
+                        let names: Vec<_> = PROFILES.iter().map(|p| p.name).collect();
+                        return Err(Error::InvalidValue(
+                            format!("profile (one of {})", names.join(", ")),
+                            value.to_string(),
+                        ));
+                    };
+                    for (setting, value) in profile.settings {
+                        r.cmd(&format!("set {setting} {value}"))?;
+                    }
+                }
                 _ => return Err(Error::UnknownSetting(setting.to_string())),
             }
             Ok(())
@@ -64,25 +219,212 @@ pub const COMMANDS: &[Command] = &[
         alias: "ctx",
         desc: "show all the current context",
         func: &|r: &mut Repl, input: &str| -> Result<()> {
-            for (k, v) in r.runner.irc.scope.definitions.iter() {
-                if input.is_empty() || **k == *input {
-                    print!("{k} = ");
-                    r.runner
-                        .irc
-                        .scope
-                        .pretty_print(&r.runner.irc.scope.res_pool[v.0]);
+            for (k, v) in r.session.irc.scope.definitions.iter() {
+                if input.is_empty() || k == input {
+                    let scope = &r.session.irc.scope;
+                    let def = scope.pretty(&scope.res_pool[v.0], r.session.art.notation);
+                    println!("{k} = {def}");
                 }
             }
             Ok(())
         },
     },
+    Command {
+        cmd: "env",
+        alias: "en",
+        desc: "list the definitions with their normal forms, or their types in typed mode, and where they come from: `env [pattern] [by name|origin]`. A pattern as `map*` filters them",
+        func: &|r: &mut Repl, input: &str| -> Result<()> { env(r, input) },
+    },
+    Command {
+        cmd: "prelude",
+        alias: "pre",
+        desc: "show the definitions of the prelude. `set prelude off` unloads them",
+        func: &|r: &mut Repl, _input: &str| -> Result<()> {
+            let width = PRELUDE.iter().map(|d| d.name.len()).max().unwrap_or(0);
+            for d in PRELUDE {
+                let loaded = r
+                    .session
+                    .irc
+                    .scope
+                    .definitions
+                    .get(d.name)
+                    .is_some_and(|id| r.session.prelude.contains(id));
+                let mark = if loaded { "" } else { "  (not loaded)" };
+                println!("{:<width$} = {}{mark}", d.name, d.body);
+            }
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "infixl",
+        alias: "il",
+        desc: "parse operators as left-associative infix: `infixl <0-9> <op>..`, as `infixl 6 + -`",
+        func: &|r: &mut Repl, input: &str| -> Result<()> { infix(r, Assoc::Left, input) },
+    },
+    Command {
+        cmd: "infixr",
+        alias: "ir",
+        desc: "parse operators as right-associative infix: `infixr <0-9> <op>..`",
+        func: &|r: &mut Repl, input: &str| -> Result<()> { infix(r, Assoc::Right, input) },
+    },
+    Command {
+        cmd: "infix",
+        alias: "in",
+        desc: "parse operators as non-associative infix, `infix <0-9> <op>..`, or show the operators declared without arguments",
+        func: &|r: &mut Repl, input: &str| -> Result<()> { infix(r, Assoc::None, input) },
+    },
+    Command {
+        cmd: "bench",
+        alias: "b",
+        desc: "evaluate an expression many times and show how long each stage took, `bench <n> <expr>`, after a warmup of the first tenth of the runs",
+        func: &|r: &mut Repl, input: &str| -> Result<()> {
+            let (n, expr) = input
+                .split_once(' ')
+                .ok_or_else(|| Error::MissingArg("expression".to_string()))?;
+            // with no runs there's nothing to summarize
+            let n = n
+                .parse()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| Error::InvalidValue("bench".into(), n.into()))?;
+            let stages = match bench::run(&mut r.session, expr, n) {
+                Ok(stages) => stages,
+                Err(e) => {
+                    r.report(Error::from(e), expr.to_string());
+                    return Ok(());
+                }
+            };
+            println!(
+                "{n} runs, {} as warmup\n{:<10} {:>12} {:>12} {:>12} {:>12}",
+                bench::warmup(n),
+                "stage",
+                "min",
+                "median",
+                "mean",
+                "stddev"
+            );
+            for (stage, s) in stages {
+                println!(
+                    "{stage:<10} {:>12} {:>12} {:>12} {:>12}",
+                    format!("{:?}", s.min),
+                    format!("{:?}", s.median),
+                    format!("{:?}", s.mean),
+                    format!("{:?}", s.stddev)
+                );
+            }
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "info",
+        alias: "i",
+        desc: "show everything known about a definition, `info <name>`, or measure an expression, `info <expr>`: its size, depth, binders, the definitions it refers to, whether it's normal, and how many steps normalizing it takes, unless `show normal` is off",
+        func: &|r: &mut Repl, name: &str| -> Result<()> {
+            let s = &r.session;
+            let scope = &s.irc.scope;
+            let Some(&id) = scope
+                .definitions
+                .get(name)
+                .filter(|id| scope.res_pool[id.0].item != IrComponent::Pending)
+            else {
+                return term_info(r, name);
+            };
+            let def = &scope.res_pool[id.0];
+            let aliases = scope.get_aliases();
+            let names = |ids: Vec<qk::ir::Id>| {
+                let names: Vec<_> = ids.iter().filter_map(|i| aliases.get(i)).cloned().collect();
+                if names.is_empty() {
+                    "none".to_string()
+                } else {
+                    names.join(", ")
+                }
+            };
+            match s.sources.get(&id) {
+                Some(src) => println!("{src}"),
+                None => println!("{name} = {}", scope.pretty(def, s.art.notation)),
+            }
+            match r.locations.get(&id) {
+                Some(&at) => println!("from:         {}", s.inputs.position(at)),
+                None => println!("from:         {}", origin(r, name, id)),
+            }
+            match s.definition_type(id) {
+                Some(ty) => println!("type:         {ty}"),
+                None => println!("type:         none"),
+            }
+            println!("arity:        {}", lint::arity(scope, id));
+            println!("size:         {} nodes", qk::ir::size(def));
+            match s.art.obj_cache.get(&id) {
+                Some(idx) => println!("compiled:     yes, as term {}", idx.0),
+                None => println!("compiled:     not yet"),
+            }
+            println!("dependencies: {}", names(scope.dependencies(id)));
+            println!("dependents:   {}", names(scope.dependents(id)));
+            Ok(())
+        },
+    },
     Command {
         cmd: "artifacts",
         alias: "arts",
         desc: "show IR compiling artifacts",
         func: &|r: &mut Repl, _input: &str| -> Result<()> {
-            let aliases = r.runner.irc.scope.get_aliases();
-            print!("{}", r.runner.art.to_string(&aliases));
+            let aliases = r.session.irc.scope.get_aliases();
+            print!("{}", r.session.art.to_string(&aliases));
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "ast",
+        alias: "t",
+        desc: "show the syntax tree of an expression, without running it: `ast <expr> [depth=<n>] [ascii]`",
+        func: &|r: &mut Repl, input: &str| -> Result<()> {
+            let mut expr = input.trim_end();
+            let mut style = TreeStyle::default();
+            while let Some((rest, opt)) = expr.rsplit_once(' ') {
+                match (opt, opt.strip_prefix("depth=")) {
+                    ("ascii", _) => style.unicode = false,
+                    (_, Some(d)) => {
+                        style.max_depth = Some(
+                            d.parse()
+                                .map_err(|_| Error::InvalidValue("depth".into(), d.into()))?,
+                        )
+                    }
+                    _ => break,
+                }
+                expr = rest.trim_end();
+            }
+            let ast = r
+                .session
+                .lexer(expr)
+                .and_then(|tokens| r.session.parse(&tokens, expr));
+            match ast {
+                Ok(ast) => print!("{}", display_node(&ast, expr, style)),
+                Err(e) => r.report(Error::from(e), expr.to_string()),
+            }
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "pool",
+        alias: "po",
+        desc: "show the compiled terms of an expression, without running it",
+        func: &|r: &mut Repl, expr: &str| -> Result<()> {
+            // a program would be defined by compiling it
+            let is_program = r
+                .session
+                .lexer(expr)
+                .and_then(|tokens| r.session.parse(&tokens, expr))
+                .is_ok_and(|ast| matches!(ast.item, Ast::Program(_)));
+            if is_program {
+                return Err(Error::InvalidValue("pool".into(), expr.into()));
+            }
+            match r.session.compile_expr(expr) {
+                Ok(Some(root)) => {
+                    let aliases = r.session.irc.scope.get_aliases();
+                    println!("{}", r.session.art.to_string_from(root, &aliases));
+                }
+                Ok(None) => return Err(Error::InvalidValue("pool".into(), expr.into())),
+                Err(e) => r.report(Error::from(e), expr.to_string()),
+            }
             Ok(())
         },
     },
@@ -104,7 +446,7 @@ pub const COMMANDS: &[Command] = &[
                 (n, SUFFIXES[suffix_i])
             }
 
-            let scope = &r.runner.irc.scope;
+            let scope = &r.session.irc.scope;
 
             println!("context");
             println!("\tno. of definitions: {}", scope.definitions.len());
@@ -120,7 +462,7 @@ pub const COMMANDS: &[Command] = &[
         cmd: "help",
         alias: "h",
         desc: "show information about a command",
-        func: &|_r: &mut Repl, name: &str| -> Result<()> {
+        func: &|r: &mut Repl, name: &str| -> Result<()> {
             if name.is_empty() {
                 for cmd in COMMANDS {
                     println!(
@@ -129,36 +471,1051 @@ pub const COMMANDS: &[Command] = &[
                         cmd.desc
                     )
                 }
+                for cmd in r.session.commands.iter() {
+                    println!("{:<25} {}", registered_name(cmd), cmd.desc)
+                }
                 return Ok(());
             }
             for cmd in COMMANDS {
                 if cmd.matches(name) {
                     println!("{} (alias {})", cmd.cmd, cmd.alias);
                     println!("\t{}", cmd.desc);
-                    break;
+                    return Ok(());
                 }
             }
+            if let Some(cmd) = r.session.commands.get(name) {
+                println!("{}", registered_name(cmd));
+                println!("\t{}", cmd.desc);
+                return Ok(());
+            }
             Err(Error::InvalidValue("help".into(), name.into()))
         },
     },
     Command {
         cmd: "clear",
         alias: "cls",
-        desc: "Clear all the `runner` configuration",
-        func: &|r: &mut Repl, _s: &str| -> Result<()> {
-            r.runner = crate::repl::runner::Runner::new();
+        desc: "clear all the `session` configuration. `clear defs` only removes the definitions but the prelude's, and `clear all` unloads the prelude too",
+        func: &|r: &mut Repl, input: &str| -> Result<()> {
+            if input == "defs" {
+                r.session.clear_definitions(true);
+                r.loaded.clear();
+                r.locations.clear();
+                return Ok(());
+            }
+            let prelude = match input {
+                "" => !r.session.prelude.is_empty(),
+                "all" => false,
+                _ => return Err(Error::InvalidValue("clear".to_string(), input.to_string())),
+            };
+            // Ctrl-C still has to reach the new session
+            let cancel = r.session.cancel.unlimited();
+            // and so do the registered commands, which aren't configuration
+            let commands = std::mem::take(&mut r.session.commands);
+            // and so does the log, where clearing is one more event
+            let log = std::mem::take(&mut r.session.log);
+            r.session = Session::new();
+            r.session.cancel = cancel;
+            r.session.commands = commands;
+            r.session.log = log;
+            r.session.set_prelude(prelude)?;
+            r.loaded.clear();
+            r.locations.clear();
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "undef",
+        alias: "ud",
+        desc: "remove definitions, `undef <name>..`, while what already refers to them keeps them",
+        func: &|r: &mut Repl, input: &str| -> Result<()> {
+            if input.is_empty() {
+                return Err(Error::MissingArg("name".to_string()));
+            }
+            for name in input.split_whitespace() {
+                if let Err(e) = r.session.undefine(name) {
+                    r.report(e, input.to_string());
+                }
+            }
             Ok(())
         },
     },
     Command {
         cmd: "load",
         alias: "l",
-        desc: "Load a script into the context. Each line is executed as a REPL entry",
+        desc: "Load a script into the context. Each line is executed as a REPL entry. In typed mode, the `name : type` lines of a `sig` ... `end` block are checked against the definitions",
         func: &|r: &mut Repl, path: &str| -> Result<()> {
             let mut reader = std::fs::File::open(path).map_err(|e| Error::Io { e })?;
             let content = std::io::read_to_string(&mut reader).map_err(|e| Error::Io { e })?;
-            content.lines().for_each(|l| r.exec(l));
+            r.session.log.record(&format!(":load {path}"));
+            r.session.log.expand(content.lines());
+            load(r, path, &content);
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "import",
+        alias: "im",
+        desc: "load a script as a module, whose definitions are referred to by a qualified name: `import \"lists.qk\" as L` defines `L.map`. Without arguments, it shows the modules imported",
+        func: &|r: &mut Repl, input: &str| -> Result<()> {
+            if input.is_empty() {
+                let mut modules: Vec<_> = r.session.modules.iter().collect();
+                modules.sort_by_key(|(alias, _)| *alias);
+                for (alias, m) in modules {
+                    println!("{alias} = {:?} ({} definitions)", m.path, m.names.len());
+                }
+                return Ok(());
+            }
+            import(r, std::path::Path::new(""), &format!("import {input}"))
+        },
+    },
+    Command {
+        cmd: "reload",
+        alias: "rl",
+        desc: "load again every file `load`ed, in the same order, replacing what they defined and keeping what was typed",
+        func: &|r: &mut Repl, _input: &str| -> Result<()> {
+            let loaded = std::mem::take(&mut r.loaded);
+            for (_, names) in &loaded {
+                for name in names {
+                    r.session.irc.scope.definitions.remove(name);
+                }
+                // the modules they imported are imported again
+                let modules = &mut r.session.modules;
+                modules.retain(|_, m| !m.names.iter().any(|n| names.contains(n)));
+            }
+            for (path, _) in loaded {
+                let content = match std::fs::read_to_string(&*path) {
+                    Ok(content) => content,
+                    Err(e) => {
+                        r.report(Error::Io { e }, path.to_string());
+                        continue;
+                    }
+                };
+                // logged as the loads it does, so a replay reads the files as they were
+                r.session.log.record(&format!(":load {path}"));
+                r.session.log.expand(content.lines());
+                load(r, &path, &content);
+            }
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "export",
+        alias: "e",
+        desc: "print the normal form of an expression in another format, `export <format> <expr>`, or the whole reduction with `show steps`",
+        func: &|r: &mut Repl, input: &str| -> Result<()> {
+            let (format, expr) = input
+                .split_once(' ')
+                .ok_or_else(|| Error::MissingArg("expression".to_string()))?;
+            if r.session.renderer(format).is_none() {
+                let formats: Vec<_> = r.session.renderers.iter().map(|r| r.name()).collect();
+                return Err(Error::InvalidValue(
+                    format!("export (one of {})", formats.join(", ")),
+                    format.to_string(),
+                ));
+            }
+            let ev = match r.session.evaluate(expr) {
+                Ok(ev) => ev,
+                Err(e) => {
+                    r.report(Error::from(e), expr.to_string());
+                    return Ok(());
+                }
+            };
+            let view = if ev.out.steps.is_empty() {
+                ev.view(format).map(|v| v.to_string())
+            } else {
+                ev.steps_view(format)
+            };
+            match view {
+                Some(view) => println!("{view}"),
+                None => return Err(Error::InvalidValue("export".into(), expr.into())),
+            }
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "save",
+        alias: "w",
+        desc: "save the definitions into a script, so it can be `load`ed later. Those written in another syntax than the file's are printed, in the `notation` set if it reads it",
+        func: &|r: &mut Repl, path: &str| -> Result<()> {
+            if path.is_empty() {
+                return Err(Error::MissingArg("path".to_string()));
+            }
+            let s = &r.session;
+            let syntax = std::path::Path::new(path)
+                .extension()
+                .and_then(|e| s.syntax_for_extension(&e.to_string_lossy()))
+                .unwrap_or(s.syntax);
+            let frontend = s.frontends[syntax].as_ref();
+            let notation = match frontend.reads(s.art.notation) {
+                true => s.art.notation,
+                false => frontend.notation(),
+            };
+            let mut content = String::new();
+            // a module is imported again instead, as its names are qualified
+            let mut modules: Vec<_> = s.modules.iter().collect();
+            modules.sort_by_key(|(alias, _)| *alias);
+            for (alias, m) in &modules {
+                let path = std::fs::canonicalize(&*m.path)
+                    .map_or_else(|_| m.path.to_string(), |p| p.display().to_string());
+                writeln!(content, "import \"{path}\" as {alias}").unwrap();
+            }
+            let scope = &s.irc.scope;
+            let mut defs: Vec<_> = scope
+                .definitions
+                .iter()
+                .filter(|(_, id)| scope.res_pool[id.0].item != IrComponent::Pending)
+                .filter(|(_, id)| !s.prelude.contains(id))
+                .filter(|(name, _)| {
+                    !modules
+                        .iter()
+                        .any(|(_, m)| m.names.iter().any(|n| **n == **name))
+                })
+                .collect();
+            // definition order, so a reload finds everything as it was
+            defs.sort_by_key(|(_, id)| **id);
+            for (name, id) in defs {
+                match s.sources.get(id).filter(|_| syntax == s.syntax) {
+                    Some(source) => writeln!(content, "{source}").unwrap(),
+                    None => {
+                        let source = scope.to_source(&scope.res_pool[id.0], notation);
+                        writeln!(content, "{name} = {source}").unwrap();
+                    }
+                }
+            }
+            std::fs::write(path, content).map_err(|e| Error::Io { e })
+        },
+    },
+    Command {
+        cmd: "synth",
+        alias: "sy",
+        desc: "search for terms of a simple type: `synth <type>`, or `synth all <type>` for every small one",
+        func: &|_r: &mut Repl, input: &str| -> Result<()> {
+            if input.is_empty() {
+                return Err(Error::MissingArg("type".to_string()));
+            }
+            match input.strip_prefix("all ") {
+                Some(ty) => synth::all(&Type::parse(ty)?)?
+                    .iter()
+                    .for_each(|t| println!("{}", t.to_source())),
+                None => println!("{}", synth::one(&Type::parse(input)?)?.to_source()),
+            }
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "prop",
+        alias: "p",
+        desc: "show the proposition an expression proves, through its simple type",
+        func: &|r: &mut Repl, expr: &str| -> Result<()> {
+            // reported here, since the spans are relative to `expr`, not to the whole input
+            let typing = match r.session.infer(expr) {
+                Ok(Some(typing)) => typing,
+                Ok(None) => return Err(Error::InvalidValue("prop".into(), expr.into())),
+                Err(e) => {
+                    r.report(Error::from(e), expr.to_string());
+                    return Ok(());
+                }
+            };
+            println!("⊢ {}", typing.ty.proposition());
+            let width = typing
+                .steps
+                .iter()
+                .map(|s| s.term.chars().count())
+                .max()
+                .unwrap_or_default();
+            for step in &typing.steps {
+                println!(
+                    "  {:<3} {:<width$}  {}",
+                    step.rule,
+                    step.term,
+                    step.ty.proposition()
+                );
+            }
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "dot",
+        alias: "d",
+        desc: "write the term graph of an expression and its normal form to `qk.dot`",
+        func: &|r: &mut Repl, expr: &str| -> Result<()> {
+            const PATH: &str = "qk.dot";
+            let out = match r.session.eval_str(expr) {
+                Ok(out) => out,
+                Err(e) => {
+                    r.report(Error::from(e), expr.to_string());
+                    return Ok(());
+                }
+            };
+            let (Some(compiled), Output::Normal(nf)) = (out.compiled, out.output) else {
+                return Err(Error::InvalidValue("dot".into(), expr.into()));
+            };
+            let aliases = r.session.irc.scope.get_aliases();
+            let dot = r.session.art.to_dot_from(&[compiled, nf], &aliases);
+            std::fs::write(PATH, dot).map_err(|e| Error::Io { e })?;
+            println!("wrote {PATH}");
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "graph",
+        alias: "g",
+        desc: "write every reduct of an expression, up to a depth, to `qk-graph.dot` (or `.json`): `graph <expr> [depth=4] [format=dot|json]`",
+        func: &|r: &mut Repl, input: &str| -> Result<()> {
+            let mut expr = input.trim_end();
+            let (mut depth, mut format) = (4, "dot");
+            while let Some((rest, opt)) = expr.rsplit_once(' ') {
+                match opt.split_once('=') {
+                    Some(("depth", d)) => {
+                        depth = d
+                            .parse()
+                            .map_err(|_| Error::InvalidValue("depth".into(), d.into()))?
+                    }
+                    Some(("format", f @ ("dot" | "json"))) => format = f,
+                    Some(("format", f)) => {
+                        return Err(Error::InvalidValue("format (dot or json)".into(), f.into()));
+                    }
+                    _ => break,
+                }
+                expr = rest.trim_end();
+            }
+            let graph = match r.session.graph(expr, depth) {
+                Ok(Some(graph)) => graph,
+                Ok(None) => return Err(Error::InvalidValue("graph".into(), expr.into())),
+                Err(e) => {
+                    r.report(Error::from(e), expr.to_string());
+                    return Ok(());
+                }
+            };
+            let aliases = r.session.irc.scope.get_aliases();
+            let content = match format {
+                "json" => graph.to_json(&r.session.art, &aliases).to_string(),
+                _ => graph.to_dot(&r.session.art, &aliases),
+            };
+            let path = format!("qk-graph.{format}");
+            std::fs::write(&path, content).map_err(|e| Error::Io { e })?;
+            let cut = if graph.truncated {
+                format!(", cut at {} terms", qk::graph::MAX_TERMS)
+            } else {
+                String::new()
+            };
+            println!(
+                "wrote {path}: {} terms, {} reductions{cut}",
+                graph.nodes.len(),
+                graph.edges.len()
+            );
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "standardize",
+        alias: "std",
+        desc: "reduce an expression with applicative order, next to its standard (leftmost) reduction, step by step",
+        func: &|r: &mut Repl, expr: &str| -> Result<()> {
+            // applicative order diverges more often than not, so it's never left unbounded
+            if r.session.cancel.budget.is_none() {
+                r.session.cancel.budget = Some(STANDARDIZE_BUDGET);
+            }
+            let result = r.session.standardize(expr);
+            if r.session.cancel.budget == Some(STANDARDIZE_BUDGET) {
+                r.session.cancel.budget = None;
+            }
+            let std = match result {
+                Ok(Some(std)) => std,
+                Ok(None) => return Err(Error::InvalidValue("standardize".into(), expr.into())),
+                Err(e) => {
+                    r.report(Error::from(e), expr.to_string());
+                    return Ok(());
+                }
+            };
+            // the standard reduction finds a normal form whenever there's one, so only applicative
+            // order running out of steps is shown next to it
+            let (given, standard) = match (std.given, std.standard) {
+                (Ok(given), Ok(standard)) => (Some(given), standard),
+                (Err(qk::session::Error::Cancelled(_)), Ok(standard)) => (None, standard),
+                (Err(e), _) | (_, Err(e)) => {
+                    r.report(Error::from(e), expr.to_string());
+                    return Ok(());
+                }
+            };
+            let aliases = r.session.irc.scope.get_aliases();
+            let pretty = |steps: &[qk::arts::TermIdx]| -> Vec<String> {
+                steps
+                    .iter()
+                    .map(|s| r.session.art.pretty(*s, &aliases))
+                    .collect()
+            };
+            let standard = pretty(&standard);
+            let given = given.as_deref().map(pretty);
+            let width = standard
+                .iter()
+                .map(|s| s.chars().count())
+                .max()
+                .unwrap_or(0)
+                .max("standard".len());
+            println!("     {:<width$}   applicative", "standard");
+            let rows = standard.len().max(given.as_ref().map_or(0, Vec::len));
+            for i in 0..rows {
+                let cell = |steps: &[String]| steps.get(i).cloned().unwrap_or_default();
+                let applicative = given.as_deref().map(cell).unwrap_or_default();
+                println!("{i:>3}  {:<width$}   {applicative}", cell(&standard));
+            }
+            match given {
+                Some(given) => println!(
+                    "{} steps in standard order, {} in applicative order",
+                    standard.len() - 1,
+                    given.len() - 1
+                ),
+                None => println!(
+                    "{} steps in standard order, while applicative order found no normal form \
+                     within the budget",
+                    standard.len() - 1
+                ),
+            }
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "cps",
+        alias: "cp",
+        desc: "print the continuation-passing style form of an expression, which `set cps on` compiles every input to",
+        func: &|r: &mut Repl, expr: &str| -> Result<()> {
+            match r.session.cps(expr) {
+                Ok(Some(cps)) => println!("{cps}"),
+                Ok(None) => return Err(Error::InvalidValue("cps".into(), expr.into())),
+                Err(e) => r.report(Error::from(e), expr.to_string()),
+            }
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "optimal",
+        alias: "opt",
+        desc: "reduce an expression as an interaction net, sharing what normal order copies, and compare their β-steps. Experimental: it may not read back terms that copy themselves, as `(fn x => x x) two`",
+        func: &|r: &mut Repl, expr: &str| -> Result<()> {
+            let optimal = match r.session.optimal(expr) {
+                Ok(Some(optimal)) => optimal,
+                Ok(None) => return Err(Error::InvalidValue("optimal".into(), expr.into())),
+                Err(e) => {
+                    r.report(Error::from(e), expr.to_string());
+                    return Ok(());
+                }
+            };
+            let aliases = r.session.irc.scope.get_aliases();
+            match optimal.normal {
+                Some(nf) => println!("{}", r.session.art.pretty(nf, &aliases)),
+                None => println!("the net doesn't read back as a term"),
+            }
+            println!(
+                "{} β-steps in {} interactions, against {} in normal order",
+                optimal.stats.beta, optimal.stats.interactions, optimal.normal_order
+            );
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "fmt",
+        alias: "fm",
+        desc: "print an expression or a definition in the canonical style, with only the parenthesis it needs: `fmt <expr>`",
+        func: &|r: &mut Repl, input: &str| -> Result<()> {
+            match formatter::format_line(r.session.frontend(), input) {
+                Ok(formatted) => println!("{formatted}"),
+                Err(e) => r.report(Error::from(session::Error::from(e)), input.to_string()),
+            }
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "cbpv",
+        alias: "cb",
+        desc: "run a call-by-push-value computation, or translate an expression with `cbv` or `cbn` and run it: `cbpv [cbv|cbn] <term>`. Experimental",
+        func: &|r: &mut Repl, input: &str| -> Result<()> {
+            let translated = input
+                .split_once(' ')
+                .and_then(|(order, expr)| Some((cbpv::Order::try_from(order).ok()?, expr)));
+            let run = match translated {
+                Some((order, expr)) => match r.session.cbpv_translate(order, expr) {
+                    Ok(Some((m, run))) => {
+                        println!("{m}");
+                        run
+                    }
+                    Ok(None) => return Err(Error::InvalidValue("cbpv".into(), expr.into())),
+                    Err(e) => {
+                        r.report(Error::from(e), expr.to_string());
+                        return Ok(());
+                    }
+                },
+                None => match r.session.cbpv(input) {
+                    Ok(run) => run,
+                    Err(e) => {
+                        r.report(Error::from(e), input.to_string());
+                        return Ok(());
+                    }
+                },
+            };
+            println!("{}", run.result);
+            println!("{} steps, {} β-steps", run.steps, run.beta);
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "bohm",
+        alias: "bt",
+        desc: "print the Böhm tree of an expression, down to a depth, with ⊥ for heads with no head normal form within the budget: `bohm <expr> [depth=3]`",
+        func: &|r: &mut Repl, input: &str| -> Result<()> {
+            let mut expr = input.trim_end();
+            let mut depth = 3;
+            if let Some((rest, d)) = expr
+                .rsplit_once(' ')
+                .and_then(|(rest, opt)| Some((rest, opt.strip_prefix("depth=")?)))
+            {
+                depth = d
+                    .parse()
+                    .map_err(|_| Error::InvalidValue("depth".into(), d.into()))?;
+                expr = rest.trim_end();
+            }
+            match r.session.bohm(expr, depth) {
+                Ok(Some(tree)) => print!("{}", tree.to_tree()),
+                Ok(None) => return Err(Error::InvalidValue("bohm".into(), expr.into())),
+                Err(e) => r.report(Error::from(e), expr.to_string()),
+            }
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "separate",
+        alias: "sep",
+        desc: "find a context telling two expressions apart, turning the first into `true` and the second into `false`: `separate <expr> ; <expr>`",
+        func: &|r: &mut Repl, input: &str| -> Result<()> {
+            let Some((left, right)) = input.split_once(';') else {
+                return Err(Error::MissingArg("`;` between the expressions".into()));
+            };
+            let (left, right) = (left.trim(), right.trim());
+            let sep = match r.session.separate(left, right) {
+                Ok(Some(sep)) => sep,
+                Ok(None) => return Err(Error::InvalidValue("separate".into(), input.into())),
+                Err(e) => {
+                    r.report(Error::from(e), input.to_string());
+                    return Ok(());
+                }
+            };
+            let aliases = r.session.irc.scope.get_aliases();
+            let art = &r.session.art;
+            let args: Vec<_> = sep
+                .args
+                .iter()
+                .map(|a| format!("({})", art.pretty(*a, &aliases)))
+                .collect();
+            println!("context: [] {}", args.join(" "));
+            // `Session::separate` already checked them
+            println!("{left} ~> true");
+            println!("{right} ~> false");
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "check",
+        alias: "ck",
+        desc: "reduce an expression and check it reaches a normal form, up to α-equivalence: `check <expr> normalizes-to <expr>`. Given a file, check each of its `normalizes-to` lines, running the others",
+        func: &|r: &mut Repl, input: &str| -> Result<()> {
+            let aliases = |r: &Repl| r.session.irc.scope.get_aliases();
+            let verdict = |r: &Repl, check: exercise::Check| match check.passed {
+                true => "pass".to_string(),
+                false => format!(
+                    "fail: reached {}, expected {}",
+                    r.session.art.pretty(check.normal, &aliases(r)),
+                    r.session.art.pretty(check.expected, &aliases(r))
+                ),
+            };
+            if let Some((left, right)) = exercise::split(input) {
+                match r.session.check(left, right) {
+                    Ok(Some(check)) => println!("{}", verdict(r, check)),
+                    Ok(None) => return Err(Error::InvalidValue("check".into(), input.into())),
+                    Err(e) => r.report(Error::from(e), input.to_string()),
+                }
+                return Ok(());
+            }
+            if input.is_empty() {
+                return Err(Error::MissingArg("expression or path".to_string()));
+            }
+            let content = std::fs::read_to_string(input).map_err(|e| Error::Io { e })?;
+            let checked = r.session.check_file(&content);
+            let total = checked.len();
+            let mut passed = 0;
+            for c in checked {
+                match c.result {
+                    Ok(Some(check)) => {
+                        passed += check.passed as usize;
+                        println!("{input}:{}: {}", c.line, verdict(r, check));
+                    }
+                    Ok(None) => println!("{input}:{}: not an expression", c.line),
+                    Err(e) => {
+                        println!("{input}:{}: error", c.line);
+                        r.report(Error::from(e), c.src.to_string());
+                    }
+                }
+            }
+            println!("{passed} of {total} passed");
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "log",
+        alias: "lg",
+        desc: "show the inputs that changed the session, or write them to a file for `qk replay`: `log [path]`",
+        func: &|r: &mut Repl, path: &str| -> Result<()> {
+            let log = r.session.log.to_string();
+            match path {
+                "" => print!("{log}"),
+                path => std::fs::write(path, log).map_err(|e| Error::Io { e })?,
+            }
+            Ok(())
+        },
+    },
+    Command {
+        cmd: "undo",
+        alias: "u",
+        desc: "revert the last definition, `set`, `load`, `reload` or `clear`, or the last n of them: `undo [n]`",
+        func: &|r: &mut Repl, n: &str| -> Result<()> { undo_redo(r, n, false) },
+    },
+    Command {
+        cmd: "redo",
+        alias: "re",
+        desc: "apply again what the last `undo` reverted: `redo [n]`",
+        func: &|r: &mut Repl, n: &str| -> Result<()> { undo_redo(r, n, true) },
+    },
+    Command {
+        cmd: "back",
+        alias: "bk",
+        desc: "step back through the reduction of the last expression shown with `show steps` or `show redexes`: `back [n]`",
+        func: &|r: &mut Repl, n: &str| -> Result<()> { step_through(r, n, false) },
+    },
+    Command {
+        cmd: "forward",
+        alias: "fw",
+        desc: "step forward through the reduction `back` went back in: `forward [n]`",
+        func: &|r: &mut Repl, n: &str| -> Result<()> { step_through(r, n, true) },
+    },
+    #[cfg(feature = "plugins")]
+    Command {
+        cmd: "plugin",
+        alias: "plug",
+        desc: "load the commands of a plugin, a dynamic library exporting `qk_register`: `plugin <path>`",
+        func: &|r: &mut Repl, path: &str| -> Result<()> {
+            // SAFETY: it's whoever asks for a plugin that trusts it
+            match unsafe { r.session.commands.load(std::path::Path::new(path)) } {
+                Ok(n) => println!("loaded {n} commands"),
+                Err(e) => r.report(e, path.to_string()),
+            }
             Ok(())
         },
     },
 ];
+
+/// the metrics of an expression, as `info <expr>` shows them
+fn term_info(r: &mut Repl, src: &str) -> Result<()> {
+    if src.trim().is_empty() {
+        return Err(Error::MissingArg("name or expression".to_string()));
+    }
+    let s = &mut r.session;
+    // by hand instead of `eval_str`, to only reduce it if `show normal` is on
+    let compiled = (|| -> qk::session::Result<_> {
+        let tokens = s.lexer(src)?;
+        let ast = s.parse(&tokens, src)?;
+        if let Ast::Program(..) = ast.item {
+            return Ok(None);
+        }
+        let Some(expr) = s.ir(ast, src)? else {
+            return Ok(None);
+        };
+        // a name defined nowhere doesn't compile, so what's left are definitions
+        let references = s.irc.scope.references(&expr);
+        Ok(Some((references, s.compile(expr, src)?)))
+    })();
+    let (references, root) = match compiled {
+        Ok(Some(compiled)) => compiled,
+        Ok(None) => return Err(Error::InvalidValue("info".into(), src.into())),
+        Err(e) => {
+            r.report(Error::from(e), src.to_string());
+            return Ok(());
+        }
+    };
+    let s = &mut r.session;
+    let (art, aliases) = (&s.art, s.irc.scope.get_aliases());
+    let references: Vec<_> = references
+        .iter()
+        .filter_map(|id| aliases.get(id))
+        .cloned()
+        .collect();
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    println!("size:           {} nodes", art.size(root));
+    println!("depth:          {}", art.depth(root));
+    println!("binders:        {}", art.binders(root));
+    match references.is_empty() {
+        true => println!("references:     none"),
+        false => println!("references:     {}", references.join(", ")),
+    }
+    println!("normal form:    {}", yes_no(art.is_normal_form(root)));
+    println!("whnf:           {}", yes_no(art.is_whnf(root)));
+    if s.show.is_on("normal") {
+        let mut token = s.cancel.start();
+        match s.cpu(&mut token) {
+            Ok((_, _, stats)) => println!("steps:          {}", stats.beta + stats.delta),
+            Err(e) => println!("steps:          not normalized ({e})"),
+        }
+    }
+    Ok(())
+}
+
+/// the reductions a normal form in `env` may take, and for how long, so a divergent definition
+/// doesn't hold up the rest
+const ENV_BUDGET: usize = 10_000;
+const ENV_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// `env [pattern] [by name|origin]`
+fn env(r: &mut Repl, input: &str) -> Result<()> {
+    let (pattern, by) = match input.rsplit_once("by ") {
+        Some((pattern, by)) if pattern.is_empty() || pattern.ends_with(' ') => (pattern.trim(), by),
+        _ => (input.trim(), "name"),
+    };
+    let scope = &r.session.irc.scope;
+    let mut bindings: Vec<(Box<str>, ir::Id, String)> = scope
+        .definitions
+        .iter()
+        .filter(|(name, id)| {
+            scope.res_pool[id.0].item != IrComponent::Pending
+                && (pattern.is_empty() || glob(pattern, name))
+        })
+        .map(|(name, id)| (name.into(), *id, origin(r, name, *id)))
+        .collect();
+    match by {
+        "name" => bindings.sort_by(|a, b| a.0.cmp(&b.0)),
+        "origin" => bindings.sort_by(|a, b| (&a.2, &a.0).cmp(&(&b.2, &b.0))),
+        _ => return Err(Error::InvalidValue("env by".to_string(), by.to_string())),
+    }
+    let width = bindings.iter().map(|b| b.0.len()).max().unwrap_or(0);
+    let (budget, timeout) = (r.session.cancel.budget, r.session.cancel.timeout);
+    r.session.cancel.budget = Some(budget.map_or(ENV_BUDGET, |b| b.min(ENV_BUDGET)));
+    r.session.cancel.timeout = Some(timeout.map_or(ENV_TIMEOUT, |t| t.min(ENV_TIMEOUT)));
+    for (name, id, origin) in bindings {
+        let value = match r.session.typed {
+            true => match r.session.definition_type(id) {
+                Some(ty) => format!(": {ty}"),
+                None => ": no simple type".to_string(),
+            },
+            // without aliases, or the definition would be printed as its own name
+            false => match r.session.evaluate(&name) {
+                Ok(ev) => match ev.normal() {
+                    Some(nf) => format!("= {}", ev.session.art.pretty_stable(nf, &HashMap::new())),
+                    None => "=".to_string(),
+                },
+                Err(qk::session::Error::Cancelled(_)) => "= (no normal form found)".to_string(),
+                Err(e) => format!("= ({e})"),
+            },
+        };
+        println!("{name:<width$} {value}  ({origin})");
+    }
+    r.session.cancel.budget = budget;
+    r.session.cancel.timeout = timeout;
+    Ok(())
+}
+
+/// where the definition `name` comes from: the prelude, the typed primitives, a module, a
+/// `load`ed file or the REPL
+fn origin(r: &Repl, name: &str, id: ir::Id) -> String {
+    let s = &r.session;
+    if s.prelude.contains(&id) {
+        return "prelude".to_string();
+    }
+    if s.signatures.contains_key(&id) && PRIMITIVES.iter().any(|p| p.name == name) {
+        return "builtin".to_string();
+    }
+    let module = s
+        .modules
+        .values()
+        .find(|m| m.names.iter().any(|n| **n == *name));
+    if let Some(m) = module {
+        return m.path.to_string();
+    }
+    match r
+        .loaded
+        .iter()
+        .rev()
+        .find(|(_, names)| names.iter().any(|n| **n == *name))
+    {
+        Some((path, _)) => path.to_string(),
+        None => "repl".to_string(),
+    }
+}
+
+/// whether `name` matches `pattern`, where `*` is any text and `?` any char
+fn glob(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), name.chars().collect());
+    // the position after the last `*`, and where the name was then, to retry from
+    let (mut p, mut n, mut star) = (0, 0, None);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    star = Some((sp, sn + 1));
+                    (p, n) = (sp, sn + 1);
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// `infixl`, `infixr` and `infix`: declares the fixity of each operator of `input`, or lists them
+fn infix(r: &mut Repl, assoc: Assoc, input: &str) -> Result<()> {
+    let mut words = input.split_whitespace();
+    let Some(prec) = words.next() else {
+        let mut operators: Vec<_> = r.session.irc.operators.iter().collect();
+        operators.sort_by_key(|(op, f)| (std::cmp::Reverse(f.prec), *op));
+        for (op, f) in operators {
+            println!("{} {} {op}", f.keyword(), f.prec);
+        }
+        return Ok(());
+    };
+    let prec = prec
+        .parse()
+        .ok()
+        .filter(|p| *p <= Fixity::MAX_PREC)
+        .ok_or_else(|| Error::InvalidValue("precedence".to_string(), prec.to_string()))?;
+    let operators: Vec<_> = words.collect();
+    if operators.is_empty() {
+        return Err(Error::MissingArg("operator".to_string()));
+    }
+    // only bare operators are left for their fixity to group
+    if let Some(op) = operators
+        .iter()
+        .find(|op| lexer::operator().parse(op).ok() != Some(**op))
+    {
+        return Err(Error::InvalidValue("operator".to_string(), op.to_string()));
+    }
+    for op in operators {
+        let fixity = Fixity { assoc, prec };
+        r.session.irc.operators.insert(op.into(), fixity);
+    }
+    Ok(())
+}
+
+/// `import "<path>" as <alias>`, with `path` relative to `dir`
+fn import(r: &mut Repl, dir: &std::path::Path, line: &str) -> Result<()> {
+    let (path, alias) = modules::parse_import(line)
+        .ok_or_else(|| Error::InvalidValue("import".to_string(), line.to_string()))?;
+    if lexer::ident().parse(alias).ok() != Some(alias) {
+        return Err(Error::InvalidValue(
+            "module name".to_string(),
+            alias.to_string(),
+        ));
+    }
+    let path = dir.join(path);
+    let content = std::fs::read_to_string(&path).map_err(|e| Error::Io { e })?;
+    let path = path.to_string_lossy();
+    if let Err(e) = r.session.import(alias, &path, &content) {
+        r.report(e, content);
+    }
+    Ok(())
+}
+
+/// runs a script as `load` does, with `path` picking its syntax. Its lines aren't logged, as the
+/// log has the whole script
+pub fn load(r: &mut Repl, path: &str, content: &str) {
+    // the names only reserved by a forward reference are defined by the file too
+    let scope = &r.session.irc.scope;
+    let before: Vec<_> = scope
+        .definitions
+        .iter()
+        .filter(|(_, id)| scope.res_pool[id.0].item != IrComponent::Pending)
+        .map(|(name, id)| (Box::<str>::from(name), *id))
+        .collect();
+    let previous_syntax = r.session.syntax;
+    if let Some(syntax) = std::path::Path::new(path)
+        .extension()
+        .and_then(|e| r.session.syntax_for_extension(&e.to_string_lossy()))
+    {
+        r.session.syntax = syntax;
+    }
+    r.session.log.pause();
+    let mut signature = Vec::new();
+    let mut in_signature = false;
+    let dir = std::path::Path::new(path)
+        .parent()
+        .unwrap_or(std::path::Path::new(""));
+    // the lines point into the file, instead of into themselves
+    let outer = r.running.take();
+    let file = r.session.inputs.add(path, content);
+    for (at, l) in source_map::lines(content) {
+        r.running = Some(Located { source: file, at });
+        match l.trim() {
+            "sig" => in_signature = true,
+            "end" if in_signature => in_signature = false,
+            _ if in_signature => signature.push(l),
+            i if i.starts_with("import ") => {
+                if let Err(e) = import(r, dir, i) {
+                    r.report(e, l.into());
+                }
+            }
+            _ => r.exec(l),
+        }
+    }
+    r.running = outer;
+    r.session.log.resume();
+    r.session.syntax = previous_syntax;
+    let defined = r.session.irc.scope.definitions.iter();
+    let names = defined
+        .filter(|(name, id)| !before.iter().any(|(n, i)| **n == **name && i == *id))
+        .map(|(name, _)| name.into())
+        .collect();
+    r.loaded.retain(|(p, _)| **p != *path);
+    r.loaded.push((path.into(), names));
+    if r.session.typed {
+        for l in signature {
+            let decl = l.split_once('#').map_or(l, |(decl, _)| decl).trim();
+            if decl.is_empty() {
+                continue;
+            }
+            let Some((name, ty)) = decl.split_once(':') else {
+                r.report(
+                    Error::InvalidValue("signature".into(), decl.into()),
+                    l.into(),
+                );
+                continue;
+            };
+            match r.session.declare(name.trim(), ty.trim()) {
+                Ok(()) => (),
+                Err(session::Error::Types(types::Error::SignatureMismatch {
+                    name,
+                    declared,
+                    inferred,
+                    ..
+                })) => {
+                    // both are pointed at in the file
+                    let ty_at = ty.trim().as_ptr() as usize - content.as_ptr() as usize;
+                    let id = r.session.irc.scope.definitions[&*name];
+                    let defined_at = r.locations.get(&id).filter(|d| d.source == file);
+                    let e = types::Error::SignatureMismatch {
+                        name,
+                        declared,
+                        inferred,
+                        declared_at: Some((ty_at, ty.trim().len()).into()),
+                        defined_at: defined_at.map(|d| d.at),
+                    };
+                    r.running = Some(r.session.inputs.whole(file));
+                    r.report(Error::from(e), content.into());
+                    r.running = outer;
+                }
+                Err(e) => r.report(Error::from(e), ty.trim().into()),
+            }
+        }
+    }
+}
+
+/// whether running `input` goes in the log: an expression or a definition, or a command that
+/// changes the session. `load` and `reload` log the loads they do themselves, with what they read
+pub fn is_logged(input: &str) -> bool {
+    const LOGGED: &[&str] = &[
+        "set", "infixl", "infixr", "infix", "clear", "undef", "import", "undo", "redo", "plugin",
+    ];
+    let Some(c) = input.strip_prefix(':') else {
+        return true;
+    };
+    let name = c.split_once(' ').map_or(c, |(name, _)| name);
+    COMMANDS
+        .iter()
+        .any(|c| c.matches(name) && LOGGED.contains(&c.cmd))
+}
+
+/// whether `input` may change the session in a way worth undoing: a definition, or a command
+/// changing the context or a setting of the session
+pub fn is_undoable(input: &str) -> bool {
+    const UNDOABLE: &[&str] = &[
+        "set", "load", "reload", "clear", "undef", "infixl", "infixr", "infix", "import",
+    ];
+    let Some(c) = input.strip_prefix(':') else {
+        return true;
+    };
+    let (name, args) = c.split_once(' ').unwrap_or((c, ""));
+    // the prompt, the output mode and the colors belong to the REPL, not to the session
+    let repl_setting = ["prompt", "output", "color"]
+        .iter()
+        .any(|s| args.starts_with(s));
+    COMMANDS
+        .iter()
+        .any(|c| c.matches(name) && UNDOABLE.contains(&c.cmd) && !(c.cmd == "set" && repl_setting))
+}
+
+/// `undo [n]` and `redo [n]`
+fn undo_redo(r: &mut Repl, n: &str, redo: bool) -> Result<()> {
+    let name = if redo { "redo" } else { "undo" };
+    let n: usize = match n {
+        "" => 1,
+        n => n
+            .parse()
+            .map_err(|_| Error::InvalidValue(name.into(), n.into()))?,
+    };
+    for _ in 0..n {
+        let input = if redo {
+            r.history.redo(&mut r.session)
+        } else {
+            r.history.undo(&mut r.session)
+        };
+        match input {
+            Some(input) => println!("{name}: {input}"),
+            None => {
+                println!("nothing to {name}");
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `back [n]` or `forward [n]`, printing the step it gets to
+fn step_through(r: &mut Repl, n: &str, forward: bool) -> Result<()> {
+    let name = if forward { "forward" } else { "back" };
+    let n: usize = match n {
+        "" => 1,
+        n => n
+            .parse()
+            .map_err(|_| Error::InvalidValue(name.into(), n.into()))?,
+    };
+    let moved = match forward {
+        true => r.trace.forward(n),
+        false => r.trace.back(n),
+    };
+    let Some((at, step)) = r.trace.current() else {
+        println!("nothing traced: turn `show steps` on and reduce an expression");
+        return Ok(());
+    };
+    if moved < n {
+        let end = if forward { "last" } else { "first" };
+        println!("at the {end} step");
+    }
+    println!("[{}/{}]", at + 1, r.trace.len());
+    let color = r.theme.section(Section::Step);
+    println!("{}", r.theme.paint(color, &step.render()));
+    Ok(())
+}
+
+/// `name (alias a)`, or just `name`
+fn registered_name(cmd: &qk::session::commands::UserCommand) -> String {
+    match &cmd.alias {
+        Some(alias) => format!("{} (alias {alias})", cmd.name),
+        None => cmd.name.to_string(),
+    }
+}
+
+/// the step limit of `standardize` when no `budget` is set
+const STANDARDIZE_BUDGET: usize = 10_000;
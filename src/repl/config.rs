@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::repl::{Error, Repl, Result};
+
+/// what the REPL starts with, so the same `set`s aren't typed every session. Read from
+/// `config.toml`, before the command line flags
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// the settings the others start from, as `set profile` takes them
+    pub profile: Option<String>,
+    pub prompt: Option<String>,
+    /// a file loaded after the prelude, as `load` does
+    pub prelude: Option<PathBuf>,
+    pub strategy: Option<String>,
+    /// how terms are printed, as `set notation` takes it
+    pub notation: Option<String>,
+    pub bench: Option<String>,
+    pub show: Option<String>,
+    /// the reductions an expression may take, as `set budget` takes it
+    pub budget: Option<String>,
+    /// in milliseconds, as `set timeout` takes it
+    pub timeout: Option<String>,
+    /// where the inputs are kept between sessions
+    pub history: Option<PathBuf>,
+    /// `on`, `off` or `auto`
+    pub color: Option<String>,
+    /// the color of each part of the output, as `set color <part> <color>` takes them
+    pub colors: BTreeMap<String, String>,
+}
+
+/// `$XDG_CONFIG_HOME/qk/config.toml`, or `~/.config/qk/config.toml`
+pub fn default_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("qk").join("config.toml"))
+}
+
+/// `src` with the top-level `settings` set, as strings. A key already there keeps its line, and
+/// a new one goes before the first table, so the comments and the rest stay as they were
+pub fn merge(src: &str, settings: &[(&str, String)]) -> String {
+    let mut lines: Vec<String> = src.lines().map(String::from).collect();
+    let tables = lines
+        .iter()
+        .position(|l| l.trim_start().starts_with('['))
+        .unwrap_or(lines.len());
+    let mut missing = Vec::new();
+    for (key, value) in settings {
+        let line = format!("{key} = {:?}", value);
+        let existing = lines[..tables].iter().position(|l| {
+            l.split_once('=')
+                .is_some_and(|(k, _)| k.trim() == *key && !l.trim_start().starts_with('#'))
+        });
+        match existing {
+            Some(i) => lines[i] = line,
+            None => missing.push(line),
+        }
+    }
+    // a blank line between them and the first table
+    if tables < lines.len() && !missing.is_empty() {
+        missing.push(String::new());
+    }
+    lines.splice(tables..tables, missing);
+    let mut merged = lines.join("\n");
+    merged.push('\n');
+    merged
+}
+
+/// `path`, with a leading `~` standing for the home directory
+fn expand(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+impl Config {
+    /// applies the config at `path` to `r`. A missing file is an empty config, and a wrong one is
+    /// reported, leaving `r` as it was
+    pub fn load(r: &mut Repl, path: &Path) {
+        let src = match std::fs::read_to_string(path) {
+            Ok(src) => src,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return,
+            Err(e) => return r.report(Error::Io { e }, path.display().to_string()),
+        };
+        match Self::parse(&src) {
+            Ok(config) => config.apply(r),
+            Err(e) => r.report(e, src),
+        }
+    }
+
+    pub fn parse(src: &str) -> Result<Self> {
+        toml::from_str(src).map_err(|e| Error::Config {
+            message: e.message().to_string(),
+            at: e.span().map(Into::into),
+        })
+    }
+
+    /// the commands it stands for, in the order they're run
+    pub fn commands(&self) -> Vec<String> {
+        let settings = [
+            ("profile", &self.profile),
+            ("prompt", &self.prompt),
+            ("strategy", &self.strategy),
+            ("notation", &self.notation),
+            ("bench", &self.bench),
+            ("show", &self.show),
+            ("budget", &self.budget),
+            ("timeout", &self.timeout),
+            ("color", &self.color),
+        ];
+        let mut cmds: Vec<_> = settings
+            .into_iter()
+            .filter_map(|(setting, value)| Some(format!("set {setting} {}", value.as_ref()?)))
+            .collect();
+        for (part, color) in &self.colors {
+            cmds.push(format!("set color {part} {color}"));
+        }
+        if let Some(path) = &self.prelude {
+            cmds.push(format!("load {}", expand(path).display()));
+        }
+        cmds
+    }
+
+    /// runs its commands on `r`, reporting the ones that fail, and opens its history. They're
+    /// logged, so a replay starts from the same settings
+    pub fn apply(&self, r: &mut Repl) {
+        for cmd in self.commands() {
+            r.session.log.record(&format!(":{cmd}"));
+            if let Err(e) = r.cmd(&cmd) {
+                r.report(e, cmd);
+            }
+        }
+        if let Some(path) = &self.history {
+            let path = expand(path);
+            // there's none the first time
+            let _ = r.rl.load_history(&path);
+            r.history_file = Some(path);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::repl::Error;
+    use crate::repl::config::{Config, merge};
+
+    #[test]
+    pub fn commands() {
+        let src = r#"
+            profile = "teaching"
+            prompt = "qk> "
+            prelude = "lists.qk"
+            color = "off"
+
+            [colors]
+            step = "blue"
+            error = "none"
+        "#;
+        let config = Config::parse(src).unwrap();
+        assert_eq!(
+            config.commands(),
+            [
+                "set profile teaching",
+                "set prompt qk> ",
+                "set color off",
+                "set color error none",
+                "set color step blue",
+                "load lists.qk",
+            ]
+        );
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    pub fn merged() {
+        let settings = [
+            ("budget", "2000".to_string()),
+            ("timeout", "100".to_string()),
+        ];
+        let src = "# mine\ntimeout = \"off\"\n\n[colors]\nstep = \"blue\"\n";
+        let merged = merge(src, &settings);
+        assert_eq!(
+            merged,
+            "# mine\ntimeout = \"100\"\n\nbudget = \"2000\"\n\n[colors]\nstep = \"blue\"\n"
+        );
+        let config = Config::parse(&merged).unwrap();
+        assert_eq!(config.budget.as_deref(), Some("2000"));
+        assert_eq!(
+            merge("", &settings),
+            "budget = \"2000\"\ntimeout = \"100\"\n"
+        );
+    }
+
+    #[test]
+    pub fn unknown_keys() {
+        let err = Config::parse("prompt = \"> \"\npromt = \"> \"").unwrap_err();
+        let Error::Config { message, at } = err else {
+            panic!("{err:?}")
+        };
+        assert!(message.contains("promt"), "{message}");
+        assert_eq!(at.map(|at| at.offset()), Some(14));
+        assert!(Config::parse("prompt = 3").is_err());
+    }
+}
@@ -1,13 +1,24 @@
-use miette::Diagnostic;
-use rustyline::{DefaultEditor, error::ReadlineError};
+use miette::{Diagnostic, NamedSource, Severity, SourceSpan};
+use owo_colors::{AnsiColors, OwoColorize};
+use qk::diagnostics::{Entry, Severities};
+use qk::ir;
+use qk::session::{Output, Session, history::History, json, log::EventLog, trace::Trace};
+use qk::source_map::Located;
+use rustyline::{
+    Context, Editor, Helper, completion::Completer, error::ReadlineError, highlight::Highlighter,
+    hint::Hinter, history::DefaultHistory, validate::Validator,
+};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
+use std::path::PathBuf;
 use thiserror::Error;
 
-use crate::repl::runner::Runner;
+use crate::repl::theme::Theme;
 
 pub mod cmd;
-pub mod runner;
-pub mod settings;
+pub mod config;
+pub mod theme;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -42,37 +53,153 @@ pub enum Error {
     #[diagnostic(code(repl::command::set::unknown_setting), help("mistyping maybe?"))]
     UnknownSetting(String),
 
-    #[error(transparent)]
-    #[diagnostic(transparent)]
-    ParserError(#[from] qk::parser::Error),
+    #[error("unknown diagnostic code {0:?}")]
+    #[diagnostic(
+        code(repl::command::set::unknown_code),
+        help("`qk --explain` lists every code")
+    )]
+    UnknownCode(String),
 
     #[error(transparent)]
     #[diagnostic(transparent)]
-    IrCompilerError(#[from] qk::ir::Error),
+    SessionError(#[from] qk::session::Error),
 
     #[error(transparent)]
     #[diagnostic(transparent)]
-    CompilerError(#[from] qk::compiler::Error),
+    TypesError(#[from] qk::types::Error),
+
+    #[error("invalid config file")]
+    #[diagnostic(
+        code(repl::config::invalid),
+        help(
+            "it takes prompt, prelude, strategy, bench, show, history, color and a [colors] table"
+        )
+    )]
+    Config {
+        message: String,
+        #[label("{message}")]
+        at: Option<SourceSpan>,
+    },
 
     #[error("io error: {e:?}")]
     #[diagnostic(code(io::error))]
     Io { e: std::io::Error },
 }
 
+/// the codes of `Error`, as `qk::diagnostics::catalog()` has the library's
+pub const CATALOG: &[Entry] = &[
+    Entry {
+        code: "io::error",
+        severity: Severity::Error,
+        message: "io error: {e:?}",
+        help: None,
+    },
+    Entry {
+        code: "repl::command::missing_arg",
+        severity: Severity::Error,
+        message: "missing argument",
+        help: Some("are you sure this is the command?"),
+    },
+    Entry {
+        code: "repl::command::set::invalid_valid",
+        severity: Severity::Error,
+        message: "invalid setting value: {0} doesn't accept {1:?}",
+        help: Some("are you sure this is the setting?"),
+    },
+    Entry {
+        code: "repl::command::set::unknown_code",
+        severity: Severity::Error,
+        message: "unknown diagnostic code {0:?}",
+        help: Some("`qk --explain` lists every code"),
+    },
+    Entry {
+        code: "repl::command::set::unknown_setting",
+        severity: Severity::Error,
+        message: "unknown {0:?} setting",
+        help: Some("mistyping maybe?"),
+    },
+    Entry {
+        code: "repl::command::unknown",
+        severity: Severity::Error,
+        message: "unknown command",
+        help: Some("sometimes we just miss it!"),
+    },
+    Entry {
+        code: "repl::config::invalid",
+        severity: Severity::Error,
+        message: "invalid config file",
+        help: Some(
+            "it takes prompt, prelude, strategy, bench, show, history, color and a [colors] table",
+        ),
+    },
+    Entry {
+        code: "repl::input::readline_error",
+        severity: Severity::Error,
+        message: "can't read the next line",
+        help: Some("are you really running this on interactive mode?"),
+    },
+];
+
+/// how results and diagnostics are printed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    #[default]
+    Human,
+    /// as `Human`, but with diagnostics in plain text, the same on any terminal
+    Plain,
+    /// one JSON object per input, for tools consuming qk's output
+    Json,
+}
+
+impl TryFrom<&str> for OutputMode {
+    type Error = ();
+
+    fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
+        match value {
+            "human" => Ok(Self::Human),
+            "plain" => Ok(Self::Plain),
+            "json" => Ok(Self::Json),
+            _ => Err(()),
+        }
+    }
+}
+
 pub struct Repl {
     pub prompt: String,
-    pub rl: DefaultEditor,
-    pub runner: Runner,
+    pub output: OutputMode,
+    pub theme: Theme,
+    pub rl: Editor<Completions, DefaultHistory>,
+    pub session: Session,
+    /// the inputs that changed the session, to undo them
+    pub history: History,
+    /// the files `load`ed, with the names each one defined, in the order they were loaded
+    pub loaded: Vec<(Box<str>, Vec<Box<str>>)>,
+    /// where the line editor's history is saved on quitting
+    pub history_file: Option<PathBuf>,
+    /// what warnings are reported as
+    pub severities: Severities,
+    /// the line being run, in the input it's part of
+    pub running: Option<Located>,
+    /// where each definition typed or `load`ed was written
+    pub locations: HashMap<ir::Id, Located>,
+    /// the steps of the last expression reduced with them shown, for `back` and `forward`
+    pub trace: Trace,
+    pub warnings: usize,
+    pub errors: usize,
 }
 
 impl Repl {
     pub fn run(&mut self) -> Result<()> {
+        // while reading a line, Ctrl-C is handled by the line editor. Anywhere else, it stops
+        // whatever is running
+        let cancel = self.session.cancel.clone();
+        let _ = ctrlc::set_handler(move || cancel.cancel());
         loop {
             let input = self.input();
             let input = match input {
                 Ok(s) => s,
                 Err(ReadlineError::Eof | ReadlineError::Interrupted) => {
-                    return Ok(());
+                    return self.save_history();
                 }
                 Err(e) => return Err(Error::Input(e)),
             };
@@ -83,18 +210,192 @@ impl Repl {
         }
     }
 
+    /// writes the line editor's history to `history_file`, if there's one
+    pub fn save_history(&mut self) -> Result<()> {
+        let Some(path) = &self.history_file else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| Error::Io { e })?;
+        }
+        self.rl.save_history(path).map_err(Error::Input)
+    }
+
     pub fn exec(&mut self, input: impl AsRef<str> + ToString) {
-        self.runner.reset_diagnostics();
+        // a line of a loaded file comes located already
+        let running = self.running.take().unwrap_or_else(|| {
+            let inputs = &mut self.session.inputs;
+            let id = inputs.add(format!("<input {}>", inputs.len() + 1), input.as_ref());
+            inputs.whole(id)
+        });
+        self.running = Some(running);
+        self.reset_diagnostics();
+        self.session.cancel.reset();
+        // what a `load` runs is part of it, and is undone with it
+        let before = (!self.session.log.is_paused() && cmd::is_undoable(input.as_ref()))
+            .then(|| self.session.snapshot());
         let result = if let Some(input) = input.as_ref().strip_prefix(':') {
-            self.cmd(input)
+            self.cmd(input).map(|_| true)
         } else {
-            self.runner.expression(input.as_ref())
+            self.expression(input.as_ref())
+                .map(|out| out == Output::Defined)
+        };
+        match result {
+            Ok(changed) => {
+                if cmd::is_logged(input.as_ref()) {
+                    self.session.log.record(input.as_ref());
+                }
+                if let Some(before) = before.filter(|_| changed) {
+                    self.history.push(input.as_ref(), before);
+                }
+            }
+            Err(e) => self.report(e, input.to_string()),
+        }
+        self.running = None;
+    }
+
+    /// runs a script a line at a time, each printed as if it was typed: expressions and
+    /// definitions are evaluated, and `:` commands run. Blank lines and comments are skipped.
+    /// Returns how many lines had errors
+    pub fn script(&mut self, content: &str) -> usize {
+        let mut failed = 0;
+        for line in content.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            // JSON output stays one object per line
+            if self.output != OutputMode::Json {
+                println!("{}{trimmed}", self.prompt);
+            }
+            self.exec(trimmed);
+            failed += (self.errors > 0) as usize;
+        }
+        failed
+    }
+
+    /// runs the events of a log again, each printed as if it was typed
+    pub fn replay(&mut self, log: &EventLog) {
+        for e in &log.events {
+            println!("{}{}", self.prompt, e.input);
+            match e.input.strip_prefix(":load ") {
+                Some(path) if !e.expansion.is_empty() => {
+                    self.reset_diagnostics();
+                    self.session.log.record(&e.input);
+                    self.session.log.expand(e.expansion.iter().map(|l| &**l));
+                    let before = self.session.snapshot();
+                    cmd::load(self, path, &e.expansion.join("\n"));
+                    self.history.push(&e.input, before);
+                }
+                _ => self.exec(&*e.input),
+            }
+        }
+    }
+
+    /// evaluates `input`, returning what it did to the session
+    pub fn expression(&mut self, input: &str) -> Result<Output> {
+        let output = self.output;
+        let before: HashSet<_> = self
+            .session
+            .irc
+            .scope
+            .definitions
+            .values()
+            .copied()
+            .collect();
+        let ev = self.session.evaluate(input)?;
+        let warnings = match output {
+            OutputMode::Human => {
+                for (section, text) in ev.sections() {
+                    println!("{}", self.theme.paint(self.theme.section(section), &text));
+                }
+                ev.out.warnings.clone()
+            }
+            OutputMode::Plain => {
+                // what the `show` setting asks for
+                print!("{ev}");
+                ev.out.warnings.clone()
+            }
+            OutputMode::Json => {
+                println!("{}", ev.json());
+                Vec::new()
+            }
+        };
+        if !ev.out.steps.is_empty() {
+            self.trace = Trace::new(ev.traced());
+        }
+        let out = ev.out.output;
+        if out == Output::Defined {
+            self.locate_definitions(&before, input);
+        }
+        for w in warnings {
+            self.report(w, input.to_string());
+        }
+        Ok(out)
+    }
+
+    /// keeps where the definitions not in `before` are, as parts of `input`
+    fn locate_definitions(&mut self, before: &HashSet<ir::Id>, input: &str) {
+        let inputs = &self.session.inputs;
+        let Some(part) = self.running.and_then(|line| inputs.locate(line, input)) else {
+            return;
         };
-        if let Err(e) = result {
-            self.runner.report(e, input.to_string());
+        let scope = &self.session.irc.scope;
+        for &id in scope.definitions.values().filter(|id| !before.contains(id)) {
+            let at = scope.res_pool[id.0].at;
+            let at = (part.at.offset() + at.offset(), at.len()).into();
+            self.locations.insert(id, Located { at, ..part });
         }
     }
 
+    pub fn report(&mut self, e: impl Into<Box<dyn Diagnostic + Send + Sync>>, input: String) {
+        let Some(e) = self.severities.apply(e.into()) else {
+            return;
+        };
+        let e: Box<dyn Diagnostic + Send + Sync> = Box::new(e);
+        match e.severity().unwrap_or_default() {
+            Severity::Error => self.errors += 1,
+            Severity::Warning => self.warnings += 1,
+            _ => (),
+        }
+        print!("{}", self.render(e, input));
+    }
+
+    /// `e` as `report` prints it. Its spans are of its own source if it carries one, as a
+    /// definition's, and otherwise of `input`, which is the tail of the line being run
+    pub fn render(&self, e: Box<dyn Diagnostic + Send + Sync>, input: String) -> String {
+        let own = qk::diagnostics::own_source(&*e);
+        let inputs = &self.session.inputs;
+        let part = match own {
+            Some(_) => None,
+            None => self.running.and_then(|line| inputs.locate(line, &input)),
+        };
+        match (self.output, part) {
+            (OutputMode::Human, Some(part)) => {
+                let report = miette::Report::new(inputs.attach(e, part));
+                format!("{}\n", self.theme.render(report.as_ref()))
+            }
+            (OutputMode::Human, None) => {
+                let report =
+                    miette::Report::new_boxed(e).with_source_code(NamedSource::new("repl", input));
+                format!("{}\n", self.theme.render(report.as_ref()))
+            }
+            (OutputMode::Plain, Some(part)) => {
+                let e = inputs.attach(e, part);
+                qk::diagnostics::render_named(&e, &e.name, &e.text)
+            }
+            (OutputMode::Plain, None) => {
+                qk::diagnostics::render(&*e, own.as_deref().unwrap_or(&input))
+            }
+            (OutputMode::Json, _) => format!("{}\n", json::error(&input, &*e)),
+        }
+    }
+
+    pub fn reset_diagnostics(&mut self) {
+        self.warnings = 0;
+        self.errors = 0;
+    }
+
     pub fn cmd(&mut self, input: &str) -> Result<()> {
         let (command, args) = input.split_once(' ').unwrap_or((input, ""));
         for c in cmd::COMMANDS {
@@ -102,16 +403,52 @@ impl Repl {
                 return (c.func)(self, args);
             }
         }
-        Err(Error::UnknownCommand(command.to_string()))
+        match self.session.run_command(command, args) {
+            Some(Ok(out)) if out.is_empty() => Ok(()),
+            Some(Ok(out)) => {
+                println!("{out}");
+                Ok(())
+            }
+            Some(Err(e)) => {
+                self.report(e, input.to_string());
+                Ok(())
+            }
+            None => Err(Error::UnknownCommand(command.to_string())),
+        }
+    }
+
+    /// what the line editor completes with, as the session is now
+    pub fn completions(&self) -> Completions {
+        let builtins = cmd::COMMANDS.iter().flat_map(|c| [c.cmd, c.alias]);
+        let registered = self.session.commands.iter();
+        let mut completions = Completions {
+            commands: builtins.map(str::to_string).collect(),
+            args: HashMap::new(),
+            prompt: self.theme.prompt.filter(|_| self.theme.enabled()),
+        };
+        for c in registered {
+            let names = std::iter::once(&c.name).chain(&c.alias);
+            for name in names {
+                completions.commands.push(name.to_string());
+                if let Some(complete) = &c.complete {
+                    completions
+                        .args
+                        .insert(name.to_string(), complete(&self.session));
+                }
+            }
+        }
+        completions
     }
 
     pub fn input(&mut self) -> rustyline::Result<String> {
+        let completions = self.completions();
+        self.rl.set_helper(Some(completions));
         let mut prefix = String::default();
-        if self.runner.warnings > 0 {
-            write!(prefix, "{}  ", self.runner.warnings).unwrap();
+        if self.warnings > 0 {
+            write!(prefix, "{}  ", self.warnings).unwrap();
         }
-        if self.runner.errors > 0 {
-            write!(prefix, "{}  ", self.runner.errors).unwrap();
+        if self.errors > 0 {
+            write!(prefix, "{}  ", self.errors).unwrap();
         }
         let input = if prefix.is_empty() {
             self.rl.readline(&self.prompt)?
@@ -125,11 +462,149 @@ impl Repl {
     }
 
     pub fn new() -> Result<Self> {
-        let s = Self {
+        let mut s = Self {
             prompt: "λ> ".to_string(),
-            rl: DefaultEditor::new().map_err(Error::Input)?,
-            runner: Runner::new(),
+            output: OutputMode::default(),
+            theme: Theme::default(),
+            rl: Editor::new().map_err(Error::Input)?,
+            session: Session::new(),
+            history: History::default(),
+            loaded: Vec::new(),
+            history_file: None,
+            severities: Severities::default(),
+            running: None,
+            locations: HashMap::new(),
+            trace: Trace::default(),
+            warnings: 0,
+            errors: 0,
         };
+        s.session.set_prelude(true)?;
         Ok(s)
     }
 }
+
+/// completes the names of the commands and, after a registered one, the values of its argument
+#[derive(Debug, Default)]
+pub struct Completions {
+    pub commands: Vec<String>,
+    pub args: HashMap<String, Vec<String>>,
+    /// the color of the prompt
+    pub prompt: Option<AnsiColors>,
+}
+
+impl Completer for Completions {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let Some(cmd) = line[..pos].strip_prefix(':') else {
+            return Ok((pos, Vec::new()));
+        };
+        let (start, prefix, candidates) = match cmd.split_once(' ') {
+            None => (1, cmd, &self.commands),
+            Some((name, arg)) => match self.args.get(name) {
+                Some(values) => (pos - arg.len(), arg, values),
+                None => return Ok((pos, Vec::new())),
+            },
+        };
+        let found = candidates.iter().filter(|c| c.starts_with(prefix));
+        Ok((start, found.cloned().collect()))
+    }
+}
+
+impl Hinter for Completions {
+    type Hint = String;
+}
+
+impl Highlighter for Completions {
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(
+        &'s self,
+        prompt: &'p str,
+        _default: bool,
+    ) -> Cow<'b, str> {
+        match self.prompt {
+            Some(c) => Cow::Owned(prompt.color(c).to_string()),
+            None => Cow::Borrowed(prompt),
+        }
+    }
+}
+
+impl Validator for Completions {}
+
+impl Helper for Completions {}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::repl::{Error, OutputMode, Repl, cmd};
+
+    #[test]
+    pub fn casts_fail_inside_definitions() {
+        let mut r = Repl::new().unwrap();
+        r.session.set_typed(true).unwrap();
+        r.session.gradual = true;
+        r.session.irc.primitives = true;
+        r.exec("f = fn (x : dyn) => x x");
+        let inputs = &mut r.session.inputs;
+        let id = inputs.add("<input 2>".to_string(), "f 3");
+        r.running = Some(inputs.whole(id));
+        let e = r.expression("f 3").unwrap_err();
+        let shown = r.render(Box::new(e), "f 3".to_string());
+        assert!(shown.contains("f = fn (x : dyn) => x x"), "{shown}");
+        let e = r.expression("f 3").unwrap_err();
+        r.output = OutputMode::Plain;
+        let shown = r.render(Box::new(e), "f 3".to_string());
+        assert!(shown.contains("1 | f = fn (x : dyn) => x x"), "{shown}");
+        assert!(shown.contains("^^^ expected a function here"), "{shown}");
+    }
+
+    #[test]
+    pub fn logs_what_changed_the_session() {
+        let mut r = Repl::new().unwrap();
+        for input in [
+            ":set typed on",
+            "I x = x",
+            ":env",
+            ":type I",
+            "nope",
+            ":set nope on",
+        ] {
+            r.exec(input);
+        }
+        r.exec("I I");
+        let logged: Vec<_> = r.session.log.events.iter().map(|e| &*e.input).collect();
+        assert_eq!(logged, [":set typed on", "I x = x", "I I"]);
+    }
+
+    #[test]
+    pub fn bench_needs_runs() {
+        let mut r = Repl::new().unwrap();
+        assert!(r.cmd("bench 0 I").is_err());
+        assert!(r.cmd("bench nope I").is_err());
+    }
+
+    #[test]
+    pub fn diag_codes_are_known() {
+        let mut r = Repl::new().unwrap();
+        assert!(r.cmd("set diag lint::unused_variable=error").is_ok());
+        let e = r.cmd("set diag lint::unused=error").unwrap_err();
+        assert!(matches!(e, Error::UnknownCode(code) if code == "lint::unused"));
+    }
+
+    #[test]
+    pub fn commands_have_their_own_names() {
+        let mut names: Vec<_> = cmd::COMMANDS
+            .iter()
+            .flat_map(|c| [c.cmd, c.alias])
+            .collect();
+        let all = names.len();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), all);
+        let resources = cmd::COMMANDS.iter().find(|c| c.matches("r"));
+        assert_eq!(resources.map(|c| c.cmd), Some("resources"));
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt;
+use std::io::IsTerminal;
+
+use miette::{
+    Diagnostic, GraphicalTheme, MietteHandler, MietteHandlerOpts, ReportHandler, ThemeCharacters,
+    ThemeStyles,
+};
+use owo_colors::{AnsiColors, Style};
+use qk::session::evaluated::Section;
+
+/// when the output is colored
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    /// only on a terminal, and if `NO_COLOR` isn't set
+    #[default]
+    Auto,
+    On,
+    Off,
+}
+
+impl TryFrom<&str> for ColorMode {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "auto" => Ok(Self::Auto),
+            "on" => Ok(Self::On),
+            "off" => Ok(Self::Off),
+            _ => Err(()),
+        }
+    }
+}
+
+/// the names `set color <part> <color>` takes, `none` being no color at all
+pub const COLORS: &[(&str, AnsiColors)] = &[
+    ("black", AnsiColors::Black),
+    ("red", AnsiColors::Red),
+    ("green", AnsiColors::Green),
+    ("yellow", AnsiColors::Yellow),
+    ("blue", AnsiColors::Blue),
+    ("magenta", AnsiColors::Magenta),
+    ("cyan", AnsiColors::Cyan),
+    ("white", AnsiColors::White),
+    ("bright-black", AnsiColors::BrightBlack),
+    ("bright-red", AnsiColors::BrightRed),
+    ("bright-green", AnsiColors::BrightGreen),
+    ("bright-yellow", AnsiColors::BrightYellow),
+    ("bright-blue", AnsiColors::BrightBlue),
+    ("bright-magenta", AnsiColors::BrightMagenta),
+    ("bright-cyan", AnsiColors::BrightCyan),
+    ("bright-white", AnsiColors::BrightWhite),
+];
+
+/// the parts of the output `set color <part> <color>` colors
+pub const PARTS: &[&str] = &["prompt", "error", "warning", "help", "step", "normal"];
+
+/// the colors of the REPL's output: its prompt, the diagnostics and what an expression shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    pub mode: ColorMode,
+    pub prompt: Option<AnsiColors>,
+    pub error: Option<AnsiColors>,
+    pub warning: Option<AnsiColors>,
+    pub help: Option<AnsiColors>,
+    /// the reduction steps
+    pub step: Option<AnsiColors>,
+    /// the normal form
+    pub normal: Option<AnsiColors>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            mode: ColorMode::Auto,
+            prompt: Some(AnsiColors::Green),
+            error: Some(AnsiColors::Red),
+            warning: Some(AnsiColors::Yellow),
+            help: Some(AnsiColors::Cyan),
+            step: Some(AnsiColors::BrightBlack),
+            normal: None,
+        }
+    }
+}
+
+impl Theme {
+    /// whether anything is colored at all
+    pub fn enabled(&self) -> bool {
+        match self.mode {
+            ColorMode::On => true,
+            ColorMode::Off => false,
+            ColorMode::Auto => {
+                std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+        }
+    }
+
+    /// sets the color of `part` to the one called `color`. `false` if there's no such part or
+    /// color
+    pub fn set(&mut self, part: &str, color: &str) -> bool {
+        let color = match color {
+            "none" => None,
+            name => match COLORS.iter().find(|(n, _)| *n == name) {
+                Some((_, c)) => Some(*c),
+                None => return false,
+            },
+        };
+        let part = match part {
+            "prompt" => &mut self.prompt,
+            "error" => &mut self.error,
+            "warning" => &mut self.warning,
+            "help" => &mut self.help,
+            "step" => &mut self.step,
+            "normal" => &mut self.normal,
+            _ => return false,
+        };
+        *part = color;
+        true
+    }
+
+    fn style(&self, color: Option<AnsiColors>) -> Style {
+        match color.filter(|_| self.enabled()) {
+            Some(c) => Style::new().color(c),
+            None => Style::new(),
+        }
+    }
+
+    /// `text` in `color`, if the output is colored
+    pub fn paint(&self, color: Option<AnsiColors>, text: &str) -> String {
+        self.style(color).style(text).to_string()
+    }
+
+    /// the color of a section of an expression's output
+    pub fn section(&self, section: Section) -> Option<AnsiColors> {
+        match section {
+            Section::Step => self.step,
+            Section::Normal => self.normal,
+            _ => None,
+        }
+    }
+
+    /// miette's handler, drawing diagnostics with these colors
+    pub fn handler(&self) -> MietteHandler {
+        let opts = MietteHandlerOpts::new().color(self.enabled());
+        if !self.enabled() {
+            return opts.build();
+        }
+        let styles = ThemeStyles {
+            error: self.style(self.error),
+            warning: self.style(self.warning),
+            advice: self.style(self.help),
+            help: self.style(self.help),
+            ..ThemeStyles::ansi()
+        };
+        opts.graphical_theme(GraphicalTheme {
+            characters: ThemeCharacters::unicode(),
+            styles,
+        })
+        .build()
+    }
+
+    /// `diag` as drawn by `handler`
+    pub fn render(&self, diag: &dyn Diagnostic) -> String {
+        struct Rendered<'a>(MietteHandler, &'a dyn Diagnostic);
+
+        impl fmt::Debug for Rendered<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.debug(self.1, f)
+            }
+        }
+
+        format!("{:?}", Rendered(self.handler(), diag))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use owo_colors::AnsiColors;
+
+    use crate::repl::theme::{ColorMode, Theme};
+
+    #[test]
+    pub fn set_colors() {
+        let mut theme = Theme::default();
+        assert!(theme.set("normal", "bright-green"));
+        assert!(theme.set("prompt", "none"));
+        assert_eq!(
+            (theme.normal, theme.prompt),
+            (Some(AnsiColors::BrightGreen), None)
+        );
+        assert!(!theme.set("normal", "purple"));
+        assert!(!theme.set("footer", "red"));
+        assert_eq!(theme.normal, Some(AnsiColors::BrightGreen));
+    }
+
+    #[test]
+    pub fn modes() {
+        assert_eq!(ColorMode::try_from("auto"), Ok(ColorMode::Auto));
+        assert_eq!(ColorMode::try_from("always"), Err(()));
+        let mut theme = Theme {
+            mode: ColorMode::Off,
+            ..Theme::default()
+        };
+        assert_eq!(theme.paint(theme.error, "oops"), "oops");
+        theme.mode = ColorMode::On;
+        assert_eq!(theme.paint(theme.error, "oops"), "\u{1b}[31moops\u{1b}[0m");
+        assert_eq!(theme.paint(None, "oops"), "oops");
+    }
+}
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::arts::{CompArtifact, OuterIdx, Term, TermIdx};
+
+/// the β-reductions the search may take, for each step down the trees
+pub const FUEL: usize = 10_000;
+
+#[derive(Error, Diagnostic, Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    #[error("the normal forms are the same, up to η")]
+    #[diagnostic(
+        code(separate::equivalent),
+        help("by Böhm's theorem, only βη-distinct normal forms can be told apart")
+    )]
+    Equivalent,
+
+    #[error("no separating context was found")]
+    #[diagnostic(
+        code(separate::not_found),
+        help("the terms may be too big to search through")
+    )]
+    NotFound,
+
+    #[error("the terms have constants")]
+    #[diagnostic(
+        code(separate::constants),
+        help("Böhm's theorem is about pure λ-terms, so there's no telling a number apart")
+    )]
+    Constants,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// a λ-term with named variables, so substituting the free ones of a context is direct. Each
+/// binder gets a fresh name, and substitutions rename the binders they go through
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Var(usize),
+    Lam(usize, Box<Expr>),
+    App(Box<Expr>, Box<Expr>),
+}
+
+fn app(f: Expr, x: Expr) -> Expr {
+    Expr::App(Box::new(f), Box::new(x))
+}
+
+/// finds the arguments of a Böhm-out context `[] A..`, which turns the first term into `true`
+/// and the second into `false`
+#[derive(Default)]
+struct Separator {
+    next: usize,
+    fuel: usize,
+}
+
+impl Separator {
+    fn fresh(&mut self) -> usize {
+        self.next += 1;
+        self.next
+    }
+
+    fn lam(&mut self, arity: usize, body: impl FnOnce(&[usize]) -> Expr) -> Expr {
+        let params: Vec<_> = (0..arity).map(|_| self.fresh()).collect();
+        let body = body(&params);
+        params
+            .into_iter()
+            .rev()
+            .fold(body, |b, p| Expr::Lam(p, Box::new(b)))
+    }
+
+    /// `fn t f => t` if `truth`, or else `fn t f => f`, after ignoring `arity` arguments
+    fn constant(&mut self, arity: usize, truth: bool) -> Expr {
+        self.lam(arity + 2, |p| Expr::Var(p[arity + usize::from(!truth)]))
+    }
+
+    /// `fn a.. => a_i`
+    fn projection(&mut self, arity: usize, i: usize) -> Expr {
+        self.lam(arity, |p| Expr::Var(p[i]))
+    }
+
+    /// `fn a.. w => w a..`, so each occurrence of what's replaced gets a head of its own
+    fn tupler(&mut self, arity: usize) -> Expr {
+        self.lam(arity + 1, |p| {
+            p[..arity]
+                .iter()
+                .fold(Expr::Var(p[arity]), |f, a| app(f, Expr::Var(*a)))
+        })
+    }
+
+    fn read(&mut self, art: &CompArtifact, idx: TermIdx, names: &mut Vec<usize>) -> Expr {
+        match art.get(idx) {
+            Term::Var(OuterIdx(i)) => Expr::Var(names[names.len() - 1 - i]),
+            Term::Abs { inner } => {
+                let name = self.fresh();
+                names.push(name);
+                let body = self.read(art, inner, names);
+                names.pop();
+                Expr::Lam(name, Box::new(body))
+            }
+            Term::App(l, r) => app(self.read(art, l, names), self.read(art, r, names)),
+            Term::Const(_) => unreachable!("the terms were checked to have no constants"),
+        }
+    }
+
+    fn subst(&mut self, e: &Expr, map: &mut Vec<(usize, Expr)>) -> Expr {
+        match e {
+            Expr::Var(x) => match map.iter().rev().find(|(y, _)| y == x) {
+                Some((_, s)) => s.clone(),
+                None => e.clone(),
+            },
+            Expr::Lam(x, body) => {
+                let name = self.fresh();
+                map.push((*x, Expr::Var(name)));
+                let body = self.subst(body, map);
+                map.pop();
+                Expr::Lam(name, Box::new(body))
+            }
+            Expr::App(l, r) => app(self.subst(l, map), self.subst(r, map)),
+        }
+    }
+
+    fn beta(&mut self, x: usize, body: &Expr, arg: Expr) -> Option<Expr> {
+        self.fuel = self.fuel.checked_sub(1)?;
+        Some(self.subst(body, &mut vec![(x, arg)]))
+    }
+
+    fn whnf(&mut self, e: Expr) -> Option<Expr> {
+        match e {
+            Expr::App(f, x) => match self.whnf(*f)? {
+                Expr::Lam(p, body) => {
+                    let r = self.beta(p, &body, *x)?;
+                    self.whnf(r)
+                }
+                f => Some(app(f, *x)),
+            },
+            e => Some(e),
+        }
+    }
+
+    /// the normal form, by normal order. `None` if the fuel runs out first
+    fn normalize(&mut self, e: Expr) -> Option<Expr> {
+        match self.whnf(e)? {
+            Expr::Lam(p, body) => Some(Expr::Lam(p, Box::new(self.normalize(*body)?))),
+            Expr::App(f, x) => Some(app(self.normalize(*f)?, self.normalize(*x)?)),
+            v => Some(v),
+        }
+    }
+
+    /// the normal form of `e`, with `t` for `x`
+    fn replace(&mut self, e: &Expr, x: usize, t: Expr) -> Option<Expr> {
+        let e = self.subst(e, &mut vec![(x, t)]);
+        self.normalize(e)
+    }
+
+    /// finds the arguments separating `m` from `n`. They're closed, since both terms are
+    fn separate(&mut self, mut m: Expr, mut n: Expr) -> Result<Vec<Expr>> {
+        let mut args = Vec::new();
+        let mut found = HashMap::new();
+        let mut tupled = 0;
+        loop {
+            // η-expanding the shorter one, neither starts with a λ anymore
+            let lams = binders(&m).max(binders(&n));
+            for _ in 0..lams {
+                let v = self.fresh();
+                args.push(Expr::Var(v));
+                m = self
+                    .normalize(app(m, Expr::Var(v)))
+                    .ok_or(Error::NotFound)?;
+                n = self
+                    .normalize(app(n, Expr::Var(v)))
+                    .ok_or(Error::NotFound)?;
+            }
+            let (y, ms) = spine(&m);
+            let (z, ns) = spine(&n);
+            let (k, l) = (ms.len(), ns.len());
+            if y != z {
+                found.insert(y, self.constant(k, true));
+                found.insert(z, self.constant(l, false));
+                break;
+            }
+            if k != l {
+                // `y` takes one more argument than the longest one, and returns it. The shorter
+                // application still waits for `d` more, the longer one for a single one
+                let (long, d) = (k.max(l), k.abs_diff(l) + 1);
+                let t = self.lam(long + 1, |p| Expr::Var(p[long]));
+                found.insert(y, t);
+                let short_is_m = k < l;
+                let first = self.constant(d - 1, !short_is_m);
+                args.push(first);
+                args.extend((0..d - 2).map(|_| self.projection(1, 0)));
+                args.push(self.constant(0, short_is_m));
+                break;
+            }
+            let Some(i) = (0..k).find(|i| !same(ms[*i], ns[*i])) else {
+                return Err(Error::Equivalent);
+            };
+            let t = if occurrences(&m, y) + occurrences(&n, y) == 2 {
+                self.projection(k, i)
+            } else {
+                // bigger than any before, or two tupled variables could become the same
+                tupled = arity(&m, y).max(arity(&n, y)).max(tupled) + 1;
+                self.tupler(tupled)
+            };
+            found.insert(y, t.clone());
+            m = self.replace(&m, y, t.clone()).ok_or(Error::NotFound)?;
+            n = self.replace(&n, y, t).ok_or(Error::NotFound)?;
+        }
+        let mut map: Vec<_> = found.into_iter().collect();
+        let args = args
+            .iter()
+            .map(|a| self.subst(a, &mut map))
+            .collect::<Vec<_>>();
+        // whatever was never looked at can be anything
+        let free: Vec<_> = args.iter().flat_map(free_vars).collect();
+        let mut map: Vec<_> = free
+            .into_iter()
+            .map(|v| (v, self.projection(1, 0)))
+            .collect();
+        Ok(args.iter().map(|a| self.subst(a, &mut map)).collect())
+    }
+}
+
+fn binders(e: &Expr) -> usize {
+    match e {
+        Expr::Lam(_, body) => 1 + binders(body),
+        _ => 0,
+    }
+}
+
+/// the head variable of a term with no leading λ, and its arguments
+fn spine(e: &Expr) -> (usize, Vec<&Expr>) {
+    let mut args = Vec::new();
+    let mut e = e;
+    while let Expr::App(f, x) = e {
+        args.push(&**x);
+        e = f;
+    }
+    args.reverse();
+    match e {
+        Expr::Var(v) => (*v, args),
+        _ => unreachable!("a normal form has a variable at its head"),
+    }
+}
+
+fn occurrences(e: &Expr, x: usize) -> usize {
+    match e {
+        Expr::Var(v) => usize::from(*v == x),
+        Expr::Lam(_, body) => occurrences(body, x),
+        Expr::App(l, r) => occurrences(l, x) + occurrences(r, x),
+    }
+}
+
+/// the most arguments `x` is ever applied to
+fn arity(e: &Expr, x: usize) -> usize {
+    match e {
+        Expr::Var(_) => 0,
+        Expr::Lam(_, body) => arity(body, x),
+        Expr::App(..) => {
+            let (mut e, mut args) = (e, Vec::new());
+            while let Expr::App(f, a) = e {
+                args.push(&**a);
+                e = f;
+            }
+            let here = if *e == Expr::Var(x) { args.len() } else { 0 };
+            let inner = match e {
+                Expr::Lam(_, body) => arity(body, x),
+                _ => 0,
+            };
+            args.iter()
+                .map(|a| arity(a, x))
+                .fold(here.max(inner), usize::max)
+        }
+    }
+}
+
+fn free_vars(e: &Expr) -> Vec<usize> {
+    fn go(e: &Expr, bound: &mut Vec<usize>, free: &mut Vec<usize>) {
+        match e {
+            Expr::Var(v) if !bound.contains(v) && !free.contains(v) => free.push(*v),
+            Expr::Var(_) => (),
+            Expr::Lam(x, body) => {
+                bound.push(*x);
+                go(body, bound, free);
+                bound.pop();
+            }
+            Expr::App(l, r) => {
+                go(l, bound, free);
+                go(r, bound, free);
+            }
+        }
+    }
+    let mut free = Vec::new();
+    go(e, &mut Vec::new(), &mut free);
+    free
+}
+
+/// `fn x => f x` as `f`, when `x` isn't free in `f`
+fn eta(e: &Expr) -> Expr {
+    match e {
+        Expr::Var(_) => e.clone(),
+        Expr::App(l, r) => app(eta(l), eta(r)),
+        Expr::Lam(x, body) => match eta(body) {
+            Expr::App(f, a) if *a == Expr::Var(*x) && !free_vars(&f).contains(x) => *f,
+            body => Expr::Lam(*x, Box::new(body)),
+        },
+    }
+}
+
+/// βη-equivalence of normal forms
+fn same(a: &Expr, b: &Expr) -> bool {
+    fn alpha(a: &Expr, b: &Expr, pairs: &mut Vec<(usize, usize)>) -> bool {
+        match (a, b) {
+            (Expr::Var(x), Expr::Var(y)) => {
+                match pairs.iter().rev().find(|(p, q)| p == x || q == y) {
+                    Some((p, q)) => p == x && q == y,
+                    None => x == y,
+                }
+            }
+            (Expr::Lam(x, l), Expr::Lam(y, r)) => {
+                pairs.push((*x, *y));
+                let same = alpha(l, r, pairs);
+                pairs.pop();
+                same
+            }
+            (Expr::App(f, x), Expr::App(g, y)) => alpha(f, g, pairs) && alpha(x, y, pairs),
+            _ => false,
+        }
+    }
+    alpha(&eta(a), &eta(b), &mut Vec::new())
+}
+
+fn to_arena(art: &mut CompArtifact, e: &Expr, names: &mut Vec<usize>) -> TermIdx {
+    match e {
+        Expr::Var(v) => {
+            let i = names.iter().rev().position(|n| n == v);
+            art.push(Term::Var(OuterIdx(i.expect("the arguments are closed"))))
+        }
+        Expr::Lam(x, body) => {
+            names.push(*x);
+            let inner = to_arena(art, body, names);
+            names.pop();
+            art.push(Term::Abs { inner })
+        }
+        Expr::App(l, r) => {
+            let (l, r) = (to_arena(art, l, names), to_arena(art, r, names));
+            art.push(Term::App(l, r))
+        }
+    }
+}
+
+/// the arguments of a context `[] A..` that reduces the normal form `m` to `fn t f => t` and `n`
+/// to `fn t f => f`, pushed into `art`. It only exists if they aren't βη-equivalent, so it's
+/// also a proof that they aren't. Both must be closed, and pure
+pub fn separate(art: &mut CompArtifact, m: TermIdx, n: TermIdx) -> Result<Vec<TermIdx>> {
+    let consts = art.reachable(&[m, n]);
+    if consts.iter().any(|t| matches!(art.get(*t), Term::Const(_))) {
+        return Err(Error::Constants);
+    }
+    let mut s = Separator {
+        fuel: FUEL,
+        ..Separator::default()
+    };
+    let (em, en) = (
+        s.read(art, m, &mut Vec::new()),
+        s.read(art, n, &mut Vec::new()),
+    );
+    if same(&em, &en) {
+        return Err(Error::Equivalent);
+    }
+    let args = s.separate(em, en)?;
+    Ok(args
+        .iter()
+        .map(|a| to_arena(art, a, &mut Vec::new()))
+        .collect())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::separate::Error;
+    use crate::session::{self, Session};
+
+    #[test]
+    pub fn separations() {
+        let mut s = Session::new();
+        for (m, n) in [
+            ("fn x y => x", "fn x y => y"),
+            ("fn x => x", "fn x y => x y y"),
+            ("fn f x => f x", "fn f x => f (f x)"),
+            ("fn f x => f (f x)", "fn f x => f (f (f x))"),
+            ("fn x => x (fn y => y) x", "fn x => x (fn y => x) x"),
+            ("fn x y => x (y y)", "fn x y => x (y x)"),
+        ] {
+            // `Session::separate` checks the context does what it says
+            assert!(s.separate(m, n).expect(m).is_some(), "{m} ; {n}");
+            assert!(s.separate(n, m).expect(n).is_some(), "{n} ; {m}");
+        }
+        assert!(matches!(
+            s.separate("fn x y => x y", "fn x => x"),
+            Err(session::Error::Separate(Error::Equivalent))
+        ));
+    }
+}
@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use crate::session::{BENCH_SETTING, Result, Session};
+
+/// the stages timed by `run`, in the order they happen
+pub const STAGES: &[&str] = &["lexer", "parser", "ir", "compiler", "normal"];
+
+/// the spread of a stage's timings
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Summary {
+    pub min: Duration,
+    pub median: Duration,
+    pub mean: Duration,
+    pub stddev: Duration,
+}
+
+impl Summary {
+    pub fn new(samples: &[Duration]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let mid = sorted.len() / 2;
+        let median = if sorted.len().is_multiple_of(2) {
+            (sorted[mid - 1] + sorted[mid]) / 2
+        } else {
+            sorted[mid]
+        };
+        let n = samples.len() as f64;
+        let mean = samples.iter().map(Duration::as_secs_f64).sum::<f64>() / n;
+        let variance = samples
+            .iter()
+            .map(|s| (s.as_secs_f64() - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        Self {
+            min: sorted[0],
+            median,
+            mean: Duration::from_secs_f64(mean),
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+        }
+    }
+}
+
+/// how many of `n` runs are thrown away, so caches and allocations settle down
+pub fn warmup(n: usize) -> usize {
+    n / 10
+}
+
+/// evaluates `input` `n` times and summarizes how long each stage took, after the warmup. The
+/// `bench` setting is restored afterwards
+pub fn run(s: &mut Session, input: &str, n: usize) -> Result<Vec<(&'static str, Summary)>> {
+    let bench = std::mem::replace(
+        &mut s.bench,
+        BENCH_SETTING
+            .parse_inspired(&STAGES.join(" "))
+            .expect("the stages are bench settings"),
+    );
+    let mut samples = vec![Vec::with_capacity(n); STAGES.len()];
+    let mut r = Ok(());
+    for i in 0..n {
+        let out = match s.eval_str(input) {
+            Ok(out) => out,
+            Err(e) => {
+                r = Err(e);
+                break;
+            }
+        };
+        if i < warmup(n) {
+            continue;
+        }
+        for (label, elapsed) in out.timings {
+            if let Some(stage) = STAGES.iter().position(|s| *s == label) {
+                samples[stage].push(elapsed);
+            }
+        }
+    }
+    s.bench = bench;
+    r?;
+    Ok(STAGES
+        .iter()
+        .zip(samples)
+        .map(|(stage, samples)| (*stage, Summary::new(&samples)))
+        .collect())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::time::Duration;
+
+    use crate::session::{
+        Session,
+        bench::{self, Summary},
+    };
+
+    #[test]
+    pub fn summaries() {
+        let ms = Duration::from_millis;
+        let s = Summary::new(&[ms(4), ms(1), ms(3), ms(2)]);
+        assert_eq!(s.min, ms(1));
+        assert_eq!(s.median, Duration::from_micros(2500));
+        assert_eq!(s.mean.as_micros(), 2500);
+        assert_eq!(s.stddev.as_micros(), 1118);
+
+        let mut session = Session::new();
+        let stages = bench::run(&mut session, "(fn x => x) (fn y => y)", 3).unwrap();
+        assert_eq!(stages.len(), bench::STAGES.len());
+        assert!(!session.bench.is_on("lexer"));
+    }
+}
@@ -0,0 +1,219 @@
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::session::Session;
+
+#[derive(Error, Diagnostic, Debug)]
+pub enum Error {
+    #[error("there's already a command called {0:?}")]
+    #[diagnostic(
+        code(commands::duplicated),
+        help("remove the old one first, or pick another name")
+    )]
+    Duplicated(Box<str>),
+
+    #[error("can't load the plugin: {0}")]
+    #[diagnostic(
+        code(commands::plugin),
+        help(
+            "a plugin is a dynamic library exporting `qk_register`, built with the same compiler"
+        )
+    )]
+    Plugin(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// whatever a command fails with. It's reported as any other diagnostic
+pub type CommandError = Box<dyn Diagnostic + Send + Sync + 'static>;
+/// the text a command prints
+pub type CommandResult = std::result::Result<String, CommandError>;
+pub type CommandFn = Box<dyn Fn(&mut Session, &str) -> CommandResult>;
+pub type CompleteFn = Box<dyn Fn(&Session) -> Vec<String>>;
+
+/// a `:` command added by whoever embeds the session, and not built in the REPL
+pub struct UserCommand {
+    pub name: Box<str>,
+    pub alias: Option<Box<str>>,
+    pub desc: Box<str>,
+    pub func: CommandFn,
+    /// every value its argument can take. Callers filter them by what was typed
+    pub complete: Option<CompleteFn>,
+}
+
+impl UserCommand {
+    pub fn new(
+        name: &str,
+        desc: &str,
+        func: impl Fn(&mut Session, &str) -> CommandResult + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            alias: None,
+            desc: desc.into(),
+            func: Box::new(func),
+            complete: None,
+        }
+    }
+
+    pub fn with_alias(mut self, alias: &str) -> Self {
+        self.alias = Some(alias.into());
+        self
+    }
+
+    pub fn with_completion(mut self, complete: impl Fn(&Session) -> Vec<String> + 'static) -> Self {
+        self.complete = Some(Box::new(complete));
+        self
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        *self.name == *name || self.alias.as_deref() == Some(name)
+    }
+}
+
+/// the commands added to a session. The REPL looks them up after its own
+#[derive(Default)]
+pub struct CommandRegistry {
+    commands: Vec<UserCommand>,
+    /// the plugins the commands came from, which must outlive them
+    #[cfg(feature = "plugins")]
+    libraries: Vec<libloading::Library>,
+}
+
+impl std::fmt::Debug for CommandRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.commands.iter().map(|c| &c.name))
+            .finish()
+    }
+}
+
+impl CommandRegistry {
+    pub fn register(&mut self, cmd: UserCommand) -> Result<()> {
+        let names = std::iter::once(&cmd.name).chain(&cmd.alias);
+        if let Some(taken) = names.into_iter().find(|n| self.get(n).is_some()) {
+            return Err(Error::Duplicated(taken.clone()));
+        }
+        self.commands.push(cmd);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<UserCommand> {
+        let i = self.commands.iter().position(|c| c.matches(name))?;
+        Some(self.commands.remove(i))
+    }
+
+    /// by name or alias
+    pub fn get(&self, name: &str) -> Option<&UserCommand> {
+        self.commands.iter().find(|c| c.matches(name))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &UserCommand> {
+        self.commands.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// the ways to go on with `line`, a command without its `:`: the names starting like it
+    /// or, after the name, the values of its argument
+    pub fn complete(&self, session: &Session, line: &str) -> Vec<String> {
+        let Some((name, arg)) = line.split_once(' ') else {
+            return self
+                .commands
+                .iter()
+                .flat_map(|c| std::iter::once(&c.name).chain(&c.alias))
+                .filter(|n| n.starts_with(line))
+                .map(|n| n.to_string())
+                .collect();
+        };
+        let Some(complete) = self.get(name).and_then(|c| c.complete.as_ref()) else {
+            return Vec::new();
+        };
+        complete(session)
+            .into_iter()
+            .filter(|v| v.starts_with(arg.trim_start()))
+            .collect()
+    }
+
+    /// loads the dynamic library at `path` and calls its `qk_register`, which adds its commands.
+    /// Returns how many it added
+    ///
+    /// # Safety
+    /// the library runs any code it wants when loaded. `qk_register` must be a
+    /// `fn(&mut CommandRegistry)`, built by the same compiler and against the same qk
+    #[cfg(feature = "plugins")]
+    pub unsafe fn load(&mut self, path: &std::path::Path) -> Result<usize> {
+        let before = self.commands.len();
+        let plugin = |e: libloading::Error| Error::Plugin(e.to_string());
+        // SAFETY: up to the caller
+        let library = unsafe { libloading::Library::new(path) }.map_err(plugin)?;
+        let register =
+            unsafe { library.get::<fn(&mut CommandRegistry)>(b"qk_register") }.map_err(plugin)?;
+        register(self);
+        self.libraries.push(library);
+        Ok(self.commands.len() - before)
+    }
+
+    fn merge(&mut self, other: Self) {
+        self.commands.extend(other.commands);
+        #[cfg(feature = "plugins")]
+        self.libraries.extend(other.libraries);
+    }
+}
+
+impl Session {
+    /// runs a registered command. `None` if there's no such command
+    pub fn run_command(&mut self, name: &str, args: &str) -> Option<CommandResult> {
+        // the command gets the whole session, so the registry is set aside meanwhile
+        let registry = std::mem::take(&mut self.commands);
+        let result = registry.get(name).map(|c| (c.func)(self, args));
+        let added = std::mem::replace(&mut self.commands, registry);
+        self.commands.merge(added);
+        result
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::session::Session;
+    use crate::session::commands::UserCommand;
+
+    #[test]
+    pub fn registry() {
+        let mut s = Session::new();
+        let names = UserCommand::new("names", "list the definitions", |s, prefix| {
+            let mut names: Vec<_> = s
+                .irc
+                .scope
+                .definitions
+                .iter()
+                .map(|(k, _)| k.to_string())
+                .filter(|k| k.starts_with(prefix))
+                .collect();
+            names.sort();
+            Ok(names.join(" "))
+        })
+        .with_alias("n")
+        .with_completion(|_| vec!["I".into(), "K".into()]);
+        s.commands.register(names).unwrap();
+        assert!(
+            s.commands
+                .register(UserCommand::new("n", "", |_, _| Ok(String::new())))
+                .is_err()
+        );
+
+        s.eval_str("I x = x").unwrap();
+        s.eval_str("Id = I").unwrap();
+        assert_eq!(s.run_command("n", "I").unwrap().unwrap(), "I Id");
+        assert!(s.run_command("nope", "").is_none());
+        assert_eq!(s.commands.complete(&s, "na"), ["names"]);
+        assert_eq!(s.commands.complete(&s, "names K"), ["K"]);
+        assert_eq!(s.commands.len(), 1);
+    }
+}
@@ -0,0 +1,396 @@
+use std::cell::{OnceCell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use miette::{Diagnostic, SourceSpan};
+use thiserror::Error;
+
+use crate::arts::{Term, TermIdx};
+use crate::ast::{self, TreeStyle};
+use crate::backend::Aliases;
+use crate::closure::Converted;
+use crate::diagnostics;
+use crate::lift::Lifted;
+use crate::readback::{self, Value};
+use crate::session::{EvalOutput, Output, Session};
+use crate::types::Type;
+
+/// the result of running an input, with every way to look at it. Views are only rendered when
+/// asked for, and once, so whoever consumes it doesn't have to normalize or render again
+#[derive(Debug)]
+pub struct Evaluated<'s> {
+    pub session: &'s Session,
+    /// the source `out` came from
+    pub input: &'s str,
+    pub out: EvalOutput,
+    aliases: OnceCell<Aliases>,
+    readings: OnceCell<Vec<Value>>,
+    views: RefCell<HashMap<Box<str>, Rc<str>>>,
+}
+
+impl<'s> Evaluated<'s> {
+    pub fn new(session: &'s Session, input: &'s str, out: EvalOutput) -> Self {
+        Self {
+            session,
+            input,
+            out,
+            aliases: OnceCell::new(),
+            readings: OnceCell::new(),
+            views: RefCell::default(),
+        }
+    }
+
+    pub fn output(&self) -> Output {
+        self.out.output
+    }
+
+    /// the normal form, if the input was an expression
+    pub fn normal(&self) -> Option<TermIdx> {
+        match self.out.output {
+            Output::Normal(nf) => Some(nf),
+            _ => None,
+        }
+    }
+
+    /// the names of the definitions, as the views print them
+    pub fn aliases(&self) -> &Aliases {
+        self.aliases
+            .get_or_init(|| self.session.irc.scope.get_aliases())
+    }
+
+    /// the compiled expression, lambda lifted. `None` if it isn't an expression, or isn't closed
+    pub fn lifted(&self) -> Option<Lifted> {
+        Lifted::new(&self.session.art, self.out.compiled?)
+    }
+
+    /// the compiled expression, closure converted. `None` if it isn't an expression, or isn't
+    /// closed
+    pub fn closures(&self) -> Option<Converted> {
+        Converted::new(&self.session.art, self.out.compiled?)
+    }
+
+    /// the normal form printed by the renderer called `format`. `None` if there's no normal
+    /// form or no such renderer
+    pub fn view(&self, format: &str) -> Option<Rc<str>> {
+        let nf = self.normal()?;
+        if let Some(view) = self.views.borrow().get(format) {
+            return Some(view.clone());
+        }
+        let renderer = self.session.renderer(format)?;
+        let view: Rc<str> = renderer
+            .render(&self.session.art, nf, self.aliases())
+            .into();
+        self.views.borrow_mut().insert(format.into(), view.clone());
+        Some(view)
+    }
+
+    /// the normal form in the REPL's notation. `unit` has a single value, so it's read back as
+    /// `()`, the way it's written
+    pub fn text(&self) -> Option<Rc<str>> {
+        if self.out.ty == Some(Type::Unit) && self.normal().is_some() {
+            return Some("()".into());
+        }
+        self.view("text")
+    }
+
+    /// every reduction step, printed by the renderer called `format`
+    pub fn steps_view(&self, format: &str) -> Option<String> {
+        let renderer = self.session.renderer(format)?;
+        Some(renderer.render_steps(&self.session.art, &self.out.steps, self.aliases()))
+    }
+
+    /// every reduction step with the redex it contracts labelled, against the step as `pretty`
+    /// prints it. The last one, the normal form, has nothing to contract
+    pub fn traced(&self) -> Vec<Traced> {
+        let art = &self.session.art;
+        self.out
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(i, &step)| {
+                let redex = self.out.redexes.get(i).copied().flatten();
+                let (term, at) = match redex {
+                    Some(redex) => art.pretty_marked(step, redex, self.aliases()),
+                    None => (art.pretty(step, self.aliases()), None),
+                };
+                let rule = match redex.map(|r| art.get(r)) {
+                    Some(Term::App(l, _)) if matches!(art.get(l), Term::Abs { .. }) => "β",
+                    _ => "δ",
+                };
+                Traced {
+                    step: i + 1,
+                    term,
+                    redex: at.map(|at| at.into()),
+                    rule,
+                }
+            })
+            .collect()
+    }
+
+    /// the data the normal form Church-encodes, as in `readback::readback`
+    pub fn readings(&self) -> &[Value] {
+        self.readings.get_or_init(|| match self.normal() {
+            Some(nf) => readback::readback(&self.session.art, nf),
+            None => Vec::new(),
+        })
+    }
+
+    /// the normal form as a Church numeral
+    pub fn numeral(&self) -> Option<usize> {
+        self.readings().iter().find_map(|v| match v {
+            Value::Numeral(n) => Some(*n),
+            _ => None,
+        })
+    }
+
+    /// the readings, as the REPL prints them
+    pub fn readings_view(&self) -> Vec<String> {
+        self.readings()
+            .iter()
+            .map(|v| v.display(&self.session.art, self.aliases()))
+            .collect()
+    }
+
+    /// the normal form, its readings and its type, in a line as the REPL prints it
+    pub fn line(&self) -> Option<String> {
+        let mut line = self.text()?.to_string();
+        if self.session.readback {
+            let values = self.readings_view();
+            if !values.is_empty() {
+                line = format!("{line} ≡ {}", values.join(" | "));
+            }
+        }
+        Some(match (&self.out.ty, &self.out.poly) {
+            (Some(ty), _) => format!("{line} : {ty}"),
+            (_, Some(poly)) => format!("{line} : {poly}"),
+            _ => line,
+        })
+    }
+
+    /// everything as a JSON object, as `json::eval_output` builds it
+    #[cfg(feature = "serde")]
+    pub fn json(&self) -> serde_json::Value {
+        crate::session::json::eval_output(self)
+    }
+}
+
+/// a reduction step, printed, with the redex it contracts
+#[derive(Error, Diagnostic, Debug, Clone)]
+#[error("step {step}")]
+#[diagnostic(severity(Advice))]
+pub struct Traced {
+    pub step: usize,
+    #[source_code]
+    pub term: String,
+    #[label("{rule}-redex")]
+    pub redex: Option<SourceSpan>,
+    /// `β`, or `δ` for a primitive
+    pub rule: &'static str,
+}
+
+impl Traced {
+    /// the term with its redex labelled, as `diagnostics::render` draws it, or just the term if
+    /// it has none. It doesn't end in a new line
+    pub fn render(&self) -> String {
+        match self.redex {
+            Some(_) => diagnostics::render(self, &self.term).trim_end().to_string(),
+            None => self.term.clone(),
+        }
+    }
+}
+
+/// a part of what the `show` setting asks for, so whoever prints it can tell them apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Tokens,
+    Tree,
+    Ir,
+    Pool,
+    /// the supercombinators the expression was lambda lifted to
+    Lifted,
+    /// the code blocks the expression was closure converted to
+    Closures,
+    /// a reduction step
+    Step,
+    /// the normal form, with its readings and type
+    Normal,
+    /// how long a stage took, or what it spent
+    Timing,
+}
+
+impl Evaluated<'_> {
+    /// what the `show` setting asks for, in the order it's printed. None of them ends in a new
+    /// line
+    pub fn sections(&self) -> Vec<(Section, String)> {
+        let (out, show, art) = (&self.out, &self.session.show, &self.session.art);
+        let aliases = self.aliases();
+        let mut sections = Vec::new();
+        if show.is_on("lexer") {
+            sections.push((Section::Tokens, format!("{:?}", out.tokens)));
+        }
+        if show.is_on("parser")
+            && let Some(ast) = &out.ast
+        {
+            let tree = ast::display_node(ast, self.input, TreeStyle::default());
+            sections.push((Section::Tree, tree.trim_end().to_string()));
+        }
+        if show.is_on("ir")
+            && let Some(ir) = &out.ir
+        {
+            sections.push((Section::Ir, format!("{ir:#?}")));
+        }
+        if show.is_on("compiler") && out.compiled.is_some() {
+            sections.push((Section::Pool, art.to_string(aliases)));
+        }
+        if show.is_on("lifted")
+            && let Some(lifted) = self.lifted()
+        {
+            sections.push((Section::Lifted, lifted.to_string()));
+        }
+        if show.is_on("closures")
+            && let Some(converted) = self.closures()
+        {
+            sections.push((Section::Closures, converted.to_string()));
+        }
+        if show.is_on("redexes") {
+            for traced in self.traced() {
+                sections.push((Section::Step, traced.render()));
+            }
+        } else if show.is_on("steps") {
+            for step in &out.steps {
+                sections.push((Section::Step, art.pretty(*step, aliases)));
+            }
+        }
+        if show.is_on("steps_raw") {
+            for step in &out.steps {
+                sections.push((Section::Step, art.pretty(*step, &HashMap::new())));
+            }
+            sections.push((Section::Pool, art.to_string(aliases)));
+        }
+        if show.is_on("normal")
+            && !show.is_on("steps")
+            && !show.is_on("redexes")
+            && let Some(line) = self.line()
+        {
+            sections.push((Section::Normal, line));
+        }
+        for (label, elapsed) in &out.timings {
+            sections.push((Section::Timing, format!("[{label}: {elapsed:?}]")));
+        }
+        if let Some(stats) = &out.stats {
+            // δ-steps only happen with primitives on
+            let delta = match stats.delta {
+                0 => String::new(),
+                n => format!(", {n} δ-steps"),
+            };
+            // and thunks with the `need` strategy
+            let forced = match stats.forced {
+                0 => String::new(),
+                n => format!(", {n} forced"),
+            };
+            let stats = format!(
+                "[eval: {} β-steps{delta}, max size {}, {} allocations, {} shared{forced}]",
+                stats.beta, stats.max_size, stats.allocations, stats.shared
+            );
+            sections.push((Section::Timing, stats));
+        }
+        sections
+    }
+}
+
+/// the `sections`, a line after each
+impl fmt::Display for Evaluated<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (_, section) in self.sections() {
+            writeln!(f, "{section}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::printer::Notation;
+    use crate::session::Session;
+
+    #[test]
+    pub fn views() {
+        let mut s = Session::new();
+        s.eval_str("two f x = f (f x)").unwrap();
+        let ev = s.evaluate("two").unwrap();
+        assert_eq!(ev.view("debruijn").as_deref(), Some("λ λ 1 (1 0)"));
+        assert_eq!(
+            ev.lifted().unwrap().to_string(),
+            "$0 x0 x1 = x0 (x0 x1)\nmain = $0"
+        );
+        assert_eq!(ev.numeral(), Some(2));
+        assert!(ev.view("nope").is_none());
+        let text = ev.text().unwrap();
+        assert!(std::rc::Rc::ptr_eq(&text, &ev.text().unwrap()));
+
+        assert_eq!(ev.to_string(), format!("{}\n", ev.line().unwrap()));
+
+        let ev = s.evaluate("K x y = x").unwrap();
+        assert_eq!(ev.to_string(), "");
+        assert!(ev.text().is_none());
+        assert!(ev.readings().is_empty());
+    }
+
+    #[test]
+    pub fn in_the_notation_set() {
+        let mut s = Session::new();
+        s.show = s.show.parse_inspired("steps").unwrap();
+        let shown = |s: &mut Session, notation| {
+            s.art.notation = notation;
+            let ev = s.evaluate("(fn x => x) (fn f x => f (f x))").unwrap();
+            let steps: Vec<_> = ev.sections().into_iter().map(|(_, t)| t).collect();
+            (ev.text().unwrap().to_string(), steps)
+        };
+        let (text, steps) = shown(&mut s, Notation::DeBruijn);
+        assert_eq!(text, "λ λ 1 (1 0)");
+        assert_eq!(steps, ["(λ 0) λ λ 1 (1 0)", "λ λ 1 (1 0)"]);
+        let (text, steps) = shown(&mut s, Notation::Fn);
+        assert!(text.starts_with("fn ") && text.contains(" => "), "{text}");
+        assert!(steps[0].starts_with("(fn "), "{}", steps[0]);
+        let (text, _) = shown(&mut s, Notation::Backslash);
+        assert!(text.starts_with('\\'), "{text}");
+    }
+
+    #[test]
+    pub fn redexes_labelled() {
+        let mut s = Session::new();
+        s.eval_str("I x = x").unwrap();
+        s.show = s.show.parse_inspired("redexes").unwrap();
+        let ev = s.evaluate("fn f y => f (I (I y))").unwrap();
+        let traced = ev.traced();
+        let redexes: Vec<_> = traced
+            .iter()
+            .map(|t| {
+                t.redex
+                    .map(|at| &t.term[at.offset()..at.offset() + at.len()])
+            })
+            .collect();
+        // the names of the binders are made up, so `y` is printed as something else
+        assert!(redexes[0].unwrap().starts_with("I (I "), "{redexes:?}");
+        assert!(redexes[1].unwrap().starts_with("I "), "{redexes:?}");
+        assert_eq!(redexes[2], None);
+        let sections = ev.sections();
+        assert!(
+            sections[0].1.contains("^^^^^^^ β-redex"),
+            "{}",
+            sections[0].1
+        );
+        assert!(sections[2].1.starts_with('λ'), "{}", sections[2].1);
+    }
+
+    #[test]
+    pub fn unit_round_trips() {
+        let mut s = Session::new();
+        s.set_typed(true).unwrap();
+        let line = s.evaluate("()").unwrap().line().unwrap();
+        assert_eq!(line, "() : unit");
+        let again = s.evaluate("fst (pair () unit)").unwrap().line().unwrap();
+        assert_eq!(again, line);
+    }
+}
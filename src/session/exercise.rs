@@ -0,0 +1,107 @@
+use crate::arts::TermIdx;
+use crate::session::{Output, Result, Session};
+
+/// what separates an expression from the normal form it's expected to reach
+pub const NORMALIZES_TO: &str = "normalizes-to";
+
+/// the sides of `<expr> normalizes-to <expr>`
+pub fn split(input: &str) -> Option<(&str, &str)> {
+    let (left, right) = input.split_once(NORMALIZES_TO)?;
+    // `normalizes-to` can't be glued to the expressions around it
+    let spaced = left.ends_with(char::is_whitespace) && right.starts_with(char::is_whitespace);
+    spaced.then(|| (left.trim(), right.trim()))
+}
+
+/// the normal form of an expression, next to the one it should be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Check {
+    pub normal: TermIdx,
+    pub expected: TermIdx,
+    /// whether they're α-equivalent
+    pub passed: bool,
+}
+
+/// a check of an exercise file, or a line which failed to run
+#[derive(Debug)]
+pub struct Checked<'a> {
+    /// counted from 1
+    pub line: usize,
+    pub src: &'a str,
+    /// `None` if a side was a program
+    pub result: Result<Option<Check>>,
+}
+
+impl Session {
+    /// reduces `left` and compares its normal form to the one of `right`. `right` is reduced as
+    /// well, since definitions as `two = succ one` aren't kept in normal form. Programs return
+    /// `None`
+    pub fn check(&mut self, left: &str, right: &str) -> Result<Option<Check>> {
+        let Output::Normal(normal) = self.eval_str(left)?.output else {
+            return Ok(None);
+        };
+        let Output::Normal(expected) = self.eval_str(right)?.output else {
+            return Ok(None);
+        };
+        let passed = self.art.alpha_eq(normal, expected);
+        Ok(Some(Check {
+            normal,
+            expected,
+            passed,
+        }))
+    }
+
+    /// runs an exercise file: every `<expr> normalizes-to <expr>` line is checked, and the others
+    /// are run as usual, so definitions are added to the session. Blank lines and comments are
+    /// skipped, and only the lines which are checked or fail show up
+    pub fn check_file<'a>(&mut self, content: &'a str) -> Vec<Checked<'a>> {
+        let mut checked = Vec::new();
+        for (line, src) in content.lines().enumerate() {
+            let trimmed = src.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let result = match split(src) {
+                Some((left, right)) => self.check(left, right),
+                None => match self.eval_str(src) {
+                    Ok(_) => continue,
+                    Err(e) => Err(e),
+                },
+            };
+            checked.push(Checked {
+                line: line + 1,
+                src,
+                result,
+            });
+        }
+        checked
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::session::Session;
+    use crate::session::exercise::split;
+
+    #[test]
+    pub fn checks_normal_forms() {
+        let mut s = Session::new();
+        s.set_prelude(true).unwrap();
+        let check = |s: &mut Session, src| {
+            let (left, right) = split(src).unwrap();
+            s.check(left, right).unwrap().map(|c| c.passed)
+        };
+        assert_eq!(check(&mut s, "K I normalizes-to fn a b => b"), Some(true));
+        assert_eq!(check(&mut s, "K I normalizes-to K"), Some(false));
+        assert_eq!(check(&mut s, "add one two normalizes-to three"), Some(true));
+        assert_eq!(check(&mut s, "x = K normalizes-to K"), None);
+        assert_eq!(split("a normalizes-tob"), None);
+
+        let file = "# sums\nsix = fn f x => f (f (f (f (f (f x)))))\n\nadd two (add two two) normalizes-to six\nsix normalizes-to three\nnope\n";
+        let checked = s.check_file(file);
+        let lines: Vec<_> = checked.iter().map(|c| c.line).collect();
+        assert_eq!(lines, [4, 5, 6]);
+        assert!(checked[0].result.as_ref().unwrap().unwrap().passed);
+        assert!(!checked[1].result.as_ref().unwrap().unwrap().passed);
+        assert!(checked[2].result.is_err());
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::arts::CompArtifact;
+use crate::cpu::Strategy;
+use crate::engine::Backend;
+use crate::ir::{self, IrCompiler};
+use crate::session::{Language, Session, modules::Module, settings::Setting};
+use crate::types::{Type, system_f};
+
+/// whatever an input can change in a session. Frontends, renderers and commands aren't in it, as
+/// only whoever embeds the session adds them
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    irc: IrCompiler,
+    art: CompArtifact,
+    bench: Setting,
+    show: Setting,
+    strategy: Strategy,
+    backend: Backend,
+    cps: bool,
+    language: Language,
+    system_f_types: HashMap<Box<str>, system_f::Type>,
+    syntax: usize,
+    typed: bool,
+    gradual: bool,
+    signatures: HashMap<ir::Id, Type>,
+    prelude: Vec<ir::Id>,
+    sources: HashMap<ir::Id, Box<str>>,
+    modules: HashMap<Box<str>, Module>,
+    readback: bool,
+    shadow_warnings: bool,
+    unused_warnings: bool,
+    shadowed_binder_warnings: bool,
+    timeout: Option<Duration>,
+    budget: Option<usize>,
+    max_size: Option<usize>,
+}
+
+impl Session {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            irc: self.irc.clone(),
+            art: self.art.clone(),
+            bench: self.bench.clone(),
+            show: self.show.clone(),
+            strategy: self.strategy,
+            backend: self.backend,
+            cps: self.cps,
+            language: self.language,
+            system_f_types: self.system_f_types.clone(),
+            syntax: self.syntax,
+            typed: self.typed,
+            gradual: self.gradual,
+            signatures: self.signatures.clone(),
+            prelude: self.prelude.clone(),
+            sources: self.sources.clone(),
+            modules: self.modules.clone(),
+            readback: self.readback,
+            shadow_warnings: self.shadow_warnings,
+            unused_warnings: self.unused_warnings,
+            shadowed_binder_warnings: self.shadowed_binder_warnings,
+            timeout: self.cancel.timeout,
+            budget: self.cancel.budget,
+            max_size: self.cancel.max_size,
+        }
+    }
+
+    /// goes back to `s`, returning how the session was
+    pub fn restore(&mut self, s: Snapshot) -> Snapshot {
+        let now = self.snapshot();
+        self.irc = s.irc;
+        self.art = s.art;
+        self.bench = s.bench;
+        self.show = s.show;
+        self.strategy = s.strategy;
+        self.backend = s.backend;
+        self.cps = s.cps;
+        self.language = s.language;
+        self.system_f_types = s.system_f_types;
+        self.syntax = s.syntax.min(self.frontends.len().saturating_sub(1));
+        self.typed = s.typed;
+        self.gradual = s.gradual;
+        self.signatures = s.signatures;
+        self.prelude = s.prelude;
+        self.sources = s.sources;
+        self.modules = s.modules;
+        self.readback = s.readback;
+        self.shadow_warnings = s.shadow_warnings;
+        self.unused_warnings = s.unused_warnings;
+        self.shadowed_binder_warnings = s.shadowed_binder_warnings;
+        self.cancel.timeout = s.timeout;
+        self.cancel.budget = s.budget;
+        self.cancel.max_size = s.max_size;
+        now
+    }
+}
+
+/// an input, with how the session was on the other side of it: before it for an undo, after it
+/// for a redo
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub input: Box<str>,
+    state: Snapshot,
+}
+
+/// the inputs that can be undone, and the undone ones that can be redone
+#[derive(Debug, Default)]
+pub struct History {
+    undo: Vec<Change>,
+    redo: Vec<Change>,
+}
+
+impl History {
+    /// `input` changed the session, which was as `before`. Nothing undone can be redone anymore
+    pub fn push(&mut self, input: &str, before: Snapshot) {
+        self.undo.push(Change {
+            input: input.into(),
+            state: before,
+        });
+        self.redo.clear();
+    }
+
+    /// reverts the last change to `session`, returning its input
+    pub fn undo(&mut self, session: &mut Session) -> Option<Box<str>> {
+        let Change { input, state } = self.undo.pop()?;
+        let after = session.restore(state);
+        self.redo.push(Change {
+            input: input.clone(),
+            state: after,
+        });
+        Some(input)
+    }
+
+    /// applies the last undone change to `session` again, returning its input
+    pub fn redo(&mut self, session: &mut Session) -> Option<Box<str>> {
+        let Change { input, state } = self.redo.pop()?;
+        let before = session.restore(state);
+        self.undo.push(Change {
+            input: input.clone(),
+            state: before,
+        });
+        Some(input)
+    }
+
+    pub fn undoable(&self) -> usize {
+        self.undo.len()
+    }
+
+    pub fn redoable(&self) -> usize {
+        self.redo.len()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::session::Session;
+    use crate::session::history::History;
+
+    #[test]
+    pub fn undo_redo() {
+        let mut s = Session::new();
+        let mut h = History::default();
+        let defined = |s: &Session, name| s.irc.scope.definitions.get(name).is_some();
+
+        s.eval_str("I x = x").unwrap();
+        let before = s.snapshot();
+        s.eval_str("K x y = x").unwrap();
+        h.push("K x y = x", before);
+        let before = s.snapshot();
+        s.readback = true;
+        h.push(":set readback on", before);
+
+        assert_eq!(h.undo(&mut s).as_deref(), Some(":set readback on"));
+        assert!(!s.readback && defined(&s, "K"));
+        assert_eq!(h.undo(&mut s).as_deref(), Some("K x y = x"));
+        assert!(!defined(&s, "K") && defined(&s, "I"));
+        assert!(h.undo(&mut s).is_none());
+        // `K` can be defined again, with another body
+        s.eval_str("K x y = y").unwrap();
+
+        let mut s = Session::new();
+        let mut h = History::default();
+        let before = s.snapshot();
+        s.eval_str("K x y = x").unwrap();
+        h.push("K x y = x", before);
+        h.undo(&mut s);
+        assert_eq!(h.redo(&mut s).as_deref(), Some("K x y = x"));
+        assert!(defined(&s, "K"));
+        assert_eq!((h.undoable(), h.redoable()), (1, 0));
+    }
+}
@@ -0,0 +1,172 @@
+use miette::{Diagnostic, Severity};
+use serde_json::{Map, Value, json};
+
+use crate::arts::{CompArtifact, Const, Term, TermIdx};
+use crate::session::{Output, evaluated::Evaluated};
+
+/// the result as a JSON object. As in the REPL, `Session::show` decides which stages are included
+pub fn eval_output(ev: &Evaluated) -> Value {
+    let (s, input, out) = (ev.session, ev.input, &ev.out);
+    let show = &s.show;
+    let mut obj = Map::new();
+    obj.insert("input".into(), input.into());
+    if show.is_on("lexer") {
+        let lexer = s.frontend().lexer();
+        let tokens = out
+            .tokens
+            .iter()
+            .map(|t| json!({ "type": lexer.get_type(t.item), "at": t.at, "text": t.from_code(input) }))
+            .collect();
+        obj.insert("tokens".into(), Value::Array(tokens));
+    }
+    if show.is_on("parser")
+        && let Some(ast) = &out.ast
+    {
+        obj.insert("ast".into(), json!(ast));
+    }
+    if show.is_on("ir")
+        && let Some(ir) = &out.ir
+    {
+        obj.insert("ir".into(), json!(ir));
+    }
+    if show.is_on("compiler") && out.compiled.is_some() {
+        obj.insert("pool".into(), json!(s.art));
+    }
+    if show.is_on("lifted")
+        && let Some(lifted) = ev.lifted()
+    {
+        obj.insert("lifted".into(), lifted.to_string().into());
+    }
+    if show.is_on("closures")
+        && let Some(converted) = ev.closures()
+    {
+        obj.insert("closures".into(), converted.to_string().into());
+    }
+    if show.is_on("steps") || show.is_on("steps_raw") {
+        let steps = out
+            .steps
+            .iter()
+            .map(|step| s.art.pretty(*step, ev.aliases()).into())
+            .collect();
+        obj.insert("steps".into(), Value::Array(steps));
+    }
+    if show.is_on("redexes") {
+        let traced = ev
+            .traced()
+            .into_iter()
+            .map(|t| match t.redex {
+                Some(at) => json!({
+                    "term": t.term,
+                    "redex": { "offset": at.offset(), "len": at.len(), "rule": t.rule },
+                }),
+                None => json!({ "term": t.term }),
+            })
+            .collect();
+        obj.insert("redexes".into(), Value::Array(traced));
+    }
+    let result = match out.output {
+        Output::Empty => json!({ "output": "empty" }),
+        Output::Defined => json!({ "output": "defined" }),
+        Output::Normal(nf) => json!({
+            "output": "normal",
+            "normal": ev.text().as_deref(),
+            "term": term(&s.art, nf),
+        }),
+    };
+    if ev.normal().is_some() && s.readback {
+        let values = ev.readings_view().into_iter().map(Value::from).collect();
+        obj.insert("readback".into(), Value::Array(values));
+    }
+    obj.insert("result".into(), result);
+    if let Some(ty) = &out.ty {
+        obj.insert("type".into(), ty.to_string().into());
+    } else if let Some(poly) = &out.poly {
+        obj.insert("type".into(), poly.to_string().into());
+    }
+    if !out.warnings.is_empty() {
+        let warnings = out.warnings.iter().map(|w| diagnostic(w)).collect();
+        obj.insert("diagnostics".into(), Value::Array(warnings));
+    }
+    if !out.timings.is_empty() {
+        let timings = out
+            .timings
+            .iter()
+            .map(|(label, elapsed)| json!({ "stage": label, "ns": elapsed.as_nanos() as u64 }))
+            .collect();
+        obj.insert("timings".into(), Value::Array(timings));
+    }
+    if let Some(stats) = &out.stats {
+        let stats = json!({
+            "beta": stats.beta,
+            "delta": stats.delta,
+            "max_size": stats.max_size,
+            "allocations": stats.allocations,
+            "shared": stats.shared,
+            "forced": stats.forced,
+        });
+        obj.insert("stats".into(), stats);
+    }
+    Value::Object(obj)
+}
+
+/// `{ input, diagnostics: [...] }`, for an input that failed
+pub fn error(input: &str, e: &dyn Diagnostic) -> Value {
+    json!({ "input": input, "diagnostics": [diagnostic(e)] })
+}
+
+/// `{ code, message, severity, help, labels: [{ label, at }] }`
+pub fn diagnostic(e: &dyn Diagnostic) -> Value {
+    let severity = match e.severity().unwrap_or(Severity::Error) {
+        Severity::Advice => "advice",
+        Severity::Warning => "warning",
+        Severity::Error => "error",
+    };
+    let labels: Vec<_> = e
+        .labels()
+        .into_iter()
+        .flatten()
+        .map(|l| json!({ "label": l.label(), "at": l.inner() }))
+        .collect();
+    json!({
+        "code": e.code().map(|c| c.to_string()),
+        "message": e.to_string(),
+        "severity": severity,
+        "help": e.help().map(|h| h.to_string()),
+        "labels": labels,
+    })
+}
+
+/// the term at `idx` as a tree, with de Bruijn indexes: `{"abs":{"var":0}}`
+pub fn term(art: &CompArtifact, idx: TermIdx) -> Value {
+    match art.get(idx) {
+        Term::Var(v) => json!({ "var": v.0 }),
+        Term::Const(Const::Int(n)) => json!({ "int": n }),
+        Term::Const(Const::Prim(p)) => json!({ "prim": p.name() }),
+        Term::Abs { inner } => json!({ "abs": term(art, inner) }),
+        Term::App(l, r) => json!({ "app": [term(art, l), term(art, r)] }),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::session::{Session, json};
+
+    #[test]
+    pub fn results_and_diagnostics() {
+        let mut s = Session::new();
+        let input = "fn x y => x";
+        let v = s.evaluate(input).unwrap().json();
+        assert_eq!(v["result"]["output"], "normal");
+        assert_eq!(
+            v["result"]["term"].to_string(),
+            r#"{"abs":{"abs":{"var":1}}}"#
+        );
+        assert!(v.get("tokens").is_none());
+
+        let input = "(fn x => x";
+        let e = s.eval_str(input).unwrap_err();
+        let v = json::error(input, &e);
+        assert_eq!(v["diagnostics"][0]["code"], "parser::unexpected_eof");
+        assert_eq!(v["diagnostics"][0]["severity"], "error");
+    }
+}
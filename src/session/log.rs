@@ -0,0 +1,114 @@
+use std::fmt;
+
+/// an input that changed the session, as it was typed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub input: Box<str>,
+    /// what it read from outside the session, like the script of a `:load`. A replay uses this
+    /// instead, so it doesn't depend on files that may have changed since
+    pub expansion: Vec<Box<str>>,
+}
+
+/// every event of a session, in order. Running them again on a new session gets to the same one,
+/// since nothing else changes it
+///
+/// As text, it's one event per line, with the expansion of each one indented under it and `#`
+/// starting comments
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EventLog {
+    pub events: Vec<Event>,
+    /// while above 0, nothing is recorded, as the inputs come from an event already recorded
+    paused: usize,
+}
+
+impl EventLog {
+    pub fn record(&mut self, input: &str) {
+        if self.paused == 0 {
+            self.events.push(Event {
+                input: input.trim_start().into(),
+                expansion: Vec::new(),
+            });
+        }
+    }
+
+    /// sets what the last event read from outside
+    pub fn expand<'a>(&mut self, lines: impl IntoIterator<Item = &'a str>) {
+        if self.paused == 0
+            && let Some(last) = self.events.last_mut()
+        {
+            last.expansion = lines.into_iter().map(Into::into).collect();
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.paused += 1;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = self.paused.saturating_sub(1);
+    }
+
+    /// whether inputs run now are part of one already recorded
+    pub fn is_paused(&self) -> bool {
+        self.paused > 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut log = Self::default();
+        for line in text.lines() {
+            let input = line.trim_start();
+            match log.events.last_mut() {
+                // an expansion keeps its blank lines and comments, as it's a script
+                Some(last) if line.starts_with("  ") => last.expansion.push(line[2..].into()),
+                _ if input.is_empty() || input.starts_with('#') => (),
+                _ => log.record(input),
+            }
+        }
+        log
+    }
+}
+
+impl fmt::Display for EventLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# qk {} session log", env!("CARGO_PKG_VERSION"))?;
+        for e in &self.events {
+            writeln!(f, "{}", e.input)?;
+            for line in &e.expansion {
+                writeln!(f, "  {line}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::session::log::EventLog;
+
+    #[test]
+    pub fn round_trip() {
+        let mut log = EventLog::default();
+        log.record("I x = x");
+        log.record(":load lib.lc");
+        log.expand("K x y = x\n\n# typed\n  :set typed on".lines());
+        log.pause();
+        log.record("K x y = x");
+        log.resume();
+        log.record("  K I");
+        assert_eq!(log.len(), 3);
+        assert_eq!(log.events[1].expansion.len(), 4);
+        assert_eq!(&*log.events[2].input, "K I");
+
+        let text = log.to_string();
+        assert!(text.contains("\n    :set typed on\n"));
+        assert_eq!(EventLog::parse(&text), log);
+    }
+}
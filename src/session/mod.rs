@@ -0,0 +1,1679 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use miette::{Diagnostic, SourceSpan};
+use smallvec::SmallVec;
+use thiserror::Error;
+
+use crate::arts::{CompArtifact, Term, TermIdx};
+use crate::ast::{Ast, Node};
+use crate::backend::{self, Renderer};
+use crate::bohm::{self, Bohm};
+use crate::cancel::{self, CancelToken};
+use crate::cbpv;
+use crate::compiler::CodeUnit;
+use crate::cps;
+use crate::cpu::{self, Cpu, Normal, Reductor};
+use crate::engine::{self, Backend};
+use crate::formatter;
+use crate::frontend::{self, Frontend};
+use crate::graph::Graph;
+use crate::ir::{self, IrCompiler, IrComponent, IrObj};
+use crate::lexer::{self, Trace};
+use crate::lint;
+use crate::nets::{self, Net};
+use crate::padam;
+use crate::readback::{self, Value};
+use crate::separate;
+use crate::source_map::SourceMap;
+use crate::types::{
+    self, Type,
+    infer::Typing,
+    primitives::{FIX, PRIMITIVES},
+    system_f::{self, Checker},
+};
+
+use evaluated::Evaluated;
+use prelude::PRELUDE;
+use settings::Setting;
+
+pub mod bench;
+pub mod commands;
+pub mod evaluated;
+pub mod exercise;
+pub mod history;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod log;
+pub mod modules;
+pub mod prelude;
+pub mod profiles;
+pub mod settings;
+pub mod trace;
+
+pub const BENCH_SETTING: Setting = Setting {
+    all: &[
+        "lexer", "parser", "command", "ir", "types", "compiler", "steps", "normal", "eval",
+    ],
+    on: SmallVec::new_const(),
+};
+
+pub const SHOW_SETTING: Setting = Setting {
+    all: &[
+        "lexer",
+        "parser",
+        "command",
+        "ir",
+        "compiler",
+        "lifted",
+        "closures",
+        "steps",
+        "steps_raw",
+        "redexes",
+        "normal",
+    ],
+    on: SmallVec::new_const(),
+};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// the terms a reduction went through, and the redex contracted in each but the last
+pub type Steps = (Vec<TermIdx>, Vec<Option<TermIdx>>);
+
+#[derive(Error, Diagnostic, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Lexer(#[from] crate::lexer::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Parser(#[from] crate::padam::parser::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    IrCompiler(#[from] crate::ir::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Compiler(#[from] crate::compiler::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Types(#[from] crate::types::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Cancelled(#[from] cancel::Cancelled),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Separate(#[from] separate::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Modules(#[from] modules::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Engine(engine::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Cps(#[from] cps::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    SystemF(#[from] system_f::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Cbpv(cbpv::Error),
+
+    #[error("effects can't be stepped here")]
+    #[diagnostic(
+        code(session::effect),
+        help("the reduction asked for an effect, which only a runner can perform")
+    )]
+    Effect,
+
+    #[error("`{found}` isn't a function")]
+    #[diagnostic(
+        code(session::cast_failed),
+        help("it went through `dyn`, so it's only checked as it's used as a function")
+    )]
+    CastFailed {
+        found: String,
+        /// the definition the cast is in, which the span is of. `None` if it's in the input
+        #[source_code]
+        def: Option<String>,
+        #[label("expected a function here")]
+        at: Option<SourceSpan>,
+    },
+}
+
+impl From<cbpv::Error> for Error {
+    fn from(e: cbpv::Error) -> Self {
+        match e {
+            cbpv::Error::Cancelled(c) => Self::Cancelled(c),
+            e => Self::Cbpv(e),
+        }
+    }
+}
+
+impl From<engine::Error> for Error {
+    fn from(e: engine::Error) -> Self {
+        match e {
+            engine::Error::Cancelled(c) => Self::Cancelled(c),
+            e => Self::Engine(e),
+        }
+    }
+}
+
+/// an expression reduced as an interaction net, next to its normal order reduction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Optimal {
+    /// `None` if the net didn't read back as a term
+    pub normal: Option<TermIdx>,
+    pub stats: nets::Stats,
+    /// the β-steps of normal order
+    pub normal_order: usize,
+}
+
+/// an expression reduced with applicative order, next to its standard reduction. Each is every
+/// term it went through to its normal form, or how it failed
+#[derive(Debug)]
+pub struct Standardized {
+    pub given: Result<Vec<TermIdx>>,
+    pub standard: Result<Vec<TermIdx>>,
+}
+
+/// a context `[] A..` telling two expressions apart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Separation {
+    pub args: Vec<TermIdx>,
+    /// the normal forms of the expressions inside the context: `true` and `false`
+    pub left: TermIdx,
+    pub right: TermIdx,
+}
+
+/// the language inputs are written in
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    /// the untyped λ-calculus of the current syntax, maybe type checked with `typed`
+    #[default]
+    Untyped,
+    /// `system-f`: the polymorphic λ-calculus, type checked and then erased to the untyped one
+    SystemF,
+}
+
+impl Language {
+    pub const ALL: &[Self] = &[Self::Untyped, Self::SystemF];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Untyped => "untyped",
+            Self::SystemF => "system-f",
+        }
+    }
+}
+
+impl TryFrom<&str> for Language {
+    type Error = ();
+
+    fn try_from(value: &str) -> std::result::Result<Self, Self::Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|l| l.name() == value)
+            .ok_or(())
+    }
+}
+
+/// what an input did to the session
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    /// there was nothing to run
+    #[default]
+    Empty,
+
+    /// the input was a program. Its definitions are now in the scope
+    Defined,
+
+    /// the input was an expression, reduced to this normal form inside `Session::art`
+    Normal(TermIdx),
+}
+
+/// everything produced while running an input. It's up to the caller what to render
+#[derive(Debug, Default, Clone)]
+pub struct EvalOutput {
+    pub tokens: Vec<padam::Token>,
+    pub ast: Option<Node>,
+    pub ir: Option<IrObj>,
+    /// the compiled expression, before any reduction. Lives in `Session::art`
+    pub compiled: Option<TermIdx>,
+    /// the type of the expression, in typed mode
+    pub ty: Option<Type>,
+    /// the type of the expression, in `system-f`
+    pub poly: Option<system_f::Type>,
+    /// every term the reduction went through, when `show steps`, `steps_raw` or `redexes` is on
+    pub steps: Vec<TermIdx>,
+    /// the redex contracted in each of the `steps` but the last, inside it. `None` when the
+    /// step only forced a thunk, or the backend has no steps in between
+    pub redexes: Vec<Option<TermIdx>>,
+    pub output: Output,
+    /// how long each stage in `bench` took
+    pub timings: Vec<(&'static str, Duration)>,
+    /// what the reduction cost, when `bench eval` is on
+    pub stats: Option<cpu::Stats>,
+    /// what the lints found. Those about terms only run outside of typed mode
+    pub warnings: Vec<lint::Warning>,
+}
+
+/// everything needed to run qk code, without any terminal attached to it
+#[derive(Debug)]
+pub struct Session {
+    pub irc: IrCompiler,
+    pub art: CompArtifact,
+    pub bench: Setting,
+    pub show: Setting,
+    /// how inputs are reduced to their normal forms
+    pub strategy: cpu::Strategy,
+    /// what runs the strategy, or replaces it
+    pub backend: Backend,
+    pub language: Language,
+    /// the types of the definitions made in `system-f`
+    pub system_f_types: HashMap<Box<str>, system_f::Type>,
+    /// compiles inputs in continuation-passing style. Definitions made without it aren't, so
+    /// they can't be called from those made with it
+    pub cps: bool,
+    pub timings: Vec<(&'static str, Duration)>,
+    pub frontends: Vec<Box<dyn Frontend>>,
+    /// index of the current syntax in `frontends`
+    pub syntax: usize,
+    pub renderers: Vec<Box<dyn Renderer>>,
+    /// type check every input before running it
+    pub typed: bool,
+    /// in typed mode, allow `dyn` in the annotations, checking the values that go through it
+    /// when the term runs
+    pub gradual: bool,
+    /// declared types of definitions, such as the typed primitives
+    pub signatures: HashMap<ir::Id, Type>,
+    /// the definitions loaded by the prelude
+    pub prelude: Vec<ir::Id>,
+    /// the source of each definition, as it was written
+    pub sources: HashMap<ir::Id, Box<str>>,
+    /// warn when a definition replaces one from an earlier input
+    pub shadow_warnings: bool,
+    /// warn about the variables bound and never used
+    pub unused_warnings: bool,
+    /// warn about the binders that hide an outer one of the same name
+    pub shadowed_binder_warnings: bool,
+    /// the files imported, by the name they were imported as
+    pub modules: HashMap<Box<str>, modules::Module>,
+    /// show the Church-encoded data in normal forms, like numerals and booleans
+    pub readback: bool,
+    /// stops a run, from its limits or from anyone holding a clone of it. Each run gets its own
+    /// `CancelToken::start`
+    pub cancel: CancelToken,
+    /// the `:` commands added by whoever embeds the session
+    pub commands: commands::CommandRegistry,
+    /// the inputs that changed the session, to replay it. Whoever runs them records them
+    pub log: log::EventLog,
+    /// every input given, as typed or as the file it was read from, for diagnostics to point
+    /// into. Whoever runs them adds them
+    pub inputs: SourceMap,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self {
+            irc: IrCompiler::default(),
+            art: CompArtifact::default(),
+            bench: BENCH_SETTING,
+            show: SHOW_SETTING.parse_inspired("normal").unwrap(),
+            strategy: cpu::Strategy::default(),
+            backend: Backend::default(),
+            cps: false,
+            language: Language::default(),
+            system_f_types: HashMap::new(),
+            timings: Vec::new(),
+            frontends: frontend::defaults(),
+            syntax: 0,
+            renderers: backend::defaults(),
+            typed: false,
+            signatures: HashMap::new(),
+            prelude: Vec::new(),
+            sources: HashMap::new(),
+            shadow_warnings: true,
+            unused_warnings: true,
+            shadowed_binder_warnings: true,
+            modules: HashMap::new(),
+            readback: false,
+            cancel: CancelToken::default(),
+            gradual: false,
+            commands: commands::CommandRegistry::default(),
+            log: log::EventLog::default(),
+            inputs: SourceMap::default(),
+        }
+    }
+
+    pub fn renderer(&self, name: &str) -> Option<&dyn Renderer> {
+        self.renderers
+            .iter()
+            .find(|r| r.name() == name)
+            .map(|r| r.as_ref())
+    }
+
+    /// makes `r` available to `renderer`. Replaces a renderer with the same name
+    pub fn register_renderer(&mut self, r: Box<dyn Renderer>) {
+        match self.renderers.iter().position(|o| o.name() == r.name()) {
+            Some(i) => self.renderers[i] = r,
+            None => self.renderers.push(r),
+        }
+    }
+
+    pub fn frontend(&self) -> &dyn Frontend {
+        self.frontends[self.syntax].as_ref()
+    }
+
+    /// makes `f` available to `set_syntax`. Replaces a front-end with the same name
+    pub fn register_frontend(&mut self, f: Box<dyn Frontend>) {
+        match self.frontends.iter().position(|o| o.name() == f.name()) {
+            Some(i) => self.frontends[i] = f,
+            None => self.frontends.push(f),
+        }
+    }
+
+    /// returns `false` if there's no such syntax
+    pub fn set_syntax(&mut self, name: &str) -> bool {
+        self.frontends
+            .iter()
+            .position(|f| f.name() == name)
+            .map(|i| self.syntax = i)
+            .is_some()
+    }
+
+    /// the front-end responsible for files ending with `ext`
+    pub fn syntax_for_extension(&self, ext: &str) -> Option<usize> {
+        self.frontends
+            .iter()
+            .position(|f| f.extensions().contains(&ext))
+    }
+
+    /// same as `eval_str`, but the result can be looked at in any format
+    pub fn evaluate<'s>(&'s mut self, input: &'s str) -> Result<Evaluated<'s>> {
+        let out = self.eval_str(input)?;
+        Ok(Evaluated::new(self, input, out))
+    }
+
+    /// runs a snippet of code: a program gets its definitions added to the session, while an
+    /// expression gets reduced to its normal form
+    pub fn eval_str(&mut self, input: &str) -> Result<EvalOutput> {
+        self.timings.clear();
+        let mut token = self.cancel.start();
+        let mut out = EvalOutput::default();
+        // the tokens are only kept around if someone wants to see or time them
+        let streamed =
+            !self.show.is_on("lexer") && !self.bench.is_on("lexer") && !self.bench.is_on("parser");
+        let ast = if self.language == Language::SystemF {
+            let typed = self.system_f(input)?;
+            typed.map(|(ast, poly)| {
+                out.poly = poly;
+                ast
+            })
+        } else if streamed {
+            self.parse_stream(input)?
+        } else {
+            out.tokens = self.lexer(input)?;
+            token.check()?;
+            match out.tokens.is_empty() {
+                true => None,
+                false => Some(self.parse(&out.tokens, input)?),
+            }
+        };
+        if let Some(ast) = ast {
+            out.ast = Some(ast.clone());
+            token.check()?;
+            let shadowed = match self.shadow_warnings {
+                true => self.shadowed(&ast, input),
+                false => Vec::new(),
+            };
+            let mut binders = Vec::new();
+            if self.unused_warnings {
+                binders.extend(lint::unused_variables(&ast, input));
+            }
+            if self.shadowed_binder_warnings {
+                binders.extend(lint::shadowed_variables(&ast, input));
+            }
+            if let Some(mut expr) = self.ir(ast, input)? {
+                token.check()?;
+                if self.simply_typed() {
+                    let (ty, casts) = self.bench("types", |s| s.type_check(&expr))?;
+                    types::infer::insert_casts(&mut expr, &casts);
+                    out.ty = Some(ty);
+                } else {
+                    out.warnings = lint::over_applications(&self.irc.scope, &expr);
+                }
+                out.warnings.extend(binders);
+                out.ir = Some(expr.clone());
+                token.check()?;
+                out.compiled = Some(self.compile(expr, input)?);
+                let (normal, (steps, redexes), stats) = self.cpu(&mut token)?;
+                out.steps = steps;
+                out.redexes = redexes;
+                out.stats = self.bench.is_on("eval").then_some(stats);
+                out.output = Output::Normal(normal);
+            } else {
+                if self.simply_typed() {
+                    self.bench("types", |s| {
+                        s.check_definitions(out.ast.as_ref().unwrap(), input)
+                    })?;
+                } else {
+                    let scope = &self.irc.scope;
+                    for name in defined_names(out.ast.as_ref().unwrap(), input) {
+                        let def = &scope.res_pool[scope.definitions[name].0];
+                        out.warnings.extend(lint::over_applications(scope, def));
+                    }
+                }
+                for name in defined_names(out.ast.as_ref().unwrap(), input) {
+                    self.keep_source(name, input);
+                }
+                out.warnings.splice(0..0, shadowed);
+                out.warnings.extend(binders);
+                out.output = Output::Defined;
+            }
+        }
+        out.timings = std::mem::take(&mut self.timings);
+        Ok(out)
+    }
+
+    pub fn lexer(&mut self, src: &str) -> Result<Vec<padam::Token>> {
+        let lexer = self.frontend().lexer();
+        Ok(self.bench("lexer", |s| {
+            lexer.lex_interned(src, &mut s.irc.scope.definitions.symbols)
+        })?)
+    }
+
+    /// lexes and parses `src` together, without collecting its tokens. `None` if there are none
+    pub fn parse_stream(&mut self, src: &str) -> Result<Option<Node>> {
+        let frontend = self.frontends[self.syntax].as_ref();
+        let lexer = frontend.lexer();
+        let symbols = &mut self.irc.scope.definitions.symbols;
+        let mut tokens = lexer.tokens(src).inspect(|t| {
+            if let Ok(t) = t {
+                lexer.intern(t, src, symbols);
+            }
+        });
+        Ok(frontend.parse_stream(&lexer, &mut tokens)?)
+    }
+
+    pub fn parse(&mut self, tokens: &[padam::Token], _src: &str) -> Result<Node> {
+        let lexer = self.frontend().lexer();
+        Ok(self.bench("parser", |s| s.frontend().parse(&lexer, tokens))?)
+    }
+
+    pub fn ir(&mut self, ast: crate::ast::Node, src: &str) -> Result<Option<crate::ir::IrObj>> {
+        self.bench("ir", |s| -> Result<_> {
+            // the names it binds are read from the end of the source
+            let extended;
+            let (ast, src) = match s.cps {
+                true => {
+                    let mut cps_src = src.to_string();
+                    let ast = cps::stage(ast, &mut cps_src)?;
+                    extended = cps_src;
+                    (ast, extended.as_str())
+                }
+                false => (ast, src),
+            };
+            if matches!(ast.item, crate::ast::Ast::Program(..)) {
+                // the definitions of earlier inputs can be overridden, and are back if this one
+                // fails
+                let mut overridden = Vec::new();
+                for name in defined_names(&ast, src) {
+                    let scope = &mut s.irc.scope;
+                    if let Some(id) = scope.definitions.get(name)
+                        && scope.res_pool[id.0].item != IrComponent::Pending
+                    {
+                        overridden.push((name, scope.definitions.remove(name).unwrap()));
+                    }
+                }
+                if let Err(e) = s.irc.compile_program(*ast, src) {
+                    for (name, id) in overridden {
+                        s.irc.scope.definitions.insert(name, id);
+                    }
+                    return Err(e.into());
+                }
+                let gone: Vec<_> = overridden.into_iter().map(|(_, id)| id).collect();
+                s.prelude.retain(|p| !gone.contains(p));
+                Ok(None)
+            } else {
+                Ok(Some(s.irc.compile(*ast, src)?))
+            }
+        })
+    }
+
+    /// whether inputs are checked with simple types. System F has its own checker, and many
+    /// of its terms have no simple type
+    fn simply_typed(&self) -> bool {
+        self.typed && self.language == Language::Untyped
+    }
+
+    /// parses and type checks an input in System F, erasing it to the untyped AST. Definitions
+    /// keep their types for the inputs after, and an expression returns its own. `None` if
+    /// there's nothing
+    pub fn system_f(&mut self, src: &str) -> Result<Option<(Node, Option<system_f::Type>)>> {
+        let input = self.bench("parser", |_| system_f::parse(src))?;
+        let checked = self.bench("types", |s| -> system_f::Result<_> {
+            let Some(input) = input else {
+                return Ok(None);
+            };
+            Ok(Some(match input {
+                system_f::Input::Expr(term) => {
+                    let ty = Checker::new(src, &s.system_f_types).check(&term)?;
+                    (system_f::erase(*term), Some(ty))
+                }
+                system_f::Input::Defs(defs) => {
+                    let mut types = s.system_f_types.clone();
+                    let mut steps = Vec::new();
+                    for (ident, body) in defs {
+                        let ty = Checker::new(src, &types).check(&body)?;
+                        types.insert(lexer::from_code(ident, src).into(), ty);
+                        let at = lexer::over(ident, body.at);
+                        let body = system_f::erase(*body);
+                        let def = Ast::Def {
+                            ident,
+                            params: Vec::new(),
+                            body,
+                        };
+                        steps.push(def.at(at));
+                    }
+                    s.system_f_types = types;
+                    let at = lexer::over(steps[0].at, steps[steps.len() - 1].at);
+                    (Ast::Program(steps).at(at), None)
+                }
+            }))
+        })?;
+        Ok(checked)
+    }
+
+    /// turning it on also defines the typed primitives that aren't defined yet
+    pub fn set_typed(&mut self, on: bool) -> Result<()> {
+        if on {
+            for p in PRIMITIVES {
+                if let Some(id) = self.irc.scope.definitions.get(p.name)
+                    && self.irc.scope.res_pool[id.0].item != IrComponent::Pending
+                {
+                    continue;
+                }
+                let id = self.define(p.name, p.body)?;
+                self.signatures.insert(id, Type::parse(p.ty)?);
+            }
+        }
+        self.typed = on;
+        Ok(())
+    }
+
+    /// defines `fix : (a -> a) -> a`, so recursive programs can be type checked. Turning it off
+    /// keeps the typed fragment strongly normalizing
+    pub fn set_fix(&mut self, on: bool) -> Result<()> {
+        let current = self
+            .irc
+            .scope
+            .definitions
+            .get(FIX.name)
+            .filter(|id| self.signatures.contains_key(id))
+            .copied();
+        match (on, current) {
+            (true, None) => {
+                let id = self.define(FIX.name, FIX.body)?;
+                self.signatures.insert(id, Type::parse(FIX.ty)?);
+                self.irc.fix = Some(id);
+            }
+            (false, Some(id)) => {
+                self.signatures.remove(&id);
+                self.irc.scope.definitions.remove(FIX.name);
+                self.irc.fix = None;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// checks the definition of `name` against the declared type `ty`, which then becomes its
+    /// signature. A definition can be more general than declared, but never less. A mismatch
+    /// points into `ty`
+    pub fn declare(&mut self, name: &str, ty: &str) -> Result<()> {
+        let declared = Type::parse(ty)?;
+        let scope = &self.irc.scope;
+        let Some(&id) = scope
+            .definitions
+            .get(name)
+            .filter(|id| scope.res_pool[id.0].item != IrComponent::Pending)
+        else {
+            return Err(types::Error::Undefined { name: name.into() }.into());
+        };
+        let inferred = self.type_of(&scope.res_pool[id.0])?;
+        if !declared.instance_of(&inferred) {
+            let e = types::Error::SignatureMismatch {
+                name: name.into(),
+                declared: declared.into(),
+                inferred: inferred.into(),
+                declared_at: Some((0, ty.len()).into()),
+                defined_at: None,
+            };
+            return Err(e.into());
+        }
+        self.signatures.insert(id, declared);
+        Ok(())
+    }
+
+    /// loads or unloads the prelude. Loading keeps whatever is already defined under the same
+    /// name, and unloading keeps whatever was defined over the prelude
+    pub fn set_prelude(&mut self, on: bool) -> Result<()> {
+        if !on {
+            let prelude = std::mem::take(&mut self.prelude);
+            self.irc
+                .scope
+                .definitions
+                .retain(|_, id| !prelude.contains(id));
+            self.signatures.retain(|id, _| !prelude.contains(id));
+            // the pairs may be gone, so the typed primitives are brought back
+            if self.typed {
+                self.set_typed(true)?;
+            }
+            return Ok(());
+        }
+        for d in PRELUDE {
+            if let Some(id) = self.irc.scope.definitions.get(d.name)
+                && self.irc.scope.res_pool[id.0].item != IrComponent::Pending
+            {
+                continue;
+            }
+            let id = self.define(d.name, d.body)?;
+            if let Some(p) = PRIMITIVES.iter().find(|p| p.name == d.name) {
+                self.signatures.insert(id, Type::parse(p.ty)?);
+            }
+            self.prelude.push(id);
+        }
+        Ok(())
+    }
+
+    /// removes the definition of `name`. What already refers to it keeps it
+    pub fn undefine(&mut self, name: &str) -> Result<ir::Id> {
+        let scope = &mut self.irc.scope;
+        let Some(id) = scope
+            .definitions
+            .get(name)
+            .copied()
+            .filter(|id| scope.res_pool[id.0].item != IrComponent::Pending)
+        else {
+            return Err(ir::Error::Undefined { name: name.into() }.into());
+        };
+        scope.definitions.remove(name);
+        self.signatures.remove(&id);
+        self.sources.remove(&id);
+        self.prelude.retain(|p| *p != id);
+        for m in self.modules.values_mut() {
+            m.names.retain(|n| **n != *name);
+        }
+        Ok(id)
+    }
+
+    /// removes every definition but the typed primitives, and the prelude's if `keep_prelude`.
+    /// The settings stay as they are
+    pub fn clear_definitions(&mut self, keep_prelude: bool) {
+        if !keep_prelude {
+            self.prelude.clear();
+        }
+        let builtin = |name: &str| PRIMITIVES.iter().any(|p| p.name == name) || name == FIX.name;
+        let (prelude, signatures) = (&self.prelude, &self.signatures);
+        self.irc.scope.definitions.retain(|name, id| {
+            prelude.contains(id) || (builtin(name) && signatures.contains_key(id))
+        });
+        let kept: Vec<_> = self
+            .irc
+            .scope
+            .definitions
+            .iter()
+            .map(|(_, id)| *id)
+            .collect();
+        self.signatures.retain(|id, _| kept.contains(id));
+        self.sources.retain(|id, _| kept.contains(id));
+        self.modules.clear();
+    }
+
+    /// defines `name` as `body`, written in the `qk` syntax whatever the current one is
+    pub fn define(&mut self, name: &str, body: &str) -> Result<ir::Id> {
+        let src = format!("{name} = {body}");
+        let lexer = frontend::Qk.lexer();
+        let tokens = lexer.lex(&src)?;
+        let ast = frontend::Qk.parse(&lexer, &tokens)?;
+        self.irc.compile_program(*ast, &src)?;
+        Ok(self.keep_source(name, &src))
+    }
+
+    /// a warning for each definition of `program` replacing one from an earlier input
+    fn shadowed(&self, program: &Node, src: &str) -> Vec<lint::Warning> {
+        let Ast::Program(defs) = &program.item else {
+            return Vec::new();
+        };
+        let scope = &self.irc.scope;
+        defs.iter()
+            .filter_map(|d| match &d.item {
+                Ast::Def { ident, .. } => {
+                    let name = lexer::from_code(*ident, src);
+                    let id = scope.definitions.get(name)?;
+                    let previous = match &scope.res_pool[id.0].item {
+                        IrComponent::Pending => return None,
+                        _ => self.sources.get(id).cloned().unwrap_or_else(|| name.into()),
+                    };
+                    Some(lint::Warning::ShadowedDefinition {
+                        at: *ident,
+                        name: name.into(),
+                        previous,
+                    })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn keep_source(&mut self, name: &str, src: &str) -> ir::Id {
+        let id = self.irc.scope.definitions[name];
+        let def = lexer::from_code(self.irc.scope.res_pool[id.0].at, src);
+        self.sources.insert(id, def.into());
+        id
+    }
+
+    /// the declared type of the definition `id`, or else the inferred one, if there's any
+    pub fn definition_type(&self, id: ir::Id) -> Option<Type> {
+        if let Some(ty) = self.signatures.get(&id) {
+            return Some(ty.clone());
+        }
+        self.type_of(&self.irc.scope.res_pool[id.0]).ok()
+    }
+
+    fn type_of(&self, expr: &IrObj) -> Result<Type> {
+        self.type_check(expr).map(|(ty, _)| ty)
+    }
+
+    /// the type of `expr`, and the casts it needs where `dyn` values are used as functions
+    fn type_check(&self, expr: &IrObj) -> Result<(Type, Vec<types::infer::Cast>)> {
+        let scope = &self.irc.scope;
+        Ok(types::infer::infer_casts(
+            scope,
+            &self.signatures,
+            expr,
+            self.gradual,
+        )?)
+    }
+
+    fn infer_ir(&self, expr: &IrObj) -> types::Result<Typing> {
+        let scope = &self.irc.scope;
+        if self.gradual {
+            types::infer::infer_gradual(scope, &self.signatures, expr)
+        } else {
+            types::infer::infer(scope, &self.signatures, expr)
+        }
+    }
+
+    /// type checks the definitions of `program`, inserting the casts they need. If any fails,
+    /// all of them are undone
+    fn check_definitions(&mut self, program: &Node, src: &str) -> Result<()> {
+        let names = defined_names(program, src);
+        let scope = &self.irc.scope;
+        let checked = names
+            .iter()
+            .map(|name| {
+                let id = scope.definitions[*name];
+                self.type_check(&scope.res_pool[id.0])
+                    .map(|(_, casts)| (id, casts))
+            })
+            .collect::<Result<Vec<_>>>();
+        match checked {
+            Ok(checked) => {
+                for (id, casts) in checked {
+                    types::infer::insert_casts(&mut self.irc.scope.res_pool[id.0], &casts);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                names.iter().for_each(|name| {
+                    self.irc.scope.definitions.remove(name);
+                });
+                Err(e)
+            }
+        }
+    }
+
+    /// the simple type of an expression, without running it. Programs have no type, so they
+    /// return `None`
+    pub fn infer(&mut self, src: &str) -> Result<Option<Typing>> {
+        let tokens = self.lexer(src)?;
+        let ast = self.parse(&tokens, src)?;
+        let subterms = self.subterms(&ast, src);
+        let Some(expr) = self.ir(ast, src)? else {
+            return Ok(None);
+        };
+        let mut typing = self.infer_ir(&expr)?;
+        for step in &mut typing.steps {
+            step.term = match subterms.get(&(step.at.offset(), step.at.len())) {
+                Some(term) => term.clone(),
+                None => lexer::from_code(step.at, src).to_string(),
+            };
+        }
+        Ok(Some(typing))
+    }
+
+    /// each node of `ast` printed, by its span. An abstraction inside another starts at its
+    /// parameter, so its source alone isn't a term
+    fn subterms(&self, ast: &Node, src: &str) -> HashMap<(usize, usize), String> {
+        let notation = self.frontend().notation();
+        let mut printed = HashMap::new();
+        let mut stack = vec![ast];
+        while let Some(node) = stack.pop() {
+            printed
+                .entry((node.at.offset(), node.at.len()))
+                .or_insert_with(|| formatter::print(node, src, notation));
+            stack.extend(node.item.children());
+        }
+        printed
+    }
+
+    /// the reduction graph of an expression, `depth` reductions deep. Programs have none, so
+    /// they return `None`
+    pub fn graph(&mut self, src: &str, depth: usize) -> Result<Option<Graph>> {
+        let mut token = self.cancel.start();
+        let Some(root) = self.compile_expr(src)? else {
+            return Ok(None);
+        };
+        let mut cpu = Cpu::new(std::mem::take(&mut self.art));
+        let g = Graph::explore(&mut cpu, root, depth, &mut token);
+        self.art = cpu.art;
+        Ok(Some(g?))
+    }
+
+    /// approximates the Böhm tree of an expression, `depth` levels deep. Each head gets the
+    /// `budget` of head reductions, or `bohm::HEAD_FUEL`. Programs return `None`
+    pub fn bohm(&mut self, src: &str, depth: usize) -> Result<Option<Bohm>> {
+        let token = self.cancel.start();
+        let Some(root) = self.compile_expr(src)? else {
+            return Ok(None);
+        };
+        let fuel = self.cancel.budget.unwrap_or(bohm::HEAD_FUEL);
+        let mut cpu = Cpu::new(std::mem::take(&mut self.art));
+        let tree = Bohm::approximate(&mut cpu, root, depth, fuel, &token);
+        self.art = cpu.art;
+        Ok(Some(tree?))
+    }
+
+    /// finds a context sending the normal form of `left` to `true` and the one of `right` to
+    /// `false`, and checks it does. Programs return `None`
+    pub fn separate(&mut self, left: &str, right: &str) -> Result<Option<Separation>> {
+        let (Some(l), Some(r)) = (self.compile_expr(left)?, self.compile_expr(right)?) else {
+            return Ok(None);
+        };
+        let mut token = self.cancel.start();
+        let mut cpu = Cpu::new(std::mem::take(&mut self.art));
+        let nfs = Normal::reduce_until(&mut cpu, l, &mut token)
+            .and_then(|l| Ok((l, Normal::reduce_until(&mut cpu, r, &mut token)?)));
+        let found = nfs.map(|(l, r)| separate::separate(&mut cpu.art, l, r));
+        let checked = match found {
+            Ok(Ok(args)) => {
+                let mut apply = |root| {
+                    let root = args
+                        .iter()
+                        .fold(root, |f, a| cpu.art.push(Term::App(f, *a)));
+                    Normal::reduce_until(&mut cpu, root, &mut token)
+                };
+                apply(l).and_then(|left| Ok((args.clone(), left, apply(r)?)))
+            }
+            Ok(Err(e)) => {
+                self.art = cpu.art;
+                return Err(e.into());
+            }
+            Err(e) => Err(e),
+        };
+        self.art = cpu.art;
+        let (args, left, right) = checked?;
+        let is = |t, b| readback::readback(&self.art, t).contains(&Value::Bool(b));
+        if !is(left, true) || !is(right, false) {
+            return Err(separate::Error::NotFound.into());
+        }
+        Ok(Some(Separation { args, left, right }))
+    }
+
+    /// compiles an expression without running it. Programs are defined instead, returning `None`
+    pub fn compile_expr(&mut self, src: &str) -> Result<Option<TermIdx>> {
+        let ast = match self.language {
+            Language::Untyped => {
+                let tokens = self.lexer(src)?;
+                self.parse(&tokens, src)?
+            }
+            Language::SystemF => match self.system_f(src)? {
+                Some((ast, _)) => ast,
+                None => return Ok(None),
+            },
+        };
+        match self.ir(ast, src)? {
+            Some(expr) => Ok(Some(self.compile(expr, src)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// reduces `root` to its normal form with the `R` strategy, returning every term it went
+    /// through, from `root` to the normal form. Each reduction spends a step of `token`
+    pub fn reduction<R: Reductor>(
+        &mut self,
+        root: TermIdx,
+        token: &mut CancelToken,
+    ) -> Result<Vec<TermIdx>> {
+        let mut cpu = Cpu::new(std::mem::take(&mut self.art));
+        let mut steps = vec![root];
+        let r = loop {
+            match R::step(&mut cpu, *steps.last().unwrap()) {
+                cpu::Op::Reduced(idx) => steps.push(idx),
+                cpu::Op::Normal => break Ok(()),
+                cpu::Op::Effect(..) => break Err(Error::Effect),
+                cpu::Op::Blame { cast, value } => {
+                    let found = cpu.art.pretty(value, &self.irc.scope.get_aliases());
+                    break Err(self.cast_failed(&cpu.art, cast, found));
+                }
+            }
+            if let Err(e) = token.tick() {
+                break Err(e.into());
+            }
+        };
+        self.art = cpu.art;
+        r?;
+        Ok(steps)
+    }
+
+    /// reduces an expression with applicative order, then gives its standard reduction: the
+    /// leftmost one, which reaches the same normal form by the standardization theorem. Each is
+    /// run with a cancel token of its own, and fails alone, as applicative order diverges where
+    /// the standard reduction doesn't. Programs return `None`
+    pub fn standardize(&mut self, src: &str) -> Result<Option<Standardized>> {
+        let Some(root) = self.compile_expr(src)? else {
+            return Ok(None);
+        };
+        let given = self.reduction::<cpu::Applicative>(root, &mut self.cancel.start());
+        let standard = self.reduction::<cpu::Normal>(root, &mut self.cancel.start());
+        Ok(Some(Standardized { given, standard }))
+    }
+
+    /// the CPS form of an expression, as source. Programs return `None`
+    pub fn cps(&mut self, src: &str) -> Result<Option<String>> {
+        let tokens = self.lexer(src)?;
+        let ast = self.parse(&tokens, src)?;
+        if matches!(ast.item, Ast::Program(..)) {
+            return Ok(None);
+        }
+        let mut cps_src = src.to_string();
+        let cps = cps::transform(ast, &mut cps::Fresh::new(&mut cps_src))?;
+        Ok(Some(cps::display(&cps, &cps_src)))
+    }
+
+    /// parses and runs a call-by-push-value computation
+    pub fn cbpv(&mut self, src: &str) -> Result<cbpv::Run> {
+        let m = cbpv::parse(src)?;
+        Ok(cbpv::run(&m, &mut self.cancel.start())?)
+    }
+
+    /// translates an expression to call-by-push-value with `order`, and runs it. Programs return
+    /// `None`
+    pub fn cbpv_translate(
+        &mut self,
+        order: cbpv::Order,
+        src: &str,
+    ) -> Result<Option<(cbpv::Comp, cbpv::Run)>> {
+        let tokens = self.lexer(src)?;
+        let ast = self.parse(&tokens, src)?;
+        if matches!(ast.item, Ast::Program(..)) {
+            return Ok(None);
+        }
+        let m = cbpv::translate(&ast, src, order)?;
+        let run = cbpv::run(&m, &mut self.cancel.start())?;
+        Ok(Some((m, run)))
+    }
+
+    /// reduces an expression as an interaction net, and with normal order to compare. Programs,
+    /// and expressions with constants, return `None`
+    pub fn optimal(&mut self, src: &str) -> Result<Option<Optimal>> {
+        let Some(root) = self.compile_expr(src)? else {
+            return Ok(None);
+        };
+        let Some(mut net) = Net::new(&self.art, root) else {
+            return Ok(None);
+        };
+        let normal = net.normalize(&mut self.art, &mut self.cancel.start())?;
+        let steps = self.reduction::<Normal>(root, &mut self.cancel.start())?;
+        Ok(Some(Optimal {
+            normal,
+            stats: net.stats,
+            normal_order: steps.len() - 1,
+        }))
+    }
+
+    /// compiles `expr` into the artifacts, returning its root
+    pub fn compile(&mut self, expr: IrObj, src: &str) -> Result<TermIdx> {
+        self.bench("compiler", |s| -> Result<()> {
+            let mut art = CompArtifact::default();
+            std::mem::swap(&mut art, &mut s.art);
+            let mut cu = CodeUnit::with_artifacts(&mut s.irc.scope, src, art)?;
+            // kept on errors too, as the terms already reduced may still be referred to
+            let r = cu.compile(&expr);
+            s.art = cu.art;
+            Ok(r?)
+        })?;
+        Ok(self.art.root.unwrap())
+    }
+
+    /// reduces the artifact's root to its normal form. Also returns the steps taken if they're
+    /// meant to be shown, with the redex contracted in each, and what it cost. The terms' sizes
+    /// are only measured with `bench eval`. Each reduction spends a step of `token`. The closures
+    /// backend has no steps in between, so only the first and the last terms are shown
+    pub fn cpu(&mut self, token: &mut CancelToken) -> Result<(TermIdx, Steps, cpu::Stats)> {
+        let mut root = self.art.root.unwrap();
+        let record = ["steps", "steps_raw", "redexes"]
+            .into_iter()
+            .any(|s| self.show.is_on(s));
+        if self.backend == Backend::Closures {
+            let (nf, stats) = self
+                .bench("normal", |s| engine::normalize(&mut s.art, root, token))
+                .map_err(|e| match e {
+                    engine::Error::Blame { cast, found } => {
+                        self.cast_failed(&self.art, cast, found)
+                    }
+                    e => e.into(),
+                })?;
+            let steps = if record { vec![root, nf] } else { Vec::new() };
+            let redexes = vec![None; steps.len().saturating_sub(1)];
+            return Ok((nf, (steps, redexes), stats));
+        }
+        #[cfg(feature = "jit")]
+        if self.backend == Backend::Jit
+            && let Some(n) = self.bench("normal", |s| {
+                crate::lift::Lifted::new(&s.art, root).and_then(|l| crate::jit::run(&l))
+            })
+        {
+            let nf = self.art.push(Term::Const(crate::arts::Const::Int(n)));
+            let steps = if record { vec![root, nf] } else { Vec::new() };
+            let redexes = vec![None; steps.len().saturating_sub(1)];
+            return Ok((nf, (steps, redexes), cpu::Stats::default()));
+        }
+        let measure = self.bench.is_on("eval");
+        let strategy = self.strategy;
+        let mut steps = Vec::new();
+        let mut redexes = Vec::new();
+        let mut stats = cpu::Stats::default();
+        let r = self.bench("normal", |s| {
+            let mut art = CompArtifact::default();
+            std::mem::swap(&mut art, &mut s.art);
+            let mut cpu = Cpu::new(art);
+            // where the last redex from the input was written, as the reducts aren't anywhere
+            let mut last_span = None;
+            let r = loop {
+                if record {
+                    steps.push(root);
+                }
+                if measure {
+                    cpu.measure(root);
+                }
+                let op = s.bench("steps", |_| strategy.step(&mut cpu, root));
+                match op {
+                    cpu::Op::Normal => break Ok(()),
+                    cpu::Op::Effect(..) => break Err(Error::Effect),
+                    cpu::Op::Blame { cast, value } => {
+                        let found = cpu.art.pretty(value, &s.irc.scope.get_aliases());
+                        break Err(s.cast_failed(&cpu.art, cast, found));
+                    }
+                    cpu::Op::Reduced(idx) => {
+                        root = idx;
+                    }
+                }
+                // forcing a thunk contracts nothing
+                let redex = cpu.last_redex.take();
+                if record {
+                    redexes.push(redex);
+                }
+                if let Some(at) = redex.and_then(|r| cpu.art.spans.get(&r)) {
+                    last_span = Some(*at);
+                }
+                if token.max_size.is_some()
+                    && let Err(e) = token.check_size(cpu.size(root), last_span)
+                {
+                    break Err(e.into());
+                }
+                if let Err(e) = token.tick() {
+                    break Err(e.into());
+                }
+            };
+            stats = cpu.stats;
+            s.art = cpu.art;
+            r
+        });
+        r?;
+        // the casts stuck on a variable stay in the normal form
+        let root = self.art.erase_casts(root);
+        Ok((root, (steps, redexes), stats))
+    }
+
+    /// the error of the cast `cast` of `art` finding `found`, with the source of the definition
+    /// it's in, if there's one
+    fn cast_failed(&self, art: &CompArtifact, cast: u32, found: String) -> Error {
+        let Some(blame) = art.casts.get(cast as usize) else {
+            return Error::CastFailed {
+                found,
+                def: None,
+                at: None,
+            };
+        };
+        let Some(def) = blame.def else {
+            return Error::CastFailed {
+                found,
+                def: None,
+                at: Some(blame.at),
+            };
+        };
+        // the source kept is only the definition's, from where it starts
+        let start = self.irc.scope.res_pool[def.0].at.offset();
+        let at = SourceSpan::new((blame.at.offset() - start).into(), blame.at.len());
+        match self.sources.get(&def) {
+            Some(src) => Error::CastFailed {
+                found,
+                def: Some(src.to_string()),
+                at: Some(at),
+            },
+            None => Error::CastFailed {
+                found,
+                def: None,
+                at: None,
+            },
+        }
+    }
+
+    pub fn bench<T>(&mut self, label: &'static str, f: impl FnOnce(&mut Self) -> T) -> T {
+        if !self.bench.is_on(label) {
+            return f(self);
+        }
+        let start = Instant::now();
+        let r = f(self);
+        self.timings.push((label, start.elapsed()));
+        r
+    }
+}
+
+/// the names a program defines. Anything else defines nothing
+pub(crate) fn defined_names<'a>(program: &Node, src: &'a str) -> Vec<&'a str> {
+    let Ast::Program(defs) = &program.item else {
+        return Vec::new();
+    };
+    defs.iter()
+        .filter_map(|d| match &d.item {
+            Ast::Def { ident, .. } => Some(lexer::from_code(*ident, src)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::HashMap;
+
+    use crate::arts::{OuterIdx, Term, TermIdx};
+    use crate::cancel::Cancelled;
+    use crate::engine::Backend;
+    use crate::lexer;
+    use crate::printer::Notation;
+    use crate::session::{Error, Output, Session};
+    use crate::types;
+
+    #[test]
+    pub fn definitions_are_kept() {
+        let mut s = Session::new();
+        assert_eq!(s.eval_str("I x = x").unwrap().output, Output::Defined);
+        assert_eq!(s.eval_str("  ").unwrap().output, Output::Empty);
+        let Output::Normal(nf) = s.eval_str("I I").unwrap().output else {
+            panic!("expected a normal form");
+        };
+        let Term::Abs { inner } = s.art.get(nf) else {
+            panic!("expected an abstraction");
+        };
+        assert_eq!(s.art.get(inner), Term::Var(OuterIdx(0)));
+    }
+
+    #[test]
+    pub fn typed_mode() {
+        let mut s = Session::new();
+        s.set_typed(true).unwrap();
+        let out = s.eval_str("snd (pair (fn x y => x) (fn x => x))").unwrap();
+        assert_eq!(out.ty.unwrap().to_string(), "a -> a");
+        assert!(s.eval_str("swap p = case p inr inl").is_ok());
+        assert!(s.eval_str("bad = fst (inl swap)").is_err());
+        assert!(!s.irc.scope.definitions.contains_key("bad"));
+    }
+
+    #[test]
+    pub fn deep_inputs() {
+        const DEPTH: usize = 20_000;
+        let mut s = Session::new();
+        let src = format!("{}x", "fn x => (".repeat(DEPTH)) + &")".repeat(DEPTH);
+        let out = s.eval_str(&src).unwrap();
+        assert!(matches!(out.output, Output::Normal(_)));
+        assert!(out.ir.is_some());
+    }
+
+    #[test]
+    pub fn subterm_annotations() {
+        let mut s = Session::new();
+        let typing = s.infer("fn x y => x").unwrap().unwrap();
+        let steps: Vec<_> = typing
+            .steps
+            .iter()
+            .map(|s| (s.term.as_str(), s.ty.to_string()))
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                ("fn x y => x", "a -> b -> a".to_string()),
+                ("fn y => x", "b -> a".to_string()),
+                ("x", "a".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    pub fn unit_literal() {
+        let mut s = Session::new();
+        s.set_typed(true).unwrap();
+        let out = s.eval_str("pair () unit").unwrap();
+        assert_eq!(out.ty.unwrap().to_string(), "unit * unit");
+        s.eval_str("u x = ()").unwrap();
+        let scope = &s.irc.scope;
+        let u = &scope.res_pool[scope.definitions["u"].0];
+        assert!(scope.to_source(u, Notation::Fn).ends_with(" => ()"));
+        assert!(s.eval_str("() ()").is_err());
+    }
+
+    #[test]
+    pub fn gradual_mode() {
+        let mut s = Session::new();
+        s.set_typed(true).unwrap();
+        s.gradual = true;
+        assert!(s.eval_str("W x = x x").is_err());
+        assert!(!s.irc.scope.definitions.contains_key("W"));
+        assert!(s.eval_str("U = fn (x : dyn) => x x").is_ok());
+        let out = s.eval_str("fst (pair U inl)").unwrap();
+        assert_eq!(out.ty.unwrap().to_string(), "dyn -> dyn");
+        assert!(s.eval_str("fst (inl U)").is_err());
+        s.gradual = false;
+        let err = s.eval_str("fn (x : dyn) => x x").unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Types(types::Error::DynAnnotation { .. })
+        ));
+    }
+
+    #[test]
+    pub fn failed_casts() {
+        let mut s = Session::new();
+        s.set_typed(true).unwrap();
+        s.gradual = true;
+        s.irc.primitives = true;
+        let out = s.eval_str("(fn (f : dyn) => f f) (fn x => x)").unwrap();
+        let Output::Normal(nf) = out.output else {
+            panic!("expected a normal form");
+        };
+        assert!(matches!(s.art.get(nf), Term::Abs { .. }));
+        let src = "(fn (f : dyn) => f 1) 3";
+        let err = s.eval_str(src).unwrap_err();
+        let Error::CastFailed { found, def, at } = &err else {
+            panic!("{err:?}")
+        };
+        assert_eq!((found.as_str(), def), ("3", &None));
+        assert_eq!(at.map(|at| &src[at.offset()..][..at.len()]), Some("dyn"));
+        s.eval_str("apply = fn (f : dyn) x => f x").unwrap();
+        let err = s.eval_str("apply 2 1").unwrap_err();
+        let Error::CastFailed { def, at, .. } = &err else {
+            panic!("{err:?}")
+        };
+        let def = def.as_deref().unwrap();
+        assert_eq!(at.map(|at| &def[at.offset()..][..at.len()]), Some("dyn"));
+        s.backend = Backend::Closures;
+        let err = s.eval_str(src).unwrap_err();
+        assert!(matches!(err, Error::CastFailed { at: Some(_), .. }));
+    }
+
+    #[test]
+    pub fn signatures() {
+        let mut s = Session::new();
+        s.set_typed(true).unwrap();
+        s.eval_str("K x y = x").unwrap();
+        assert!(s.declare("K", "a -> b -> a").is_ok());
+        assert!(s.declare("K", "unit -> a -> unit").is_ok());
+        assert!(s.declare("K", "a -> b -> b").is_err());
+        assert!(s.declare("S", "a").is_err());
+        let out = s.eval_str("K unit").unwrap();
+        assert_eq!(out.ty.unwrap().to_string(), "a -> unit");
+    }
+
+    #[test]
+    pub fn fixpoint() {
+        let mut s = Session::new();
+        s.set_typed(true).unwrap();
+        assert!(s.eval_str("fix (fn f x => x)").is_err());
+        s.set_fix(true).unwrap();
+        let out = s.eval_str("fix (fn f x => x)").unwrap();
+        assert_eq!(out.ty.unwrap().to_string(), "a -> a");
+        let typing = s.infer("letrec f = fn x => f x in f").unwrap().unwrap();
+        assert_eq!(typing.ty.to_string(), "a -> b");
+        let out = s.eval_str("letrec k = fn x y => x in k").unwrap();
+        assert_eq!(out.ty.unwrap().to_string(), "a -> b -> a");
+        s.set_fix(false).unwrap();
+        assert!(!s.irc.scope.definitions.contains_key("fix"));
+    }
+
+    /// the libraries in `assets/`. `assets/src` has sketches in a syntax qk doesn't read
+    #[test]
+    pub fn bundled_assets() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|e| e != "qk") {
+                continue;
+            }
+            let mut s = Session::new();
+            s.set_prelude(false).unwrap();
+            let src = std::fs::read_to_string(&path).unwrap();
+            let out = s.eval_str(&src);
+            assert!(out.is_ok(), "{}: {out:?}", path.display());
+        }
+    }
+
+    #[test]
+    pub fn prelude() {
+        let mut s = Session::new();
+        s.set_prelude(true).unwrap();
+        s.eval_str("K x y = y").unwrap();
+        let Output::Normal(nf) = s.eval_str("is_zero (pred one) true false").unwrap().output else {
+            panic!("expected a normal form");
+        };
+        let Term::Abs { inner } = s.art.get(nf) else {
+            panic!("expected an abstraction");
+        };
+        assert!(matches!(s.art.get(inner), Term::Abs { .. }));
+        s.set_prelude(false).unwrap();
+        assert!(s.irc.scope.definitions.contains_key("K"));
+        assert!(!s.irc.scope.definitions.contains_key("S"));
+    }
+
+    #[test]
+    pub fn strategy() {
+        let mut s = Session::new();
+        s.cancel.budget = Some(100);
+        s.eval_str("K x y = x\nD x = x x").unwrap();
+        assert!(s.eval_str("K K (D D)").is_ok());
+        s.strategy = "applicative".try_into().unwrap();
+        assert!(matches!(
+            s.eval_str("K K (D D)"),
+            Err(crate::session::Error::Cancelled(_))
+        ));
+    }
+
+    #[test]
+    pub fn recursive_definitions() {
+        let mut s = Session::new();
+        s.cancel.budget = Some(1_000);
+        s.eval_str("g x = h x\nh y = y").unwrap();
+        assert!(s.eval_str("g (fn x => x)").is_ok());
+        assert!(s.eval_str("f x = f x").is_err());
+        assert!(!s.irc.scope.definitions.contains_key("f"));
+        assert!(s.eval_str("L = M").is_ok());
+        assert!(s.eval_str("M = L").is_err());
+        // only what refers to the missing definition fails
+        assert!(s.eval_str("g g").is_ok());
+        assert!(s.eval_str("L").is_err());
+
+        s.irc.recursion = crate::ir::Recursion::YCombinator;
+        s.eval_str("K x y = x\nf x = K x (f x)").unwrap();
+        let Output::Normal(nf) = s.eval_str("f (fn x => x)").unwrap().output else {
+            panic!("expected a normal form");
+        };
+        assert!(matches!(s.art.get(nf), Term::Abs { .. }));
+        assert!(s.eval_str("M = L").is_err());
+    }
+
+    #[test]
+    pub fn lets() {
+        use crate::readback::{Value, readback};
+
+        let mut s = Session::new();
+        s.set_prelude(true).unwrap();
+        s.cancel.budget = Some(100_000);
+        let fact = "letrec fact n = is_zero n one (mul n (fact (pred n))) in fact three";
+        let Output::Normal(nf) = s.eval_str(fact).unwrap().output else {
+            panic!("expected a normal form");
+        };
+        assert!(readback(&s.art, nf).contains(&Value::Numeral(6)));
+        let Output::Normal(nf) = s.eval_str("let x = two in add x x").unwrap().output else {
+            panic!("expected a normal form");
+        };
+        assert!(readback(&s.art, nf).contains(&Value::Numeral(4)));
+        // without `rec`, the name isn't bound in its own value
+        assert!(s.eval_str("let g = g in g").is_err());
+    }
+
+    #[test]
+    pub fn primitives() {
+        use crate::arts::Const;
+
+        let mut s = Session::new();
+        s.cancel.budget = Some(10_000);
+        assert!(s.eval_str("+ 2 3").is_err());
+        s.irc.primitives = true;
+        let int = |s: &mut Session, src| match s.eval_str(src).unwrap().output {
+            Output::Normal(nf) => s.art.get(nf),
+            _ => panic!("expected a normal form"),
+        };
+        assert_eq!(int(&mut s, "+ 2 (* 3 -4)"), Term::Const(Const::Int(-10)));
+        let fact = "letrec fact n = if0 n 1 (* n (fact (- n 1))) in fact 5";
+        assert_eq!(int(&mut s, fact), Term::Const(Const::Int(120)));
+        // only the branch taken is reduced
+        s.eval_str("D x = x x").unwrap();
+        assert_eq!(int(&mut s, "if0 0 7 (D D)"), Term::Const(Const::Int(7)));
+        s.strategy = "applicative".try_into().unwrap();
+        assert_eq!(
+            int(&mut s, "(fn x => * x x) (- 9 2)"),
+            Term::Const(Const::Int(49))
+        );
+        // a definition hides the primitive
+        s.eval_str("+ x y = y").unwrap();
+        assert_eq!(int(&mut s, "+ 2 3"), Term::Const(Const::Int(3)));
+    }
+
+    #[test]
+    pub fn max_term_size() {
+        let mut s = Session::new();
+        s.eval_str("I x = x\nF x = x x x").unwrap();
+        s.cancel.max_size = Some(100);
+        let src = "I (I (F F))";
+        match s.eval_str(src) {
+            Err(Error::Cancelled(Cancelled::TermSize { size, max, at })) => {
+                assert!(size > max);
+                assert_eq!(lexer::from_code(at.unwrap(), src).trim(), "(F F)");
+            }
+            r => panic!("expected it to grow too big, got {r:?}"),
+        }
+        // `three` is shared by both arguments, and counted once
+        s.set_prelude(true).unwrap();
+        s.cancel.max_size = Some(60);
+        assert!(s.eval_str("mul three three").is_ok());
+        s.cancel.max_size = None;
+        assert!(s.eval_str("I (F I)").is_ok());
+    }
+
+    #[test]
+    pub fn shadowed_definitions() {
+        let mut s = Session::new();
+        s.set_prelude(true).unwrap();
+        let shadowed = |s: &mut Session, src| {
+            let out = s.eval_str(src).unwrap();
+            out.warnings
+                .iter()
+                .filter(|w| matches!(w, crate::lint::Warning::ShadowedDefinition { .. }))
+                .count()
+        };
+        assert_eq!(shadowed(&mut s, "x = true"), 0);
+        assert_eq!(shadowed(&mut s, "x = false\nnot b = b"), 2);
+        // a definition that fails keeps the old one
+        assert!(s.eval_str("x = fn y => y y\nx = x").is_err());
+        let x = s.irc.scope.definitions["x"];
+        assert!(s.sources[&x].contains("false"));
+        s.shadow_warnings = false;
+        assert_eq!(shadowed(&mut s, "x = true"), 0);
+    }
+
+    #[test]
+    pub fn undefine_and_clear() {
+        let mut s = Session::new();
+        s.set_prelude(true).unwrap();
+        s.set_typed(true).unwrap();
+        s.eval_str("twice f x = f (f x)\nquad f = twice twice f")
+            .unwrap();
+        s.undefine("twice").unwrap();
+        assert!(s.undefine("twice").is_err());
+        // `quad` still has it
+        assert!(s.eval_str("quad").is_ok());
+        assert!(s.eval_str("twice").is_err());
+        s.clear_definitions(true);
+        assert!(!s.irc.scope.definitions.contains_key("quad"));
+        assert!(s.irc.scope.definitions.contains_key("true"));
+        s.clear_definitions(false);
+        assert!(!s.irc.scope.definitions.contains_key("true"));
+        // the typed primitives stay, as typed mode is still on
+        assert!(s.eval_str("fst").is_ok());
+    }
+
+    #[test]
+    pub fn modules() {
+        use crate::session::modules::parse_import;
+
+        assert_eq!(
+            parse_import(r#"import "lib/lists.qk" as L"#),
+            Some(("lib/lists.qk", "L"))
+        );
+        assert_eq!(parse_import(r#"import "lists.qk" asL"#), None);
+        let mut s = Session::new();
+        s.set_prelude(true).unwrap();
+        let lists = "not b = b false true\nid x = not (not x)";
+        s.import("L", "lists.qk", lists).unwrap();
+        // inside the module, `not` is its own. Outside, it's still the prelude's
+        let nf = |s: &mut Session, src| match s.eval_str(src).unwrap().output {
+            Output::Normal(nf) => s.art.pretty(nf, &HashMap::new()),
+            _ => panic!("expected a normal form"),
+        };
+        assert_eq!(nf(&mut s, "L.id true"), nf(&mut s, "true"));
+        assert_eq!(nf(&mut s, "L.not true"), nf(&mut s, "not true"));
+        assert!(s.eval_str("id").is_err());
+        assert!(s.import("L", "other.qk", "x = x").is_err());
+        s.eval_str("M.x = true").unwrap();
+        assert!(s.import("M", "other.qk", "x = false").is_err());
+        assert!(s.eval_str("M.x").is_ok());
+    }
+
+    #[test]
+    pub fn pattern_lambdas() {
+        use crate::arts::Const;
+
+        let mut s = Session::new();
+        s.irc.primitives = true;
+        let int = |s: &mut Session, src| match s.eval_str(src).unwrap().output {
+            Output::Normal(nf) => s.art.get(nf),
+            _ => panic!("expected a normal form"),
+        };
+        let second = "(fn (x, y) => y) (1, 2)";
+        assert_eq!(int(&mut s, second), Term::Const(Const::Int(2)));
+        let sum = "(fn n (x, y, z) => - (+ x y) (* n z)) 10 (1, 2, 3)";
+        assert_eq!(int(&mut s, sum), Term::Const(Const::Int(-27)));
+        // the sides are bound in the body only
+        assert!(s.eval_str("(fn (x, y) => x) (y, 1)").is_err());
+    }
+
+    #[test]
+    pub fn infix_operators() {
+        use crate::arts::Const;
+        use crate::infix::{Assoc, Fixity};
+
+        let mut s = Session::new();
+        s.irc.primitives = true;
+        let mut declare = |op: &str, assoc, prec| {
+            s.irc.operators.insert(op.into(), Fixity { assoc, prec });
+        };
+        declare("+", Assoc::Left, 6);
+        declare("-", Assoc::Left, 6);
+        declare("*", Assoc::Left, 7);
+        declare("<|", Assoc::Right, 0);
+        let int = |s: &mut Session, src| match s.eval_str(src).unwrap().output {
+            Output::Normal(nf) => s.art.get(nf),
+            _ => panic!("expected a normal form"),
+        };
+        assert_eq!(int(&mut s, "1 + 2 * 3 - 4"), Term::Const(Const::Int(3)));
+        assert_eq!(int(&mut s, "(+) 1 2 * 3"), Term::Const(Const::Int(9)));
+        // operators are bound to definitions like any other name
+        s.eval_str("<| f x = f x").unwrap();
+        let sq = "(fn x => x * x) <| 1 + 2";
+        assert_eq!(int(&mut s, sq), Term::Const(Const::Int(9)));
+        assert!(s.eval_str("1 + * 2").is_err());
+    }
+
+    #[test]
+    pub fn definition_info() {
+        let mut s = Session::new();
+        s.eval_str("id x = x\nK x y = x\nKI = K id").unwrap();
+        let scope = &s.irc.scope;
+        let (id, k, ki) = (
+            scope.definitions["id"],
+            scope.definitions["K"],
+            scope.definitions["KI"],
+        );
+        assert_eq!(scope.dependencies(ki), [k, id]);
+        assert_eq!(scope.dependents(id), [ki]);
+        assert_eq!(&*s.sources[&k], "K x y = x");
+        assert_eq!(s.definition_type(k).unwrap().to_string(), "a -> b -> a");
+    }
+
+    #[test]
+    pub fn standard_reduction() {
+        let mut s = Session::new();
+        s.eval_str("I x = x").unwrap();
+        let std = s.standardize("(fn x => x x) (I I)").unwrap().unwrap();
+        let (given, standard) = (std.given.unwrap(), std.standard.unwrap());
+        assert_eq!((given.len(), standard.len()), (4, 5));
+        let nf = |idx: &TermIdx| s.art.pretty(*idx, &HashMap::new());
+        assert_eq!(nf(given.last().unwrap()), nf(standard.last().unwrap()));
+
+        // only applicative order reduces the argument thrown away
+        s.cancel.budget = Some(100);
+        let std = s
+            .standardize("(fn x y => y) ((fn x => x x) fn x => x x)")
+            .unwrap()
+            .unwrap();
+        assert!(matches!(std.given, Err(Error::Cancelled(_))));
+        assert_eq!(std.standard.unwrap().len(), 2);
+    }
+
+    #[test]
+    pub fn errors_are_reported() {
+        let mut s = Session::new();
+        assert!(s.eval_str("(fn x => x").is_err());
+        assert!(s.eval_str("x = ?").is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::session::{Session, defined_names};
+
+#[derive(Error, Diagnostic, Debug)]
+pub enum Error {
+    #[error("{alias:?} already names {path:?}")]
+    #[diagnostic(
+        code(modules::alias_taken),
+        help("each module needs its own name. Import it `as` another one")
+    )]
+    AliasTaken { alias: Box<str>, path: Box<str> },
+
+    #[error("{name:?} is already defined")]
+    #[diagnostic(
+        code(modules::clash),
+        help("a definition can't have the qualified name of one in a module")
+    )]
+    Clash { name: Box<str> },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// a file imported under a name, as `import "lists.qk" as L`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Module {
+    pub path: Box<str>,
+    /// what it defines, qualified as `L.map`
+    pub names: Vec<Box<str>>,
+}
+
+/// the path and the name of `import "<path>" as <alias>`
+pub fn parse_import(line: &str) -> Option<(&str, &str)> {
+    let rest = line.trim().strip_prefix("import")?.trim_start();
+    let (path, rest) = rest.strip_prefix('"')?.split_once('"')?;
+    let alias = rest.trim().strip_prefix("as")?;
+    // `asL` isn't `as L`
+    alias
+        .starts_with(char::is_whitespace)
+        .then(|| (path, alias.trim()))
+}
+
+impl Session {
+    /// defines the definitions of `src` as `<alias>.<name>`. Inside it, they refer to each other
+    /// by their plain names, which stay as they were outside
+    pub fn import(&mut self, alias: &str, path: &str, src: &str) -> super::Result<&Module> {
+        if let Some(m) = self.modules.get(alias) {
+            let (alias, path) = (alias.into(), m.path.clone());
+            return Err(Error::AliasTaken { alias, path }.into());
+        }
+        let tokens = self.lexer(src)?;
+        let ast = self.parse(&tokens, src)?;
+        let names = defined_names(&ast, src);
+        let qualified: Vec<Box<str>> = names
+            .iter()
+            .map(|n| format!("{alias}.{n}").into())
+            .collect();
+        if let Some(name) = qualified
+            .iter()
+            .find(|q| self.irc.scope.definitions.contains_key(q))
+        {
+            return Err(Error::Clash { name: name.clone() }.into());
+        }
+        let hidden: Vec<_> = names
+            .iter()
+            .map(|n| self.irc.scope.definitions.remove(n))
+            .collect();
+        let compiled = self.irc.compile_program(*ast, src);
+        for ((name, q), old) in names.iter().zip(&qualified).zip(hidden) {
+            let defined = self.irc.scope.definitions.remove(name);
+            if let (Ok(()), Some(id)) = (&compiled, defined) {
+                let def = crate::lexer::from_code(self.irc.scope.res_pool[id.0].at, src);
+                self.sources.insert(id, def.into());
+                self.irc.scope.definitions.insert(q, id);
+            }
+            if let Some(old) = old {
+                self.irc.scope.definitions.insert(name, old);
+            }
+        }
+        compiled?;
+        let module = Module {
+            path: path.into(),
+            names: qualified,
+        };
+        Ok(self.modules.entry(alias.into()).or_insert(module))
+    }
+}
@@ -0,0 +1,72 @@
+/// a definition of the prelude, written in the `qk` syntax
+#[derive(Debug, Clone, Copy)]
+pub struct Definition {
+    pub name: &'static str,
+    pub body: &'static str,
+}
+
+const fn def(name: &'static str, body: &'static str) -> Definition {
+    Definition { name, body }
+}
+
+/// the standard combinators and Church encodings. Each definition may use the ones before it.
+/// `pair`, `fst` and `snd` are the same as the typed primitives
+pub const PRELUDE: &[Definition] = &[
+    // combinators
+    def("I", "fn x => x"),
+    def("K", "fn x y => x"),
+    def("S", "fn x y z => x z (y z)"),
+    def("B", "fn f g x => f (g x)"),
+    def("C", "fn f x y => f y x"),
+    def("W", "fn f x => f x x"),
+    def("Y", "fn f => (fn x => f (x x)) (fn x => f (x x))"),
+    def("omega", "fn x => x x"),
+    // Ω. Never reaches a normal form
+    def("Omega", "omega omega"),
+    // booleans
+    def("true", "fn t f => t"),
+    def("false", "fn t f => f"),
+    def("not", "fn b => b false true"),
+    def("and", "fn a b => a b false"),
+    def("or", "fn a b => a true b"),
+    // numerals
+    def("zero", "fn f x => x"),
+    def("succ", "fn n f x => f (n f x)"),
+    def("one", "succ zero"),
+    def("two", "succ one"),
+    def("three", "succ two"),
+    def("add", "fn m n f x => m f (n f x)"),
+    def("mul", "fn m n f => m (n f)"),
+    def("pow", "fn m n => n m"),
+    def(
+        "pred",
+        "fn n f x => n (fn g h => h (g f)) (fn u => x) (fn u => u)",
+    ),
+    def("sub", "fn m n => n pred m"),
+    def("is_zero", "fn n => n (fn x => false) true"),
+    // pairs
+    def("pair", "fn x y p => p x y"),
+    def("fst", "fn p => p (fn x y => x)"),
+    def("snd", "fn p => p (fn x y => y)"),
+    // lists, as nested pairs ended by `nil`
+    def("nil", "fn p => true"),
+    def("cons", "pair"),
+    def("head", "fst"),
+    def("tail", "snd"),
+    def("is_nil", "fn l => l (fn h t => false)"),
+];
+
+#[cfg(test)]
+pub mod tests {
+    use crate::session::prelude::PRELUDE;
+    use crate::types::primitives::PRIMITIVES;
+
+    #[test]
+    pub fn pairs_match_the_primitives() {
+        for p in PRIMITIVES {
+            if let Some(d) = PRELUDE.iter().find(|d| d.name == p.name) {
+                assert_eq!(d.body, p.body, "`{}` differs from its primitive", p.name);
+            }
+        }
+    }
+}
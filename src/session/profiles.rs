@@ -0,0 +1,94 @@
+/// a named bundle of settings, each one as `set` takes it. Every profile sets the same ones, so
+/// switching between them doesn't depend on what was set before. The colors are the REPL's, as
+/// `set color` takes them
+#[derive(Debug, Clone, Copy)]
+pub struct Profile {
+    pub name: &'static str,
+    pub desc: &'static str,
+    pub settings: &'static [(&'static str, &'static str)],
+}
+
+pub const PROFILES: &[Profile] = &[
+    Profile {
+        name: "teaching",
+        desc: "shows the Church-encoded data with the normal forms highlighted, and stops runaway terms quickly",
+        settings: &[
+            ("prelude", "on"),
+            ("typed", "off"),
+            ("readback", "on"),
+            ("show", "normal"),
+            ("strategy", "normal"),
+            ("backend", "interpreter"),
+            ("cps", "off"),
+            ("language", "untyped"),
+            ("bench", ""),
+            ("timeout", "5000"),
+            ("budget", "100000"),
+            ("max-term-size", "100000"),
+            ("output", "human"),
+            ("color", "auto"),
+            ("color prompt", "green"),
+            ("color error", "red"),
+            ("color warning", "yellow"),
+            ("color help", "cyan"),
+            ("color step", "bright-black"),
+            ("color normal", "bright-green"),
+        ],
+    },
+    Profile {
+        name: "benchmark",
+        desc: "times every stage, without any limit",
+        settings: &[
+            ("prelude", "on"),
+            ("typed", "off"),
+            ("readback", "off"),
+            ("show", "normal"),
+            ("strategy", "need"),
+            ("backend", "interpreter"),
+            ("cps", "off"),
+            ("language", "untyped"),
+            ("bench", "all"),
+            ("timeout", "off"),
+            ("budget", "off"),
+            ("max-term-size", "off"),
+            ("output", "human"),
+            ("color", "auto"),
+            ("color prompt", "green"),
+            ("color error", "red"),
+            ("color warning", "yellow"),
+            ("color help", "cyan"),
+            ("color step", "bright-black"),
+            ("color normal", "none"),
+        ],
+    },
+    Profile {
+        name: "server",
+        desc: "answers in JSON without colors, under tight limits",
+        settings: &[
+            ("prelude", "on"),
+            ("typed", "off"),
+            ("readback", "off"),
+            ("show", "normal"),
+            ("strategy", "need"),
+            ("backend", "interpreter"),
+            ("cps", "off"),
+            ("language", "untyped"),
+            ("bench", ""),
+            ("timeout", "1000"),
+            ("budget", "1000000"),
+            ("max-term-size", "1000000"),
+            ("output", "json"),
+            ("color", "off"),
+            ("color prompt", "none"),
+            ("color error", "none"),
+            ("color warning", "none"),
+            ("color help", "none"),
+            ("color step", "none"),
+            ("color normal", "none"),
+        ],
+    },
+];
+
+pub fn get(name: &str) -> Option<&'static Profile> {
+    PROFILES.iter().find(|p| p.name == name)
+}
@@ -18,7 +18,10 @@ impl Setting {
                 on: self.all.to_smallvec(),
             });
         }
-        let mut s = Self::default();
+        let mut s = Self {
+            all: self.all,
+            ..Self::default()
+        };
         for v in value.split_whitespace() {
             let trimmed = v.trim();
             if let Some(set) = self.all.iter().find(|&&a_v| a_v == trimmed) {
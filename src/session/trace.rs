@@ -0,0 +1,69 @@
+use crate::session::evaluated::Traced;
+
+/// the steps of the last traced reduction, and the one being looked at, to walk back and forth
+/// through them
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    steps: Vec<Traced>,
+    at: usize,
+}
+
+impl Trace {
+    /// looking at the last step, the normal form, as that's where the reduction left it
+    pub fn new(steps: Vec<Traced>) -> Self {
+        let at = steps.len().saturating_sub(1);
+        Self { steps, at }
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// the step looked at, counted from 0. `None` if nothing was traced
+    pub fn current(&self) -> Option<(usize, &Traced)> {
+        Some((self.at, self.steps.get(self.at)?))
+    }
+
+    /// goes `n` steps back, or up to the first. Returns how many it went
+    pub fn back(&mut self, n: usize) -> usize {
+        let moved = n.min(self.at);
+        self.at -= moved;
+        moved
+    }
+
+    /// goes `n` steps forward, or up to the last. Returns how many it went
+    pub fn forward(&mut self, n: usize) -> usize {
+        let moved = n.min(self.len().saturating_sub(self.at + 1));
+        self.at += moved;
+        moved
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::session::Session;
+    use crate::session::trace::Trace;
+
+    #[test]
+    pub fn back_and_forth() {
+        let mut s = Session::new();
+        s.show = s.show.parse_inspired("steps").unwrap();
+        s.eval_str("I x = x").unwrap();
+        let mut trace = Trace::new(s.evaluate("I (I (I I))").unwrap().traced());
+        assert_eq!(trace.len(), 4);
+        assert_eq!(trace.current().unwrap().1.term, "I");
+        assert_eq!(trace.forward(1), 0);
+        assert_eq!(trace.back(2), 2);
+        assert_eq!(trace.current().unwrap().1.term, "I (I I)");
+        assert_eq!(trace.back(5), 1);
+        let (at, first) = trace.current().unwrap();
+        assert_eq!((at, &*first.term), (0, "I (I (I I))"));
+        assert!(first.redex.is_some());
+
+        assert!(Trace::default().current().is_none());
+    }
+}
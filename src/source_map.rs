@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use miette::{Diagnostic, LabeledSpan, NamedSource, Severity, SourceCode, SourceSpan};
+
+use crate::lexer::{self, Columns};
+
+/// which of the sources of a `SourceMap` something comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourceId(pub usize);
+
+/// an input, as a line typed into the REPL or a file loaded
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub name: Box<str>,
+    pub text: Arc<str>,
+}
+
+/// a span inside one of the sources, instead of inside the snippet it was lexed from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Located {
+    pub source: SourceId,
+    pub at: SourceSpan,
+}
+
+/// every input of a session, so a diagnostic can point into the source it came from by name and
+/// with its real lines
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    sources: Vec<Source>,
+}
+
+impl SourceMap {
+    pub fn add(&mut self, name: impl Into<Box<str>>, text: impl Into<Arc<str>>) -> SourceId {
+        self.sources.push(Source {
+            name: name.into(),
+            text: text.into(),
+        });
+        SourceId(self.sources.len() - 1)
+    }
+
+    pub fn get(&self, id: SourceId) -> &Source {
+        &self.sources[id.0]
+    }
+
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    /// the whole of `id`
+    pub fn whole(&self, id: SourceId) -> Located {
+        Located {
+            source: id,
+            at: (0, self.get(id).text.len()).into(),
+        }
+    }
+
+    /// `part`, a snippet ending where `within` does, as commands are given the tail of a line.
+    /// `None` if it's not there
+    pub fn locate(&self, within: Located, part: &str) -> Option<Located> {
+        let text = &self.get(within.source).text;
+        let end = within.at.offset() + within.at.len();
+        let start = end.checked_sub(part.len())?;
+        (start >= within.at.offset() && text.get(start..end)? == part).then(|| Located {
+            source: within.source,
+            at: (start, part.len()).into(),
+        })
+    }
+
+    /// where `at` starts, as `lists.qk:3:1`
+    pub fn position(&self, at: Located) -> String {
+        let source = self.get(at.source);
+        match lexer::position(&source.text, at.at.offset(), Columns::Chars) {
+            Some(pos) => format!("{}:{pos}", source.name),
+            None => source.name.to_string(),
+        }
+    }
+
+    /// `diag`, with its labels moved from `part` into its source
+    pub fn attach(&self, diag: Box<dyn Diagnostic + Send + Sync>, part: Located) -> Sourced {
+        let source = self.get(part.source);
+        Sourced {
+            diag,
+            shift: part.at.offset(),
+            name: source.name.clone(),
+            text: source.text.clone(),
+            source: NamedSource::new(&*source.name, source.text.clone()),
+        }
+    }
+}
+
+/// the lines of `text`, each with where it is in `text`
+pub fn lines(text: &str) -> impl Iterator<Item = (SourceSpan, &str)> {
+    let base = text.as_ptr() as usize;
+    text.lines()
+        .map(move |l| ((l.as_ptr() as usize - base, l.len()).into(), l))
+}
+
+/// a diagnostic of a snippet, pointing into the source the snippet is part of
+#[derive(Debug)]
+pub struct Sourced {
+    diag: Box<dyn Diagnostic + Send + Sync>,
+    /// where the snippet starts in the source
+    shift: usize,
+    pub name: Box<str>,
+    pub text: Arc<str>,
+    source: NamedSource<Arc<str>>,
+}
+
+impl std::fmt::Display for Sourced {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.diag, f)
+    }
+}
+
+impl std::error::Error for Sourced {}
+
+impl Diagnostic for Sourced {
+    fn code<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        self.diag.code()
+    }
+
+    fn severity(&self) -> Option<Severity> {
+        self.diag.severity()
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        self.diag.help()
+    }
+
+    fn url<'a>(&'a self) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        self.diag.url()
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.source)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let labels = self.diag.labels()?.map(|l| {
+            let at = (l.offset() + self.shift, l.len());
+            LabeledSpan::new_with_span(l.label().map(String::from), at)
+        });
+        Some(Box::new(labels))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use miette::Diagnostic;
+
+    use crate::diagnostics;
+    use crate::session::Session;
+    use crate::source_map::{Located, SourceMap, lines};
+
+    #[test]
+    pub fn points_into_the_source() {
+        let file = "I x = x\n\nI y\n";
+        let mut map = SourceMap::default();
+        let id = map.add("ids.qk", file);
+        let (at, line) = lines(file).nth(2).unwrap();
+        assert_eq!((at.offset(), line), (9, "I y"));
+        let line = Located { source: id, at };
+        assert_eq!(map.locate(line, "y").unwrap().at, (11, 1).into());
+        assert_eq!(map.locate(line, "I"), None);
+        assert_eq!(map.position(map.locate(line, "y").unwrap()), "ids.qk:3:3");
+
+        let mut s = Session::new();
+        s.eval_str("I x = x").unwrap();
+        let e = s.eval_str("I y").unwrap_err();
+        let sourced = map.attach(Box::new(e), line);
+        assert_eq!(sourced.labels().unwrap().next().unwrap().offset(), 11);
+        let rendered = diagnostics::render_named(&sourced, &sourced.name, &sourced.text);
+        assert!(rendered.contains("--> ids.qk:3:3"), "{rendered}");
+    }
+}
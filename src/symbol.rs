@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// an interned name. Comparing or hashing it is comparing or hashing an integer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(pub u32);
+
+/// gives each name a `Symbol`, allocating it only the first time it's seen
+#[derive(Debug, Default, Clone)]
+pub struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    names: Vec<Rc<str>>,
+}
+
+impl Interner {
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(sym) = self.symbols.get(name) {
+            return *sym;
+        }
+        let sym = Symbol(self.names.len() as u32);
+        let name: Rc<str> = name.into();
+        self.names.push(name.clone());
+        self.symbols.insert(name, sym);
+        sym
+    }
+
+    /// the symbol of `name`, without interning it
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.symbols.get(name).copied()
+    }
+
+    pub fn resolve(&self, sym: Symbol) -> &str {
+        &self.names[sym.0 as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::symbol::Interner;
+
+    #[test]
+    pub fn interning() {
+        let mut i = Interner::default();
+        let x = i.intern("x");
+        let y = i.intern("y");
+        assert_ne!(x, y);
+        assert_eq!(i.intern("x"), x);
+        assert_eq!(i.get("y"), Some(y));
+        assert_eq!(i.get("z"), None);
+        assert_eq!(i.resolve(y), "y");
+        assert_eq!(i.len(), 2);
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+
+use miette::SourceSpan;
+
+use crate::ast::{Ast, Node};
+use crate::lexer::{Meta, from_code};
+
+/// a node of a `SyntaxTree`, by its position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(pub usize);
+
+#[derive(Debug)]
+struct Entry<'a> {
+    node: &'a Meta<Ast>,
+    parent: Option<NodeId>,
+    /// siblings are built one after the other, so the children are a range
+    children: Range<usize>,
+}
+
+/// a read-only view of an AST, with the parent of each node and lookups by source position.
+/// Nodes are numbered breadth first, so the root is the first one
+#[derive(Debug)]
+pub struct SyntaxTree<'a> {
+    entries: Vec<Entry<'a>>,
+}
+
+impl<'a> SyntaxTree<'a> {
+    pub fn new(root: &'a Node) -> Self {
+        let mut entries = vec![Entry {
+            node: root,
+            parent: None,
+            children: 0..0,
+        }];
+        let mut queue = VecDeque::from([0]);
+        while let Some(i) = queue.pop_front() {
+            let start = entries.len();
+            for child in entries[i].node.item.children() {
+                queue.push_back(entries.len());
+                entries.push(Entry {
+                    node: child,
+                    parent: Some(NodeId(i)),
+                    children: 0..0,
+                });
+            }
+            entries[i].children = start..entries.len();
+        }
+        Self { entries }
+    }
+
+    pub fn root(&self) -> Cursor<'_, 'a> {
+        self.cursor(NodeId(0))
+    }
+
+    pub fn cursor(&self, id: NodeId) -> Cursor<'_, 'a> {
+        Cursor { tree: self, id }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// every node, breadth first
+    pub fn iter(&self) -> impl Iterator<Item = Cursor<'_, 'a>> {
+        (0..self.entries.len()).map(|i| self.cursor(NodeId(i)))
+    }
+
+    /// the innermost node whose span has `offset`
+    pub fn find_node_at(&self, offset: usize) -> Option<Cursor<'_, 'a>> {
+        let contains = |c: &Cursor| {
+            let at = c.span();
+            at.offset() <= offset && offset < at.offset() + at.len()
+        };
+        let mut found = Some(self.root()).filter(contains)?;
+        while let Some(child) = found.children().find(contains) {
+            found = child;
+        }
+        Some(found)
+    }
+}
+
+/// a position in a `SyntaxTree`, to move around from
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor<'t, 'a> {
+    tree: &'t SyntaxTree<'a>,
+    pub id: NodeId,
+}
+
+impl<'t, 'a> Cursor<'t, 'a> {
+    fn entry(&self) -> &'t Entry<'a> {
+        &self.tree.entries[self.id.0]
+    }
+
+    pub fn node(&self) -> &'a Meta<Ast> {
+        self.entry().node
+    }
+
+    pub fn ast(&self) -> &'a Ast {
+        &self.node().item
+    }
+
+    pub fn span(&self) -> SourceSpan {
+        self.node().at
+    }
+
+    pub fn text<'s>(&self, src: &'s str) -> &'s str {
+        from_code(self.span(), src)
+    }
+
+    pub fn parent(&self) -> Option<Self> {
+        self.entry().parent.map(|id| self.tree.cursor(id))
+    }
+
+    /// the parent, its parent, and so on up to the root
+    pub fn ancestors(&self) -> impl Iterator<Item = Self> + use<'t, 'a> {
+        std::iter::successors(self.parent(), Self::parent)
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = Self> + use<'t, 'a> {
+        let tree = self.tree;
+        self.entry()
+            .children
+            .clone()
+            .map(move |i| tree.cursor(NodeId(i)))
+    }
+
+    /// the names this node binds: an abstraction's variable or pattern, a definition's parameters,
+    /// or the name a `let` gives
+    pub fn binders(&self) -> &'a [SourceSpan] {
+        match self.ast() {
+            Ast::Abs(v, _) | Ast::Annotated { name: v, .. } | Ast::Let { ident: v, .. } => {
+                std::slice::from_ref(v)
+            }
+            Ast::Def { params, .. } | Ast::Unpack { names: params, .. } => params,
+            _ => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::ast::Ast;
+    use crate::frontend::parse_lambda;
+    use crate::padam::lexer::Lexer;
+    use crate::tree::SyntaxTree;
+
+    #[test]
+    pub fn queries() {
+        let src = "K x y = x\nS f g x = f x (g x)";
+        let lexer = Lexer::default();
+        let ast = parse_lambda(&lexer, &lexer.lex(src).unwrap()).unwrap();
+        let tree = SyntaxTree::new(&ast);
+        assert!(matches!(tree.root().ast(), Ast::Program(_)));
+        assert_eq!(tree.root().children().count(), 2);
+
+        // the `g` of `(g x)`
+        let g = tree.find_node_at(25).unwrap();
+        assert!(matches!(g.ast(), Ast::Var));
+        assert_eq!(g.text(src), "g");
+        let def = g
+            .ancestors()
+            .find(|a| matches!(a.ast(), Ast::Def { .. }))
+            .unwrap();
+        assert_eq!(def.parent().unwrap().id, tree.root().id);
+        let names: Vec<_> = def
+            .binders()
+            .iter()
+            .map(|b| &src[b.offset()..][..1])
+            .collect();
+        assert_eq!(names, ["f", "g", "x"]);
+        assert!(tree.find_node_at(src.len()).is_none());
+        assert_eq!(
+            tree.iter().filter(|c| matches!(c.ast(), Ast::Var)).count(),
+            5
+        );
+    }
+}
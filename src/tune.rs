@@ -0,0 +1,186 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use miette::Diagnostic;
+
+use crate::analyze::collect_files;
+use crate::arts::TermIdx;
+use crate::cancel::CancelToken;
+use crate::cpu::{Applicative, Need, Normal};
+use crate::session::{self, Session};
+
+/// the steps an expression may take under each strategy before it's taken as divergent
+pub const EXPLORE_BUDGET: usize = 1_000_000;
+
+/// the strategies every expression is run with. The session evaluates with the first one
+pub const STRATEGIES: &[&str] = &["normal", "applicative", "need"];
+
+/// how an expression ran under a strategy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Run {
+    pub strategy: &'static str,
+    /// `None` if it didn't reach a normal form within `EXPLORE_BUDGET`
+    pub steps: Option<usize>,
+    pub time: Duration,
+}
+
+/// an expression of the corpus, with a run for each of `STRATEGIES`
+#[derive(Debug, Clone)]
+pub struct Case {
+    pub path: PathBuf,
+    pub expr: Box<str>,
+    pub runs: Vec<Run>,
+}
+
+/// runs every expression of a corpus with each strategy, to pick the limits it needs
+#[derive(Debug, Default, Clone)]
+pub struct Tuning {
+    pub cases: Vec<Case>,
+    /// the lines that couldn't be run, with the code of their error
+    pub errors: Vec<(PathBuf, Box<str>, String)>,
+}
+
+impl Tuning {
+    /// runs every `.qk` file inside `dir`, recursively
+    pub fn from_dir(dir: impl AsRef<Path>) -> std::io::Result<Self> {
+        let mut paths = Vec::new();
+        collect_files(dir.as_ref(), &mut paths)?;
+        paths.sort();
+        let mut tuning = Self::default();
+        for path in paths {
+            let src = std::fs::read_to_string(&path)?;
+            tuning.push(path, &src);
+        }
+        Ok(tuning)
+    }
+
+    /// runs a file a line at a time, on a new session: definitions are added to it, and
+    /// expressions are timed with every strategy
+    pub fn push(&mut self, path: PathBuf, src: &str) {
+        let mut s = Session::new();
+        for line in src.lines().filter(|l| !l.trim().is_empty()) {
+            let runs = s.compile_expr(line).and_then(|root| {
+                root.map(|root| STRATEGIES.iter().map(|st| run(&mut s, root, st)).collect())
+                    .transpose()
+            });
+            match runs {
+                Ok(Some(runs)) => self.cases.push(Case {
+                    path: path.clone(),
+                    expr: line.into(),
+                    runs,
+                }),
+                Ok(None) => (),
+                Err(e) => {
+                    let code = e.code().map_or_else(|| "unknown".into(), |c| c.to_string());
+                    self.errors.push((path.clone(), line.into(), code));
+                }
+            }
+        }
+    }
+
+    /// the runs of `strategy`
+    fn runs(&self, strategy: &str) -> impl Iterator<Item = &Run> {
+        self.cases
+            .iter()
+            .flat_map(|c| &c.runs)
+            .filter(move |r| r.strategy == strategy)
+    }
+
+    /// how many expressions `strategy` normalized, in how many steps and how long
+    pub fn totals(&self, strategy: &str) -> (usize, usize, Duration) {
+        self.runs(strategy)
+            .filter_map(|r| Some((r.steps?, r.time)))
+            .fold((0, 0, Duration::ZERO), |(n, steps, time), (s, t)| {
+                (n + 1, steps + s, time + t)
+            })
+    }
+
+    /// the `budget` and `timeout` that let every expression normalizing under the session's
+    /// strategy do it with room to spare, as `set` takes them. Nothing if none normalizes
+    pub fn recommended(&self) -> Vec<(&'static str, String)> {
+        let normalized: Vec<_> = self
+            .runs(STRATEGIES[0])
+            .filter_map(|r| Some((r.steps?, r.time)))
+            .collect();
+        let Some(steps) = normalized.iter().map(|(s, _)| *s).max() else {
+            return Vec::new();
+        };
+        let time = normalized.iter().map(|(_, t)| *t).max().unwrap_or_default();
+        // twice the steps, and a timeout that a slower machine or a busier moment still meets
+        let budget = (steps * 2).max(1_000);
+        let timeout = (time.as_millis() * 4).max(100);
+        vec![
+            ("budget", budget.to_string()),
+            ("timeout", timeout.to_string()),
+        ]
+    }
+
+    /// a line per strategy, then the recommended settings as `:set` commands
+    pub fn to_text(&self) -> String {
+        let mut s = String::new();
+        let _ = writeln!(
+            s,
+            "{} expressions, {} errors",
+            self.cases.len(),
+            self.errors.len()
+        );
+        for strategy in STRATEGIES {
+            let (n, steps, time) = self.totals(strategy);
+            let _ = writeln!(
+                s,
+                "{strategy:<12} {n:>5} normalized {steps:>10} steps {:>10.2?}",
+                time
+            );
+        }
+        for (setting, value) in self.recommended() {
+            let _ = writeln!(s, ":set {setting} {value}");
+        }
+        s
+    }
+}
+
+/// reduces `root` with `strategy`, timing it
+fn run(s: &mut Session, root: TermIdx, strategy: &'static str) -> session::Result<Run> {
+    let mut token = CancelToken::new().with_budget(EXPLORE_BUDGET).start();
+    let start = Instant::now();
+    let reduced = match strategy {
+        "applicative" => s.reduction::<Applicative>(root, &mut token),
+        "need" => s.reduction::<Need>(root, &mut token),
+        _ => s.reduction::<Normal>(root, &mut token),
+    };
+    let time = start.elapsed();
+    let steps = match reduced {
+        Ok(steps) => Some(steps.len() - 1),
+        Err(session::Error::Cancelled(_)) => None,
+        Err(e) => return Err(e),
+    };
+    Ok(Run {
+        strategy,
+        steps,
+        time,
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::tune::Tuning;
+
+    #[test]
+    pub fn tunes_a_corpus() {
+        let mut t = Tuning::default();
+        t.push(
+            "a.qk".into(),
+            "I x = x\nK x y = x\nD x = x x\nK I (D D)\nK I I\nZ I\n",
+        );
+        assert_eq!(t.cases.len(), 2);
+        assert_eq!(t.errors.len(), 1);
+        // only the normal order skips the divergent argument
+        assert!(t.cases[0].runs[0].steps.is_some());
+        assert!(t.cases[0].runs[1].steps.is_none());
+        let (n, ..) = t.totals("applicative");
+        assert_eq!(n, 1);
+        let budget = &t.recommended()[0];
+        assert_eq!(budget, &("budget", "1000".to_string()));
+    }
+}
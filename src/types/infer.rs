@@ -0,0 +1,705 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use miette::SourceSpan;
+
+use crate::arts::Const;
+use crate::ir::{self, Id, IrComponent, IrObj, Scope};
+use crate::lexer::{Meta, Trace};
+use crate::provenance::Sugar;
+use crate::types::{Error, Rebuild, Result, Type};
+
+/// the typing rule that gave a subterm its type. Read as logic, the type is the formula the
+/// subterm proves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    /// a variable bound by an abstraction
+    Hypothesis,
+    /// a reference to a definition, i. e, a lemma
+    Definition,
+    /// an abstraction: `⇒` introduction
+    Intro,
+    /// an application: `⇒` elimination, or modus ponens
+    Elim,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Hypothesis => "hyp",
+            Self::Definition => "def",
+            Self::Intro => "⇒I",
+            Self::Elim => "⇒E",
+        };
+        f.pad(s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub at: SourceSpan,
+    pub rule: Rule,
+    pub ty: Type,
+    /// the subterm, printed. Only `Session::infer` has the source to fill it in
+    pub term: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Typing {
+    pub ty: Type,
+    /// the type of each subterm, from the outermost one
+    pub steps: Vec<Step>,
+}
+
+/// the principal simple type of `ir`, à la Curry: no annotations are needed. Each use of a
+/// definition gets a fresh copy of its type, which is taken from `signatures` when it's there
+pub fn infer(scope: &Scope, signatures: &HashMap<Id, Type>, ir: &IrObj) -> Result<Typing> {
+    run(scope, signatures, ir, false)
+}
+
+/// like `infer`, but binders may be annotated with `dyn`, which fits any type
+pub fn infer_gradual(scope: &Scope, signatures: &HashMap<Id, Type>, ir: &IrObj) -> Result<Typing> {
+    run(scope, signatures, ir, true)
+}
+
+/// the type `infer` or `infer_gradual` gives, without the type of each subterm. Those are as
+/// big as the term together, so deep terms are only typed through here
+pub fn infer_type(
+    scope: &Scope,
+    signatures: &HashMap<Id, Type>,
+    ir: &IrObj,
+    gradual: bool,
+) -> Result<Type> {
+    infer_casts(scope, signatures, ir, gradual).map(|(ty, _)| ty)
+}
+
+/// a `dyn` value used as a function, which is checked to be one when the term runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cast {
+    /// the subterm cast, by its position in the term from the outermost one, left to right
+    pub node: usize,
+    /// what's blamed if it fails: the annotation the value or the function it's given to comes
+    /// from, or else the subterm itself
+    pub at: SourceSpan,
+}
+
+/// like `infer_type`, also giving where the term needs a cast, in order
+pub fn infer_casts(
+    scope: &Scope,
+    signatures: &HashMap<Id, Type>,
+    ir: &IrObj,
+    gradual: bool,
+) -> Result<(Type, Vec<Cast>)> {
+    let mut inf = Infer::new(scope, signatures, gradual);
+    let (ty, apps) = inf.infer(ir, false)?;
+    let casts = apps.into_iter().filter_map(|app| inf.cast(app)).collect();
+    Ok((inf.canonical(&ty, &mut HashMap::new()), casts))
+}
+
+/// wraps each subterm of `ir` that `casts` points to in a `IrComponent::Cast`, blaming its span.
+/// Goes through the term in the order `infer_casts` numbers it
+pub fn insert_casts(ir: &mut IrObj, casts: &[Cast]) {
+    let mut casts = casts.iter().peekable();
+    let mut stack = vec![ir];
+    let mut node = 0;
+    while let Some(mut ir) = stack.pop() {
+        // the casts of an earlier inference aren't counted
+        let earlier = matches!(ir.item, IrComponent::Cast(_));
+        if !earlier && casts.peek().is_some_and(|c| c.node == node) {
+            let at = casts.next().expect("it was peeked").at;
+            let inner = Box::new(Meta {
+                item: std::mem::take(&mut ir.item),
+                at: ir.at,
+                sugar: ir.sugar,
+            });
+            **ir = *IrComponent::Cast(inner).at(at);
+            ir = match &mut ir.item {
+                IrComponent::Cast(inner) => inner,
+                _ => unreachable!("it was just cast"),
+            };
+        }
+        node += usize::from(!earlier);
+        // typed as a whole, as `infer` does
+        if ir.sugar == Some(Sugar::Unit) {
+            continue;
+        }
+        match &mut ir.item {
+            IrComponent::App(l, r) => stack.extend([r, l]),
+            IrComponent::Abs(_, inner) | IrComponent::Def(inner) | IrComponent::Cast(inner) => {
+                stack.push(inner)
+            }
+            _ => (),
+        }
+    }
+}
+
+fn run(scope: &Scope, signatures: &HashMap<Id, Type>, ir: &IrObj, gradual: bool) -> Result<Typing> {
+    let mut inf = Infer::new(scope, signatures, gradual);
+    let (ty, _) = inf.infer(ir, true)?;
+    let mut names = HashMap::new();
+    let ty = inf.canonical(&ty, &mut names);
+    let steps = std::mem::take(&mut inf.steps)
+        .into_iter()
+        .map(|(at, rule, ty)| Step {
+            at,
+            rule,
+            ty: inf.canonical(&ty, &mut names),
+            term: String::new(),
+        })
+        .collect();
+    Ok(Typing { ty, steps })
+}
+
+/// why two types can't be unified
+enum Conflict {
+    /// the variable would have to contain itself
+    Occurs(Type, Type),
+    Mismatch(Type, Type),
+}
+
+/// what's left to do with a subterm, once the ones under it are typed
+enum Task<'a> {
+    Visit(&'a IrObj),
+    /// the parameter's type, the step to fill in and the position of the subterm
+    Abs(Type, Option<usize>, usize),
+    App(&'a IrObj, Option<usize>, usize),
+}
+
+/// an application whose sides may need a cast, once every type is known
+struct App {
+    /// the position of each side, as `Cast::node` counts them
+    nodes: (usize, usize),
+    types: (Type, Type),
+    /// what each side's cast would blame
+    blame: (SourceSpan, SourceSpan),
+    /// whether the function is known to be one without running it
+    function: bool,
+}
+
+struct Infer<'a> {
+    scope: &'a Scope,
+    signatures: &'a HashMap<Id, Type>,
+    gradual: bool,
+    subst: HashMap<Box<str>, Type>,
+    fresh: usize,
+    bindings: HashMap<Id, Type>,
+    /// the generalized type of each definition already seen
+    defs: HashMap<Id, Type>,
+    steps: Vec<(SourceSpan, Rule, Type)>,
+}
+
+impl<'a> Infer<'a> {
+    fn new(scope: &'a Scope, signatures: &'a HashMap<Id, Type>, gradual: bool) -> Self {
+        Self {
+            scope,
+            signatures,
+            gradual,
+            subst: HashMap::new(),
+            fresh: 0,
+            bindings: HashMap::new(),
+            defs: HashMap::new(),
+            steps: Vec::new(),
+        }
+    }
+
+    fn fresh(&mut self) -> Type {
+        self.fresh += 1;
+        Type::Var(format!("?{}", self.fresh).into())
+    }
+
+    /// `record` is off inside definitions, since their spans belong to another source. Works
+    /// through an explicit stack, so any nesting depth fits. Along with the type, gives every
+    /// application of the term, for `cast`
+    fn infer(&mut self, ir: &IrObj, record: bool) -> Result<(Type, Vec<App>)> {
+        let mut tasks = vec![Task::Visit(ir)];
+        // each type with the position of its subterm
+        let mut done = Vec::new();
+        let mut apps = Vec::new();
+        let mut node = 0;
+        while let Some(task) = tasks.pop() {
+            match task {
+                // inserted by an earlier inference, and as transparent to this one
+                Task::Visit(ir) if let IrComponent::Cast(inner) = &ir.item => {
+                    tasks.push(Task::Visit(inner));
+                }
+                // `()` is the `unit` primitive, written as the term it is
+                Task::Visit(ir) if ir.sugar == Some(Sugar::Unit) => {
+                    if record {
+                        self.steps.push((ir.at, Rule::Definition, Type::Unit));
+                    }
+                    done.push((Type::Unit, node));
+                    node += 1;
+                }
+                Task::Visit(ir) => {
+                    let step = (record && !matches!(ir.item, IrComponent::Def(..))).then(|| {
+                        let placeholder = self.fresh();
+                        self.steps.push((ir.at, self.rule(ir), placeholder));
+                        self.steps.len() - 1
+                    });
+                    let at = node;
+                    node += 1;
+                    let done = &mut done;
+                    match &ir.item {
+                        IrComponent::Var(id) => {
+                            let ty = self.var(*id, ir.at)?;
+                            self.typed(step, ty, at, done);
+                        }
+                        IrComponent::Abs(v, inner) => {
+                            let param = match self.scope.annotations.get(v) {
+                                Some(a) if !self.gradual && mentions_dyn(&a.ty) => {
+                                    return Err(Error::DynAnnotation { at: a.at });
+                                }
+                                Some(a) => self.instantiate(&a.ty, &mut HashMap::new()),
+                                None => self.fresh(),
+                            };
+                            self.bindings.insert(*v, param.clone());
+                            tasks.push(Task::Abs(param, step, at));
+                            tasks.push(Task::Visit(inner));
+                        }
+                        IrComponent::App(l, r) => {
+                            tasks.push(Task::App(ir, step, at));
+                            tasks.push(Task::Visit(r));
+                            tasks.push(Task::Visit(l));
+                        }
+                        IrComponent::Def(body) => tasks.push(Task::Visit(body)),
+                        // the types know nothing about numbers, so they're as unknown as in the
+                        // gradual mode
+                        IrComponent::Const(_) => self.typed(step, Type::Dyn, at, done),
+                        IrComponent::Pending | IrComponent::Binding | IrComponent::Cast(_) => {
+                            return Err(ir::Error::UndeclaredVariable { at: ir.at }.into());
+                        }
+                    }
+                }
+                Task::Abs(param, step, at) => {
+                    let (body, _) = done.pop().expect("the body was typed");
+                    self.typed(step, Type::arrow(param, body), at, &mut done);
+                }
+                Task::App(app, step, at) => {
+                    let IrComponent::App(l, r) = &app.item else {
+                        unreachable!("only applications are pushed as `Task::App`")
+                    };
+                    let (r_ty, r_node) = done.pop().expect("the argument was typed");
+                    let (l_ty, l_node) = done.pop().expect("the function was typed");
+                    apps.push(App {
+                        nodes: (l_node, r_node),
+                        types: (l_ty.clone(), r_ty.clone()),
+                        blame: self.blame(l, r),
+                        function: is_function(l),
+                    });
+                    let ty = if matches!(self.head(l_ty.clone()), Type::Dyn) {
+                        // nothing is known about what it returns either
+                        Type::Dyn
+                    } else {
+                        let ret = self.fresh();
+                        let expected = Type::arrow(r_ty.clone(), ret.clone());
+                        if let Err(c) = self.unify(&l_ty, &expected) {
+                            return Err(self.report(c, app, l, r, &l_ty, &r_ty));
+                        }
+                        ret
+                    };
+                    self.typed(step, ty, at, &mut done);
+                }
+            }
+        }
+        let (ty, _) = done.pop().expect("the root was typed");
+        Ok((ty, apps))
+    }
+
+    /// what a cast of `l` or of `r`, applied to it, blames: the annotation of the variable or
+    /// abstraction they come from, if there's one, or else themselves
+    fn blame(&self, l: &IrObj, r: &IrObj) -> (SourceSpan, SourceSpan) {
+        let annotation = |ir: &IrObj| match &ir.item {
+            IrComponent::Var(v) | IrComponent::Abs(v, _) => self.scope.annotations.get(v),
+            _ => None,
+        };
+        let l_blame = match &l.item {
+            IrComponent::Var(_) => annotation(l).map(|a| a.at),
+            _ => None,
+        };
+        let r_blame = annotation(l).map(|a| a.at);
+        (l_blame.unwrap_or(l.at), r_blame.unwrap_or(r.at))
+    }
+
+    /// the cast `app` needs, now that every type is known: of the function, if it's `dyn`, or of
+    /// the argument, if it's `dyn` and the function takes one of a known shape. Every shape is a
+    /// function, once encoded
+    fn cast(&mut self, app: App) -> Option<Cast> {
+        let (l_ty, r_ty) = app.types;
+        let (l_node, r_node) = app.nodes;
+        let (l_blame, r_blame) = app.blame;
+        match &self.head(l_ty) {
+            Type::Dyn if app.function => None,
+            Type::Dyn => Some(Cast {
+                node: l_node,
+                at: l_blame,
+            }),
+            Type::Arrow(param, _) => {
+                let shaped = !matches!(self.head((**param).clone()), Type::Var(_) | Type::Dyn);
+                (shaped && matches!(self.head(r_ty), Type::Dyn)).then_some(Cast {
+                    node: r_node,
+                    at: r_blame,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn rule(&self, ir: &IrObj) -> Rule {
+        match &ir.item {
+            IrComponent::Var(id) => match &self.scope.res_pool[id.0].item {
+                IrComponent::Binding => Rule::Hypothesis,
+                _ => Rule::Definition,
+            },
+            IrComponent::Abs(..) => Rule::Intro,
+            IrComponent::App(..) => Rule::Elim,
+            _ => Rule::Definition,
+        }
+    }
+
+    /// fills in the placeholder of the subterm's step, if it was recorded
+    fn typed(&mut self, step: Option<usize>, ty: Type, node: usize, done: &mut Vec<(Type, usize)>) {
+        if let Some(step) = step {
+            self.steps[step].2 = ty.clone();
+        }
+        done.push((ty, node));
+    }
+
+    fn var(&mut self, id: Id, at: SourceSpan) -> Result<Type> {
+        let res = &self.scope.res_pool[id.0];
+        match &res.item {
+            IrComponent::Binding => Ok(self.bindings[&id].clone()),
+            IrComponent::Pending => Err(ir::Error::UndeclaredVariable { at }.into()),
+            _ => {
+                let ty = match self.signatures.get(&id).or_else(|| self.defs.get(&id)) {
+                    Some(ty) => ty.clone(),
+                    None => {
+                        // a definition is closed, so anything left unknown is generic
+                        let (ty, _) = self.infer(res, false)?;
+                        let ty = self.resolve(&ty);
+                        self.defs.insert(id, ty.clone());
+                        ty
+                    }
+                };
+                Ok(self.instantiate(&ty, &mut HashMap::new()))
+            }
+        }
+    }
+
+    fn instantiate(&mut self, ty: &Type, fresh: &mut HashMap<Box<str>, Type>) -> Type {
+        ty.rebuild(|t| match t {
+            Type::Var(v) => Rebuild::Replace(match fresh.get(v) {
+                Some(t) => t.clone(),
+                None => {
+                    let t = self.fresh();
+                    fresh.insert(v.clone(), t.clone());
+                    t
+                }
+            }),
+            _ => Rebuild::Keep,
+        })
+    }
+
+    /// applies every substitution found so far
+    fn resolve(&self, ty: &Type) -> Type {
+        ty.rebuild(|t| match t {
+            Type::Var(v) if let Some(t) = self.subst.get(v) => Rebuild::Visit(t),
+            _ => Rebuild::Keep,
+        })
+    }
+
+    /// follows the substitutions of `ty` up to its outermost constructor, leaving its sides as
+    /// they are. The variables on the way are pointed straight at it, so no chain is followed twice
+    fn head(&mut self, mut ty: Type) -> Type {
+        let mut chain = Vec::new();
+        while let Type::Var(v) = &ty
+            && let Some(t) = self.subst.get(v)
+        {
+            chain.push(v.clone());
+            ty = t.clone();
+        }
+        // the last one already points at it
+        chain.pop();
+        for v in chain {
+            self.subst.insert(v, ty.clone());
+        }
+        ty
+    }
+
+    fn occurs(&self, v: &str, ty: &Type) -> bool {
+        let mut pending = vec![ty];
+        // the substitutions may share variables, which only need a look each
+        let mut seen = HashSet::new();
+        while let Some(ty) = pending.pop() {
+            match ty {
+                Type::Var(o) if !seen.insert(o) => {}
+                Type::Var(o) => match self.subst.get(o) {
+                    Some(t) => pending.push(t),
+                    None if **o == *v => return true,
+                    None => {}
+                },
+                ty => pending.extend(ty.split().into_iter().flat_map(|(l, r, _)| [r, l])),
+            }
+        }
+        false
+    }
+
+    fn unify(&mut self, l: &Type, r: &Type) -> std::result::Result<(), Conflict> {
+        let var = |t: &Type| match t {
+            Type::Var(v) => Some(v.clone()),
+            _ => None,
+        };
+        let mut pairs = vec![(l.clone(), r.clone())];
+        while let Some((l, r)) = pairs.pop() {
+            let (l, r) = (self.head(l), self.head(r));
+            match (var(&l), var(&r)) {
+                (Some(l), Some(r)) if l == r => {}
+                (Some(v), _) => self.bind(v, r)?,
+                (_, Some(v)) => self.bind(v, l)?,
+                // consistency, as in gradual typing: `dyn` fits anywhere
+                _ if matches!(l, Type::Dyn) || matches!(r, Type::Dyn) => {}
+                _ if std::mem::discriminant(&l) == std::mem::discriminant(&r) => {
+                    if let (Some((ll, lr)), Some((rl, rr))) = (l.into_sides(), r.into_sides()) {
+                        pairs.push((lr, rr));
+                        pairs.push((ll, rl));
+                    }
+                }
+                _ => return Err(Conflict::Mismatch(self.resolve(&l), self.resolve(&r))),
+            }
+        }
+        Ok(())
+    }
+
+    fn bind(&mut self, v: Box<str>, ty: Type) -> std::result::Result<(), Conflict> {
+        if self.occurs(&v, &ty) {
+            return Err(Conflict::Occurs(Type::Var(v), self.resolve(&ty)));
+        }
+        self.subst.insert(v, ty);
+        Ok(())
+    }
+
+    /// turns a conflict found while applying `l` to `r` into an error pointing at both of them
+    fn report(
+        &self,
+        c: Conflict,
+        app: &IrObj,
+        l: &IrObj,
+        r: &IrObj,
+        l_ty: &Type,
+        r_ty: &Type,
+    ) -> Error {
+        let mut names = HashMap::new();
+        let func_ty = self.canonical(l_ty, &mut names);
+        let arg_ty = self.canonical(r_ty, &mut names);
+        match c {
+            Conflict::Occurs(v, ty) => Error::NotTypable {
+                at: app.at,
+                l: self.canonical(&v, &mut names),
+                r: self.canonical(&ty, &mut names),
+            },
+            // the sides of a lowered application may not be anything written
+            Conflict::Mismatch(expected, found) if let Some(sugar) = app.sugar => {
+                Error::LoweredMismatch {
+                    at: app.at,
+                    sugar,
+                    l: self.canonical(&expected, &mut names),
+                    r: self.canonical(&found, &mut names),
+                    func_ty: func_ty.into(),
+                    arg_ty: arg_ty.into(),
+                }
+            }
+            Conflict::Mismatch(expected, found) => Error::Mismatch {
+                func: l.at,
+                arg: r.at,
+                l: self.canonical(&expected, &mut names),
+                r: self.canonical(&found, &mut names),
+                func_ty: func_ty.into(),
+                arg_ty: arg_ty.into(),
+            },
+        }
+    }
+
+    /// resolves `ty` and renames its variables to `a`, `b`, ... in the order they're found
+    fn canonical(&self, ty: &Type, names: &mut HashMap<Box<str>, Box<str>>) -> Type {
+        ty.rebuild(|t| match t {
+            Type::Var(v) => match self.subst.get(v) {
+                Some(t) => Rebuild::Visit(t),
+                None => {
+                    let next = Scope::id_to_str(&Id(names.len()));
+                    Rebuild::Replace(Type::Var(
+                        names
+                            .entry(v.clone())
+                            .or_insert_with(|| next.into())
+                            .clone(),
+                    ))
+                }
+            },
+            _ => Rebuild::Keep,
+        })
+    }
+}
+
+/// whether `ty` has `dyn` anywhere in it
+fn mentions_dyn(ty: &Type) -> bool {
+    let mut pending = vec![ty];
+    while let Some(ty) = pending.pop() {
+        match ty.split() {
+            Some((l, r, _)) => pending.extend([l, r]),
+            None if *ty == Type::Dyn => return true,
+            None => (),
+        }
+    }
+    false
+}
+
+/// whether `ir` is a function without running it: an abstraction, or a primitive given fewer
+/// arguments than it takes
+fn is_function(mut ir: &IrObj) -> bool {
+    let mut args = 0;
+    loop {
+        match &ir.item {
+            IrComponent::Abs(..) => return args == 0,
+            IrComponent::Const(Const::Prim(p)) => return args < p.arity(),
+            IrComponent::App(l, _) => {
+                args += 1;
+                ir = l;
+            }
+            _ => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use miette::Diagnostic;
+
+    use crate::session::Session;
+    use crate::types::infer::{Rule, infer, infer_casts, infer_gradual};
+
+    fn type_of(s: &mut Session, src: &str) -> String {
+        let tokens = s.lexer(src).unwrap();
+        let ast = s.parse(&tokens, src).unwrap();
+        let ir = s.ir(ast, src).unwrap().unwrap();
+        match infer(&s.irc.scope, &s.signatures, &ir) {
+            Ok(t) => t.ty.to_string(),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    pub fn principal_types() {
+        let mut s = Session::new();
+        s.eval_str("K x y = x\nS x y z = x z (y z)").unwrap();
+        assert_eq!(type_of(&mut s, "fn x => x"), "a -> a");
+        assert_eq!(type_of(&mut s, "K"), "a -> b -> a");
+        assert_eq!(type_of(&mut s, "S K K"), "a -> a");
+        assert_eq!(type_of(&mut s, "K K"), "a -> b -> c -> b");
+        assert_eq!(type_of(&mut s, "fn f x => f (f x)"), "(a -> a) -> a -> a");
+        assert_eq!(
+            type_of(&mut s, "fn x => x x"),
+            "this term has no simple type"
+        );
+    }
+
+    #[test]
+    pub fn steps_follow_the_source() {
+        let mut s = Session::new();
+        let src = "fn f x => f x";
+        let tokens = s.lexer(src).unwrap();
+        let ast = s.parse(&tokens, src).unwrap();
+        let ir = s.ir(ast, src).unwrap().unwrap();
+        let typing = infer(&s.irc.scope, &s.signatures, &ir).unwrap();
+        let rules: Vec<_> = typing.steps.iter().map(|s| s.rule).collect();
+        assert_eq!(
+            rules,
+            [
+                Rule::Intro,
+                Rule::Intro,
+                Rule::Elim,
+                Rule::Hypothesis,
+                Rule::Hypothesis
+            ]
+        );
+        assert_eq!(typing.steps[0].ty, typing.ty);
+        assert_eq!(typing.steps[2].ty.to_string(), "b");
+    }
+
+    #[test]
+    pub fn mismatches_point_at_both_sides() {
+        let mut s = Session::new();
+        s.set_typed(true).unwrap();
+        let src = "fst (inl unit)";
+        let tokens = s.lexer(src).unwrap();
+        let ast = s.parse(&tokens, src).unwrap();
+        let ir = s.ir(ast, src).unwrap().unwrap();
+        let e = infer(&s.irc.scope, &s.signatures, &ir).unwrap_err();
+        let labels: Vec<_> = e
+            .labels()
+            .unwrap()
+            .map(|l| (l.offset(), l.label().unwrap().to_string()))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                (0, "this function has type `a * b -> a`".into()),
+                (4, "but its argument has type `unit + c`".into())
+            ]
+        );
+    }
+
+    #[test]
+    pub fn gradual() {
+        let mut s = Session::new();
+        s.eval_str("U = fn (x : dyn) => x x\nfirst x y = x")
+            .unwrap();
+        let src = "fn z => first z (U U)";
+        let tokens = s.lexer(src).unwrap();
+        let ast = s.parse(&tokens, src).unwrap();
+        let ir = s.ir(ast, src).unwrap().unwrap();
+        assert!(infer(&s.irc.scope, &s.signatures, &ir).is_err());
+        let typing = infer_gradual(&s.irc.scope, &s.signatures, &ir).unwrap();
+        assert_eq!(typing.ty.to_string(), "a -> a");
+    }
+
+    #[test]
+    pub fn untypable_in_gradual() {
+        let mut s = Session::new();
+        s.eval_str("U x = x x").unwrap();
+        let src = "U";
+        let tokens = s.lexer(src).unwrap();
+        let ast = s.parse(&tokens, src).unwrap();
+        let ir = s.ir(ast, src).unwrap().unwrap();
+        assert!(infer_gradual(&s.irc.scope, &s.signatures, &ir).is_err());
+    }
+
+    #[test]
+    pub fn casts() {
+        let mut s = Session::new();
+        let src = "fn (f : dyn) (g : a -> a) => g (f f)";
+        let tokens = s.lexer(src).unwrap();
+        let ast = s.parse(&tokens, src).unwrap();
+        let ir = s.ir(ast, src).unwrap().unwrap();
+        let (ty, casts) = infer_casts(&s.irc.scope, &s.signatures, &ir, true).unwrap();
+        assert_eq!(ty.to_string(), "dyn -> (dyn -> dyn) -> dyn");
+        // the annotation of `f`, as the function of `f f`. None for `g`, whose parameter can be
+        // anything
+        let at: Vec<_> = casts
+            .iter()
+            .map(|c| &src[c.at.offset()..][..c.at.len()])
+            .collect();
+        assert_eq!(at, ["dyn"]);
+    }
+
+    #[test]
+    pub fn deep_terms() {
+        const DEPTH: usize = 30_000;
+        let mut s = Session::new();
+        s.set_typed(true).unwrap();
+        let src = format!("deep = {}x", "fn x => ".repeat(DEPTH));
+        s.eval_str(&src).unwrap();
+        let ty = s.definition_type(s.irc.scope.definitions["deep"]).unwrap();
+        let (params, ret) = ty.uncurry();
+        assert_eq!(params.len(), DEPTH);
+        assert_eq!(ret, params[DEPTH - 1]);
+        assert!(ty.to_string().starts_with("a -> b -> c"));
+    }
+}
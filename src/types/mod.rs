@@ -0,0 +1,444 @@
+// Type =
+//      Sum ("->" Type)?
+// Sum =
+//      Product ("+" Sum)?
+// Product =
+//      Atom ("*" Product)?
+// Atom =
+//      "(" Type ")"
+//      "(" ")"
+//      <Ident>
+
+use std::collections::HashMap;
+use std::fmt;
+
+use miette::{Diagnostic, SourceSpan};
+use thiserror::Error;
+
+use crate::padam::{
+    Token,
+    lexer::{
+        CLOSE_PAREN_TY, IDENT_TY, Lexer, OPEN_PAREN_TY, Tokenizer, ident, literal, single_char,
+    },
+    parser,
+};
+use crate::provenance::Sugar;
+
+pub mod infer;
+pub mod primitives;
+pub mod synth;
+pub mod system_f;
+
+pub const ARROW_TY: &str = "Arrow";
+pub const PRODUCT_TY: &str = "Product";
+pub const SUM_TY: &str = "Sum";
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Error, Diagnostic, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Lexer(#[from] crate::lexer::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Parser(#[from] parser::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Ir(#[from] crate::ir::Error),
+
+    #[error("this term has no simple type")]
+    #[diagnostic(
+        code(types::not_typable),
+        help(
+            "`{l}` would need to be the same as `{r}`. Self-application, like `x x`, is the usual suspect"
+        )
+    )]
+    NotTypable {
+        #[label("no type fits here")]
+        at: SourceSpan,
+        l: Type,
+        r: Type,
+    },
+
+    #[error("mismatched types")]
+    #[diagnostic(code(types::mismatch), help("expected `{l}`, but found `{r}`"))]
+    Mismatch {
+        #[label("this function has type `{func_ty}`")]
+        func: SourceSpan,
+        #[label("but its argument has type `{arg_ty}`")]
+        arg: SourceSpan,
+        l: Type,
+        r: Type,
+        func_ty: Box<Type>,
+        arg_ty: Box<Type>,
+    },
+
+    #[error("mismatched types in this {sugar}")]
+    #[diagnostic(
+        code(types::lowered_mismatch),
+        help("{}: expected `{l}`, but found `{r}`", sugar.lowering())
+    )]
+    LoweredMismatch {
+        #[label("it applies `{func_ty}` to `{arg_ty}`")]
+        at: SourceSpan,
+        sugar: Sugar,
+        l: Type,
+        r: Type,
+        func_ty: Box<Type>,
+        arg_ty: Box<Type>,
+    },
+
+    #[error("`{name}` doesn't match its signature")]
+    #[diagnostic(
+        code(types::signature_mismatch),
+        help("it was declared as `{declared}`, but its most general type is `{inferred}`")
+    )]
+    SignatureMismatch {
+        name: Box<str>,
+        declared: Box<Type>,
+        inferred: Box<Type>,
+        /// the declared type, in the signature it's written in
+        #[label("declared here")]
+        declared_at: Option<SourceSpan>,
+        /// the definition, when it's in the same source as the signature
+        #[label("but defined here")]
+        defined_at: Option<SourceSpan>,
+    },
+
+    #[error("`{name}` is declared, but never defined")]
+    #[diagnostic(code(types::undefined_signature))]
+    Undefined { name: Box<str> },
+
+    #[error("`dyn` is only known to the gradual mode")]
+    #[diagnostic(
+        code(types::dyn_annotation),
+        help("`set typed gradual` allows it, checking the values when the term runs")
+    )]
+    DynAnnotation {
+        #[label("this annotation")]
+        at: SourceSpan,
+    },
+
+    #[error("no inhabitant of {ty} was found")]
+    #[diagnostic(
+        code(types::no_inhabitant),
+        help("either the type is empty or its terms are bigger than the search bound")
+    )]
+    NoInhabitant { ty: Type },
+}
+
+/// builds a compound type out of its two sides
+pub type Constructor = fn(Type, Type) -> Type;
+
+/// what `Type::rebuild` does with a subtype
+pub enum Rebuild<'a> {
+    /// takes it apart, or clones it if it has no sides
+    Keep,
+    /// rebuilds another type in its place
+    Visit(&'a Type),
+    /// puts this one in its place
+    Replace(Type),
+}
+
+/// a simple type. Types are as deep as the terms they're inferred from, so they're built, taken
+/// apart and printed without recursion
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub enum Type {
+    /// a type variable. Works as a base type
+    Var(Box<str>),
+    Arrow(Box<Type>, Box<Type>),
+    /// `a * b`, the type of `pair x y`
+    Product(Box<Type>, Box<Type>),
+    /// `a + b`, the type of `inl x` and `inr y`
+    Sum(Box<Type>, Box<Type>),
+    /// `unit` or `()`, the type of `unit`. Read as logic, it's always true
+    Unit,
+    /// `void`, with no values at all. Read as logic, it's always false
+    Void,
+    /// `dyn`, the unknown type of the gradual mode. It's consistent with every other type, and
+    /// checked when the term runs
+    Dyn,
+}
+
+impl Type {
+    pub fn arrow(l: Type, r: Type) -> Self {
+        Self::Arrow(l.into(), r.into())
+    }
+
+    pub fn product(l: Type, r: Type) -> Self {
+        Self::Product(l.into(), r.into())
+    }
+
+    pub fn sum(l: Type, r: Type) -> Self {
+        Self::Sum(l.into(), r.into())
+    }
+
+    /// the two sides of a compound type, along with its constructor
+    pub fn split(&self) -> Option<(&Type, &Type, Constructor)> {
+        match self {
+            Self::Var(_) | Self::Unit | Self::Void | Self::Dyn => None,
+            Self::Arrow(l, r) => Some((l, r, Self::arrow)),
+            Self::Product(l, r) => Some((l, r, Self::product)),
+            Self::Sum(l, r) => Some((l, r, Self::sum)),
+        }
+    }
+
+    /// takes a compound type apart into its two sides
+    pub fn into_sides(mut self) -> Option<(Type, Type)> {
+        match &mut self {
+            Self::Arrow(l, r) | Self::Product(l, r) | Self::Sum(l, r) => Some((
+                std::mem::replace(l, Self::Unit),
+                std::mem::replace(r, Self::Unit),
+            )),
+            _ => None,
+        }
+    }
+
+    /// copies the type from the outermost subtype in, asking `f` what to do with each
+    pub fn rebuild<'a>(&'a self, mut f: impl FnMut(&'a Type) -> Rebuild<'a>) -> Type {
+        enum Task<'a> {
+            Visit(&'a Type),
+            Build(Constructor),
+        }
+        let mut tasks = vec![Task::Visit(self)];
+        let mut done = Vec::new();
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Visit(ty) => match f(ty) {
+                    Rebuild::Visit(other) => tasks.push(Task::Visit(other)),
+                    Rebuild::Replace(other) => done.push(other),
+                    Rebuild::Keep => match ty.split() {
+                        Some((l, r, build)) => {
+                            tasks.push(Task::Build(build));
+                            tasks.push(Task::Visit(r));
+                            tasks.push(Task::Visit(l));
+                        }
+                        None => done.push(ty.clone()),
+                    },
+                },
+                Task::Build(build) => {
+                    let r = done.pop().expect("the right side was built");
+                    let l = done.pop().expect("the left side was built");
+                    done.push(build(l, r));
+                }
+            }
+        }
+        done.pop().expect("the type was built")
+    }
+
+    /// whether `self` is `general` with its variables replaced, each by the same type everywhere
+    pub fn instance_of(&self, general: &Type) -> bool {
+        self.matches(general, &mut HashMap::new())
+    }
+
+    fn matches(&self, general: &Type, subst: &mut HashMap<Box<str>, Type>) -> bool {
+        match (self, general) {
+            (_, Self::Var(v)) => match subst.get(v) {
+                Some(ty) => ty == self,
+                None => {
+                    subst.insert(v.clone(), self.clone());
+                    true
+                }
+            },
+            (Self::Dyn, _) | (_, Self::Dyn) => true,
+            _ => match (self.split(), general.split()) {
+                (Some((l, r, _)), Some((gl, gr, _)))
+                    if std::mem::discriminant(self) == std::mem::discriminant(general) =>
+                {
+                    l.matches(gl, subst) && r.matches(gr, subst)
+                }
+                _ => self == general,
+            },
+        }
+    }
+
+    /// splits `a -> b -> c` into `[a, b]` and `c`
+    pub fn uncurry(&self) -> (Vec<&Type>, &Type) {
+        let mut params = Vec::new();
+        let mut ty = self;
+        while let Type::Arrow(l, r) = ty {
+            params.push(&**l);
+            ty = r;
+        }
+        (params, ty)
+    }
+
+    /// reads the type as a logical formula: `a ∧ b ⇒ a ∨ b`
+    pub fn proposition(&self) -> String {
+        let mut s = String::new();
+        self.buff(&mut s, ["⇒", "∨", "∧", "⊤", "⊥", "?"], 0);
+        s
+    }
+
+    /// the lower, the looser it binds
+    fn precedence(&self) -> usize {
+        match self {
+            Self::Arrow(..) => 0,
+            Self::Sum(..) => 1,
+            Self::Product(..) => 2,
+            Self::Var(_) | Self::Unit | Self::Void | Self::Dyn => 3,
+        }
+    }
+
+    /// `ops` are the arrow, sum and product operators, followed by the unit, void and dyn types.
+    /// Every operator is right associative
+    fn buff(&self, s: &mut String, ops: [&str; 6], min_precedence: usize) {
+        enum Task<'a> {
+            Visit(&'a Type, usize),
+            Push(&'a str),
+        }
+        let mut tasks = vec![Task::Visit(self, min_precedence)];
+        while let Some(task) = tasks.pop() {
+            let (ty, min_precedence) = match task {
+                Task::Visit(ty, min_precedence) => (ty, min_precedence),
+                Task::Push(text) => {
+                    s.push_str(text);
+                    continue;
+                }
+            };
+            let prec = ty.precedence();
+            let Some((l, r, _)) = ty.split() else {
+                match ty {
+                    Self::Var(v) => s.push_str(v),
+                    Self::Unit => s.push_str(ops[3]),
+                    Self::Void => s.push_str(ops[4]),
+                    Self::Dyn => s.push_str(ops[5]),
+                    _ => unreachable!(),
+                }
+                continue;
+            };
+            let parens = prec < min_precedence;
+            if parens {
+                s.push('(');
+                tasks.push(Task::Push(")"));
+            }
+            tasks.push(Task::Visit(r, prec));
+            tasks.push(Task::Push(" "));
+            tasks.push(Task::Push(ops[prec]));
+            tasks.push(Task::Push(" "));
+            tasks.push(Task::Visit(l, prec + 1));
+        }
+    }
+
+    pub fn parse(src: &str) -> Result<Self> {
+        let lexer = lexer();
+        let tokens = lexer.lex(src)?;
+        let (ty, rem) = parse_binary(&lexer, src, &tokens, 0)?;
+        match rem.first() {
+            Some(tk) => Err(parser::Error::UnexpectedToken { at: tk.at }.into()),
+            None => Ok(ty),
+        }
+    }
+}
+
+impl Clone for Type {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Var(v) => Self::Var(v.clone()),
+            Self::Unit => Self::Unit,
+            Self::Void => Self::Void,
+            Self::Dyn => Self::Dyn,
+            ty => ty.rebuild(|_| Rebuild::Keep),
+        }
+    }
+}
+
+impl Drop for Type {
+    fn drop(&mut self) {
+        // the compound sides are moved out before a type is dropped, so the drops don't nest
+        fn take(ty: &mut Type, sides: &mut Vec<Type>) {
+            if let Type::Arrow(l, r) | Type::Product(l, r) | Type::Sum(l, r) = ty {
+                for side in [&mut **l, &mut **r] {
+                    if side.split().is_some() {
+                        sides.push(std::mem::replace(side, Type::Unit));
+                    }
+                }
+            }
+        }
+        let mut sides = Vec::new();
+        take(self, &mut sides);
+        while let Some(mut side) = sides.pop() {
+            take(&mut side, &mut sides);
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = String::new();
+        self.buff(&mut s, ["->", "+", "*", "unit", "void", "dyn"], 0);
+        f.write_str(&s)
+    }
+}
+
+pub fn lexer() -> Lexer {
+    let tokenizers = [
+        Tokenizer::new(ARROW_TY, literal("->")),
+        Tokenizer::new(SUM_TY, single_char('+')),
+        Tokenizer::new(PRODUCT_TY, single_char('*')),
+        Tokenizer::new(OPEN_PAREN_TY, single_char('(')),
+        Tokenizer::new(CLOSE_PAREN_TY, single_char(')')),
+        Tokenizer::ignore(single_char(' ')),
+        Tokenizer::ignore(single_char('\t')),
+        Tokenizer::new(IDENT_TY, ident()),
+    ];
+    Lexer::new(tokenizers.into_iter())
+}
+
+/// the operators, from the loosest to the tightest
+const OPERATORS: [(&str, Constructor); 3] = [
+    (ARROW_TY, Type::arrow),
+    (SUM_TY, Type::sum),
+    (PRODUCT_TY, Type::product),
+];
+
+/// parses the operators from `level` on, right associative
+fn parse_binary<'a>(
+    lex: &Lexer,
+    src: &str,
+    tks: &'a [Token],
+    level: usize,
+) -> parser::Result<(Type, &'a [Token])> {
+    let Some(&(op, build)) = OPERATORS.get(level) else {
+        return parse_atom(lex, src, tks);
+    };
+    let (l, tks) = parse_binary(lex, src, tks, level + 1)?;
+    match tks.split_first() {
+        Some((tk, rem)) if lex.get_type(tk.item) == op => {
+            let (r, rem) = parse_binary(lex, src, rem, level)?;
+            Ok((build(l, r), rem))
+        }
+        _ => Ok((l, tks)),
+    }
+}
+
+fn parse_atom<'a>(lex: &Lexer, src: &str, tks: &'a [Token]) -> parser::Result<(Type, &'a [Token])> {
+    let Some((tk, rem)) = tks.split_first() else {
+        return Err(parser::Error::UnexpectedEof);
+    };
+    match lex.get_type(tk.item) {
+        OPEN_PAREN_TY => {
+            if let Some((tk, rem)) = rem.split_first()
+                && lex.get_type(tk.item) == CLOSE_PAREN_TY
+            {
+                return Ok((Type::Unit, rem));
+            }
+            let (ty, rem) = parse_binary(lex, src, rem, 0)?;
+            match rem.split_first() {
+                Some((tk, rem)) if lex.get_type(tk.item) == CLOSE_PAREN_TY => Ok((ty, rem)),
+                Some((tk, _)) => Err(parser::Error::UnexpectedToken { at: tk.at }),
+                None => Err(parser::Error::UnexpectedEof),
+            }
+        }
+        IDENT_TY => match tk.from_code(src) {
+            "unit" => Ok((Type::Unit, rem)),
+            "void" => Ok((Type::Void, rem)),
+            "dyn" => Ok((Type::Dyn, rem)),
+            v => Ok((Type::Var(v.into()), rem)),
+        },
+        _ => Err(parser::Error::UnexpectedToken { at: tk.at }),
+    }
+}
@@ -0,0 +1,60 @@
+/// a built-in of the typed mode: a definition with a declared type. Its body is a Church
+/// encoding, so every engine runs it as any other term
+#[derive(Debug, Clone, Copy)]
+pub struct Primitive {
+    pub name: &'static str,
+    pub ty: &'static str,
+    pub body: &'static str,
+}
+
+pub const PRIMITIVES: &[Primitive] = &[
+    Primitive {
+        name: "pair",
+        ty: "a -> b -> a * b",
+        body: "fn x y p => p x y",
+    },
+    Primitive {
+        name: "fst",
+        ty: "a * b -> a",
+        body: "fn p => p (fn x y => x)",
+    },
+    Primitive {
+        name: "snd",
+        ty: "a * b -> b",
+        body: "fn p => p (fn x y => y)",
+    },
+    Primitive {
+        name: "inl",
+        ty: "a -> a + b",
+        body: "fn x l r => l x",
+    },
+    Primitive {
+        name: "inr",
+        ty: "b -> a + b",
+        body: "fn y l r => r y",
+    },
+    Primitive {
+        name: "case",
+        ty: "a + b -> (a -> c) -> (b -> c) -> c",
+        body: "fn s l r => s l r",
+    },
+    Primitive {
+        name: "unit",
+        ty: "unit",
+        body: "fn x => x",
+    },
+    // there's no value of `void` to call it with, so the body doesn't matter
+    Primitive {
+        name: "absurd",
+        ty: "void -> a",
+        body: "fn v => v",
+    },
+];
+
+/// general recursion, as the Y combinator. With it, every type is inhabited and typed terms may
+/// no longer normalize, so it's only there when `Session::set_fix` asks for it
+pub const FIX: Primitive = Primitive {
+    name: "fix",
+    ty: "(a -> a) -> a",
+    body: "fn f => (fn x => f (x x)) (fn x => f (x x))",
+};
@@ -0,0 +1,242 @@
+use crate::ir::{Id, Scope};
+use crate::types::{Error, Result, Type};
+
+/// how many nested heads a search goes through before giving up
+pub const MAX_DEPTH: usize = 5;
+
+/// how many terms are kept for each searched type, so types with lots of inhabitants don't explode
+pub const MAX_SOLUTIONS: usize = 64;
+
+/// what an inhabitant applies its arguments to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Head {
+    /// a variable, numbered by the order it was bound, from the outermost abstraction
+    Var(usize),
+    /// one of the typed primitives, like `pair` or `fst`
+    Prim(&'static str),
+}
+
+/// a term in β-normal η-long form: `fn x1 .. xn => h M1 .. Mm`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Inhabitant {
+    pub params: usize,
+    pub head: Head,
+    pub args: Vec<Inhabitant>,
+}
+
+impl Inhabitant {
+    pub fn size(&self) -> usize {
+        self.params + 1 + self.args.iter().map(|a| a.size() + 1).sum::<usize>()
+    }
+
+    /// prints it in the `qk` syntax
+    pub fn to_source(&self) -> String {
+        let mut s = String::new();
+        self.buff_source(&mut s, 0, false);
+        s
+    }
+
+    fn buff_source(&self, s: &mut String, bound: usize, nested: bool) {
+        let parens = nested && (self.params > 0 || !self.args.is_empty());
+        if parens {
+            s.push('(');
+        }
+        if self.params > 0 {
+            s.push_str("fn");
+            (bound..bound + self.params).for_each(|v| {
+                s.push(' ');
+                s.push_str(&Scope::id_to_str(&Id(v)));
+            });
+            s.push_str(" => ");
+        }
+        match self.head {
+            Head::Var(v) => s.push_str(&Scope::id_to_str(&Id(v))),
+            Head::Prim(p) => s.push_str(p),
+        }
+        for arg in &self.args {
+            s.push(' ');
+            arg.buff_source(s, bound + self.params, true);
+        }
+        if parens {
+            s.push(')');
+        }
+    }
+}
+
+/// the smallest inhabitant of `ty`
+pub fn one(ty: &Type) -> Result<Inhabitant> {
+    (1..=MAX_DEPTH)
+        .find_map(|depth| {
+            search(&mut Context::default(), ty, depth)
+                .into_iter()
+                .min_by_key(Inhabitant::size)
+        })
+        .ok_or_else(|| Error::NoInhabitant { ty: ty.clone() })
+}
+
+/// every inhabitant of `ty` inside the search bounds, from the smallest to the biggest
+pub fn all(ty: &Type) -> Result<Vec<Inhabitant>> {
+    let mut found = search(&mut Context::default(), ty, MAX_DEPTH);
+    if found.is_empty() {
+        return Err(Error::NoInhabitant { ty: ty.clone() });
+    }
+    found.sort_by_key(Inhabitant::size);
+    Ok(found)
+}
+
+#[derive(Debug, Default)]
+struct Context {
+    /// the type of each variable in scope
+    vars: Vec<Type>,
+    /// the sums already taken apart by `case`, which doesn't need to happen again in its branches
+    cased: Vec<usize>,
+}
+
+/// introduces every parameter of `ty` and then tries each variable whose type ends on the same
+/// target as `ty`, searching arguments for its own parameters. Products and sums are built with
+/// the typed primitives, and the ones in the context are taken apart with them
+fn search(ctx: &mut Context, ty: &Type, depth: usize) -> Vec<Inhabitant> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    let (params, target) = ty.uncurry();
+    let bound = ctx.vars.len();
+    ctx.vars.extend(params.iter().map(|p| (*p).clone()));
+    let mut found = Vec::new();
+    let intro = match target {
+        Type::Product(l, r) => vec![("pair", vec![&**l, &**r])],
+        Type::Sum(l, r) => vec![("inl", vec![&**l]), ("inr", vec![&**r])],
+        Type::Unit => vec![("unit", Vec::new())],
+        _ => Vec::new(),
+    };
+    for (prim, args) in intro {
+        let head = Head::Prim(prim);
+        found.extend(apply(ctx, head, Vec::new(), &args, params.len(), depth));
+    }
+    for (head, prefix, head_ty) in heads(ctx, target) {
+        let (head_params, head_target) = head_ty.uncurry();
+        if head_target == target {
+            found.extend(apply(ctx, head, prefix, &head_params, params.len(), depth));
+        }
+    }
+    ctx.vars.truncate(bound);
+    found.truncate(MAX_SOLUTIONS);
+    found
+}
+
+/// every way to build `head prefix M1 .. Mn`, where each `Mi` has the type `args[i]`
+fn apply(
+    ctx: &mut Context,
+    head: Head,
+    prefix: Vec<Inhabitant>,
+    args: &[&Type],
+    params: usize,
+    depth: usize,
+) -> Vec<Inhabitant> {
+    let cased = match (head, prefix.first()) {
+        (
+            Head::Prim("case"),
+            Some(Inhabitant {
+                head: Head::Var(v),
+                args,
+                ..
+            }),
+        ) if args.is_empty() => Some(*v),
+        _ => None,
+    };
+    ctx.cased.extend(cased);
+    let mut combinations = vec![prefix];
+    for p in args {
+        let solutions = search(ctx, p, depth - 1);
+        combinations = combinations
+            .into_iter()
+            .flat_map(|args| {
+                solutions.iter().map(move |s| {
+                    let mut args = args.clone();
+                    args.push(s.clone());
+                    args
+                })
+            })
+            .take(MAX_SOLUTIONS)
+            .collect();
+    }
+    if cased.is_some() {
+        ctx.cased.pop();
+    }
+    combinations
+        .into_iter()
+        .map(|args| Inhabitant { params, head, args })
+        .collect()
+}
+
+/// the variables in the context, plus what can be taken out of them: `fst x` and `snd x` for
+/// products, `case x` for sums and `absurd x` for void. Each comes with the arguments already
+/// applied to its head
+fn heads(ctx: &Context, target: &Type) -> Vec<(Head, Vec<Inhabitant>, Type)> {
+    let mut heads: Vec<_> = ctx
+        .vars
+        .iter()
+        .enumerate()
+        .map(|(v, ty)| (Head::Var(v), Vec::new(), ty.clone()))
+        .collect();
+    let mut i = 0;
+    while i < heads.len() {
+        let (head, prefix, ty) = heads[i].clone();
+        let term = Inhabitant {
+            params: 0,
+            head,
+            args: prefix,
+        };
+        match &ty {
+            Type::Product(l, r) => {
+                heads.push((Head::Prim("fst"), vec![term.clone()], (**l).clone()));
+                heads.push((Head::Prim("snd"), vec![term], (**r).clone()));
+            }
+            Type::Sum(l, r) if !matches!(head, Head::Var(v) if ctx.cased.contains(&v)) => {
+                let t = || target.clone();
+                let (l, r) = ((**l).clone(), (**r).clone());
+                let ty = Type::arrow(Type::arrow(l, t()), Type::arrow(Type::arrow(r, t()), t()));
+                heads.push((Head::Prim("case"), vec![term], ty));
+            }
+            Type::Void => heads.push((Head::Prim("absurd"), vec![term], target.clone())),
+            _ => (),
+        }
+        i += 1;
+    }
+    heads
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::types::{Type, synth};
+
+    fn synth(ty: &str) -> Vec<String> {
+        let ty = Type::parse(ty).unwrap();
+        synth::all(&ty)
+            .map(|s| s.iter().map(|s| s.to_source()).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    pub fn inhabitants() {
+        assert_eq!(synth("a -> a"), ["fn a => a"]);
+        assert_eq!(synth("(a -> b) -> a -> b"), ["fn a b => a b"]);
+        assert_eq!(synth("a -> b -> a"), ["fn a b => a"]);
+        assert_eq!(
+            synth("(a -> a) -> a -> a")[..3],
+            ["fn a b => b", "fn a b => a b", "fn a b => a (a b)"]
+        );
+        assert!(synth("a -> b").is_empty());
+        assert_eq!(
+            synth("(a -> b -> c) -> (a -> b) -> a -> c"),
+            ["fn a b c => a c (b c)"]
+        );
+        assert_eq!(synth("a * b -> b * a"), ["fn a => pair (snd a) (fst a)"]);
+        assert_eq!(synth("void -> a"), ["fn a => absurd a"]);
+        assert_eq!(synth("a -> ()"), ["fn a => unit"]);
+        assert_eq!(
+            synth("a + b -> b + a"),
+            ["fn a => case a (fn b => inr b) (fn b => inl b)"]
+        );
+    }
+}
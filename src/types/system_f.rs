@@ -0,0 +1,596 @@
+// Line =
+//      <Ident> "=" Term
+//      Term
+// Term =
+//      "λ" <Ident> ":" Arrow "." Term
+//      "Λ" <Ident> "." Term
+//      Atom (Atom | "[" Type "]")* Term?
+// Atom =
+//      "(" Term ")"
+//      <Ident>
+// Type =
+//      "∀" <Ident> "." Type
+//      Arrow
+// Arrow =
+//      "(" Type ")" ("->" Arrow)?
+//      <Ident> ("->" Arrow)?
+
+use std::collections::HashMap;
+use std::fmt;
+
+use miette::{Diagnostic, SourceSpan};
+use thiserror::Error;
+
+use crate::ast::{Ast, Node};
+use crate::lexer::{Meta, Trace, from_code, over};
+use crate::padam::{
+    Token,
+    lexer::{
+        ASSIGN_TY, CLOSE_BRACKET_TY, CLOSE_PAREN_TY, EOL_TY, FN_IMPL_TY, FN_KW_TY, IDENT_TY, Lexer,
+        OPEN_BRACKET_TY, OPEN_PAREN_TY, Tokenizer, comment, ident, literal, single_char,
+    },
+    parser,
+};
+use crate::types::ARROW_TY;
+
+pub const TY_ABS_TY: &str = "TyAbs";
+pub const FORALL_TY: &str = "Forall";
+pub const COLON_TY: &str = "Colon";
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Error, Diagnostic, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Lexer(#[from] crate::lexer::Error),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Parser(#[from] parser::Error),
+
+    #[error("unknown variable `{name}`")]
+    #[diagnostic(
+        code(system_f::unbound),
+        help("a System F term only sees its binders, and what was defined in `system-f`")
+    )]
+    Unbound {
+        name: Box<str>,
+        #[label("not bound here")]
+        at: SourceSpan,
+    },
+
+    #[error("unknown type variable `{name}`")]
+    #[diagnostic(
+        code(system_f::unbound_type),
+        help("a type variable must be bound by a `Λ` or a `∀` around it")
+    )]
+    UnboundType {
+        name: Box<str>,
+        #[label("in this type")]
+        at: SourceSpan,
+    },
+
+    #[error("the type variable `{name}` is already bound")]
+    #[diagnostic(
+        code(system_f::shadowed_type),
+        help("types would mix up the two, so give this one another name")
+    )]
+    ShadowedType {
+        name: Box<str>,
+        #[label("bound again here")]
+        at: SourceSpan,
+    },
+
+    #[error("this isn't a function")]
+    #[diagnostic(
+        code(system_f::not_a_function),
+        help("it has type `{ty}`, and only arrows take an argument")
+    )]
+    NotAFunction {
+        #[label("applied to a term")]
+        at: SourceSpan,
+        ty: Type,
+    },
+
+    #[error("this isn't polymorphic")]
+    #[diagnostic(
+        code(system_f::not_polymorphic),
+        help("it has type `{ty}`, and only `∀` types take a type")
+    )]
+    NotPolymorphic {
+        #[label("applied to a type")]
+        at: SourceSpan,
+        ty: Type,
+    },
+
+    #[error("mismatched types")]
+    #[diagnostic(
+        code(system_f::mismatch),
+        help("expected `{expected}`, but found `{found}`")
+    )]
+    Mismatch {
+        #[label("this argument")]
+        at: SourceSpan,
+        expected: Type,
+        found: Type,
+    },
+}
+
+/// a type of System F
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Var(Box<str>),
+    Arrow(Box<Type>, Box<Type>),
+    /// `∀a. t`, what a term has if it takes any type `a`
+    Forall(Box<str>, Box<Type>),
+}
+
+impl Type {
+    /// whether the two are the same type, up to the names of the bound variables
+    pub fn alpha_eq(&self, other: &Type) -> bool {
+        fn eq<'a>(l: &'a Type, r: &'a Type, bound: &mut Vec<(&'a str, &'a str)>) -> bool {
+            match (l, r) {
+                (Type::Var(a), Type::Var(b)) => {
+                    let l = bound.iter().rposition(|(v, _)| **v == **a);
+                    let r = bound.iter().rposition(|(_, v)| **v == **b);
+                    match (l, r) {
+                        (None, None) => a == b,
+                        (l, r) => l == r,
+                    }
+                }
+                (Type::Arrow(a, b), Type::Arrow(c, d)) => eq(a, c, bound) && eq(b, d, bound),
+                (Type::Forall(a, t), Type::Forall(b, u)) => {
+                    bound.push((a, b));
+                    let same = eq(t, u, bound);
+                    bound.pop();
+                    same
+                }
+                _ => false,
+            }
+        }
+        eq(self, other, &mut Vec::new())
+    }
+
+    pub fn is_free(&self, name: &str) -> bool {
+        match self {
+            Self::Var(v) => **v == *name,
+            Self::Arrow(l, r) => l.is_free(name) || r.is_free(name),
+            Self::Forall(v, t) => **v != *name && t.is_free(name),
+        }
+    }
+
+    /// `self` with `by` for the free `name`. The bound variables `by` would be captured by are
+    /// renamed
+    pub fn subst(&self, name: &str, by: &Type) -> Type {
+        match self {
+            Self::Var(v) if **v == *name => by.clone(),
+            Self::Var(_) => self.clone(),
+            Self::Arrow(l, r) => Self::Arrow(l.subst(name, by).into(), r.subst(name, by).into()),
+            Self::Forall(v, _) if **v == *name => self.clone(),
+            Self::Forall(v, t) if by.is_free(v) => {
+                let mut fresh = format!("{v}'");
+                while by.is_free(&fresh) || t.is_free(&fresh) {
+                    fresh.push('\'');
+                }
+                let t = t.subst(v, &Type::Var(fresh.as_str().into()));
+                Self::Forall(fresh.into(), t.subst(name, by).into())
+            }
+            Self::Forall(v, t) => Self::Forall(v.clone(), t.subst(name, by).into()),
+        }
+    }
+}
+
+/// `∀` stretches as far right as it can, and arrows are right associative
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Var(v) => f.write_str(v),
+            Self::Arrow(l, r) if matches!(**l, Self::Var(_)) => write!(f, "{l} -> {r}"),
+            Self::Arrow(l, r) => write!(f, "({l}) -> {r}"),
+            Self::Forall(v, t) => write!(f, "∀{v}. {t}"),
+        }
+    }
+}
+
+pub type Term = Box<Meta<Expr>>;
+
+/// a term of System F. The names of variables are their spans in the source
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Var,
+    /// `λx:t. body`
+    Abs {
+        var: SourceSpan,
+        ty: Type,
+        body: Term,
+    },
+    App(Term, Term),
+    /// `Λa. body`
+    TyAbs {
+        var: SourceSpan,
+        body: Term,
+    },
+    /// `term [t]`
+    TyApp(Term, Type),
+}
+
+/// what an input is in System F
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    Expr(Term),
+    /// a definition a line, by its name
+    Defs(Vec<(SourceSpan, Term)>),
+}
+
+pub fn lexer() -> Lexer {
+    let tokenizers = [
+        Tokenizer::new(FN_KW_TY, single_char('λ')),
+        Tokenizer::new(FN_KW_TY, single_char('\\')),
+        Tokenizer::new(TY_ABS_TY, single_char('Λ')),
+        Tokenizer::new(TY_ABS_TY, literal("/\\")),
+        Tokenizer::new(FORALL_TY, single_char('∀')),
+        Tokenizer::new(FORALL_TY, literal("forall")),
+        Tokenizer::new(FN_IMPL_TY, single_char('.')),
+        Tokenizer::new(COLON_TY, single_char(':')),
+        Tokenizer::new(ARROW_TY, literal("->")),
+        Tokenizer::new(OPEN_PAREN_TY, single_char('(')),
+        Tokenizer::new(CLOSE_PAREN_TY, single_char(')')),
+        Tokenizer::new(OPEN_BRACKET_TY, single_char('[')),
+        Tokenizer::new(CLOSE_BRACKET_TY, single_char(']')),
+        Tokenizer::new(ASSIGN_TY, single_char('=')),
+        Tokenizer::new(EOL_TY, single_char('\n')),
+        Tokenizer::ignore(single_char(' ')),
+        Tokenizer::ignore(single_char('\t')),
+        Tokenizer::new(IDENT_TY, ident()),
+        Tokenizer::ignore(comment()),
+    ];
+    Lexer::new(tokenizers.into_iter())
+}
+
+/// parses an expression, or definitions if the first line is one. `None` if there's nothing
+pub fn parse(src: &str) -> Result<Option<Input>> {
+    let lexer = lexer();
+    let tokens = lexer.lex(src)?;
+    let mut lines = tokens
+        .split(|t| lexer.get_type(t.item) == EOL_TY)
+        .filter(|l| !l.is_empty());
+    let Some(first) = lines.next() else {
+        return Ok(None);
+    };
+    let is_def = |line: &[Token]| {
+        line.get(1)
+            .is_some_and(|t| lexer.get_type(t.item) == ASSIGN_TY)
+    };
+    if !is_def(first) {
+        if let Some(next) = lines.next() {
+            return Err(parser::Error::UnexpectedToken { at: next[0].at }.into());
+        }
+        return Ok(Some(Input::Expr(Parser::new(&lexer, src, first).line()?)));
+    }
+    let mut defs = Vec::new();
+    for line in std::iter::once(first).chain(lines) {
+        if !is_def(line) || lexer.get_type(line[0].item) != IDENT_TY {
+            return Err(parser::Error::UnexpectedToken { at: line[0].at }.into());
+        }
+        defs.push((line[0].at, Parser::new(&lexer, src, &line[2..]).line()?));
+    }
+    Ok(Some(Input::Defs(defs)))
+}
+
+struct Parser<'a> {
+    lexer: &'a Lexer,
+    src: &'a str,
+    tokens: &'a [Token],
+}
+
+impl<'a> Parser<'a> {
+    fn new(lexer: &'a Lexer, src: &'a str, tokens: &'a [Token]) -> Self {
+        Self { lexer, src, tokens }
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.first().map(|t| self.lexer.get_type(t.item))
+    }
+
+    fn next(&mut self) -> parser::Result<Token> {
+        let (tk, rest) = self
+            .tokens
+            .split_first()
+            .ok_or(parser::Error::UnexpectedEof)?;
+        self.tokens = rest;
+        Ok(tk.clone())
+    }
+
+    fn expect(&mut self, ty: &str) -> parser::Result<Token> {
+        let tk = self.next()?;
+        match self.lexer.get_type(tk.item) == ty {
+            true => Ok(tk),
+            false => Err(parser::Error::UnexpectedToken { at: tk.at }),
+        }
+    }
+
+    /// a whole line, with nothing left after the term
+    fn line(mut self) -> parser::Result<Term> {
+        let term = self.term()?;
+        match self.tokens.first() {
+            Some(tk) => Err(parser::Error::UnexpectedToken { at: tk.at }),
+            None => Ok(term),
+        }
+    }
+
+    fn term(&mut self) -> parser::Result<Term> {
+        match self.peek() {
+            Some(FN_KW_TY) => {
+                let start = self.next()?.at;
+                let var = self.expect(IDENT_TY)?.at;
+                self.expect(COLON_TY)?;
+                let ty = self.arrow()?;
+                self.expect(FN_IMPL_TY)?;
+                let body = self.term()?;
+                let at = over(start, body.at);
+                Ok(Expr::Abs { var, ty, body }.at(at))
+            }
+            Some(TY_ABS_TY) => {
+                let start = self.next()?.at;
+                let var = self.expect(IDENT_TY)?.at;
+                self.expect(FN_IMPL_TY)?;
+                let body = self.term()?;
+                let at = over(start, body.at);
+                Ok(Expr::TyAbs { var, body }.at(at))
+            }
+            _ => {
+                let mut term = self.atom()?;
+                loop {
+                    term = match self.peek() {
+                        Some(IDENT_TY | OPEN_PAREN_TY) => {
+                            let arg = self.atom()?;
+                            let at = over(term.at, arg.at);
+                            Expr::App(term, arg).at(at)
+                        }
+                        Some(OPEN_BRACKET_TY) => {
+                            self.next()?;
+                            let ty = self.ty()?;
+                            let end = self.expect(CLOSE_BRACKET_TY)?.at;
+                            let at = over(term.at, end);
+                            Expr::TyApp(term, ty).at(at)
+                        }
+                        // an abstraction takes the rest of the line
+                        Some(FN_KW_TY | TY_ABS_TY) => {
+                            let arg = self.term()?;
+                            let at = over(term.at, arg.at);
+                            Expr::App(term, arg).at(at)
+                        }
+                        _ => return Ok(term),
+                    }
+                }
+            }
+        }
+    }
+
+    fn atom(&mut self) -> parser::Result<Term> {
+        let tk = self.next()?;
+        match self.lexer.get_type(tk.item) {
+            IDENT_TY => Ok(Expr::Var.at(tk.at)),
+            OPEN_PAREN_TY => {
+                let mut term = self.term()?;
+                let end = self.expect(CLOSE_PAREN_TY)?.at;
+                term.at = over(tk.at, end);
+                Ok(term)
+            }
+            _ => Err(parser::Error::UnexpectedToken { at: tk.at }),
+        }
+    }
+
+    fn ty(&mut self) -> parser::Result<Type> {
+        if self.peek() != Some(FORALL_TY) {
+            return self.arrow();
+        }
+        self.next()?;
+        let var = self.expect(IDENT_TY)?.from_code(self.src).into();
+        self.expect(FN_IMPL_TY)?;
+        Ok(Type::Forall(var, self.ty()?.into()))
+    }
+
+    /// a type without a `∀` outside of parenthesis, so it can't take the `.` of an abstraction
+    fn arrow(&mut self) -> parser::Result<Type> {
+        let tk = self.next()?;
+        let l = match self.lexer.get_type(tk.item) {
+            IDENT_TY => Type::Var(tk.from_code(self.src).into()),
+            OPEN_PAREN_TY => {
+                let ty = self.ty()?;
+                self.expect(CLOSE_PAREN_TY)?;
+                ty
+            }
+            _ => return Err(parser::Error::UnexpectedToken { at: tk.at }),
+        };
+        if self.peek() != Some(ARROW_TY) {
+            return Ok(l);
+        }
+        self.next()?;
+        Ok(Type::Arrow(l.into(), self.arrow()?.into()))
+    }
+}
+
+/// type checks terms, given the types of the definitions
+pub struct Checker<'a> {
+    src: &'a str,
+    globals: &'a HashMap<Box<str>, Type>,
+    /// the variables in scope, the innermost last
+    vars: Vec<(&'a str, Type)>,
+    /// the type variables in scope
+    tys: Vec<&'a str>,
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(src: &'a str, globals: &'a HashMap<Box<str>, Type>) -> Self {
+        Self {
+            src,
+            globals,
+            vars: Vec::new(),
+            tys: Vec::new(),
+        }
+    }
+
+    pub fn check(&mut self, term: &Term) -> Result<Type> {
+        match &term.item {
+            Expr::Var => {
+                let name = term.from_code(self.src);
+                let bound = self.vars.iter().rev().find(|(v, _)| *v == name);
+                match bound.map(|(_, ty)| ty).or_else(|| self.globals.get(name)) {
+                    Some(ty) => Ok(ty.clone()),
+                    None => Err(Error::Unbound {
+                        name: name.into(),
+                        at: term.at,
+                    }),
+                }
+            }
+            Expr::Abs { var, ty, body } => {
+                self.bound(ty, term.at)?;
+                self.vars.push((from_code(*var, self.src), ty.clone()));
+                let body = self.check(body);
+                self.vars.pop();
+                Ok(Type::Arrow(ty.clone().into(), body?.into()))
+            }
+            Expr::App(f, arg) => {
+                let ty = self.check(f)?;
+                let Type::Arrow(param, result) = ty else {
+                    return Err(Error::NotAFunction { at: f.at, ty });
+                };
+                let found = self.check(arg)?;
+                match param.alpha_eq(&found) {
+                    true => Ok(*result),
+                    false => Err(Error::Mismatch {
+                        at: arg.at,
+                        expected: *param,
+                        found,
+                    }),
+                }
+            }
+            Expr::TyAbs { var, body } => {
+                let name = from_code(*var, self.src);
+                if self.tys.contains(&name) {
+                    return Err(Error::ShadowedType {
+                        name: name.into(),
+                        at: *var,
+                    });
+                }
+                self.tys.push(name);
+                let body = self.check(body);
+                self.tys.pop();
+                Ok(Type::Forall(name.into(), body?.into()))
+            }
+            Expr::TyApp(f, arg) => {
+                self.bound(arg, term.at)?;
+                match self.check(f)? {
+                    Type::Forall(v, body) => Ok(body.subst(&v, arg)),
+                    ty => Err(Error::NotPolymorphic { at: f.at, ty }),
+                }
+            }
+        }
+    }
+
+    /// checks every type variable of `ty` is in scope
+    fn bound(&self, ty: &Type, at: SourceSpan) -> Result<()> {
+        fn free<'t>(ty: &'t Type, bound: &mut Vec<&'t str>) -> Option<&'t str> {
+            match ty {
+                Type::Var(v) => (!bound.contains(&&**v)).then_some(v),
+                Type::Arrow(l, r) => free(l, bound).or_else(|| free(r, bound)),
+                Type::Forall(v, t) => {
+                    bound.push(v);
+                    let found = free(t, bound);
+                    bound.pop();
+                    found
+                }
+            }
+        }
+        match free(ty, &mut self.tys.clone()) {
+            Some(name) => Err(Error::UnboundType {
+                name: name.into(),
+                at,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// the untyped term `term` runs as: without its types, and without its type abstractions and
+/// applications
+pub fn erase(term: Meta<Expr>) -> Node {
+    let at = term.at;
+    match term.item {
+        Expr::Var => Ast::Var.at(at),
+        Expr::Abs { var, body, .. } => Ast::Abs(var, erase(*body)).at(at),
+        Expr::App(f, arg) => Ast::App(erase(*f), erase(*arg)).at(at),
+        Expr::TyAbs { body, .. } | Expr::TyApp(body, _) => erase(*body),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::collections::HashMap;
+
+    use crate::session::{Language, Session};
+    use crate::types::system_f::{self, Checker, Error, Input, Type};
+
+    fn type_of(src: &str) -> Result<String, Error> {
+        with_globals(src, &HashMap::new())
+    }
+
+    fn with_globals(src: &str, globals: &HashMap<Box<str>, Type>) -> Result<String, Error> {
+        let Some(Input::Expr(term)) = system_f::parse(src)? else {
+            unreachable!("{src} is an expression")
+        };
+        Ok(Checker::new(src, globals).check(&term)?.to_string())
+    }
+
+    #[test]
+    pub fn checks_polymorphic_terms() {
+        assert_eq!(type_of("Λa. λx:a. x").unwrap(), "∀a. a -> a");
+        assert_eq!(
+            type_of("Λa. λf:a -> a. λx:a. f (f x)").unwrap(),
+            "∀a. (a -> a) -> a -> a"
+        );
+        // self-application has a type, if its argument is polymorphic
+        assert_eq!(
+            type_of("λx:(∀a. a -> a). x [∀a. a -> a] x").unwrap(),
+            "(∀a. a -> a) -> ∀a. a -> a"
+        );
+        // instantiating renames what would capture the argument
+        let k = Type::Forall(
+            "a".into(),
+            Type::Arrow(
+                Type::Var("a".into()).into(),
+                Type::Forall("b".into(), Type::Var("a".into()).into()).into(),
+            )
+            .into(),
+        );
+        let globals = HashMap::from([("k".into(), k)]);
+        assert_eq!(
+            with_globals("Λb. k [b]", &globals).unwrap(),
+            "∀b. b -> ∀b'. b"
+        );
+        assert!(matches!(
+            type_of("λx:(∀a. a). x x"),
+            Err(Error::NotAFunction { .. })
+        ));
+        assert!(matches!(
+            type_of("Λa. λx:a. Λb. λy:b. (λz:a. z) y"),
+            Err(Error::Mismatch { .. })
+        ));
+        assert!(matches!(type_of("λx:a. x"), Err(Error::UnboundType { .. })));
+    }
+
+    #[test]
+    pub fn erases_to_the_untyped_core() {
+        let mut s = Session::new();
+        s.language = Language::SystemF;
+        s.eval_str("id = Λa. λx:a. x\ntwo = Λa. λf:a -> a. λx:a. f (f x)")
+            .unwrap();
+        let out = s.eval_str("two [∀a. a -> a] (id [∀a. a -> a]) id").unwrap();
+        assert_eq!(out.poly.unwrap().to_string(), "∀a. a -> a");
+        let ev = s.evaluate("id [∀a. a -> a] id").unwrap();
+        assert_eq!(ev.line().unwrap(), "id : ∀a. a -> a");
+        assert!(s.eval_str("id id").is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use js_sys::{Array, Object, Reflect};
+use miette::{Diagnostic, Severity};
+use wasm_bindgen::prelude::*;
+
+use crate::session::{Output, Session};
+
+/// runs `source` in a fresh session. See `Playground::eval` for what is returned
+#[wasm_bindgen]
+pub fn eval(source: &str) -> JsValue {
+    Playground::new().eval(source)
+}
+
+/// a session that keeps its definitions between calls, like the REPL does
+#[wasm_bindgen]
+#[derive(Debug, Default)]
+pub struct Playground {
+    session: Session,
+}
+
+#[wasm_bindgen]
+impl Playground {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// returns `{ ok: true, output: "empty" | "defined" | "normal", normal?: string }` or, if
+    /// anything went wrong, `{ ok: false, diagnostics: [...] }`
+    pub fn eval(&mut self, source: &str) -> JsValue {
+        self.export("text", source)
+    }
+
+    /// same as `eval`, but `normal` is printed with the renderer called `format`
+    pub fn export(&mut self, format: &str, source: &str) -> JsValue {
+        let obj = Object::new();
+        match self.session.evaluate(source) {
+            Ok(ev) => {
+                set(&obj, "ok", true.into());
+                let output = match ev.output() {
+                    Output::Empty => "empty",
+                    Output::Defined => "defined",
+                    Output::Normal(_) => {
+                        if let Some(view) = ev.view(format) {
+                            set(&obj, "normal", (*view).into());
+                        }
+                        "normal"
+                    }
+                };
+                set(&obj, "output", output.into());
+            }
+            Err(e) => {
+                set(&obj, "ok", false.into());
+                let diagnostics = Array::of1(&diagnostic(&e).into());
+                set(&obj, "diagnostics", diagnostics.into());
+            }
+        }
+        obj.into()
+    }
+
+    /// returns `false` if there's no such syntax
+    #[wasm_bindgen(js_name = setSyntax)]
+    pub fn set_syntax(&mut self, name: &str) -> bool {
+        self.session.set_syntax(name)
+    }
+}
+
+/// `{ code, message, severity, help?, labels: [{ label?, offset, length }] }`, where `offset`
+/// and `length` are in bytes
+fn diagnostic(e: &dyn Diagnostic) -> Object {
+    let obj = Object::new();
+    set(&obj, "message", e.to_string().into());
+    if let Some(code) = e.code() {
+        set(&obj, "code", code.to_string().into());
+    }
+    let severity = match e.severity().unwrap_or(Severity::Error) {
+        Severity::Advice => "advice",
+        Severity::Warning => "warning",
+        Severity::Error => "error",
+    };
+    set(&obj, "severity", severity.into());
+    if let Some(help) = e.help() {
+        set(&obj, "help", help.to_string().into());
+    }
+    let labels = Array::new();
+    for l in e.labels().into_iter().flatten() {
+        let label = Object::new();
+        if let Some(text) = l.label() {
+            set(&label, "label", text.into());
+        }
+        set(&label, "offset", l.offset().into());
+        set(&label, "length", l.len().into());
+        labels.push(&label);
+    }
+    set(&obj, "labels", labels.into());
+    obj
+}
+
+fn set(obj: &Object, key: &str, value: JsValue) {
+    // only fails on frozen objects
+    let _ = Reflect::set(obj, &key.into(), &value);
+}